@@ -1,4 +1,285 @@
-fn main(){
-    //println!("cargo:rustc-link-lib=SDL2");
-    //println!("cargo:rustc-link-lib=vulkan");
-}
\ No newline at end of file
+//! Compiles every shader under `shaders/` to SPIR-V and generates `$OUT_DIR/shaders.rs`,
+//! which embeds them as `pub const NAME_STAGE: &[u8]` constants.
+//!
+//! Shaders are named `<name>.<stage>` for GLSL and `<name>.<stage>.hlsl` for HLSL, with
+//! `stage` one of `vert`, `frag`, `comp`, `geom`, `tesc` or `tese`; other files, such as
+//! `.glsl` or `.hlsli` headers, are only used through `#include`. A shader can ask for
+//! preprocessor defines and an HLSL entry point with directive comments:
+//!
+//! ```glsl
+//! // @define MAX_LIGHTS=16
+//! // @entry main_ps
+//! ```
+//!
+//! Compilation uses `glslc` from `$GLSLC`, the `PATH` or `$VULKAN_SDK/bin`. With the
+//! `precompiled-shaders` feature the `<name>.<stage>.spv` files checked in next to the
+//! sources are embedded instead; `LUDO_UPDATE_SPV=1` refreshes them from a real build.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SHADER_DIR: &str = "shaders";
+
+/// File extensions of shader stages and their `glslc -fshader-stage` names.
+const STAGES: [(&str, &str); 6] = [
+    ("vert", "vertex"),
+    ("frag", "fragment"),
+    ("comp", "compute"),
+    ("geom", "geometry"),
+    ("tesc", "tesscontrol"),
+    ("tese", "tesseval"),
+];
+
+struct Shader
+{
+    /// Path relative to the package, as reported in diagnostics.
+    source: PathBuf,
+    /// Path relative to `shaders/` without `.hlsl`, e.g. `post/blur.comp`.
+    name: String,
+    stage: &'static str,
+    hlsl: bool,
+}
+impl Shader
+{
+    fn from_path(source: &Path) -> Option<Shader>
+    {
+        let relative = source.strip_prefix(SHADER_DIR).ok()?.to_str()?.replace('\\', "/");
+        let (name, hlsl) = match relative.strip_suffix(".hlsl")
+        {
+            Some(name) => (name.to_string(), true),
+            None => (relative, false),
+        };
+        let extension = name.rsplit('.').next()?;
+        let stage = STAGES.iter().find(|(known, _)| *known == extension)?.1;
+        Some(Shader { source: source.to_path_buf(), name, stage, hlsl })
+    }
+
+    fn get_const_name(&self) -> String
+    {
+        self.name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect()
+    }
+
+    fn get_precompiled_path(&self) -> PathBuf
+    {
+        Path::new(SHADER_DIR).join(format!("{}.spv", self.name))
+    }
+}
+
+fn find_shaders(directory: &Path, shaders: &mut Vec<Shader>)
+{
+    let Ok(entries) = std::fs::read_dir(directory) else { return };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths
+    {
+        if path.is_dir()
+        {
+            find_shaders(&path, shaders);
+        }
+        else if let Some(shader) = Shader::from_path(&path)
+        {
+            shaders.push(shader);
+        }
+    }
+}
+
+/// Reports shaders that would be embedded under the same constant, such as `blur.frag` and
+/// `blur.frag.hlsl`; returns `false` if there are any.
+fn check_collisions(shaders: &[Shader]) -> bool
+{
+    let mut names: HashMap<String, &Shader> = HashMap::new();
+    let mut unique = true;
+    for shader in shaders
+    {
+        if let Some(other) = names.insert(shader.get_const_name(), shader)
+        {
+            println!("cargo::error={}: both this and {} would be embedded as `{}`; rename one of them",
+                shader.source.display(), other.source.display(), shader.get_const_name());
+            unique = false;
+        }
+    }
+    unique
+}
+
+/// Values of `// @<directive> <value>` comments in a shader source.
+fn get_directives(source: &str, directive: &str) -> Vec<String>
+{
+    let prefix = format!("@{} ", directive);
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("//")?.trim().strip_prefix(&prefix))
+        .map(|value| value.trim().to_string())
+        .collect()
+}
+
+fn find_compiler() -> Option<PathBuf>
+{
+    let mut candidates = Vec::new();
+    if let Some(glslc) = std::env::var_os("GLSLC")
+    {
+        candidates.push(PathBuf::from(glslc));
+    }
+    candidates.push(PathBuf::from("glslc"));
+    if let Some(sdk) = std::env::var_os("VULKAN_SDK")
+    {
+        candidates.push(Path::new(&sdk).join("bin").join("glslc"));
+    }
+    candidates.into_iter().find(|candidate| {
+        Command::new(candidate).arg("--version").output().is_ok_and(|output| output.status.success())
+    })
+}
+
+/// Forwards `glslc` diagnostics, which already read `file:line: error: message`.
+fn report_diagnostics(shader: &Shader, output: &str)
+{
+    let mut reported = false;
+    for line in output.lines()
+    {
+        if line.contains(": error: ")
+        {
+            println!("cargo::error={}", line);
+            reported = true;
+        }
+        else if line.contains(": warning: ")
+        {
+            println!("cargo::warning={}", line);
+        }
+    }
+    if !reported
+    {
+        println!("cargo::error={}: compilation failed: {}", shader.source.display(), output.trim());
+    }
+}
+
+/// Compiles `shader` into `output`; returns `false` after reporting errors.
+fn compile(compiler: &Path, shader: &Shader, output: &Path) -> bool
+{
+    let source = match std::fs::read_to_string(&shader.source)
+    {
+        Ok(source) => source,
+        Err(error) =>
+        {
+            println!("cargo::error={}: {}", shader.source.display(), error);
+            return false;
+        }
+    };
+    let mut command = Command::new(compiler);
+    command
+        .arg(format!("-fshader-stage={}", shader.stage))
+        .arg("--target-env=vulkan1.0")
+        .arg("-O")
+        .arg("-I")
+        .arg(SHADER_DIR);
+    if shader.hlsl
+    {
+        command.arg("-x").arg("hlsl");
+    }
+    for define in get_directives(&source, "define")
+    {
+        command.arg(format!("-D{}", define));
+    }
+    if let Some(entry) = get_directives(&source, "entry").last()
+    {
+        command.arg(format!("-fentry-point={}", entry));
+    }
+    command.arg("-o").arg(output).arg(&shader.source);
+    match command.output()
+    {
+        Ok(result) if result.status.success() =>
+        {
+            // Warnings of successful builds are still worth seeing.
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            for line in stderr.lines().filter(|line| line.contains(": warning: "))
+            {
+                println!("cargo::warning={}", line);
+            }
+            true
+        }
+        Ok(result) =>
+        {
+            report_diagnostics(shader, &String::from_utf8_lossy(&result.stderr));
+            false
+        }
+        Err(error) =>
+        {
+            println!("cargo::error={}: cannot run {}: {}", shader.source.display(), compiler.display(), error);
+            false
+        }
+    }
+}
+
+fn main()
+{
+    println!("cargo::rerun-if-changed={}", SHADER_DIR);
+    println!("cargo::rerun-if-env-changed=GLSLC");
+    println!("cargo::rerun-if-env-changed=VULKAN_SDK");
+    println!("cargo::rerun-if-env-changed=LUDO_UPDATE_SPV");
+
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").expect("OUT_DIR is not set"));
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is not set"));
+    let precompiled = std::env::var_os("CARGO_FEATURE_PRECOMPILED_SHADERS").is_some();
+    let update_spv = std::env::var_os("LUDO_UPDATE_SPV").is_some_and(|value| value != "0");
+
+    let mut shaders = Vec::new();
+    find_shaders(Path::new(SHADER_DIR), &mut shaders);
+    if !check_collisions(&shaders)
+    {
+        return;
+    }
+
+    let compiler = if precompiled { None } else { find_compiler() };
+    if !precompiled && compiler.is_none()
+    {
+        println!("cargo::error=glslc was not found in $GLSLC, the PATH or $VULKAN_SDK/bin; \
+            install the Vulkan SDK or build with `--features precompiled-shaders`");
+        return;
+    }
+
+    let mut generated = String::from("// Generated by build.rs from the shaders/ directory.\n");
+    let mut succeeded = true;
+    for shader in &shaders
+    {
+        let spv = match &compiler
+        {
+            Some(compiler) =>
+            {
+                let output = out_dir.join(SHADER_DIR).join(format!("{}.spv", shader.name));
+                std::fs::create_dir_all(output.parent().unwrap()).expect("cannot create the shader output directory");
+                if !compile(compiler, shader, &output)
+                {
+                    succeeded = false;
+                    continue;
+                }
+                if update_spv
+                {
+                    std::fs::copy(&output, shader.get_precompiled_path()).expect("cannot update the precompiled shader");
+                }
+                output
+            }
+            None =>
+            {
+                let spv = shader.get_precompiled_path();
+                if !spv.is_file()
+                {
+                    println!("cargo::error={}: no precompiled {} to embed", shader.source.display(), spv.display());
+                    succeeded = false;
+                    continue;
+                }
+                manifest_dir.join(spv)
+            }
+        };
+        generated += &format!(
+            "\n/// SPIR-V of `{}`.\npub const {}: &[u8] = include_bytes!({:?});\n",
+            shader.source.display(),
+            shader.get_const_name(),
+            spv.to_str().expect("shader path is not valid UTF-8"));
+    }
+    if succeeded
+    {
+        std::fs::write(out_dir.join("shaders.rs"), generated).expect("cannot write shaders.rs");
+    }
+}
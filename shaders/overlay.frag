@@ -0,0 +1,43 @@
+#version 450
+
+// Draws the overlay text in white on a translucent black box.
+
+#include "overlay.glsl"
+
+layout(location = 0) in vec2 v_texel;
+layout(location = 0) out vec4 out_color;
+
+// Glyphs of ASCII 32 to 95 with bit (row * 3 + column) set for lit texels, row 0 at the
+// top; the renderer upper-cases the text and replaces what is missing with '?'.
+const uint FONT[64] = uint[](
+    0x0000u, 0x2092u, 0x002Du, 0x5F7Du, 0x3C9Eu, 0x52A5u, 0x6AAAu, 0x0012u,
+    0x4494u, 0x1491u, 0x0AA8u, 0x05D0u, 0x1400u, 0x01C0u, 0x2000u, 0x12A4u,
+    0x7B6Fu, 0x749Au, 0x73E7u, 0x79A7u, 0x49EDu, 0x79CFu, 0x7BCFu, 0x24A7u,
+    0x7BEFu, 0x79EFu, 0x0410u, 0x1410u, 0x4454u, 0x0E38u, 0x1511u, 0x21A7u,
+    0x63EAu, 0x5BEAu, 0x3AEBu, 0x624Eu, 0x3B6Bu, 0x72CFu, 0x12CFu, 0x6B4Eu,
+    0x5BEDu, 0x7497u, 0x2B24u, 0x5AEDu, 0x7249u, 0x5BFDu, 0x5B6Bu, 0x2B6Au,
+    0x12EBu, 0x676Au, 0x5AEBu, 0x388Eu, 0x2497u, 0x7B6Du, 0x2B6Du, 0x5FEDu,
+    0x5AADu, 0x24ADu, 0x72A7u, 0x6496u, 0x4889u, 0x3493u, 0x002Au, 0x7000u);
+
+void main()
+{
+    out_color = vec4(0.0, 0.0, 0.0, 0.6);
+    if (v_texel.x < 0.0 || v_texel.y < 0.0)
+    {
+        return;
+    }
+    uvec2 texel = uvec2(v_texel);
+    uvec2 cell = texel / CELL_SIZE;
+    uvec2 position = texel % CELL_SIZE;
+    if (cell.x >= overlay.columns || cell.y >= overlay.rows || position.x >= 3u || position.y >= 5u)
+    {
+        return;
+    }
+    uint index = cell.y * overlay.columns + cell.x;
+    uint character = (overlay.text[index / 16u][(index / 4u) % 4u] >> (index % 4u * 8u)) & 0xFFu;
+    uint glyph = FONT[min(character - 32u, 63u)];
+    if (((glyph >> (position.y * 3u + position.x)) & 1u) != 0u)
+    {
+        out_color = vec4(1.0);
+    }
+}
@@ -0,0 +1,17 @@
+// Push constants of the text overlay, shared by its vertex and fragment shader.
+//
+// The text is ASCII, four characters to a uint starting from the low byte, in `rows` of
+// `columns` characters. Glyphs are 3x5 texels in cells of `CELL_SIZE`, which leaves one
+// texel between characters and lines; a texel is `scale` pixels wide.
+
+layout(push_constant) uniform Overlay
+{
+    uvec4 text[6];
+    vec2 target_size;
+    vec2 origin;
+    uint columns;
+    uint rows;
+    float scale;
+} overlay;
+
+const uvec2 CELL_SIZE = uvec2(4u, 6u);
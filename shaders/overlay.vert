@@ -0,0 +1,16 @@
+#version 450
+
+// Covers the overlay text, and a margin of one texel around it, with two triangles.
+
+#include "overlay.glsl"
+
+layout(location = 0) out vec2 v_texel;
+
+void main()
+{
+    vec2 corners[6] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0));
+    vec2 text_size = vec2(uvec2(overlay.columns, overlay.rows) * CELL_SIZE);
+    v_texel = mix(vec2(-1.0), text_size, corners[gl_VertexIndex]);
+    vec2 pixel = overlay.origin + v_texel * overlay.scale;
+    gl_Position = vec4(pixel / overlay.target_size * 2.0 - 1.0, 0.0, 1.0);
+}
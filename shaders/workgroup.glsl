@@ -0,0 +1,9 @@
+// Compute shaders run one invocation per element, in groups of `WORKGROUP_SIZE`; the
+// including shader picks the size with a `// @define WORKGROUP_SIZE=N` directive.
+
+layout(local_size_x = WORKGROUP_SIZE) in;
+
+uint get_element_index()
+{
+    return gl_GlobalInvocationID.x;
+}
@@ -1,40 +1,201 @@
 // use crate::*;
-// use crate::rc_string::*;
+use crate::rc_string::*;
+use ash::extensions::{ext, khr};
+use ash::vk;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+mod config;
+pub use config::*;
+#[cfg(test)]
+mod golden;
+mod headless;
+mod overlay;
+mod render_graph;
+mod renderer;
+mod resources;
+pub use resources::*;
 mod sdl2;
+mod shaders;
 mod vulkan;
 
+/// Sizes of the bindless tables; lower device limits take precedence.
+const BINDLESS_IMAGE_CAPACITY: u32 = 16384;
+const BINDLESS_BUFFER_CAPACITY: u32 = 16384;
+/// Scopes the profiler measures per frame at most.
+const PROFILER_MAX_SCOPES: u32 = 64;
+/// How often profiled timings are printed and shown in the title bar and the overlay.
+const PROFILE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Rebuilds `recover` tries when rebuilding runs into another loss.
+const MAX_RECOVERY_ATTEMPTS: u32 = 3;
+
+/// A failure `Ludo` recovers from by rebuilding what depended on the lost object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Loss
+{
+    /// `VK_ERROR_DEVICE_LOST`: the device and everything created from it.
+    Device,
+    /// `VK_ERROR_SURFACE_LOST_KHR`: the surface and everything that presents to it.
+    Surface,
+}
+impl Loss
+{
+    fn from_error(error: vk::Result) -> Option<Loss>
+    {
+        match error
+        {
+            vk::Result::ERROR_DEVICE_LOST => Some(Loss::Device),
+            vk::Result::ERROR_SURFACE_LOST_KHR => Some(Loss::Surface),
+            _ => None,
+        }
+    }
+}
+
+/// A step of building the Vulkan objects that failed; `loss` is set when it failed because
+/// something was lost, which `recover` deals with.
+#[derive(Debug)]
+struct SetupError
+{
+    message: String,
+    loss: Option<Loss>,
+}
+impl SetupError
+{
+    fn new(step: &str, error: impl std::fmt::Display, result: Option<vk::Result>) -> SetupError
+    {
+        SetupError { message: format!("{} is failed: {}", step, error), loss: result.and_then(Loss::from_error) }
+    }
+
+    fn vulkan(step: &str, result: vk::Result) -> SetupError
+    {
+        SetupError::new(step, result, Some(result))
+    }
+
+    fn renderer(step: &str, error: renderer::RendererError) -> SetupError
+    {
+        let result = match error
+        {
+            renderer::RendererError::Vulkan(result) => Some(result),
+            _ => None,
+        };
+        SetupError::new(step, error, result)
+    }
+}
+impl std::fmt::Display for SetupError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.message)
+    }
+}
+
 pub struct Ludo
 {
-    sdl_instance: sdl2::Instance,
-    window: sdl2::Window,
-    // vk_instance: vulkan::Instance,
-    // enable_validation_layers: bool,
-    // available_layers: Option<Vec<vulkan::VkLayerProperties>>,
-    // enabled_layers: Option<Vec<RCString>>,
-    // available_extensions: Option<Vec<vulkan::ExtensionProperties>>,
-    // enabled_extensions: Option<Vec<RCString>>,
+    config: Config,
+    debug_sink: Arc<dyn vulkan::DebugSink>,
+    sdl_instance: Option<sdl2::Instance>,
+    window: Option<sdl2::Window>,
+    vk_instance: Option<vulkan::Instance>,
+    debug_messenger: Option<vulkan::DebugMessenger>,
+    surface: Option<vulkan::Surface>,
+    physical_device: Option<vulkan::PhysicalDevice>,
+    device: Option<vulkan::Device>,
+    allocator: Option<vulkan::Allocator>,
+    pipeline_cache: Option<vulkan::PipelineCache>,
+    bindless: Option<vulkan::BindlessTable>,
+    swapchain: Option<vulkan::Swapchain>,
+    frames: Option<vulkan::FramesInFlight>,
+    renderer: Option<renderer::TriangleRenderer>,
+    headless: Option<headless::Headless>,
+    profiler: Option<vulkan::GpuProfiler>,
+    /// Embedder resources, created along with the device.
+    resources: Vec<Box<dyn DeviceResources>>,
+    resources_created: bool,
+    last_profile_report: Option<Instant>,
+    /// Set by `simulate_loss`, handled before the next frame.
+    pending_loss: Option<Loss>,
+    instance_extensions: Vec<RCString>,
+    optional_instance_extensions: Vec<RCString>,
+    running: bool,
+    framebuffer_resized: bool,
+    window_minimized: bool,
 } 
 
 impl Default for Ludo {
     fn default() -> Self 
     { 
-        Ludo{
-            sdl_instance: sdl2::Instance::default(),
-            window: sdl2::Window::default(),
-            // vk_instance: vulkan::Instance::default(),
-            // enable_validation_layers: true,
-            // available_layers: None,
-            // enabled_layers: None,
-            // available_extensions: None,
-            // enabled_extensions: None,
-        }
+        Ludo::new(Config::default())
     }
 }
 
 impl Ludo {
+    pub fn new(config: Config) -> Ludo
+    {
+        Ludo{
+            config,
+            debug_sink: Arc::new(vulkan::StderrSink),
+            sdl_instance: None,
+            window: None,
+            vk_instance: None,
+            debug_messenger: None,
+            surface: None,
+            physical_device: None,
+            device: None,
+            allocator: None,
+            pipeline_cache: None,
+            bindless: None,
+            swapchain: None,
+            frames: None,
+            renderer: None,
+            headless: None,
+            profiler: None,
+            resources: Vec::new(),
+            resources_created: false,
+            last_profile_report: None,
+            pending_loss: None,
+            instance_extensions: Vec::new(),
+            optional_instance_extensions: vec![
+                RCString::from_cstr(vk::KhrGetPhysicalDeviceProperties2Fn::name()),
+                RCString::from_cstr(vk::KhrPortabilityEnumerationFn::name()),
+            ],
+            running: false,
+            framebuffer_resized: false,
+            window_minimized: false,
+        }
+    }
+
+    /// Replaces the sink that receives validation messages; `StderrSink` by default.
+    #[allow(dead_code)] // For embedders; the binary keeps the default.
+    pub fn set_debug_sink(&mut self, sink: Arc<dyn vulkan::DebugSink>)
+    {
+        self.debug_sink = sink;
+    }
+
+    /// Adds resources to create once the device exists, and again after it is lost; call
+    /// before `run`.
+    #[allow(dead_code)] // For embedders; the demo renderer needs none.
+    pub fn add_resources(&mut self, resources: Box<dyn DeviceResources>)
+    {
+        self.resources.push(resources);
+    }
+
+    /// Debug hook that makes the next frame behave as if `loss` had happened, so that the
+    /// recovery path can be tried without a driver crash. F9 loses the device and F10 the
+    /// surface.
+    pub fn simulate_loss(&mut self, loss: Loss)
+    {
+        self.pending_loss = Some(loss);
+    }
+
     pub fn run(&mut self) 
     {
+        if self.config.headless
+        {
+            self.init_headless();
+            self.render_headless();
+            self.cleanup();
+            return;
+        }
         self.init_window();
         self.init_vulkan();
         self.main_loop();
@@ -45,155 +206,912 @@ impl Ludo {
     {
         println!("Starting init...");
 
-         self.sdl_instance.init(sdl2_sys::SDL_INIT_VIDEO).unwrap();
+        let sdl_instance = sdl2::Instance::init(
+            sdl2_sys::SDL_INIT_VIDEO | sdl2_sys::SDL_INIT_GAMECONTROLLER).unwrap();
         println!("SDL_Init done");
 
-        self.sdl_instance.load_vulkan(None).unwrap();
+        sdl_instance.load_vulkan(None).unwrap();
         println!("SDL_Vulkan_LoadLibrary done");
 
-        self.window = sdl2::Window::create_window(
+        self.window = Some(sdl2::Window::create_window(
+            &sdl_instance,
             "Rust Ludo", 
-            800, 
-            600)
-            .unwrap();
+            self.config.width as i32, 
+            self.config.height as i32)
+            .unwrap());
+        self.sdl_instance = Some(sdl_instance);
         println!("SDL_CreateWindow: done");
     }
 
     fn init_vulkan(&mut self)
     {
-        //self.create_instance();
-        // self.pick_physical_device();
-    }
-
-    // fn get_available_extensions(&mut self) -> &Vec<vulkan::ExtensionProperties>
-    // {
-    //     if self.available_extensions.is_none()
-    //     {
-    //         self.available_extensions = Some(vulkan::get_available_extensions(None).unwrap());
-    //         print!("Vulkan available extentions: ");
-    //         for extension in self.available_extensions.as_ref().unwrap()
-    //         {
-    //             print!("{}({}) ", extension.extension_name.get_rstr(), extension.spec_version);
-    //         }
-    //         print!("\n");
-    //     }
-    //     self.available_extensions.as_ref().unwrap()
-    // }
-
-    // fn get_enabled_extensions(&mut self) -> &Vec<RCString>
-    // {
-    //     if self.enabled_extensions.is_none()
-    //     {
-    //         self.enabled_extensions = Some(self.window.get_vulkan_extensions().unwrap());
-    //         print!("Vulkan enabled extentions: ");
-    //         for name in self.enabled_extensions.as_ref().unwrap()
-    //         {
-    //             print!("{} ", name.get_rstr());
-    //         }
-    //         print!("\n");
-    //     }
-    //     self.enabled_extensions.as_ref().unwrap()
-    // }
-
-    // fn get_available_layers(&mut self) -> &Vec<vulkan::VkLayerProperties> 
-    // {
-    //     if self.available_layers.is_none()
-    //     {
-    //         self.available_layers = Some(vulkan::vkEnumerateInstanceLayerProperties().unwrap());
-    //         print!("Vulkan available layers: \n");
-    //         for layer in self.available_layers.as_ref().unwrap()
-    //         {
-    //             println!("\t layer name              : {}", layer.layer_name.get_rstr());
-    //             println!("\t spec version            : {}", vulkan::api_version_to_string(layer.spec_version));
-    //             println!("\t implementation version  : {}", layer.implementation_version);
-    //             println!("\t description             : {}", layer.description.get_rstr());
-    //             println!();
-    //         }
-    //     }
-    //     self.available_layers.as_ref().unwrap()
-    // }
-    
-    // fn get_enabled_layer_names(&mut self) -> &Vec<RCString>
-    // {
-    //     if self.enable_validation_layers
-    //     {
-    //         let mut enabled_layers: Vec<RCString> = Vec::new();
-    //         enabled_layers.push(RCString::from_rstr("VK_LAYER_KHRONOS_validation"));
-    //         self.enabled_layers = Some(enabled_layers);
-    //     }
-    //     self.enabled_layers.as_ref().unwrap()
-    // }
-
-    // fn create_instance(&mut self)
-    // {
-    //     self.get_available_extensions();
-    //     let extensions_names = self.get_enabled_extensions().clone();
-    //     self.get_available_layers();
-    //     let enabled_layers = self.get_enabled_layer_names().clone();
-    //     let application_info = &mut self.vk_instance.instance_info.application_info;
-    //     application_info.application_name = RCString::from_rstr("Rust Ludo");
-    //     let instance_info = &mut self.vk_instance.instance_info;
-    //     instance_info.enabled_layer_names = enabled_layers;
-    //     instance_info.enabled_extension_names = extensions_names;
-    //     let result = self.vk_instance.create();
-    //     if result.is_err()
-    //     {
-    //         panic!("Vulkan instance creation is failed with error code: {}", result.unwrap_err());
-    //     }
-    // }
-
-    // fn pick_physical_device(&mut self)
-    // {
-    //     // let physical_devices = vkEnumeratePhysicalDevices(&self.vk_instance).unwrap();
-    //     // if physical_devices.len() == 0
-    //     // {
-    //     //     panic!("failed to find physical devices");
-    //     // }
-    //     // println!("Physical device count: {}", physical_devices.len());
-
-    // }
+        self.create_instance();
+        if let Err(error) = self.create_surface()
+        {
+            panic!("{}", error);
+        }
+        self.pick_physical_device();
+        if let Err(error) = self.create_device_objects()
+        {
+            panic!("{}", error);
+        }
+    }
+
+    /// Same as `init_vulkan`, minus everything that needs a window.
+    fn init_headless(&mut self)
+    {
+        println!("Starting headless init...");
+        self.create_instance();
+        self.pick_physical_device();
+        if let Err(error) = self.create_device_objects()
+        {
+            panic!("{}", error);
+        }
+        if let Some(output_dir) = &self.config.output_dir
+        {
+            if let Err(error) = std::fs::create_dir_all(output_dir)
+            {
+                panic!("Cannot create {}: {}", output_dir.display(), error);
+            }
+        }
+    }
+
+    /// Creates whichever of the device with the embedder resources, the swapchain or
+    /// offscreen target and the renderer are missing, in that order.
+    fn create_device_objects(&mut self) -> Result<(), SetupError>
+    {
+        if self.device.is_none()
+        {
+            self.create_logical_device()?;
+        }
+        if !self.resources_created
+        {
+            self.create_resources()?;
+        }
+        if self.surface.is_some()
+        {
+            if self.swapchain.is_none()
+            {
+                self.create_swapchain()?;
+            }
+            if self.frames.is_none() || self.renderer.is_none()
+            {
+                self.create_renderer()?;
+            }
+        }
+        else if self.headless.is_none()
+        {
+            self.create_headless_target()?;
+        }
+        Ok(())
+    }
+
+    fn create_resources(&mut self) -> Result<(), SetupError>
+    {
+        let mut context = ResourceContext {
+            device: self.device.as_ref().expect("Vulkan device is not created"),
+            allocator: self.allocator.as_ref().expect("Allocator is not created"),
+            bindless: self.bindless.as_mut(),
+        };
+        for resources in &mut self.resources
+        {
+            resources.create(&mut context).map_err(|error| SetupError::vulkan("Resource creation", error))?;
+        }
+        self.resources_created = true;
+        Ok(())
+    }
+
+    /// Drops the embedder resources, also after a `create_resources` that failed halfway.
+    fn release_resources(&mut self)
+    {
+        for resources in &mut self.resources
+        {
+            resources.release();
+        }
+        self.resources_created = false;
+    }
+
+    fn create_headless_target(&mut self) -> Result<(), SetupError>
+    {
+        let device = self.device.as_ref().expect("Vulkan device is not created");
+        let allocator = self.allocator.as_ref().expect("Allocator is not created");
+        let extent = vk::Extent2D { width: self.config.width, height: self.config.height };
+        let headless = headless::Headless::create(allocator, extent)
+            .map_err(|error| SetupError::vulkan("Offscreen target creation", error))?;
+        let target = headless.get_target();
+        let format = target.get_format();
+        let mut renderer = renderer::TriangleRenderer::create(
+            device, format, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.pipeline_cache.as_ref())
+            .map_err(|error| SetupError::renderer("Renderer creation", error))?;
+        renderer.update_targets(&[target.get_image()], &[target.get_image_view()], target.get_extent(), 1)
+            .map_err(|error| SetupError::vulkan("Framebuffer creation", error))?;
+        println!("Offscreen target created: {}x{} {:?}", extent.width, extent.height, format);
+        self.renderer = Some(renderer);
+        self.headless = Some(headless);
+        Ok(())
+    }
+
+    fn render_headless(&mut self)
+    {
+        let frame_count = self.config.max_frames.unwrap_or(1);
+        loop
+        {
+            let frame_number = self.headless.as_ref().expect("Offscreen target is not created").get_frame_number();
+            if frame_number >= frame_count
+            {
+                break;
+            }
+            if let Some(loss) = self.take_pending_loss(frame_number)
+            {
+                self.recover(loss);
+                continue;
+            }
+            if let Err(error) = self.render_headless_frame()
+            {
+                match Loss::from_error(error)
+                {
+                    Some(loss) => self.recover(loss),
+                    None => panic!("Offscreen frame rendering is failed: {}", error),
+                }
+            }
+        }
+        println!("Rendered {} frames headless", frame_count);
+    }
+
+    fn render_headless_frame(&mut self) -> Result<(), vk::Result>
+    {
+        let headless = self.headless.as_mut().expect("Offscreen target is not created");
+        let renderer = self.renderer.as_ref().expect("Renderer is not created");
+        let bindless = &mut self.bindless;
+        let profiler = &mut self.profiler;
+        let snapshot = headless.render(|command_buffer, frame_number| {
+            if let Some(table) = bindless
+            {
+                table.begin_frame();
+            }
+            if let Some(profiler) = profiler.as_mut()
+            {
+                profiler.begin_frame(command_buffer, 0, frame_number)?;
+                profiler.begin_scope(command_buffer, "triangle");
+            }
+            renderer.record(command_buffer, 0, frame_number);
+            if let Some(profiler) = profiler.as_mut()
+            {
+                profiler.end_scope(command_buffer);
+                profiler.end_frame();
+            }
+            Ok(())
+        })?;
+        if let Some(output_dir) = &self.config.output_dir
+        {
+            let path = output_dir.join(format!("frame_{:05}.png", headless.get_frame_number() - 1));
+            if let Err(error) = snapshot.write_png(&path)
+            {
+                panic!("Frame writing is failed: {}", error);
+            }
+            println!("Wrote {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn get_required_extensions(&self) -> Vec<RCString>
+    {
+        // Headless instances need no surface extensions at all.
+        let mut extensions = match &self.window
+        {
+            Some(window) => window.get_vulkan_extensions().unwrap(),
+            None => Vec::new(),
+        };
+        extensions.push(RCString::from_cstr(ext::DebugUtils::name()));
+        extensions.extend(self.instance_extensions.iter().cloned());
+        extensions
+    }
+
+    fn create_instance(&mut self)
+    {
+        let mut instance_info = vulkan::InstanceCreateInfo::default();
+        instance_info.application_info.application_name = RCString::from_rstr("Rust Ludo");
+        instance_info.application_info.api_version = self.config.api_version;
+        instance_info.enabled_extension_names = self.get_required_extensions();
+        instance_info.optional_extension_names = self.optional_instance_extensions.clone();
+        if self.config.enable_validation_layers
+        {
+            instance_info.enabled_layer_names.push(RCString::from_rstr(vulkan::VALIDATION_LAYER_NAME));
+        }
+        let mut result = vulkan::Instance::create(&instance_info);
+        if let Err(vulkan::InstanceError::LayerNotPresent(_, name)) = &result
+        {
+            // Debug builds validate by default, and should still run where the SDK is not installed.
+            if !self.config.require_validation_layers
+            {
+                println!("{} is not installed, continuing without validation", name);
+                self.config.enable_validation_layers = false;
+                instance_info.enabled_layer_names.clear();
+                result = vulkan::Instance::create(&instance_info);
+            }
+        }
+        let instance = match result
+        {
+            Ok(instance) => instance,
+            Err(error) => panic!("Vulkan instance creation is failed: {}", error),
+        };
+        println!("vkCreateInstance done, Vulkan {}", vulkan::api_version_to_string(instance.get_api_version()));
+        print!("Vulkan enabled extentions: ");
+        for name in instance.get_enabled_extensions()
+        {
+            print!("{} ", name.get_rstr());
+        }
+        println!();
+        self.vk_instance = Some(instance);
+        self.create_debug_messenger();
+    }
+
+    fn create_debug_messenger(&mut self)
+    {
+        if !self.config.enable_validation_layers
+        {
+            return;
+        }
+        let instance = self.vk_instance.as_ref().expect("Vulkan instance is not created");
+        let mut severity = vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+        if self.config.verbose_validation
+        {
+            severity |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+        }
+        match vulkan::DebugMessenger::create(instance, severity, self.debug_sink.clone())
+        {
+            Ok(messenger) => self.debug_messenger = Some(messenger),
+            Err(error) => panic!("vkCreateDebugUtilsMessengerEXT failed: {}", error),
+        }
+        println!("Vulkan debug messenger installed");
+    }
+
+    /// Fails as a lost surface, so that `recover` tries again.
+    fn create_surface(&mut self) -> Result<(), SetupError>
+    {
+        let window = self.window.as_ref().expect("Window is not created");
+        let instance = self.vk_instance.as_ref().expect("Vulkan instance is not created");
+        let surface = window.create_surface(instance).map_err(|error| SetupError {
+            loss: Some(Loss::Surface),
+            ..SetupError::new("SDL_Vulkan_CreateSurface", error, None)
+        })?;
+        self.surface = Some(surface);
+        println!("SDL_Vulkan_CreateSurface done");
+        Ok(())
+    }
+
+    fn get_device_extensions(&self) -> Vec<RCString>
+    {
+        match self.surface
+        {
+            Some(_) => vec![RCString::from_cstr(khr::Swapchain::name())],
+            None => Vec::new(),
+        }
+    }
+
+    fn pick_physical_device(&mut self)
+    {
+        let instance = self.vk_instance.as_ref().expect("Vulkan instance is not created");
+        let physical_devices = match vulkan::PhysicalDevice::enumerate(instance)
+        {
+            Ok(physical_devices) => physical_devices,
+            Err(error) => panic!("vkEnumeratePhysicalDevices failed: {}", error),
+        };
+        println!("Physical device count: {}", physical_devices.len());
+        for device in &physical_devices
+        {
+            println!("\t[{}] {} ({:?}, score {})",
+                device.get_index(),
+                device.get_name(),
+                device.get_device_type(),
+                vulkan::score_physical_device(device));
+        }
+        let requirements = vulkan::DeviceRequirements {
+            extensions: self.get_device_extensions(),
+            surface: self.surface.as_ref(),
+            ..Default::default()
+        };
+        let selection = match &self.config.physical_device
+        {
+            Some(value) => vulkan::DeviceSelection::parse(value),
+            None => vulkan::DeviceSelection::Best,
+        };
+        match vulkan::pick_physical_device(&physical_devices, &requirements, &selection)
+        {
+            Ok(device) =>
+            {
+                println!("Picked physical device: {}", device.get_name());
+                self.physical_device = Some(device);
+            }
+            Err(error) => panic!("Physical device selection failed: {}", error),
+        }
+    }
+
+    fn create_logical_device(&mut self) -> Result<(), SetupError>
+    {
+        let physical_device = self.physical_device.as_ref().expect("Physical device is not picked");
+        let bindless = self.config.bindless && vulkan::BindlessTable::is_supported(physical_device);
+        // Vulkan 1.3 drivers get the modern path as a whole, anything older the 1.0 one.
+        let modern = physical_device.get_modern_features().is_complete();
+        let pipeline_statistics = self.config.profile && physical_device.get_features().pipeline_statistics_query == vk::TRUE;
+        let device_info = vulkan::DeviceCreateInfo {
+            enabled_extension_names: self.get_device_extensions(),
+            enabled_features: vk::PhysicalDeviceFeatures {
+                pipeline_statistics_query: pipeline_statistics.into(),
+                ..Default::default()
+            },
+            dynamic_rendering: modern,
+            timeline_semaphore: modern,
+            synchronization2: modern,
+            descriptor_indexing: bindless.then(vulkan::BindlessTable::get_required_features),
+            surface: self.surface.as_ref(),
+            ..Default::default()
+        };
+        let device = vulkan::Device::create(physical_device, &device_info).map_err(|error| {
+            let result = match error
+            {
+                vulkan::DeviceError::CreationFailed(result) => Some(result),
+                _ => None,
+            };
+            SetupError::new("Vulkan device creation", error, result)
+        })?;
+        println!("vkCreateDevice done, queue families: {:?}", device.get_queue_families());
+        println!("Vulkan {} device, {}",
+            vulkan::api_version_to_string(device.get_api_version()),
+            match modern
+            {
+                true => "using synchronization2, timeline semaphores and dynamic rendering",
+                false => "using render passes and fences",
+            });
+        print!("Vulkan device extensions: ");
+        for name in device.get_enabled_extensions()
+        {
+            print!("{} ", name.get_rstr());
+        }
+        println!();
+        self.allocator = Some(vulkan::Allocator::create(&device));
+        let pipeline_cache = vulkan::PipelineCache::create(&device, self.config.pipeline_cache_dir.as_deref())
+            .map_err(|error| SetupError::vulkan("Pipeline cache creation", error))?;
+        pipeline_cache.set_name("pipeline cache");
+        self.pipeline_cache = Some(pipeline_cache);
+        if bindless
+        {
+            let table = vulkan::BindlessTable::create(
+                &device, self.config.frames_in_flight, BINDLESS_IMAGE_CAPACITY, BINDLESS_BUFFER_CAPACITY)
+                .map_err(|error| SetupError::vulkan("Bindless table creation", error))?;
+            table.set_name("bindless");
+            println!("Bindless table created: {} images, {} buffers",
+                table.get_image_capacity(), table.get_buffer_capacity());
+            self.bindless = Some(table);
+        }
+        else if self.config.bindless
+        {
+            println!("Bindless resources are not supported by the device");
+        }
+        if self.config.profile
+        {
+            if vulkan::GpuProfiler::is_supported(&device)
+            {
+                let mut profiler = vulkan::GpuProfiler::create(&device, self.config.frames_in_flight, PROFILER_MAX_SCOPES)
+                    .map_err(|error| SetupError::vulkan("Profiler creation", error))?;
+                if self.config.trace_path.is_some()
+                {
+                    profiler.start_session();
+                }
+                println!("Profiling with timestamps{}",
+                    if profiler.has_statistics() { " and pipeline statistics" } else { "" });
+                self.profiler = Some(profiler);
+            }
+            else
+            {
+                println!("Profiling is not supported: the graphics queue has no timestamps");
+            }
+        }
+        self.device = Some(device);
+        Ok(())
+    }
+
+    fn get_drawable_extent(&self) -> vk::Extent2D
+    {
+        let window = self.window.as_ref().expect("Window is not created");
+        let (width, height) = window.get_drawable_size();
+        vk::Extent2D { width, height }
+    }
+
+    fn create_swapchain(&mut self) -> Result<(), SetupError>
+    {
+        let device = self.device.as_ref().expect("Vulkan device is not created");
+        let surface = self.surface.as_ref().expect("Vulkan surface is not created");
+        let extent = self.get_drawable_extent();
+        let mut swapchain = vulkan::Swapchain::create(device, surface, extent, vulkan::SwapchainPreferences::default())
+            .map_err(|error| SetupError::vulkan("Vulkan swapchain creation", error))?;
+        swapchain.set_name("swapchain");
+        self.swapchain = Some(swapchain);
+        Ok(())
+    }
+
+    fn create_renderer(&mut self) -> Result<(), SetupError>
+    {
+        let device = self.device.as_ref().expect("Vulkan device is not created");
+        let swapchain = self.swapchain.as_ref().expect("Vulkan swapchain is not created");
+        let frames = vulkan::FramesInFlight::create(device, self.config.frames_in_flight)
+            .map_err(|error| SetupError::vulkan("Frame resources creation", error))?;
+        self.frames = Some(frames);
+        let renderer = renderer::TriangleRenderer::create(
+            device, swapchain.get_format().format, vk::ImageLayout::PRESENT_SRC_KHR, self.pipeline_cache.as_ref())
+            .map_err(|error| SetupError::renderer("Renderer creation", error))?;
+        self.renderer = Some(renderer);
+        println!("Renderer created with {} frames in flight", self.config.frames_in_flight);
+        Ok(())
+    }
+
+    fn draw_frame(&mut self)
+    {
+        let device = self.device.as_ref().expect("Vulkan device is not created");
+        let swapchain = self.swapchain.as_mut().expect("Vulkan swapchain is not created");
+        let frames = self.frames.as_mut().expect("Frame resources are not created");
+        let format = swapchain.get_format().format;
+        if self.renderer.as_ref().map(|renderer| renderer.get_color_format()) != Some(format)
+        {
+            self.renderer = None;
+            match renderer::TriangleRenderer::create(
+                device, format, vk::ImageLayout::PRESENT_SRC_KHR, self.pipeline_cache.as_ref())
+            {
+                Ok(renderer) => self.renderer = Some(renderer),
+                Err(error) => match SetupError::renderer("Renderer creation", error)
+                {
+                    SetupError { loss: Some(loss), .. } => return self.recover(loss),
+                    error => panic!("{}", error),
+                },
+            }
+        }
+        let renderer = self.renderer.as_mut().unwrap();
+        let result = renderer.update_targets(
+            swapchain.get_images(), swapchain.get_image_views(), swapchain.get_extent(), swapchain.get_generation());
+        if let Err(error) = result
+        {
+            match Loss::from_error(error)
+            {
+                Some(loss) => return self.recover(loss),
+                None => panic!("Framebuffer creation is failed: {}", error),
+            }
+        }
+        let graphics_queue = device.get_graphics_queue();
+        let present_queue = device.get_present_queue().expect("Device has no present queue");
+        let renderer = &*renderer;
+        let bindless = &mut self.bindless;
+        let profiler = &mut self.profiler;
+        if let Some(profiler) = profiler.as_mut()
+        {
+            profiler.begin_cpu("draw_frame");
+        }
+        let result = frames.draw(swapchain, graphics_queue, present_queue, |command_buffer, info| {
+            // Only once the frame's fence was waited on, so retired table slots are really unused
+            // and the profiler's queries of that slot are done.
+            if let Some(table) = bindless
+            {
+                table.begin_frame();
+            }
+            if let Some(profiler) = profiler.as_mut()
+            {
+                profiler.begin_frame(command_buffer, info.frame_slot, info.frame_number)?;
+                profiler.begin_scope(command_buffer, "triangle");
+            }
+            renderer.record(command_buffer, info.image_index, info.frame_number);
+            if let Some(profiler) = profiler.as_mut()
+            {
+                profiler.end_scope(command_buffer);
+                profiler.end_frame();
+            }
+            Ok(())
+        });
+        if let Some(profiler) = profiler.as_mut()
+        {
+            profiler.end_cpu();
+        }
+        if let Err(error) = result
+        {
+            match Loss::from_error(error)
+            {
+                Some(loss) => return self.recover(loss),
+                None => panic!("Frame drawing is failed: {}", error),
+            }
+        }
+        self.report_profile();
+        let frames = self.frames.as_ref().unwrap();
+        if let Some(max_frames) = self.config.max_frames
+        {
+            if frames.get_frame_number() >= max_frames
+            {
+                println!("Rendered {} frames, exiting", max_frames);
+                self.running = false;
+            }
+        }
+    }
+
+    /// Prints the last profiled frame and shows its timings in the title bar and on screen,
+    /// at most once per `PROFILE_REPORT_INTERVAL`.
+    fn report_profile(&mut self)
+    {
+        let timings = match self.profiler.as_ref().and_then(|profiler| profiler.get_last_timings())
+        {
+            Some(timings) => timings,
+            None => return,
+        };
+        if self.last_profile_report.is_some_and(|last| last.elapsed() < PROFILE_REPORT_INTERVAL)
+        {
+            return;
+        }
+        self.last_profile_report = Some(Instant::now());
+        println!("{}", timings);
+        if let Some(window) = &self.window
+        {
+            window.set_title(&format!("Rust Ludo - GPU {:.2} ms, CPU {:.2} ms", timings.gpu_ms, timings.cpu_ms));
+        }
+        if let Some(renderer) = self.renderer.as_mut()
+        {
+            let mut lines = vec![format!("GPU {:.2} ms  CPU {:.2} ms", timings.gpu_ms, timings.cpu_ms)];
+            lines.extend(timings.scopes.iter().map(|scope| {
+                format!("{:indent$}{} {:.2} ms", "", scope.name, scope.gpu_ms, indent = 2 * scope.depth)
+            }));
+            renderer.set_overlay(overlay::OverlayText::new(&lines));
+        }
+    }
+
+    /// Reads back the frames still in flight and writes the trace, if one was asked for; the
+    /// device must be idle.
+    fn finish_profile(&mut self)
+    {
+        let profiler = match self.profiler.as_mut()
+        {
+            Some(profiler) => profiler,
+            None => return,
+        };
+        if let Err(error) = profiler.flush()
+        {
+            println!("Reading the last profiled frames is failed: {}", error);
+        }
+        if let Some(timings) = profiler.get_last_timings()
+        {
+            println!("{}", timings);
+        }
+        if let (Some(session), Some(path)) = (profiler.take_session(), &self.config.trace_path)
+        {
+            match session.write_chrome_trace(path)
+            {
+                Ok(()) => println!("Wrote {} trace events to {}", session.events.len(), path.display()),
+                Err(error) => println!("Cannot write {}: {}", path.display(), error),
+            }
+        }
+    }
+
+    /// Rebuilds the swapchain when needed; returns `false` while there is nothing to draw to.
+    fn update_swapchain(&mut self) -> bool
+    {
+        if self.window_minimized
+        {
+            return false;
+        }
+        let extent = self.get_drawable_extent();
+        let swapchain = self.swapchain.as_mut().expect("Vulkan swapchain is not created");
+        if self.framebuffer_resized
+        {
+            swapchain.mark_out_of_date();
+            self.framebuffer_resized = false;
+        }
+        match swapchain.prepare(extent)
+        {
+            Ok(ready) => ready,
+            Err(error) => match Loss::from_error(error)
+            {
+                Some(loss) =>
+                {
+                    self.recover(loss);
+                    false
+                }
+                None => panic!("Vulkan swapchain recreation is failed: {}", error),
+            },
+        }
+    }
+
+    /// The loss asked for by `simulate_loss`, or by `Config::simulate_loss` once its frame
+    /// is reached.
+    fn take_pending_loss(&mut self, frame_number: u64) -> Option<Loss>
+    {
+        if let Some((loss, frame)) = self.config.simulate_loss
+        {
+            if frame_number >= frame
+            {
+                self.config.simulate_loss = None;
+                self.pending_loss = Some(loss);
+            }
+        }
+        let loss = self.pending_loss.take()?;
+        println!("Simulating a lost {:?} before frame {}", loss, frame_number);
+        Some(loss)
+    }
+
+    /// Tears down what `loss` invalidated and builds it again from the same settings and
+    /// `DeviceResources`, so that rendering carries on; frame numbers continue where they
+    /// stopped.
+    ///
+    /// The physical device, instance and window are kept, and a lost surface is assumed to
+    /// stay presentable from the same queue family. Losing the device or the surface again
+    /// while rebuilding starts over, up to `MAX_RECOVERY_ATTEMPTS` times.
+    fn recover(&mut self, loss: Loss)
+    {
+        if loss == Loss::Surface && self.surface.is_none()
+        {
+            return println!("Surface lost without a surface, nothing to recover");
+        }
+        let frame_number = match (&self.frames, &self.headless)
+        {
+            (Some(frames), _) => frames.get_frame_number(),
+            (None, Some(headless)) => headless.get_frame_number(),
+            (None, None) => 0,
+        };
+
+        let mut loss = loss;
+        for attempt in 1..=MAX_RECOVERY_ATTEMPTS
+        {
+            println!("Vulkan {:?} lost, rebuilding", loss);
+            self.release_lost(loss);
+            let result = match loss
+            {
+                Loss::Surface => self.create_surface(),
+                Loss::Device => Ok(()),
+            };
+            match result.and_then(|()| self.create_device_objects())
+            {
+                Ok(()) => break,
+                Err(SetupError { loss: Some(next), message }) if attempt < MAX_RECOVERY_ATTEMPTS =>
+                {
+                    println!("{}", message);
+                    loss = next;
+                }
+                Err(error) => panic!("Recovery is failed: {}", error),
+            }
+        }
+
+        if let Some(frames) = self.frames.as_mut()
+        {
+            frames.set_frame_number(frame_number);
+        }
+        if let Some(headless) = self.headless.as_mut()
+        {
+            headless.set_frame_number(frame_number);
+        }
+        println!("Recovered, continuing at frame {}", frame_number);
+    }
+
+    /// Drops everything `loss` invalidated, children first.
+    fn release_lost(&mut self, loss: Loss)
+    {
+        if let Some(device) = &self.device
+        {
+            // A lost device returns at once, with an error that changes nothing here.
+            let _ = device.wait_idle();
+        }
+        self.renderer = None;
+        self.headless = None;
+        self.frames = None;
+        self.swapchain = None;
+        match loss
+        {
+            Loss::Device =>
+            {
+                if self.profiler.is_some() && self.config.trace_path.is_some()
+                {
+                    println!("The profiler trace restarts with the new device");
+                }
+                self.profiler = None;
+                self.release_resources();
+                self.bindless = None;
+                self.pipeline_cache = None;
+                self.allocator = None;
+                self.device = None;
+            }
+            Loss::Surface => self.surface = None,
+        }
+    }
 
     fn main_loop(&mut self)
     {
         println!("Starting main loop...");
 
-        // let mut running = true;
-        // let mut event = SDL_Event::new();
-        // while running
-        // {
-        //     while SDL_PollEvent(&mut event) != 0
-        //     {
-        //         if event.get_type() == SDL_QUIT
-        //         {
-        //             running = false;
-        //             break;
-        //         }
-        //     }
-        // }
+        let sdl_instance = self.sdl_instance.clone().expect("SDL is not initialized");
+        self.running = true;
+        while self.running
+        {
+            for event in sdl_instance.poll_events()
+            {
+                self.handle_event(event);
+            }
+            if !self.running
+            {
+                break;
+            }
+            let frame_number = self.frames.as_ref().map_or(0, |frames| frames.get_frame_number());
+            if let Some(loss) = self.take_pending_loss(frame_number)
+            {
+                self.recover(loss);
+                continue;
+            }
+            if self.update_swapchain()
+            {
+                self.draw_frame();
+            }
+            else if let Some(event) = sdl_instance.wait_event_timeout(100)
+            {
+                // Nothing can be drawn while minimized, so block until something happens.
+                self.handle_event(event);
+            }
+        }
+        if let Some(frames) = self.frames.as_ref()
+        {
+            let _ = frames.wait_idle();
+        }
+    }
+
+    fn handle_event(&mut self, event: sdl2::Event)
+    {
+        match event
+        {
+            sdl2::Event::Quit
+            | sdl2::Event::Window { event: sdl2::WindowEvent::CloseRequested, .. } =>
+            {
+                self.running = false;
+            }
+            sdl2::Event::Window { event: sdl2::WindowEvent::Resized { width, height }, .. } =>
+            {
+                println!("Window resized to {}x{}", width, height);
+                self.framebuffer_resized = true;
+            }
+            sdl2::Event::KeyDown { keycode, repeat: false, .. }
+                if keycode == sdl2_sys::SDL_KeyCode::SDLK_F9 as i32 =>
+            {
+                self.simulate_loss(Loss::Device);
+            }
+            sdl2::Event::KeyDown { keycode, repeat: false, .. }
+                if keycode == sdl2_sys::SDL_KeyCode::SDLK_F10 as i32 =>
+            {
+                self.simulate_loss(Loss::Surface);
+            }
+            sdl2::Event::Window { event: sdl2::WindowEvent::Minimized, .. } =>
+            {
+                self.window_minimized = true;
+            }
+            sdl2::Event::Window { event: sdl2::WindowEvent::Restored, .. }
+            | sdl2::Event::Window { event: sdl2::WindowEvent::Maximized, .. } =>
+            {
+                self.window_minimized = false;
+            }
+            _ => {}
+        }
     }
 
     fn cleanup(&mut self)
     {
         println!("Starting cleanup...");
+        if let Some(allocator) = &self.allocator
+        {
+            println!("GPU memory: {}", allocator.get_stats());
+        }
+
+        self.finish_profile();
+
+        // Children are released before their parents; each of them also holds
+        // a handle to its parent, so a different order here would only delay the release.
+        self.profiler = None;
+        self.renderer = None;
+        self.headless = None;
+        self.frames = None;
+        self.swapchain = None;
+        self.release_resources();
+        self.bindless = None;
+        self.pipeline_cache = None;
+        self.allocator = None;
+        self.device = None;
+        self.physical_device = None;
+        self.surface = None;
+        self.debug_messenger = None;
+        self.vk_instance = None;
+        self.window = None;
+        println!("SDL_DestroyWindow done");
+        self.sdl_instance = None;
+        println!("SDL cleaned up");
+    }
+}
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A buffer in the bindless table and a sampler, counting how often they were built.
+    #[derive(Default)]
+    struct TestResources
+    {
+        buffer: Option<vulkan::Buffer<u32>>,
+        sampler: Option<vulkan::Sampler>,
+        handle: Option<vulkan::BindlessHandle>,
+        creations: Rc<Cell<u32>>,
+        releases: Rc<Cell<u32>>,
+    }
+    impl DeviceResources for TestResources
+    {
+        fn create(&mut self, context: &mut ResourceContext) -> Result<(), vk::Result>
+        {
+            assert!(self.buffer.is_none() && self.sampler.is_none(), "resources of the lost device are still alive");
+            let buffer = vulkan::Buffer::create_storage(context.allocator, 16)?;
+            if let Some(table) = context.bindless.as_mut()
+            {
+                self.handle = table.add_buffer(buffer.get_handle(), 0, buffer.get_size());
+            }
+            self.buffer = Some(buffer);
+            self.sampler = Some(vulkan::Sampler::create(context.device, &vulkan::SamplerDesc::default())?);
+            self.creations.set(self.creations.get() + 1);
+            Ok(())
+        }
+
+        fn release(&mut self)
+        {
+            self.handle = None;
+            self.sampler = None;
+            self.buffer = None;
+            self.releases.set(self.releases.get() + 1);
+        }
+    }
+
+    #[test]
+    fn headless_rendering_continues_after_a_lost_device()
+    {
+        if golden::get_test_device().is_none()
+        {
+            return;
+        }
+        let output_dir = std::env::temp_dir().join(format!("ludo-device-loss-{}", std::process::id()));
+        let config = Config {
+            headless: true,
+            max_frames: Some(1),
+            output_dir: Some(output_dir.clone()),
+            pipeline_cache_dir: None,
+            simulate_loss: Some((Loss::Device, 1)),
+            width: 64,
+            height: 64,
+            ..Config::default()
+        };
+        let mut ludo = Ludo::new(config);
+        let resources = TestResources::default();
+        let (creations, releases) = (resources.creations.clone(), resources.releases.clone());
+        ludo.add_resources(Box::new(resources));
+        ludo.init_headless();
+        assert_eq!(creations.get(), 1);
+        ludo.render_headless();
+        // A renumbered frame 0 after the loss would write this file again.
+        let first_frame = output_dir.join("frame_00000.png");
+        std::fs::remove_file(&first_frame).unwrap();
 
-        // self.vk_instance.destroy();
-        // let result = self.window.destroy();
-        // if result.is_ok()
-        // {
-        //     println!("SDL_DestroyWindow done");    
-        // }
-        // else 
-        // {
-        //     println!("SDL_DestroyWindow errors: {}", result.unwrap_err());
-        // }
-        // let result = self.sdl_instance.release();
-        // if result.is_ok()
-        // {
-        //     println!("SDL cleaned up without errors");    
-        // }
-        // else 
-        // {
-        //     println!("SDL cleaned up with errors: {}", result.unwrap_err());
-        // }
-    }
-}
\ No newline at end of file
+        ludo.config.max_frames = Some(3);
+        ludo.render_headless();
+        assert_eq!(ludo.config.simulate_loss, None);
+        assert_eq!(ludo.headless.as_ref().unwrap().get_frame_number(), 3);
+        // Released before the lost device went away, then built and registered again.
+        assert_eq!((creations.get(), releases.get()), (2, 1));
+        if let Some(table) = &ludo.bindless
+        {
+            assert_eq!(table.get_usage(), (0, 1));
+        }
+        ludo.cleanup();
+        assert_eq!(releases.get(), 2);
+        assert!(!first_frame.exists());
+        for frame in 1..3
+        {
+            assert!(output_dir.join(format!("frame_{:05}.png", frame)).exists());
+        }
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::ludo::Loss;
+use ash::vk;
+use std::path::PathBuf;
+
+/// Every option `Config::apply_args` accepts.
+pub const USAGE: &str = "Usage: ludo [--headless] [--frames N] [--output DIR] [--size WxH] \
+    [--device NAME|INDEX] [--vulkan-version 1.0|1.1|1.2|1.3] [--pipeline-cache DIR|off] [--no-bindless] \
+    [--profile] [--trace FILE] [--simulate-loss device|surface[:FRAME]]";
+
+/// Runtime settings of `Ludo`.
+///
+/// Every field can be overridden from the environment with `Config::from_env`, so behaviour
+/// can be switched without a code edit, and most of them from the command line with
+/// `Config::apply_args`.
+#[derive(Clone, Debug)]
+pub struct Config
+{
+    /// Enables `VK_LAYER_KHRONOS_validation` and the debug messenger (`LUDO_VALIDATION`).
+    pub enable_validation_layers: bool,
+    /// Fails instead of running without the validation layer when it is not installed; set
+    /// when `LUDO_VALIDATION` asks for validation explicitly.
+    pub require_validation_layers: bool,
+    /// Also report info and verbose validation messages (`LUDO_VALIDATION_VERBOSE`).
+    pub verbose_validation: bool,
+    /// Forces a physical device by index or by part of its name (`LUDO_DEVICE`).
+    pub physical_device: Option<String>,
+    /// Number of frames the CPU may record ahead of the GPU (`LUDO_FRAMES_IN_FLIGHT`).
+    pub frames_in_flight: usize,
+    /// Exits after this many frames, e.g. for smoke tests (`LUDO_FRAMES`, `--frames`).
+    pub max_frames: Option<u64>,
+    /// Renders offscreen without a window or surface (`LUDO_HEADLESS`, `--headless`).
+    pub headless: bool,
+    /// Directory headless frames are written to as PNG files (`LUDO_OUTPUT`, `--output`).
+    pub output_dir: Option<PathBuf>,
+    /// Directory of the on-disk pipeline cache, `~/.cache/ludo` by default; `off` disables
+    /// it (`LUDO_PIPELINE_CACHE`, `--pipeline-cache`).
+    pub pipeline_cache_dir: Option<PathBuf>,
+    /// Puts all images and storage buffers in one descriptor set, where the device
+    /// supports descriptor indexing (`LUDO_BINDLESS`, `--no-bindless`).
+    pub bindless: bool,
+    /// Highest Vulkan version to ask for, e.g. `1.0` to try the fallback path on a newer
+    /// driver (`LUDO_VULKAN_VERSION`, `--vulkan-version`).
+    pub api_version: u32,
+    /// Measures every frame on the GPU and the CPU and reports the timings on stdout, in the
+    /// title bar and in an overlay on the window (`LUDO_PROFILE`, `--profile`).
+    pub profile: bool,
+    /// File the profiled frames are written to as a Chrome trace when exiting (`LUDO_TRACE`,
+    /// `--trace`).
+    pub trace_path: Option<PathBuf>,
+    /// Loses the device or the surface on purpose once the frame number is reached, to
+    /// exercise the recovery path (`LUDO_SIMULATE_LOSS`, `--simulate-loss device|surface[:FRAME]`).
+    pub simulate_loss: Option<(Loss, u64)>,
+    /// Window size, or the image size in headless mode (`LUDO_SIZE`, `--size WxH`).
+    pub width: u32,
+    pub height: u32,
+}
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config {
+            enable_validation_layers: cfg!(debug_assertions),
+            require_validation_layers: false,
+            verbose_validation: false,
+            physical_device: None,
+            frames_in_flight: 2,
+            max_frames: None,
+            headless: false,
+            output_dir: None,
+            pipeline_cache_dir: Config::get_default_cache_dir(),
+            bindless: true,
+            api_version: vk::API_VERSION_1_3,
+            profile: false,
+            trace_path: None,
+            simulate_loss: None,
+            width: 800,
+            height: 600,
+        }
+    }
+}
+impl Config
+{
+    pub fn from_env() -> Config
+    {
+        let mut config = Config::default();
+        if let Some(value) = Config::get_env_flag("LUDO_VALIDATION")
+        {
+            config.enable_validation_layers = value;
+            config.require_validation_layers = value;
+        }
+        if let Some(value) = Config::get_env_flag("LUDO_VALIDATION_VERBOSE")
+        {
+            config.verbose_validation = value;
+        }
+        if let Ok(value) = std::env::var("LUDO_DEVICE")
+        {
+            config.physical_device = Some(value);
+        }
+        if let Some(value) = Config::get_env_number("LUDO_FRAMES_IN_FLIGHT")
+        {
+            config.frames_in_flight = (value as usize).max(1);
+        }
+        if let Some(value) = Config::get_env_number("LUDO_FRAMES")
+        {
+            config.max_frames = Some(value);
+        }
+        if let Some(value) = Config::get_env_flag("LUDO_HEADLESS")
+        {
+            config.headless = value;
+        }
+        if let Ok(value) = std::env::var("LUDO_OUTPUT")
+        {
+            config.output_dir = Some(PathBuf::from(value));
+            config.headless = true;
+        }
+        if let Ok(value) = std::env::var("LUDO_PIPELINE_CACHE")
+        {
+            config.pipeline_cache_dir = Config::parse_cache_dir(&value);
+        }
+        if let Some(value) = Config::get_env_flag("LUDO_BINDLESS")
+        {
+            config.bindless = value;
+        }
+        if let Ok(value) = std::env::var("LUDO_VULKAN_VERSION")
+        {
+            match Config::parse_api_version(&value)
+            {
+                Some(version) => config.api_version = version,
+                None => println!("Ignoring LUDO_VULKAN_VERSION={}: expected 1.0 to 1.3", value),
+            }
+        }
+        if let Some(value) = Config::get_env_flag("LUDO_PROFILE")
+        {
+            config.profile = value;
+        }
+        if let Ok(value) = std::env::var("LUDO_TRACE")
+        {
+            config.trace_path = Some(PathBuf::from(value));
+            config.profile = true;
+        }
+        if let Ok(value) = std::env::var("LUDO_SIMULATE_LOSS")
+        {
+            match Config::parse_loss(&value)
+            {
+                Some(loss) => config.simulate_loss = Some(loss),
+                None => println!("Ignoring LUDO_SIMULATE_LOSS={}: expected device or surface, optionally with :FRAME", value),
+            }
+        }
+        if let Ok(value) = std::env::var("LUDO_SIZE")
+        {
+            match Config::parse_size(&value)
+            {
+                Some((width, height)) => (config.width, config.height) = (width, height),
+                None => println!("Ignoring LUDO_SIZE={}: expected WIDTHxHEIGHT", value),
+            }
+        }
+        config
+    }
+
+    /// Applies command line options on top of the current settings.
+    ///
+    /// `--output` implies `--headless`, since there is no other way to get frames on disk,
+    /// and `--trace` implies `--profile`.
+    pub fn apply_args<I>(&mut self, args: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = String>
+    {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next()
+        {
+            let mut value = |name: &str| args.next().ok_or(format!("{} expects a value", name));
+            match arg.as_str()
+            {
+                "--headless" => self.headless = true,
+                "--no-bindless" => self.bindless = false,
+                "--profile" => self.profile = true,
+                "--trace" =>
+                {
+                    self.trace_path = Some(PathBuf::from(value("--trace")?));
+                    self.profile = true;
+                }
+                "--frames" =>
+                {
+                    let frames = value("--frames")?;
+                    let frames = frames.parse::<u64>()
+                        .map_err(|_| format!("--frames expects a number, got {}", frames))?;
+                    self.max_frames = Some(frames);
+                }
+                "--output" =>
+                {
+                    self.output_dir = Some(PathBuf::from(value("--output")?));
+                    self.headless = true;
+                }
+                "--size" =>
+                {
+                    let size = value("--size")?;
+                    (self.width, self.height) = Config::parse_size(&size)
+                        .ok_or(format!("--size expects WIDTHxHEIGHT, got {}", size))?;
+                }
+                "--device" => self.physical_device = Some(value("--device")?),
+                "--pipeline-cache" => self.pipeline_cache_dir = Config::parse_cache_dir(&value("--pipeline-cache")?),
+                "--vulkan-version" =>
+                {
+                    let version = value("--vulkan-version")?;
+                    self.api_version = Config::parse_api_version(&version)
+                        .ok_or(format!("--vulkan-version expects 1.0 to 1.3, got {}", version))?;
+                }
+                "--simulate-loss" =>
+                {
+                    let loss = value("--simulate-loss")?;
+                    self.simulate_loss = Some(Config::parse_loss(&loss)
+                        .ok_or(format!("--simulate-loss expects device or surface, optionally with :FRAME, got {}", loss))?);
+                }
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_cache_dir(value: &str) -> Option<PathBuf>
+    {
+        match value
+        {
+            "" | "0" | "off" => None,
+            _ => Some(PathBuf::from(value)),
+        }
+    }
+
+    fn get_default_cache_dir() -> Option<PathBuf>
+    {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_home.join("ludo"))
+    }
+
+    fn parse_api_version(value: &str) -> Option<u32>
+    {
+        match value.trim()
+        {
+            "1.0" => Some(vk::API_VERSION_1_0),
+            "1.1" => Some(vk::API_VERSION_1_1),
+            "1.2" => Some(vk::API_VERSION_1_2),
+            "1.3" => Some(vk::API_VERSION_1_3),
+            _ => None,
+        }
+    }
+
+    fn parse_loss(value: &str) -> Option<(Loss, u64)>
+    {
+        let (kind, frame) = match value.trim().split_once(':')
+        {
+            Some((kind, frame)) => (kind, frame.parse::<u64>().ok()?),
+            None => (value.trim(), 0),
+        };
+        match kind
+        {
+            "device" => Some((Loss::Device, frame)),
+            "surface" => Some((Loss::Surface, frame)),
+            _ => None,
+        }
+    }
+
+    fn parse_size(value: &str) -> Option<(u32, u32)>
+    {
+        let (width, height) = value.trim().split_once(['x', 'X'])?;
+        let width = width.parse::<u32>().ok()?;
+        let height = height.parse::<u32>().ok()?;
+        if width == 0 || height == 0
+        {
+            return None;
+        }
+        Some((width, height))
+    }
+
+    fn get_env_number(name: &str) -> Option<u64>
+    {
+        let value = std::env::var(name).ok()?;
+        match value.trim().parse::<u64>()
+        {
+            Ok(number) => Some(number),
+            Err(_) =>
+            {
+                println!("Ignoring {}={}: expected a number", name, value);
+                None
+            }
+        }
+    }
+
+    fn get_env_flag(name: &str) -> Option<bool>
+    {
+        let value = std::env::var(name).ok()?;
+        match value.to_ascii_lowercase().as_str()
+        {
+            "1" | "true" | "on" | "yes" => Some(true),
+            "0" | "false" | "off" | "no" => Some(false),
+            _ =>
+            {
+                println!("Ignoring {}={}: expected 1/0, true/false, on/off or yes/no", name, value);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn usage_lists_every_option()
+    {
+        let options: Vec<&str> = USAGE.split(" [").skip(1).map(|option| option.strip_suffix(']').unwrap_or(option)).collect();
+        assert_eq!(options.len(), 11);
+        for option in options
+        {
+            let mut args: Vec<String> = option.split(' ').map(str::to_string).collect();
+            if let Some(value) = args.get_mut(1)
+            {
+                // The first alternative of the placeholder, or a sample for a named one.
+                let first = value.split(['|', '[']).next().unwrap();
+                *value = match first
+                {
+                    "N" => "3",
+                    "WxH" => "64x32",
+                    _ => first,
+                }.to_string();
+            }
+            let mut config = Config::from_env();
+            assert_eq!(config.apply_args(args.clone()), Ok(()), "{:?}", args);
+        }
+        assert!(Config::from_env().apply_args(["--no-such-option".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+//! Golden-image tests: named scenes are rendered headless and compared with the reference
+//! PNGs in `tests/golden/`.
+//!
+//! Set `LUDO_UPDATE_GOLDEN=1` to rewrite the references from the current output instead.
+//! A failed comparison leaves `<scene>.actual.png` and `<scene>.diff.png` in `target/golden/`.
+//! Without a Vulkan loader, ICD or physical device the scenes are skipped, not failed; any
+//! validation error reported while rendering fails them.
+
+use crate::rc_string::RCString;
+use crate::ludo::headless::{Headless, Snapshot};
+use crate::ludo::renderer::{RendererError, TriangleRenderer};
+use crate::ludo::{vulkan, Config};
+use ash::extensions::ext;
+use ash::vk;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How far a rendered image may drift from its reference.
+#[derive(Clone, Copy, Debug)]
+pub struct GoldenOptions
+{
+    /// Largest per-channel difference, in 8-bit steps, for a pixel to still count as equal.
+    pub tolerance: u8,
+    /// Largest perceptual (YIQ) difference, in `0.0..=1.0`, for a pixel to still count as equal.
+    pub perceptual_threshold: f64,
+    /// Number of pixels allowed to exceed both limits, e.g. along rasterized edges.
+    pub max_mismatched_pixels: usize,
+}
+impl Default for GoldenOptions
+{
+    fn default() -> Self
+    {
+        GoldenOptions {
+            tolerance: 2,
+            perceptual_threshold: 0.05,
+            max_mismatched_pixels: 0,
+        }
+    }
+}
+
+/// A named, deterministic rendering checked against `tests/golden/<name>.png`.
+pub struct Scene
+{
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub options: GoldenOptions,
+    pub render: fn(&vulkan::Device, &mut Headless) -> Result<Snapshot, vk::Result>,
+}
+
+pub const SCENES: &[Scene] = &[
+    Scene {
+        name: "triangle",
+        width: 128,
+        height: 96,
+        options: GoldenOptions { tolerance: 2, perceptual_threshold: 0.05, max_mismatched_pixels: 32 },
+        render: |device, headless| render_triangle(device, headless, 0),
+    },
+    Scene {
+        name: "triangle_frame_120",
+        width: 128,
+        height: 96,
+        options: GoldenOptions { tolerance: 2, perceptual_threshold: 0.05, max_mismatched_pixels: 32 },
+        render: |device, headless| render_triangle(device, headless, 120),
+    },
+];
+
+fn render_triangle(device: &vulkan::Device, headless: &mut Headless, frame_number: u64) -> Result<Snapshot, vk::Result>
+{
+    let target = headless.get_target();
+    let mut renderer = match TriangleRenderer::create(device, target.get_format(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, None)
+    {
+        Ok(renderer) => renderer,
+        Err(RendererError::Vulkan(result)) => return Err(result),
+        Err(error) => panic!("Built-in triangle pipeline is invalid: {}", error),
+    };
+    renderer.update_targets(&[target.get_image()], &[target.get_image_view()], target.get_extent(), 1)?;
+    headless.render(|command_buffer, _| {
+        renderer.record(command_buffer, 0, frame_number);
+        Ok(())
+    })
+}
+
+/// Result of comparing a rendered image with its reference.
+pub struct Comparison
+{
+    pub mismatched_pixels: usize,
+    pub max_channel_difference: u8,
+    pub max_perceptual_difference: f64,
+    /// Mismatches in red, differences within the limits in yellow, equal pixels faded.
+    pub diff: Snapshot,
+}
+
+/// Perceptual distance of two RGB colors in `0.0..=1.0`, weighted in YIQ space.
+fn get_perceptual_difference(a: &[u8], b: &[u8]) -> f64
+{
+    // Upper bound of the weighted squared YIQ distance of two 8-bit colors.
+    const MAX_DELTA: f64 = 35215.0;
+    let (dr, dg, db) = (
+        a[0] as f64 - b[0] as f64,
+        a[1] as f64 - b[1] as f64,
+        a[2] as f64 - b[2] as f64);
+    let y = 0.29889531 * dr + 0.58662247 * dg + 0.11448223 * db;
+    let i = 0.59597799 * dr - 0.27417610 * dg - 0.32180189 * db;
+    let q = 0.21147017 * dr - 0.52261711 * dg + 0.31114694 * db;
+    ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_DELTA).sqrt()
+}
+
+/// Compares two images of the same size pixel by pixel; a pixel mismatches only when
+/// it exceeds both the channel tolerance and the perceptual threshold.
+pub fn compare(actual: &Snapshot, expected: &Snapshot, options: &GoldenOptions) -> Result<Comparison, String>
+{
+    if (actual.width, actual.height) != (expected.width, expected.height)
+    {
+        return Err(format!("size {}x{} differs from the reference size {}x{}",
+            actual.width, actual.height, expected.width, expected.height));
+    }
+    let mut comparison = Comparison {
+        mismatched_pixels: 0,
+        max_channel_difference: 0,
+        max_perceptual_difference: 0.0,
+        diff: Snapshot { width: actual.width, height: actual.height, pixels: Vec::with_capacity(actual.pixels.len()) },
+    };
+    for (a, b) in actual.pixels.chunks_exact(4).zip(expected.pixels.chunks_exact(4))
+    {
+        let channel_difference = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+        let perceptual_difference = get_perceptual_difference(a, b);
+        comparison.max_channel_difference = comparison.max_channel_difference.max(channel_difference);
+        comparison.max_perceptual_difference = comparison.max_perceptual_difference.max(perceptual_difference);
+        let color = if channel_difference > options.tolerance && perceptual_difference > options.perceptual_threshold
+        {
+            comparison.mismatched_pixels += 1;
+            [255, 0, 0, 255]
+        }
+        else if channel_difference > 0
+        {
+            [255, 255, 0, 255]
+        }
+        else
+        {
+            let luma = (0.299 * b[0] as f64 + 0.587 * b[1] as f64 + 0.114 * b[2] as f64) as u8;
+            let faded = 192 + luma / 4;
+            [faded, faded, faded, 255]
+        };
+        comparison.diff.pixels.extend_from_slice(&color);
+    }
+    Ok(comparison)
+}
+
+/// Reads an 8-bit PNG as RGBA8, whatever color type it was stored with.
+pub fn read_png(path: &Path) -> Result<Snapshot, String>
+{
+    let file = std::fs::File::open(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|error| format!("{}: {}", path.display(), error))?;
+    let mut buffer = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|error| format!("{}: {}", path.display(), error))?;
+    buffer.truncate(info.buffer_size());
+    let pixels = match info.color_type
+    {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer.chunks_exact(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]]).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|value| [*value, *value, *value, 255]).collect(),
+        png::ColorType::Indexed => return Err(format!("{}: indexed colors were not expanded", path.display())),
+    };
+    Ok(Snapshot { width: info.width, height: info.height, pixels })
+}
+
+pub enum GoldenOutcome
+{
+    Passed(Comparison),
+    Updated,
+    Skipped(String),
+}
+
+fn get_reference_path(name: &str) -> PathBuf
+{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name))
+}
+
+fn get_output_dir() -> PathBuf
+{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden")
+}
+
+/// Device for GPU tests, with the validation messages reported while it is used.
+pub struct GpuTest
+{
+    device: vulkan::Device,
+    sink: Arc<vulkan::CollectingSink>,
+    _messenger: Option<vulkan::DebugMessenger>,
+}
+impl GpuTest
+{
+    pub fn get_device(&self) -> &vulkan::Device
+    {
+        &self.device
+    }
+
+    /// Panics listing every validation error reported so far.
+    pub fn assert_no_errors(&self)
+    {
+        self.sink.assert_no_errors();
+    }
+}
+
+/// Device for GPU tests, or why there is no Vulkan implementation to run them on.
+pub enum TestDevice
+{
+    Ready(GpuTest),
+    Unavailable(String),
+}
+
+/// Creates a headless device the same way `Ludo` does.
+///
+/// Only a missing loader, ICD or physical device makes the device `Unavailable`; any other
+/// failure is an error, so that a regression in device setup is not mistaken for a skip.
+/// `VK_LAYER_KHRONOS_validation` is enabled where it is installed, and required with
+/// `LUDO_VALIDATION=1`.
+pub fn create_device() -> Result<TestDevice, String>
+{
+    let config = Config::from_env();
+    let mut instance_info = vulkan::InstanceCreateInfo::default();
+    instance_info.application_info.application_name = RCString::from_rstr("Ludo golden tests");
+    instance_info.application_info.api_version = config.api_version;
+    instance_info.optional_extension_names = vec![
+        RCString::from_cstr(vk::KhrGetPhysicalDeviceProperties2Fn::name()),
+        RCString::from_cstr(vk::KhrPortabilityEnumerationFn::name()),
+        // So that the tests run object naming and labels for real where the loader has it.
+        RCString::from_cstr(ext::DebugUtils::name()),
+    ];
+    if config.enable_validation_layers
+    {
+        instance_info.enabled_layer_names.push(RCString::from_rstr(vulkan::VALIDATION_LAYER_NAME));
+    }
+    let mut result = vulkan::Instance::create(&instance_info);
+    if let Err(vulkan::InstanceError::LayerNotPresent(..)) = &result
+    {
+        if !config.require_validation_layers
+        {
+            instance_info.enabled_layer_names.clear();
+            result = vulkan::Instance::create(&instance_info);
+        }
+    }
+    let instance = match result
+    {
+        Ok(instance) => instance,
+        Err(error @ vulkan::InstanceError::LoadingFailed(_)) => return Ok(TestDevice::Unavailable(error.to_string())),
+        // What the loader reports when it found no ICD at all.
+        Err(error @ vulkan::InstanceError::CreationFailed(vk::Result::ERROR_INCOMPATIBLE_DRIVER)) =>
+            return Ok(TestDevice::Unavailable(error.to_string())),
+        Err(error) => return Err(error.to_string()),
+    };
+    let sink = Arc::new(vulkan::CollectingSink::default());
+    let messenger = if instance.is_extension_enabled(ext::DebugUtils::name())
+    {
+        let severity = vk::DebugUtilsMessageSeverityFlagsEXT::WARNING | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+        let messenger = vulkan::DebugMessenger::create(&instance, severity, sink.clone())
+            .map_err(|error| format!("vkCreateDebugUtilsMessengerEXT failed: {}", error))?;
+        Some(messenger)
+    }
+    else
+    {
+        None
+    };
+    let physical_devices = vulkan::PhysicalDevice::enumerate(&instance).map_err(|error| error.to_string())?;
+    let selection = match &config.physical_device
+    {
+        Some(value) => vulkan::DeviceSelection::parse(value),
+        None => vulkan::DeviceSelection::Best,
+    };
+    let physical_device = match vulkan::pick_physical_device(&physical_devices, &vulkan::DeviceRequirements::default(), &selection)
+    {
+        Ok(physical_device) => physical_device,
+        Err(error @ vulkan::DeviceSelectionError::NoDevices) => return Ok(TestDevice::Unavailable(error.to_string())),
+        Err(error) => return Err(error.to_string()),
+    };
+    let modern = physical_device.get_modern_features().is_complete();
+    let device_info = vulkan::DeviceCreateInfo {
+        dynamic_rendering: modern,
+        timeline_semaphore: modern,
+        synchronization2: modern,
+        ..Default::default()
+    };
+    let device = vulkan::Device::create(&physical_device, &device_info).map_err(|error| error.to_string())?;
+    Ok(TestDevice::Ready(GpuTest { device, sink, _messenger: messenger }))
+}
+
+/// The device for a GPU test, or `None` after printing why the test is skipped.
+///
+/// Panics when the device could not be created for any other reason than a missing
+/// Vulkan implementation.
+pub fn get_test_device() -> Option<GpuTest>
+{
+    match create_device()
+    {
+        Ok(TestDevice::Ready(test)) => Some(test),
+        Ok(TestDevice::Unavailable(reason)) =>
+        {
+            println!("skipped, no usable Vulkan: {}", reason);
+            None
+        }
+        Err(error) => panic!("Test device creation is failed: {}", error),
+    }
+}
+
+/// Renders `scene` and checks it against its reference.
+///
+/// Only a missing Vulkan implementation is a skip; any other failure is an error.
+pub fn run_scene(scene: &Scene) -> Result<GoldenOutcome, String>
+{
+    let test = match create_device()?
+    {
+        TestDevice::Ready(test) => test,
+        TestDevice::Unavailable(reason) => return Ok(GoldenOutcome::Skipped(reason)),
+    };
+    let extent = vk::Extent2D { width: scene.width, height: scene.height };
+    let allocator = vulkan::Allocator::create(test.get_device());
+    let mut headless = Headless::create(&allocator, extent).map_err(|error| error.to_string())?;
+    let actual = (scene.render)(test.get_device(), &mut headless).map_err(|error| error.to_string())?;
+    test.sink.check_no_errors()?;
+
+    let reference_path = get_reference_path(scene.name);
+    if std::env::var("LUDO_UPDATE_GOLDEN").is_ok_and(|value| value != "0")
+    {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).map_err(|error| error.to_string())?;
+        actual.write_png(&reference_path)?;
+        return Ok(GoldenOutcome::Updated);
+    }
+
+    let output_dir = get_output_dir();
+    let actual_path = output_dir.join(format!("{}.actual.png", scene.name));
+    let expected = match read_png(&reference_path)
+    {
+        Ok(expected) => expected,
+        Err(error) =>
+        {
+            std::fs::create_dir_all(&output_dir).map_err(|error| error.to_string())?;
+            actual.write_png(&actual_path)?;
+            return Err(format!("{} (output written to {})", error, actual_path.display()));
+        }
+    };
+    let comparison = compare(&actual, &expected, &scene.options)?;
+    if comparison.mismatched_pixels > scene.options.max_mismatched_pixels
+    {
+        let diff_path = output_dir.join(format!("{}.diff.png", scene.name));
+        std::fs::create_dir_all(&output_dir).map_err(|error| error.to_string())?;
+        actual.write_png(&actual_path)?;
+        comparison.diff.write_png(&diff_path)?;
+        return Err(format!(
+            "{} of {} pixels mismatch (allowed {}), max channel difference {}, max perceptual difference {:.3}; see {}",
+            comparison.mismatched_pixels,
+            scene.width as usize * scene.height as usize,
+            scene.options.max_mismatched_pixels,
+            comparison.max_channel_difference,
+            comparison.max_perceptual_difference,
+            diff_path.display()));
+    }
+    Ok(GoldenOutcome::Passed(comparison))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn check_scene(name: &str)
+    {
+        let scene = SCENES.iter().find(|scene| scene.name == name).expect("unknown scene");
+        match run_scene(scene)
+        {
+            Ok(GoldenOutcome::Passed(comparison)) =>
+                println!("{}: passed, {} mismatched pixels", name, comparison.mismatched_pixels),
+            Ok(GoldenOutcome::Updated) => println!("{}: reference updated", name),
+            Ok(GoldenOutcome::Skipped(reason)) => println!("{}: skipped, no usable Vulkan: {}", name, reason),
+            Err(error) => panic!("{}: {}", name, error),
+        }
+    }
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> Snapshot
+    {
+        Snapshot { width, height, pixels: color.repeat((width * height) as usize) }
+    }
+
+    #[test]
+    fn golden_triangle()
+    {
+        check_scene("triangle");
+    }
+
+    #[test]
+    fn golden_triangle_frame_120()
+    {
+        check_scene("triangle_frame_120");
+    }
+
+    #[test]
+    fn every_scene_has_a_reference()
+    {
+        for scene in SCENES
+        {
+            let reference = read_png(&get_reference_path(scene.name)).unwrap();
+            assert_eq!((reference.width, reference.height), (scene.width, scene.height), "{}", scene.name);
+        }
+    }
+
+    #[test]
+    fn compare_accepts_differences_within_tolerance()
+    {
+        let expected = solid(4, 4, [100, 100, 100, 255]);
+        let actual = solid(4, 4, [102, 99, 100, 255]);
+        let comparison = compare(&actual, &expected, &GoldenOptions::default()).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 0);
+        assert_eq!(comparison.max_channel_difference, 2);
+        assert_eq!(&comparison.diff.pixels[..4], &[255, 255, 0, 255]);
+    }
+
+    #[test]
+    fn compare_accepts_perceptually_close_pixels()
+    {
+        let expected = solid(2, 2, [0, 0, 200, 255]);
+        let actual = solid(2, 2, [0, 0, 210, 255]);
+        let options = GoldenOptions { tolerance: 0, perceptual_threshold: 0.05, max_mismatched_pixels: 0 };
+        assert_eq!(compare(&actual, &expected, &options).unwrap().mismatched_pixels, 0);
+    }
+
+    #[test]
+    fn compare_reports_mismatches_in_the_diff()
+    {
+        let expected = solid(2, 1, [0, 0, 0, 255]);
+        let mut actual = expected.clone();
+        actual.pixels[4..8].copy_from_slice(&[255, 255, 255, 255]);
+        let comparison = compare(&actual, &expected, &GoldenOptions::default()).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 1);
+        assert!(comparison.max_perceptual_difference > 0.9);
+        assert_eq!(&comparison.diff.pixels[4..8], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn compare_rejects_size_mismatch()
+    {
+        assert!(compare(&solid(2, 2, [0; 4]), &solid(2, 3, [0; 4]), &GoldenOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::ludo::vulkan;
+use ash::vk;
+use std::path::Path;
+
+/// A frame read back to host memory: tightly packed RGBA8 rows, top row first.
+#[derive(Clone)]
+pub struct Snapshot
+{
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+impl Snapshot
+{
+    pub fn write_png(&self, path: &Path) -> Result<(), String>
+    {
+        let file = std::fs::File::create(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header()
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        writer.write_image_data(&self.pixels)
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+}
+
+/// Renders into an `OffscreenTarget` without any window or surface and reads every
+/// frame back; one frame at a time, since each of them is waited for anyway.
+pub struct Headless
+{
+    command_buffer: vk::CommandBuffer,
+    fence: vulkan::Fence,
+    command_pool: vulkan::CommandPool,
+    target: vulkan::OffscreenTarget,
+    frame_number: u64,
+    device: vulkan::Device,
+}
+impl Headless
+{
+    pub fn create(allocator: &vulkan::Allocator, extent: vk::Extent2D) -> Result<Headless, vk::Result>
+    {
+        let device = &allocator.get_device();
+        let format = vulkan::OffscreenTarget::choose_format(device)
+            .ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)?;
+        let target = vulkan::OffscreenTarget::create(allocator, extent, format)?;
+        let command_pool = vulkan::CommandPool::create(
+            device,
+            device.get_graphics_queue().get_family_index(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
+        let command_buffer = command_pool.allocate(vk::CommandBufferLevel::PRIMARY, 1)?[0];
+        let fence = vulkan::Fence::create(device, false)?;
+        target.set_name("offscreen target");
+        command_pool.set_name("headless commands");
+        device.set_object_name(command_buffer, "headless frame");
+        fence.set_name("headless frame done");
+        Ok(Headless {
+            command_buffer,
+            fence,
+            command_pool,
+            target,
+            frame_number: 0,
+            device: device.clone(),
+        })
+    }
+
+    pub fn get_target(&self) -> &vulkan::OffscreenTarget
+    {
+        &self.target
+    }
+
+    pub fn get_frame_number(&self) -> u64
+    {
+        self.frame_number
+    }
+
+    /// Continues the numbering of an earlier target, e.g. one lost with its device.
+    pub fn set_frame_number(&mut self, frame_number: u64)
+    {
+        self.frame_number = frame_number;
+    }
+
+    /// Renders one frame with `record` and returns it once the GPU is done.
+    ///
+    /// `record` gets the frame number and must leave the target image in
+    /// `TRANSFER_SRC_OPTIMAL`, e.g. as the final layout of its render pass.
+    pub fn render<F>(&mut self, record: F) -> Result<Snapshot, vk::Result>
+    where
+        F: FnOnce(vk::CommandBuffer, u64) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let command_buffer = self.command_buffer;
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+        }
+        record(command_buffer, self.frame_number)?;
+        self.target.record_readback(command_buffer);
+        unsafe { device.end_command_buffer(command_buffer) }?;
+
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        let queue = self.device.get_graphics_queue().get_handle();
+        unsafe { device.queue_submit(queue, &[submit_info.build()], self.fence.get_handle()) }?;
+        self.fence.wait(u64::MAX)?;
+        self.fence.reset()?;
+        self.frame_number += 1;
+
+        let extent = self.target.get_extent();
+        Ok(Snapshot {
+            width: extent.width,
+            height: extent.height,
+            pixels: self.target.read_pixels()?,
+        })
+    }
+}
+impl Drop for Headless
+{
+    fn drop(&mut self)
+    {
+        // A failed `render` may leave a submission behind.
+        let _ = self.device.wait_idle();
+        self.command_pool.free(&[self.command_buffer]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ludo::renderer::RendererError;
+use crate::ludo::{shaders, vulkan};
+use ash::vk;
+
+/// Characters the overlay's push constants hold, see `shaders/overlay.glsl`.
+const OVERLAY_CAPACITY: usize = 96;
+/// Pixels per font texel and the distance of the text from the top left corner.
+const OVERLAY_SCALE: f32 = 2.0;
+const OVERLAY_ORIGIN: [f32; 2] = [8.0, 8.0];
+
+/// Lines of text packed for the overlay shaders: upper-cased, padded to the longest line
+/// and cut to `OVERLAY_CAPACITY` characters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverlayText
+{
+    words: [u32; OVERLAY_CAPACITY / 4],
+    columns: u32,
+    rows: u32,
+}
+impl OverlayText
+{
+    pub fn new<S: AsRef<str>>(lines: &[S]) -> OverlayText
+    {
+        let columns = lines.iter().map(|line| line.as_ref().chars().count()).max().unwrap_or(0).min(OVERLAY_CAPACITY);
+        let rows = match columns
+        {
+            0 => 0,
+            _ => lines.len().min(OVERLAY_CAPACITY / columns),
+        };
+        let mut words = [0u32; OVERLAY_CAPACITY / 4];
+        for (row, line) in lines.iter().take(rows).enumerate()
+        {
+            let mut characters = line.as_ref().chars().map(OverlayText::get_glyph).chain(std::iter::repeat(b' '));
+            for column in 0..columns
+            {
+                let index = row * columns + column;
+                words[index / 4] |= (characters.next().unwrap() as u32) << (index % 4 * 8);
+            }
+        }
+        OverlayText { words, columns: columns as u32, rows: rows as u32 }
+    }
+
+    /// The character the font draws for `character`; it only has ASCII 32 to 95.
+    fn get_glyph(character: char) -> u8
+    {
+        match character.to_ascii_uppercase()
+        {
+            character @ ' '..='_' => character as u8,
+            _ => b'?',
+        }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.rows == 0
+    }
+}
+
+/// Draws text over the frame, e.g. the profiler timings, with a bitmap font in the
+/// fragment shader; the text travels in push constants, so no resources are needed.
+pub struct TextOverlay
+{
+    text: OverlayText,
+    pipeline: vulkan::GraphicsPipeline,
+    layout: vulkan::PipelineLayout,
+    device: vulkan::Device,
+}
+impl TextOverlay
+{
+    /// Creates the pipeline for subpass 0 of `render_pass`, or for dynamic rendering to
+    /// `color_format` without one.
+    pub fn create(
+        device: &vulkan::Device,
+        render_pass: Option<&vulkan::RenderPass>,
+        color_format: vk::Format,
+        cache: Option<&vulkan::PipelineCache>) -> Result<TextOverlay, RendererError>
+    {
+        let vertex_shader = vulkan::ShaderModule::from_bytes(device, shaders::OVERLAY_VERT)?;
+        let fragment_shader = vulkan::ShaderModule::from_bytes(device, shaders::OVERLAY_FRAG)?;
+        vertex_shader.set_name("overlay.vert");
+        fragment_shader.set_name("overlay.frag");
+        let shaders = [&vertex_shader, &fragment_shader];
+        let layout = vulkan::PipelineLayout::from_shaders(device, &shaders)?;
+        layout.set_name("overlay");
+        let builder = vulkan::GraphicsPipelineBuilder::new(&layout, &shaders).blend(&[vulkan::BlendState::Alpha]);
+        let pipeline = match render_pass
+        {
+            Some(render_pass) => builder.render_pass(render_pass, 0).build(cache)?,
+            None =>
+            {
+                let formats = vulkan::RenderingFormats { color: vec![color_format], depth: None, stencil: None };
+                builder.dynamic_rendering(formats).build(cache)?
+            }
+        };
+        pipeline.set_name("overlay");
+        Ok(TextOverlay { text: OverlayText::default(), pipeline, layout, device: device.clone() })
+    }
+
+    pub fn set_text(&mut self, text: OverlayText)
+    {
+        self.text = text;
+    }
+
+    /// Draws the text in the top left corner of `render_area`, inside a render pass or
+    /// dynamic rendering; nothing when there is no text.
+    pub fn record(&self, command_buffer: vk::CommandBuffer, render_area: vk::Rect2D)
+    {
+        if self.text.is_empty()
+        {
+            return;
+        }
+        let range = self.layout.get_push_constant_range().expect("overlay shaders have no push constants");
+        let extent = render_area.extent;
+        let mut constants = self.text.words.to_vec();
+        constants.extend([
+            (extent.width as f32).to_bits(),
+            (extent.height as f32).to_bits(),
+            OVERLAY_ORIGIN[0].to_bits(),
+            OVERLAY_ORIGIN[1].to_bits(),
+            self.text.columns,
+            self.text.rows,
+            OVERLAY_SCALE.to_bits(),
+        ]);
+        let device = self.device.get_device();
+        self.device.begin_label(command_buffer, "overlay");
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.get_handle());
+            device.cmd_push_constants(command_buffer, self.layout.get_handle(), range.stage_flags, 0, bytemuck::cast_slice(&constants));
+            device.cmd_draw(command_buffer, 6, 1, 0, 0);
+        }
+        self.device.end_label(command_buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn text_is_packed_in_rows_of_the_longest_line()
+    {
+        let text = OverlayText::new(&["Gpu 1.5", "cpu", "é"]);
+        assert_eq!((text.columns, text.rows), (7, 3));
+        let bytes: Vec<u8> = text.words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert_eq!(&bytes[..21], b"GPU 1.5CPU    ?      ");
+        assert!(bytes[21..].iter().all(|byte| *byte == 0));
+
+        assert!(OverlayText::new::<&str>(&[]).is_empty());
+        assert!(OverlayText::new(&[""]).is_empty());
+        let long = OverlayText::new(&["x".repeat(40), "y".repeat(40), "z".repeat(40)]);
+        assert_eq!((long.columns, long.rows), (40, 2));
+        let longest = OverlayText::new(&["x".repeat(200)]);
+        assert_eq!((longest.columns, longest.rows), (OVERLAY_CAPACITY as u32, 1));
+    }
+
+    #[test]
+    fn push_constants_match_the_shaders()
+    {
+        // The text, the target size and origin, columns, rows and the scale.
+        let size = (OVERLAY_CAPACITY + 4 * 4 + 4 + 4 + 4) as u32;
+        for code in [shaders::OVERLAY_VERT, shaders::OVERLAY_FRAG]
+        {
+            let reflection = vulkan::ShaderReflection::reflect(&vulkan::read_words(code).unwrap(), None).unwrap();
+            assert_eq!(reflection.push_constants, Some((0, size)));
+        }
+    }
+}
@@ -0,0 +1,1103 @@
+//! A frame described as passes and the images and buffers they use.
+//!
+//! Passes are declared in any order together with how they access each resource. Compiling
+//! the graph orders them, drops passes whose results nobody uses, plans every barrier and
+//! layout transition, and places transient resources with disjoint lifetimes in shared
+//! memory. The compiled graph is then recorded into a command buffer as often as needed.
+
+use crate::ludo::vulkan;
+use ash::vk;
+use std::fmt::{Display, Formatter};
+
+mod dot;
+mod plan;
+use plan::{BarrierOp, SlotRequest};
+
+/// An image of a `RenderGraph`, transient or imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+/// A buffer of a `RenderGraph`, transient or imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// Which kind of commands a pass records; it decides the shader stages of shader accesses.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassKind
+{
+    Graphics,
+    Compute,
+    Transfer,
+}
+impl PassKind
+{
+    fn get_shader_stages(&self) -> vk::PipelineStageFlags
+    {
+        match self
+        {
+            PassKind::Compute => vk::PipelineStageFlags::COMPUTE_SHADER,
+            PassKind::Graphics | PassKind::Transfer => vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        }
+    }
+}
+
+/// How a pass uses an image; the usages that write are marked.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageUsage
+{
+    /// Writes.
+    ColorAttachment,
+    /// Writes.
+    DepthAttachment,
+    /// Depth testing without depth writes.
+    DepthRead,
+    Sampled,
+    StorageRead,
+    /// Writes.
+    StorageWrite,
+    TransferSrc,
+    /// Writes.
+    TransferDst,
+}
+impl ImageUsage
+{
+    fn get_access(&self, kind: PassKind) -> (vk::PipelineStageFlags, vk::AccessFlags, vk::ImageLayout, vk::ImageUsageFlags)
+    {
+        let tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        match self
+        {
+            ImageUsage::ColorAttachment => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT),
+            ImageUsage::DepthAttachment => (
+                tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+            ImageUsage::DepthRead => (
+                tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+            ImageUsage::Sampled => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageUsageFlags::SAMPLED),
+            ImageUsage::StorageRead => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::GENERAL,
+                vk::ImageUsageFlags::STORAGE),
+            ImageUsage::StorageWrite => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::ImageLayout::GENERAL,
+                vk::ImageUsageFlags::STORAGE),
+            ImageUsage::TransferSrc => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageUsageFlags::TRANSFER_SRC),
+            ImageUsage::TransferDst => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageUsageFlags::TRANSFER_DST),
+        }
+    }
+}
+
+/// How a pass uses a buffer; the usages that write are marked.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage
+{
+    Vertex,
+    Index,
+    Indirect,
+    Uniform,
+    StorageRead,
+    /// Writes.
+    StorageWrite,
+    TransferSrc,
+    /// Writes.
+    TransferDst,
+}
+impl BufferUsage
+{
+    fn get_access(&self, kind: PassKind) -> (vk::PipelineStageFlags, vk::AccessFlags, vk::BufferUsageFlags)
+    {
+        match self
+        {
+            BufferUsage::Vertex => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                vk::BufferUsageFlags::VERTEX_BUFFER),
+            BufferUsage::Index => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::INDEX_READ,
+                vk::BufferUsageFlags::INDEX_BUFFER),
+            BufferUsage::Indirect => (
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::AccessFlags::INDIRECT_COMMAND_READ,
+                vk::BufferUsageFlags::INDIRECT_BUFFER),
+            BufferUsage::Uniform => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::UNIFORM_READ,
+                vk::BufferUsageFlags::UNIFORM_BUFFER),
+            BufferUsage::StorageRead => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::SHADER_READ,
+                vk::BufferUsageFlags::STORAGE_BUFFER),
+            BufferUsage::StorageWrite => (
+                kind.get_shader_stages(),
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::BufferUsageFlags::STORAGE_BUFFER),
+            BufferUsage::TransferSrc => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::BufferUsageFlags::TRANSFER_SRC),
+            BufferUsage::TransferDst => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::BufferUsageFlags::TRANSFER_DST),
+        }
+    }
+}
+
+/// Shape of a 2D image of the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageDesc
+{
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+    pub samples: vk::SampleCountFlags,
+}
+#[allow(dead_code)]
+impl ImageDesc
+{
+    /// A single-sampled image without mip levels.
+    pub fn new(format: vk::Format, extent: vk::Extent2D) -> ImageDesc
+    {
+        ImageDesc {
+            format,
+            extent,
+            mip_levels: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    fn get_aspect(&self) -> vk::ImageAspectFlags
+    {
+        match self.format
+        {
+            vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+            vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT =>
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    fn get_range(&self) -> vk::ImageSubresourceRange
+    {
+        vk::ImageSubresourceRange {
+            aspect_mask: self.get_aspect(),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderGraphError
+{
+    /// Passes that depend on each other in a circle.
+    Cycle(Vec<String>),
+    /// A transient resource and the pass that reads it before anything wrote it.
+    UndefinedRead(String, String),
+    Vulkan(vk::Result),
+}
+impl Display for RenderGraphError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            RenderGraphError::Cycle(passes) => write!(f, "passes depend on each other: {}", passes.join(", ")),
+            RenderGraphError::UndefinedRead(resource, pass) => write!(f, "pass {} reads {} before anything writes it", pass, resource),
+            RenderGraphError::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for RenderGraphError {}
+impl From<vk::Result> for RenderGraphError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        RenderGraphError::Vulkan(result)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ResourceDesc
+{
+    Image(ImageDesc),
+    Buffer(vk::DeviceSize),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Handle
+{
+    /// Transient and not created (yet), e.g. because only culled passes use it.
+    None,
+    Image(vk::Image, vk::ImageView),
+    Buffer(vk::Buffer),
+}
+
+/// Where an exported resource is left after the last pass.
+#[derive(Clone, Copy, Debug)]
+struct Export
+{
+    stage: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    layout: vk::ImageLayout,
+}
+
+struct ResourceNode
+{
+    name: String,
+    desc: ResourceDesc,
+    /// Handles and layout of an imported resource; transient resources are created by the graph.
+    imported: Option<(Handle, vk::ImageLayout)>,
+    export: Option<Export>,
+    image_usage: vk::ImageUsageFlags,
+    buffer_usage: vk::BufferUsageFlags,
+}
+
+/// One use of a resource by a pass.
+#[derive(Clone, Debug)]
+struct ResourceAccess
+{
+    resource: usize,
+    stage: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    layout: vk::ImageLayout,
+    label: String,
+}
+impl ResourceAccess
+{
+    fn writes(&self) -> bool
+    {
+        self.access.intersects(vulkan::WRITE_ACCESS)
+    }
+}
+
+type Execute<'a> = Box<dyn Fn(&PassContext) -> Result<(), vk::Result> + 'a>;
+
+struct PassNode<'a>
+{
+    name: String,
+    kind: PassKind,
+    accesses: Vec<ResourceAccess>,
+    /// Kept even if nothing uses what it writes.
+    side_effects: bool,
+    execute: Option<Execute<'a>>,
+}
+
+/// Passes and resources of a frame, to be compiled before recording.
+#[derive(Default)]
+pub struct RenderGraph<'a>
+{
+    passes: Vec<PassNode<'a>>,
+    resources: Vec<ResourceNode>,
+}
+// The demo renderer records a single pass directly and does not build a graph yet.
+#[allow(dead_code)]
+impl<'a> RenderGraph<'a>
+{
+    pub fn new() -> RenderGraph<'a>
+    {
+        RenderGraph::default()
+    }
+
+    fn add_resource(&mut self, name: &str, desc: ResourceDesc, imported: Option<(Handle, vk::ImageLayout)>) -> usize
+    {
+        self.resources.push(ResourceNode {
+            name: name.to_owned(),
+            desc,
+            imported,
+            export: None,
+            image_usage: vk::ImageUsageFlags::empty(),
+            buffer_usage: vk::BufferUsageFlags::empty(),
+        });
+        self.resources.len() - 1
+    }
+
+    /// An image created by the graph, whose contents live only within the frame.
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageId
+    {
+        ImageId(self.add_resource(name, ResourceDesc::Image(desc), None))
+    }
+
+    /// An image owned elsewhere, in `layout` when the graph starts; the first barrier
+    /// waits for all earlier commands and semaphore waits.
+    pub fn import_image(&mut self, name: &str, desc: ImageDesc, image: vk::Image, view: vk::ImageView, layout: vk::ImageLayout) -> ImageId
+    {
+        ImageId(self.add_resource(name, ResourceDesc::Image(desc), Some((Handle::Image(image, view), layout))))
+    }
+
+    /// A buffer created by the graph, whose contents live only within the frame.
+    pub fn create_buffer(&mut self, name: &str, size: vk::DeviceSize) -> BufferId
+    {
+        BufferId(self.add_resource(name, ResourceDesc::Buffer(size), None))
+    }
+
+    /// A buffer owned elsewhere whose contents are already visible to the device.
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer, size: vk::DeviceSize) -> BufferId
+    {
+        BufferId(self.add_resource(name, ResourceDesc::Buffer(size), Some((Handle::Buffer(buffer), vk::ImageLayout::UNDEFINED))))
+    }
+
+    /// Makes the image an output of the graph, left in `layout` and made available to
+    /// `stage` and `access` after the last pass, e.g. `PRESENT_SRC_KHR` and `BOTTOM_OF_PIPE`.
+    pub fn export_image(&mut self, image: ImageId, layout: vk::ImageLayout, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        self.resources[image.0].export = Some(Export { stage, access, layout });
+    }
+
+    /// Makes the buffer an output of the graph, made available to `stage` and `access`
+    /// after the last pass, e.g. `HOST` and `HOST_READ` for readback.
+    pub fn export_buffer(&mut self, buffer: BufferId, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        let layout = vk::ImageLayout::UNDEFINED;
+        self.resources[buffer.0].export = Some(Export { stage, access, layout });
+    }
+
+    /// Starts declaring a pass; it takes part in the graph once `execute` is called.
+    pub fn add_pass<'g>(&'g mut self, name: &str, kind: PassKind) -> PassBuilder<'g, 'a>
+    {
+        PassBuilder {
+            graph: self,
+            pass: PassNode {
+                name: name.to_owned(),
+                kind,
+                accesses: Vec::new(),
+                side_effects: false,
+                execute: None,
+            },
+        }
+    }
+
+    pub fn get_image_desc(&self, image: ImageId) -> ImageDesc
+    {
+        match self.resources[image.0].desc
+        {
+            ResourceDesc::Image(desc) => desc,
+            ResourceDesc::Buffer(_) => unreachable!(),
+        }
+    }
+
+    /// The graph in Graphviz DOT, with culled passes dashed; see `CompiledGraph::to_dot` for
+    /// memory aliasing too.
+    pub fn to_dot(&self) -> String
+    {
+        let culled = match plan::plan(&self.passes, &self.resources)
+        {
+            Ok(plan) => plan.culled,
+            Err(_) => Vec::new(),
+        };
+        dot::write(&self.passes, &self.resources, &culled, &[])
+    }
+
+    /// Orders and culls the passes, creates the transient resources that are still used,
+    /// and plans the barriers between all of them.
+    pub fn compile(self, allocator: &vulkan::Allocator) -> Result<CompiledGraph<'a>, RenderGraphError>
+    {
+        let plan = plan::plan(&self.passes, &self.resources)?;
+        let mut compiled = CompiledGraph {
+            passes: Vec::new(),
+            final_barriers: Vec::new(),
+            handles: vec![Handle::None; self.resources.len()],
+            allocations: Vec::new(),
+            dot: String::new(),
+            resources: Vec::new(),
+            device: allocator.get_device(),
+        };
+        // Anything created before a failure is released by `drop` of `compiled`.
+        let mut requests: Vec<(usize, SlotRequest)> = Vec::new();
+        for (index, resource) in self.resources.iter().enumerate()
+        {
+            let lifetime = match (&resource.imported, plan.lifetimes[index])
+            {
+                (None, Some(lifetime)) => lifetime,
+                _ => continue,
+            };
+            let requirements = compiled.create_resource(index, resource)?;
+            requests.push((index, SlotRequest {
+                size: requirements.size,
+                alignment: requirements.alignment,
+                type_bits: requirements.memory_type_bits,
+                lifetime,
+                image: matches!(resource.desc, ResourceDesc::Image(_)),
+            }));
+        }
+
+        let slot_requests: Vec<_> = requests.iter().map(|(_, request)| *request).collect();
+        let (request_slots, slots) = plan::assign_slots(&slot_requests);
+        let mut resource_slots = vec![None; self.resources.len()];
+        for ((index, _), slot) in requests.iter().zip(&request_slots)
+        {
+            resource_slots[*index] = Some(*slot);
+        }
+        for slot in &slots
+        {
+            let requirements = vk::MemoryRequirements {
+                size: slot.size,
+                alignment: slot.alignment,
+                memory_type_bits: slot.type_bits,
+            };
+            let info = vulkan::AllocationCreateInfo {
+                usage: vulkan::MemoryUsage::GpuOnly,
+                kind: if slot.image { vulkan::ResourceKind::Optimal } else { vulkan::ResourceKind::Linear },
+                dedicated: false,
+            };
+            compiled.allocations.push(allocator.allocate(&requirements, &info)?);
+        }
+        for (index, resource) in self.resources.iter().enumerate()
+        {
+            if let Some(slot) = resource_slots[index]
+            {
+                compiled.bind_resource(index, resource, slot)?;
+            }
+        }
+
+        let (barriers, final_barriers) = plan::plan_barriers(&self.passes, &self.resources, &plan, &resource_slots);
+        compiled.dot = dot::write(&self.passes, &self.resources, &plan.culled, &resource_slots);
+        compiled.final_barriers = final_barriers;
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for (position, index) in plan.order.iter().enumerate()
+        {
+            let pass = passes[*index].take().unwrap();
+            compiled.passes.push(CompiledPass {
+                name: pass.name,
+                execute: pass.execute,
+                barriers: barriers[position].clone(),
+            });
+        }
+        for (index, resource) in self.resources.iter().enumerate()
+        {
+            if let Some((handle, _)) = resource.imported
+            {
+                compiled.handles[index] = handle;
+            }
+        }
+        compiled.resources = self.resources;
+        Ok(compiled)
+    }
+}
+
+/// Declares what a pass of a `RenderGraph` reads and writes.
+pub struct PassBuilder<'g, 'a>
+{
+    graph: &'g mut RenderGraph<'a>,
+    pass: PassNode<'a>,
+}
+#[allow(dead_code)]
+impl<'g, 'a> PassBuilder<'g, 'a>
+{
+    pub fn image(mut self, image: ImageId, usage: ImageUsage) -> Self
+    {
+        let (stage, access, layout, flags) = usage.get_access(self.pass.kind);
+        self.graph.resources[image.0].image_usage |= flags;
+        self.pass.accesses.push(ResourceAccess { resource: image.0, stage, access, layout, label: format!("{:?}", usage) });
+        self
+    }
+
+    pub fn buffer(mut self, buffer: BufferId, usage: BufferUsage) -> Self
+    {
+        let (stage, access, flags) = usage.get_access(self.pass.kind);
+        self.graph.resources[buffer.0].buffer_usage |= flags;
+        let layout = vk::ImageLayout::UNDEFINED;
+        self.pass.accesses.push(ResourceAccess { resource: buffer.0, stage, access, layout, label: format!("{:?}", usage) });
+        self
+    }
+
+    /// Keeps the pass even when nothing uses its results, e.g. for readback it does itself.
+    pub fn side_effects(mut self) -> Self
+    {
+        self.pass.side_effects = true;
+        self
+    }
+
+    /// Adds the pass, recording its commands with `execute` every time the graph runs.
+    pub fn execute<F>(mut self, execute: F)
+    where
+        F: Fn(&PassContext) -> Result<(), vk::Result> + 'a
+    {
+        self.pass.execute = Some(Box::new(execute));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+/// What a pass records its commands with.
+pub struct PassContext<'c>
+{
+    command_buffer: vk::CommandBuffer,
+    device: &'c vulkan::Device,
+    resources: &'c [ResourceNode],
+    handles: &'c [Handle],
+}
+#[allow(dead_code)]
+impl<'c> PassContext<'c>
+{
+    pub fn get_command_buffer(&self) -> vk::CommandBuffer
+    {
+        self.command_buffer
+    }
+
+    pub fn get_device(&self) -> &vulkan::Device
+    {
+        self.device
+    }
+
+    fn get_handle(&self, index: usize) -> Handle
+    {
+        match self.handles[index]
+        {
+            Handle::None => panic!("{} is not used by any pass that runs", self.resources[index].name),
+            handle => handle,
+        }
+    }
+
+    pub fn get_image(&self, image: ImageId) -> vk::Image
+    {
+        match self.get_handle(image.0)
+        {
+            Handle::Image(image, _) => image,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get_image_view(&self, image: ImageId) -> vk::ImageView
+    {
+        match self.get_handle(image.0)
+        {
+            Handle::Image(_, view) => view,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get_image_desc(&self, image: ImageId) -> ImageDesc
+    {
+        match self.resources[image.0].desc
+        {
+            ResourceDesc::Image(desc) => desc,
+            ResourceDesc::Buffer(_) => unreachable!(),
+        }
+    }
+
+    pub fn get_buffer(&self, buffer: BufferId) -> vk::Buffer
+    {
+        match self.get_handle(buffer.0)
+        {
+            Handle::Buffer(buffer) => buffer,
+            _ => unreachable!(),
+        }
+    }
+}
+
+struct CompiledPass<'a>
+{
+    name: String,
+    execute: Option<Execute<'a>>,
+    /// Recorded before the pass.
+    barriers: Vec<BarrierOp>,
+}
+
+/// A `RenderGraph` with its passes in order and its transient resources in memory.
+///
+/// Every run starts from the layouts the images were imported in; transient resources are
+/// synchronized with the previous run on the same queue, so the graph can be recorded
+/// frame after frame, but not into command buffers that run concurrently on other queues.
+pub struct CompiledGraph<'a>
+{
+    passes: Vec<CompiledPass<'a>>,
+    /// Recorded after the last pass, for exported resources.
+    final_barriers: Vec<BarrierOp>,
+    resources: Vec<ResourceNode>,
+    handles: Vec<Handle>,
+    allocations: Vec<vulkan::Allocation>,
+    dot: String,
+    device: vulkan::Device,
+}
+#[allow(dead_code)]
+impl<'a> CompiledGraph<'a>
+{
+    /// Creates transient resource `index`, not yet bound to memory.
+    fn create_resource(&mut self, index: usize, resource: &ResourceNode) -> Result<vk::MemoryRequirements, vk::Result>
+    {
+        let device = self.device.get_device();
+        // Shared like buffers and textures, so that compute queues can use them without
+        // ownership transfers.
+        let families = self.device.get_queue_families().get_unique();
+        let sharing_mode = if families.len() > 1 { vk::SharingMode::CONCURRENT } else { vk::SharingMode::EXCLUSIVE };
+        match resource.desc
+        {
+            ResourceDesc::Image(desc) =>
+            {
+                let create_info = vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(desc.format)
+                    .extent(vk::Extent3D { width: desc.extent.width, height: desc.extent.height, depth: 1 })
+                    .mip_levels(desc.mip_levels)
+                    .array_layers(1)
+                    .samples(desc.samples)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(resource.image_usage)
+                    .sharing_mode(sharing_mode)
+                    .queue_family_indices(&families)
+                    .initial_layout(vk::ImageLayout::UNDEFINED);
+                let image = unsafe { device.create_image(&create_info, None) }?;
+                self.handles[index] = Handle::Image(image, vk::ImageView::null());
+                self.device.set_object_name(image, &resource.name);
+                Ok(unsafe { device.get_image_memory_requirements(image) })
+            }
+            ResourceDesc::Buffer(size) =>
+            {
+                let create_info = vk::BufferCreateInfo::builder()
+                    .size(size)
+                    .usage(resource.buffer_usage)
+                    .sharing_mode(sharing_mode)
+                    .queue_family_indices(&families);
+                let buffer = unsafe { device.create_buffer(&create_info, None) }?;
+                self.handles[index] = Handle::Buffer(buffer);
+                self.device.set_object_name(buffer, &resource.name);
+                Ok(unsafe { device.get_buffer_memory_requirements(buffer) })
+            }
+        }
+    }
+
+    /// Binds transient resource `index` to the start of its memory slot.
+    fn bind_resource(&mut self, index: usize, resource: &ResourceNode, slot: usize) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let allocation = &self.allocations[slot];
+        match (self.handles[index], resource.desc)
+        {
+            (Handle::Image(image, _), ResourceDesc::Image(desc)) =>
+            {
+                unsafe { device.bind_image_memory(image, allocation.get_memory(), allocation.get_offset()) }?;
+                let view_info = vk::ImageViewCreateInfo::builder()
+                    .image(image)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(desc.format)
+                    .subresource_range(desc.get_range());
+                let view = unsafe { device.create_image_view(&view_info, None) }?;
+                self.handles[index] = Handle::Image(image, view);
+                self.device.set_object_name(view, &resource.name);
+            }
+            (Handle::Buffer(buffer), _) =>
+            {
+                unsafe { device.bind_buffer_memory(buffer, allocation.get_memory(), allocation.get_offset()) }?;
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Points an imported image at another image of the same shape, e.g. the swapchain
+    /// image acquired for this frame.
+    pub fn set_image(&mut self, image: ImageId, handle: vk::Image, view: vk::ImageView)
+    {
+        assert!(self.resources[image.0].imported.is_some(), "only imported images can be replaced");
+        self.handles[image.0] = Handle::Image(handle, view);
+    }
+
+    /// Points an imported buffer at another buffer of the same size.
+    pub fn set_buffer(&mut self, buffer: BufferId, handle: vk::Buffer)
+    {
+        assert!(self.resources[buffer.0].imported.is_some(), "only imported buffers can be replaced");
+        self.handles[buffer.0] = Handle::Buffer(handle);
+    }
+
+    /// Names of the passes that run, in order.
+    pub fn get_pass_names(&self) -> Vec<&str>
+    {
+        self.passes.iter().map(|pass| pass.name.as_str()).collect()
+    }
+
+    /// Number of memory allocations the transient resources share.
+    pub fn get_allocation_count(&self) -> usize
+    {
+        self.allocations.len()
+    }
+
+    /// The graph in Graphviz DOT, with culled passes dashed and the memory slot of every
+    /// transient resource.
+    pub fn to_dot(&self) -> String
+    {
+        self.dot.clone()
+    }
+
+    fn add_barriers(&self, batch: &mut vulkan::BarrierBatch, barriers: &[BarrierOp])
+    {
+        for op in barriers
+        {
+            match (self.handles[op.resource], self.resources[op.resource].desc)
+            {
+                (Handle::Image(image, _), ResourceDesc::Image(desc)) => batch.add_image(&op.barrier, image, desc.get_range()),
+                (Handle::Buffer(buffer), _) => batch.add_buffer(&op.barrier, buffer),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Records every pass with the barriers it needs into `command_buffer`, each in a debug
+    /// label named after it.
+    pub fn execute(&self, command_buffer: vk::CommandBuffer) -> Result<(), vk::Result>
+    {
+        let mut batch = vulkan::BarrierBatch::default();
+        for pass in &self.passes
+        {
+            self.device.begin_label(command_buffer, &pass.name);
+            self.add_barriers(&mut batch, &pass.barriers);
+            batch.record(&self.device, command_buffer);
+            let result = match &pass.execute
+            {
+                Some(execute) => execute(&PassContext {
+                    command_buffer,
+                    device: &self.device,
+                    resources: &self.resources,
+                    handles: &self.handles,
+                }),
+                None => Ok(()),
+            };
+            // Closed on errors too, so the label stack stays balanced.
+            self.device.end_label(command_buffer);
+            result?;
+        }
+        self.add_barriers(&mut batch, &self.final_barriers);
+        batch.record(&self.device, command_buffer);
+        Ok(())
+    }
+}
+impl Drop for CompiledGraph<'_>
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        for (index, handle) in self.handles.iter().enumerate()
+        {
+            // Imported handles are only filled in once `compile` succeeded.
+            if self.resources.get(index).is_some_and(|resource| resource.imported.is_some())
+            {
+                continue;
+            }
+            match *handle
+            {
+                Handle::Image(image, view) => unsafe {
+                    if view != vk::ImageView::null()
+                    {
+                        device.destroy_image_view(view, None);
+                    }
+                    device.destroy_image(image, None);
+                },
+                Handle::Buffer(buffer) => unsafe { device.destroy_buffer(buffer, None) },
+                Handle::None => {}
+            }
+        }
+        self.allocations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::ludo::golden::get_test_device;
+    use crate::ludo::vulkan::{Allocator, Buffer, ComputeContext, MemoryUsage};
+
+    const SHADERS: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+        vk::PipelineStageFlags::VERTEX_SHADER.as_raw() | vk::PipelineStageFlags::FRAGMENT_SHADER.as_raw());
+
+    fn color() -> ImageDesc
+    {
+        ImageDesc::new(vk::Format::R8G8B8A8_UNORM, vk::Extent2D { width: 64, height: 64 })
+    }
+
+    /// A graph whose only output is an imported image left ready for presentation.
+    fn create_graph<'a>() -> (RenderGraph<'a>, ImageId)
+    {
+        let mut graph = RenderGraph::new();
+        let null = (vk::Image::null(), vk::ImageView::null());
+        let output = graph.import_image("swapchain", color(), null.0, null.1, vk::ImageLayout::UNDEFINED);
+        graph.export_image(output, vk::ImageLayout::PRESENT_SRC_KHR, vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::empty());
+        (graph, output)
+    }
+
+    #[test]
+    fn passes_are_ordered_and_unused_ones_culled()
+    {
+        let (mut graph, output) = create_graph();
+        let scene = graph.create_image("scene", color());
+        let debug = graph.create_image("debug", color());
+        // Declared out of order on purpose.
+        graph.add_pass("post", PassKind::Graphics)
+            .image(scene, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        graph.add_pass("debug", PassKind::Graphics).image(debug, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("scene", PassKind::Graphics).image(scene, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        assert_eq!(plan.order, [2, 0]);
+        assert_eq!(plan.culled, [1]);
+        assert_eq!(plan.lifetimes[scene.0], Some((0, 1)));
+        assert_eq!(plan.lifetimes[debug.0], None);
+    }
+
+    #[test]
+    fn cycles_and_undefined_reads_are_errors()
+    {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_buffer("a", 16);
+        let b = graph.create_buffer("b", 16);
+        graph.add_pass("ping", PassKind::Compute)
+            .buffer(a, BufferUsage::StorageRead)
+            .buffer(b, BufferUsage::StorageWrite)
+            .side_effects()
+            .execute(|_| Ok(()));
+        graph.add_pass("pong", PassKind::Compute)
+            .buffer(b, BufferUsage::StorageRead)
+            .buffer(a, BufferUsage::StorageWrite)
+            .execute(|_| Ok(()));
+        match plan::plan(&graph.passes, &graph.resources)
+        {
+            Err(RenderGraphError::Cycle(passes)) => assert_eq!(passes, ["ping", "pong"]),
+            other => panic!("expected a cycle, got {:?}", other.map(|plan| plan.order)),
+        }
+
+        let mut graph = RenderGraph::new();
+        let garbage = graph.create_buffer("garbage", 16);
+        graph.add_pass("reader", PassKind::Compute).buffer(garbage, BufferUsage::StorageRead).side_effects().execute(|_| Ok(()));
+        assert!(matches!(plan::plan(&graph.passes, &graph.resources), Err(RenderGraphError::UndefinedRead(..))));
+    }
+
+    #[test]
+    fn readers_see_the_version_declared_before_them()
+    {
+        let (mut graph, output) = create_graph();
+        let scratch = graph.create_image("scratch", color());
+        let history = graph.create_buffer("history", 1024);
+        graph.add_pass("first", PassKind::Graphics).image(scratch, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("use first", PassKind::Graphics)
+            .image(scratch, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        graph.add_pass("second", PassKind::Graphics).image(scratch, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("use second", PassKind::Graphics)
+            .image(scratch, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        assert_eq!(plan.order, [0, 1, 2, 3]);
+
+        // "overwrite" is free to run first but has to wait for the read of the imported contents.
+        let imported = graph.import_buffer("imported", vk::Buffer::null(), 1024);
+        graph.add_pass("read", PassKind::Compute)
+            .buffer(imported, BufferUsage::StorageRead)
+            .buffer(history, BufferUsage::StorageRead)
+            .side_effects()
+            .execute(|_| Ok(()));
+        graph.add_pass("overwrite", PassKind::Compute).buffer(imported, BufferUsage::StorageWrite).side_effects().execute(|_| Ok(()));
+        graph.add_pass("history", PassKind::Compute).buffer(history, BufferUsage::StorageWrite).execute(|_| Ok(()));
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        assert_eq!(plan.order, [0, 1, 2, 3, 6, 4, 5]);
+    }
+
+    #[test]
+    fn transient_memory_is_shared_between_disjoint_lifetimes()
+    {
+        let request = |size, lifetime, image, type_bits| plan::SlotRequest { size, alignment: 256, type_bits, lifetime, image };
+        let (assigned, slots) = plan::assign_slots(&[
+            request(100, (0, 1), true, 0b11),
+            request(200, (2, 3), true, 0b10),
+            request(50, (1, 2), true, 0b11),
+            // Neither the buffer nor the image of another memory type can join the first slot.
+            request(100, (0, 0), false, 0b11),
+            request(100, (4, 4), true, 0b100),
+        ]);
+        assert_eq!(assigned[0], assigned[1]);
+        assert_eq!(slots[assigned[0]].size, 200);
+        assert_eq!(slots[assigned[0]].type_bits, 0b10);
+        assert_ne!(assigned[2], assigned[0]);
+        assert_ne!(assigned[3], assigned[0]);
+        assert_ne!(assigned[4], assigned[0]);
+        assert_eq!(slots.len(), 4);
+    }
+
+    #[test]
+    fn exported_transients_are_not_aliased()
+    {
+        let mut graph = RenderGraph::new();
+        let result = graph.create_buffer("result", 1024);
+        let scratch = graph.create_buffer("scratch", 1024);
+        graph.export_buffer(result, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+        graph.add_pass("result", PassKind::Compute).buffer(result, BufferUsage::StorageWrite).execute(|_| Ok(()));
+        graph.add_pass("scratch", PassKind::Compute).buffer(scratch, BufferUsage::StorageWrite).execute(|_| Ok(()));
+        graph.add_pass("use scratch", PassKind::Compute)
+            .buffer(scratch, BufferUsage::StorageRead)
+            .side_effects()
+            .execute(|_| Ok(()));
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        assert_eq!(plan.lifetimes[result.0], Some((0, 3)));
+        assert_eq!(plan.lifetimes[scratch.0], Some((1, 2)));
+
+        let request = |index: usize| plan::SlotRequest {
+            size: 1024,
+            alignment: 256,
+            type_bits: 1,
+            lifetime: plan.lifetimes[index].unwrap(),
+            image: false,
+        };
+        let (assigned, slots) = plan::assign_slots(&[request(result.0), request(scratch.0)]);
+        assert_ne!(assigned[0], assigned[1]);
+        assert_eq!(slots.len(), 2);
+    }
+
+    #[test]
+    fn barriers_transition_layouts_and_wait_for_aliased_memory()
+    {
+        let (mut graph, output) = create_graph();
+        let first = graph.create_image("first", color());
+        let second = graph.create_image("second", color());
+        graph.add_pass("draw first", PassKind::Graphics).image(first, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("blit first", PassKind::Graphics)
+            .image(first, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        graph.add_pass("draw second", PassKind::Graphics).image(second, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("blit second", PassKind::Graphics)
+            .image(second, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        let mut slots = vec![None; graph.resources.len()];
+        slots[first.0] = Some(0);
+        slots[second.0] = Some(0);
+        let (barriers, final_barriers) = plan::plan_barriers(&graph.passes, &graph.resources, &plan, &slots);
+        let layouts = |op: &plan::BarrierOp| (op.barrier.old_layout, op.barrier.new_layout);
+
+        // The previous frame last sampled `second` from the same memory.
+        assert_eq!(barriers[0].len(), 1);
+        assert_eq!(layouts(&barriers[0][0]), (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL));
+        assert_eq!(barriers[0][0].barrier.src_stage, SHADERS);
+
+        let sampled = barriers[1].iter().find(|op| op.resource == first.0).unwrap();
+        assert_eq!(layouts(sampled), (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+        assert_eq!(sampled.barrier.src_access, vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        let acquired = barriers[1].iter().find(|op| op.resource == output.0).unwrap();
+        assert_eq!(acquired.barrier.src_stage, vk::PipelineStageFlags::ALL_COMMANDS);
+
+        // `second` takes over once `first` was sampled.
+        assert_eq!(barriers[2].len(), 1);
+        assert_eq!(barriers[2][0].resource, second.0);
+        assert_eq!(barriers[2][0].barrier.src_stage, SHADERS);
+        assert_eq!(layouts(&barriers[2][0]).0, vk::ImageLayout::UNDEFINED);
+
+        assert_eq!(final_barriers.len(), 1);
+        assert_eq!(layouts(&final_barriers[0]), (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR));
+        assert_eq!(final_barriers[0].barrier.src_access, vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    }
+
+    #[test]
+    fn reads_in_another_pass_kind_wait_for_the_write_again()
+    {
+        let (mut graph, output) = create_graph();
+        let scene = graph.create_image("scene", color());
+        let histogram = graph.create_buffer("histogram", 1024);
+        graph.add_pass("scene", PassKind::Graphics).image(scene, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("post", PassKind::Graphics)
+            .image(scene, ImageUsage::Sampled)
+            .image(output, ImageUsage::ColorAttachment)
+            .execute(|_| Ok(()));
+        graph.add_pass("histogram", PassKind::Compute)
+            .image(scene, ImageUsage::Sampled)
+            .buffer(histogram, BufferUsage::StorageWrite)
+            .side_effects()
+            .execute(|_| Ok(()));
+        let plan = plan::plan(&graph.passes, &graph.resources).unwrap();
+        assert_eq!(plan.order, [0, 1, 2]);
+        let (barriers, _) = plan::plan_barriers(&graph.passes, &graph.resources, &plan, &vec![None; graph.resources.len()]);
+
+        let sampled = barriers[2].iter().find(|op| op.resource == scene.0).unwrap();
+        assert!(sampled.barrier.src_stage.contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT));
+        assert_eq!(sampled.barrier.src_access, vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(sampled.barrier.dst_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+    }
+
+    #[test]
+    fn dot_shows_passes_resources_and_culling()
+    {
+        let (mut graph, output) = create_graph();
+        let unused = graph.create_image("unused \"debug\" view", color());
+        graph.add_pass("present", PassKind::Graphics).image(output, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        graph.add_pass("debug", PassKind::Graphics).image(unused, ImageUsage::ColorAttachment).execute(|_| Ok(()));
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.contains("pass0 [shape=box, label=\"present\\nGraphics\"];"));
+        assert!(dot.contains("pass1 [shape=box, label=\"debug\\nGraphics\", style=dashed, color=gray];"));
+        assert!(dot.contains("unused \\\"debug\\\" view"));
+        assert!(dot.contains("pass0 -> resource0 [label=\"ColorAttachment\"];"));
+        assert!(dot.contains("peripheries=2"));
+    }
+
+    #[test]
+    fn compiled_graph_runs_on_the_device()
+    {
+        let Some(test) = get_test_device() else { return };
+        let device = test.get_device();
+        let allocator = Allocator::create(device);
+        const COUNT: usize = 256;
+        const SIZE: vk::DeviceSize = COUNT as vk::DeviceSize * 4;
+        let mut readback: Buffer<u32> = Buffer::create(&allocator, COUNT, vk::BufferUsageFlags::TRANSFER_DST, MemoryUsage::Readback).unwrap();
+
+        let mut graph = RenderGraph::new();
+        let scratch = graph.create_buffer("scratch", SIZE);
+        let unused = graph.create_buffer("unused", SIZE);
+        let output = graph.import_buffer("readback", readback.get_handle(), SIZE);
+        graph.export_buffer(output, vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ);
+        graph.add_pass("fill", PassKind::Transfer).buffer(scratch, BufferUsage::TransferDst).execute(|context| {
+            let device = context.get_device().get_device();
+            unsafe { device.cmd_fill_buffer(context.get_command_buffer(), context.get_buffer(scratch), 0, vk::WHOLE_SIZE, 0x1234_5678) };
+            Ok(())
+        });
+        graph.add_pass("unused", PassKind::Transfer).buffer(unused, BufferUsage::TransferDst).execute(|_| panic!("culled passes never run"));
+        graph.add_pass("copy", PassKind::Transfer)
+            .buffer(scratch, BufferUsage::TransferSrc)
+            .buffer(output, BufferUsage::TransferDst)
+            .execute(|context| {
+                let region = vk::BufferCopy { src_offset: 0, dst_offset: 0, size: SIZE };
+                let device = context.get_device().get_device();
+                unsafe { device.cmd_copy_buffer(context.get_command_buffer(), context.get_buffer(scratch), context.get_buffer(output), &[region]) };
+                Ok(())
+            });
+
+        let compiled = graph.compile(&allocator).unwrap();
+        assert_eq!(compiled.get_pass_names(), ["fill", "copy"]);
+        assert_eq!(compiled.get_allocation_count(), 1);
+        let mut context = ComputeContext::create(device).unwrap();
+        context.run(|commands| compiled.execute(commands.get_command_buffer())).unwrap();
+        assert!(readback.map().unwrap().iter().all(|value| *value == 0x1234_5678));
+        test.assert_no_errors();
+    }
+}
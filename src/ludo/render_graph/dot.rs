@@ -0,0 +1,59 @@
+use super::{PassNode, ResourceDesc, ResourceNode};
+use std::fmt::Write;
+
+fn escape(text: &str) -> String
+{
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes passes as boxes and resources as ellipses, with an edge from every resource a
+/// pass reads and to every resource it writes.
+///
+/// Culled passes are dashed and exported resources drawn with a double border; `slots`
+/// holds the memory slot of every transient resource, or is empty before compilation.
+pub fn write(passes: &[PassNode], resources: &[ResourceNode], culled: &[usize], slots: &[Option<usize>]) -> String
+{
+    let mut dot = String::from("digraph render_graph {\n    rankdir=LR;\n");
+    for (index, pass) in passes.iter().enumerate()
+    {
+        let style = if culled.contains(&index) { ", style=dashed, color=gray" } else { "" };
+        writeln!(dot, "    pass{} [shape=box, label=\"{}\\n{:?}\"{}];", index, escape(&pass.name), pass.kind, style).unwrap();
+    }
+    for (index, resource) in resources.iter().enumerate()
+    {
+        let mut label = escape(&resource.name);
+        match resource.desc
+        {
+            ResourceDesc::Image(desc) => write!(label, "\\n{:?} {}x{}", desc.format, desc.extent.width, desc.extent.height),
+            ResourceDesc::Buffer(size) => write!(label, "\\n{} bytes", size),
+        }
+        .unwrap();
+        if resource.imported.is_some()
+        {
+            label.push_str("\\nimported");
+        }
+        if let Some(Some(slot)) = slots.get(index)
+        {
+            write!(label, "\\nmemory slot {}", slot).unwrap();
+        }
+        let style = if resource.export.is_some() { ", peripheries=2" } else { "" };
+        writeln!(dot, "    resource{} [shape=ellipse, label=\"{}\"{}];", index, label, style).unwrap();
+    }
+    for (index, pass) in passes.iter().enumerate()
+    {
+        for access in &pass.accesses
+        {
+            let (from, to) = if access.writes()
+            {
+                (format!("pass{}", index), format!("resource{}", access.resource))
+            }
+            else
+            {
+                (format!("resource{}", access.resource), format!("pass{}", index))
+            };
+            writeln!(dot, "    {} -> {} [label=\"{}\"];", from, to, access.label).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
@@ -0,0 +1,336 @@
+use super::{PassNode, RenderGraphError, ResourceNode};
+use crate::ludo::vulkan::{Barrier, ResourceState};
+use ash::vk;
+use std::collections::HashMap;
+
+/// Passes of a graph in the order they run, and what is left out.
+#[derive(Debug)]
+pub struct Plan
+{
+    /// Indices of the passes that run, in order.
+    pub order: Vec<usize>,
+    /// Indices of the passes nothing needs, in declaration order.
+    pub culled: Vec<usize>,
+    /// First and last position in `order` that uses each resource, if any does; exported
+    /// resources last until `order.len()`, after the final barriers.
+    pub lifetimes: Vec<Option<(usize, usize)>>,
+}
+
+/// Writers of every resource, in declaration order.
+fn get_writers(passes: &[PassNode], resource_count: usize) -> Vec<Vec<usize>>
+{
+    let mut writers = vec![Vec::new(); resource_count];
+    for (index, pass) in passes.iter().enumerate()
+    {
+        for access in pass.accesses.iter().filter(|access| access.writes())
+        {
+            if writers[access.resource].last() != Some(&index)
+            {
+                writers[access.resource].push(index);
+            }
+        }
+    }
+    writers
+}
+
+/// Sorts the passes so that every reader of a resource runs after the writer declared last
+/// before it and before the next one, which keeps the writers in declaration order; a
+/// transient resource read before any writer is declared reads what the first one wrote.
+/// Ties keep the declaration order.
+fn sort(passes: &[PassNode], resources: &[ResourceNode]) -> Result<Vec<usize>, RenderGraphError>
+{
+    let mut successors = vec![Vec::new(); passes.len()];
+    for (resource, node) in resources.iter().enumerate()
+    {
+        // The passes using the resource in declaration order, and whether they write it.
+        let mut uses: Vec<(usize, bool)> = Vec::new();
+        for (index, pass) in passes.iter().enumerate()
+        {
+            for access in pass.accesses.iter().filter(|access| access.resource == resource)
+            {
+                match uses.last_mut()
+                {
+                    Some((last, writes)) if *last == index => *writes |= access.writes(),
+                    _ => uses.push((index, access.writes())),
+                }
+            }
+        }
+        if node.imported.is_none()
+        {
+            if let Some(first_writer) = uses.iter().position(|(_, writes)| *writes)
+            {
+                uses[..=first_writer].rotate_right(1);
+            }
+        }
+
+        let mut writer: Option<usize> = None;
+        let mut readers: Vec<usize> = Vec::new();
+        for (index, writes) in uses
+        {
+            if let Some(writer) = writer
+            {
+                successors[writer].push(index);
+            }
+            if writes
+            {
+                for reader in readers.drain(..)
+                {
+                    successors[reader].push(index);
+                }
+                writer = Some(index);
+            }
+            else
+            {
+                readers.push(index);
+            }
+        }
+    }
+
+    let mut predecessor_counts = vec![0; passes.len()];
+    for successor in successors.iter().flatten()
+    {
+        predecessor_counts[*successor] += 1;
+    }
+    let mut order = Vec::with_capacity(passes.len());
+    let mut done = vec![false; passes.len()];
+    while let Some(next) = (0..passes.len()).find(|index| !done[*index] && predecessor_counts[*index] == 0)
+    {
+        done[next] = true;
+        order.push(next);
+        for successor in &successors[next]
+        {
+            predecessor_counts[*successor] -= 1;
+        }
+    }
+    if order.len() < passes.len()
+    {
+        let names = (0..passes.len()).filter(|index| !done[*index]).map(|index| passes[index].name.clone()).collect();
+        return Err(RenderGraphError::Cycle(names));
+    }
+    Ok(order)
+}
+
+/// Orders the passes, culls those that neither have side effects nor contribute to an
+/// exported resource, and checks that transient resources are written before being read.
+pub fn plan(passes: &[PassNode], resources: &[ResourceNode]) -> Result<Plan, RenderGraphError>
+{
+    let writers = get_writers(passes, resources.len());
+    let order = sort(passes, resources)?;
+    let mut positions = vec![0; passes.len()];
+    for (position, index) in order.iter().enumerate()
+    {
+        positions[*index] = position;
+    }
+
+    // A pass is needed if it writes an output, or anything a needed pass uses before it.
+    let mut needed = vec![false; passes.len()];
+    let mut pending: Vec<usize> = (0..passes.len())
+        .filter(|index| {
+            let pass = &passes[*index];
+            pass.side_effects || pass.accesses.iter().any(|access| access.writes() && resources[access.resource].export.is_some())
+        })
+        .collect();
+    while let Some(index) = pending.pop()
+    {
+        if needed[index]
+        {
+            continue;
+        }
+        needed[index] = true;
+        for access in &passes[index].accesses
+        {
+            pending.extend(writers[access.resource].iter().filter(|writer| positions[**writer] < positions[index]));
+        }
+    }
+
+    let order: Vec<usize> = order.into_iter().filter(|index| needed[*index]).collect();
+    let culled = (0..passes.len()).filter(|index| !needed[*index]).collect();
+    let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; resources.len()];
+    for (position, index) in order.iter().enumerate()
+    {
+        for access in &passes[*index].accesses
+        {
+            let lifetime = &mut lifetimes[access.resource];
+            match lifetime
+            {
+                Some((_, last)) => *last = position,
+                None =>
+                {
+                    let resource = &resources[access.resource];
+                    if resource.imported.is_none() && !access.writes()
+                    {
+                        return Err(RenderGraphError::UndefinedRead(resource.name.clone(), passes[*index].name.clone()));
+                    }
+                    *lifetime = Some((position, position));
+                }
+            }
+        }
+    }
+    // The caller uses exported resources after the graph, so nothing may alias them before.
+    for (lifetime, resource) in lifetimes.iter_mut().zip(resources)
+    {
+        if let (Some((_, last)), Some(_)) = (lifetime.as_mut(), resource.export)
+        {
+            *last = order.len();
+        }
+    }
+    Ok(Plan { order, culled, lifetimes })
+}
+
+/// Memory a transient resource needs, and for which passes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotRequest
+{
+    pub size: vk::DeviceSize,
+    pub alignment: vk::DeviceSize,
+    pub type_bits: u32,
+    /// First and last position in the pass order.
+    pub lifetime: (usize, usize),
+    /// Images and buffers never share a slot.
+    pub image: bool,
+}
+
+/// Memory shared by transient resources whose lifetimes do not overlap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slot
+{
+    pub size: vk::DeviceSize,
+    pub alignment: vk::DeviceSize,
+    pub type_bits: u32,
+    pub image: bool,
+    pub lifetimes: Vec<(usize, usize)>,
+}
+
+/// Packs the requests into as few slots as a greedy pass finds, largest first, and
+/// returns the slot of every request along with the slots.
+pub fn assign_slots(requests: &[SlotRequest]) -> (Vec<usize>, Vec<Slot>)
+{
+    let mut by_size: Vec<usize> = (0..requests.len()).collect();
+    by_size.sort_by_key(|index| std::cmp::Reverse(requests[*index].size));
+    let mut assigned = vec![0; requests.len()];
+    let mut slots: Vec<Slot> = Vec::new();
+    for index in by_size
+    {
+        let request = &requests[index];
+        let (first, last) = request.lifetime;
+        let fits = |slot: &Slot| {
+            slot.image == request.image
+                && slot.type_bits & request.type_bits != 0
+                && slot.lifetimes.iter().all(|(start, end)| last < *start || *end < first)
+        };
+        let slot = match slots.iter().position(fits)
+        {
+            Some(slot) => slot,
+            None =>
+            {
+                slots.push(Slot {
+                    size: 0,
+                    alignment: 1,
+                    type_bits: request.type_bits,
+                    image: request.image,
+                    lifetimes: Vec::new(),
+                });
+                slots.len() - 1
+            }
+        };
+        let slot_data = &mut slots[slot];
+        slot_data.size = slot_data.size.max(request.size);
+        slot_data.alignment = slot_data.alignment.max(request.alignment);
+        slot_data.type_bits &= request.type_bits;
+        slot_data.lifetimes.push(request.lifetime);
+        assigned[index] = slot;
+    }
+    (assigned, slots)
+}
+
+/// A barrier for one resource of the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarrierOp
+{
+    pub resource: usize,
+    pub barrier: Barrier,
+}
+
+/// Barriers to record before every pass of `plan.order`, and after the last one for the
+/// exported resources.
+///
+/// `slots` holds the memory slot of every transient resource. A resource taking over a slot
+/// waits for the accesses of the one before it, and the first one of a frame for the last
+/// one of the previous frame.
+pub fn plan_barriers(
+    passes: &[PassNode],
+    resources: &[ResourceNode],
+    plan: &Plan,
+    slots: &[Option<usize>]) -> (Vec<Vec<BarrierOp>>, Vec<BarrierOp>)
+{
+    let mut occupants: Vec<(usize, usize, usize)> = (0..resources.len())
+        .filter_map(|index| Some((slots[index]?, plan.lifetimes[index]?.0, index)))
+        .collect();
+    occupants.sort();
+    let mut previous = vec![None; resources.len()];
+    let mut last_occupants: HashMap<usize, usize> = HashMap::new();
+    for (slot, _, index) in &occupants
+    {
+        previous[*index] = last_occupants.insert(*slot, *index);
+    }
+
+    let simulate = |wrapped: &[Option<ResourceState>]| {
+        let mut states: Vec<Option<ResourceState>> = vec![None; resources.len()];
+        let initial = |index: usize, states: &[Option<ResourceState>]| {
+            if let Some((_, layout)) = resources[index].imported
+            {
+                return ResourceState::new(vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::empty(), layout);
+            }
+            let mut state = match previous[index]
+            {
+                Some(previous) => states[previous].unwrap(),
+                None => wrapped[index].unwrap_or_else(|| {
+                    ResourceState::new(vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty(), vk::ImageLayout::UNDEFINED)
+                }),
+            };
+            state.discard();
+            state
+        };
+
+        let mut barriers = Vec::with_capacity(plan.order.len());
+        for index in &plan.order
+        {
+            let mut pass_barriers = Vec::new();
+            for access in &passes[*index].accesses
+            {
+                let state = states[access.resource].unwrap_or_else(|| initial(access.resource, &states));
+                let state = states[access.resource].insert(state);
+                if let Some(barrier) = state.transition(access.stage, access.access, access.layout)
+                {
+                    pass_barriers.push(BarrierOp { resource: access.resource, barrier });
+                }
+            }
+            barriers.push(pass_barriers);
+        }
+        let mut final_barriers = Vec::new();
+        for (index, resource) in resources.iter().enumerate()
+        {
+            if let Some(export) = resource.export
+            {
+                let state = states[index].unwrap_or_else(|| initial(index, &states));
+                let state = states[index].insert(state);
+                if let Some(barrier) = state.transition(export.stage, export.access, export.layout)
+                {
+                    final_barriers.push(BarrierOp { resource: index, barrier });
+                }
+            }
+        }
+        (barriers, final_barriers, states)
+    };
+
+    // The first run finds where every slot ends up; the second starts the next frame there.
+    let (_, _, states) = simulate(&vec![None; resources.len()]);
+    let mut wrapped = vec![None; resources.len()];
+    for (slot, last) in &last_occupants
+    {
+        let first = occupants.iter().find(|(occupied, _, _)| occupied == slot).unwrap().2;
+        wrapped[first] = states[*last];
+    }
+    let (barriers, final_barriers, _) = simulate(&wrapped);
+    (barriers, final_barriers)
+}
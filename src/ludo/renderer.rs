@@ -0,0 +1,313 @@
+use crate::ludo::overlay::{OverlayText, TextOverlay};
+use crate::ludo::{shaders, vulkan};
+use ash::vk;
+
+/// Clear color of frame `frame_number`; cycles slowly so a running smoke test is visibly alive.
+pub fn get_clear_color(frame_number: u64) -> [f32; 4]
+{
+    let phase = (frame_number % 360) as f32 * std::f32::consts::PI / 180.0;
+    [
+        0.1 + 0.1 * phase.sin(),
+        0.1 + 0.1 * (phase + 2.0 * std::f32::consts::FRAC_PI_3).sin(),
+        0.2 + 0.1 * (phase + 4.0 * std::f32::consts::FRAC_PI_3).sin(),
+        1.0,
+    ]
+}
+
+/// Why a renderer could not be built; Vulkan failures of any step end up in `Vulkan`.
+#[derive(Debug)]
+pub enum RendererError
+{
+    Shader(vulkan::ShaderError),
+    Pipeline(vulkan::PipelineError),
+    Vulkan(vk::Result),
+}
+impl std::fmt::Display for RendererError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            RendererError::Shader(error) => write!(f, "{}", error),
+            RendererError::Pipeline(error) => write!(f, "{}", error),
+            RendererError::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for RendererError {}
+impl From<vulkan::ShaderError> for RendererError
+{
+    fn from(error: vulkan::ShaderError) -> Self
+    {
+        match error
+        {
+            vulkan::ShaderError::Vulkan(result) => RendererError::Vulkan(result),
+            error => RendererError::Shader(error),
+        }
+    }
+}
+impl From<vulkan::PipelineError> for RendererError
+{
+    fn from(error: vulkan::PipelineError) -> Self
+    {
+        match error
+        {
+            vulkan::PipelineError::Vulkan(result) => RendererError::Vulkan(result),
+            error => RendererError::Pipeline(error),
+        }
+    }
+}
+impl From<vk::Result> for RendererError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        RendererError::Vulkan(result)
+    }
+}
+
+/// What the renderer draws into: framebuffers of a render pass on Vulkan 1.0, or the
+/// images themselves with dynamic rendering on 1.3.
+enum Targets
+{
+    RenderPass
+    {
+        framebuffers: Vec<vulkan::Framebuffer>,
+        render_pass: vulkan::RenderPass,
+    },
+    Dynamic
+    {
+        images: Vec<(vk::Image, vk::ImageView)>,
+        extent: vk::Extent2D,
+    },
+}
+
+/// Built-in smoke test: clears the target and draws the "hello triangle", with the text of
+/// `set_overlay` on top.
+///
+/// Devices with dynamic rendering and synchronization2 draw without a render pass and
+/// transition the target with `vkCmdPipelineBarrier2`. Framebuffers, or their dynamic
+/// counterparts, are rebuilt whenever the swapchain is, the pipeline only when the color
+/// format changes.
+pub struct TriangleRenderer
+{
+    targets: Targets,
+    target_generation: u64,
+    color_format: vk::Format,
+    final_layout: vk::ImageLayout,
+    pipeline: vulkan::GraphicsPipeline,
+    _pipeline_layout: vulkan::PipelineLayout,
+    overlay: TextOverlay,
+    device: vulkan::Device,
+}
+impl TriangleRenderer
+{
+    pub fn create(
+        device: &vulkan::Device,
+        color_format: vk::Format,
+        final_layout: vk::ImageLayout,
+        cache: Option<&vulkan::PipelineCache>) -> Result<TriangleRenderer, RendererError>
+    {
+        let vertex_shader = vulkan::ShaderModule::from_bytes(device, shaders::TRIANGLE_VERT)?;
+        let fragment_shader = vulkan::ShaderModule::from_bytes(device, shaders::TRIANGLE_FRAG)?;
+        vertex_shader.set_name("triangle.vert");
+        fragment_shader.set_name("triangle.frag");
+        let shaders = [&vertex_shader, &fragment_shader];
+        let pipeline_layout = vulkan::PipelineLayout::from_shaders(device, &shaders)?;
+        pipeline_layout.set_name("triangle");
+        let (targets, pipeline, overlay) = if device.is_dynamic_rendering_enabled() && device.is_synchronization2_enabled()
+        {
+            let formats = vulkan::RenderingFormats { color: vec![color_format], depth: None, stencil: None };
+            let pipeline = vulkan::GraphicsPipelineBuilder::new(&pipeline_layout, &shaders)
+                .dynamic_rendering(formats)
+                .build(cache)?;
+            let overlay = TextOverlay::create(device, None, color_format, cache)?;
+            (Targets::Dynamic { images: Vec::new(), extent: vk::Extent2D::default() }, pipeline, overlay)
+        }
+        else
+        {
+            let render_pass = vulkan::RenderPass::create_color(device, color_format, final_layout)?;
+            render_pass.set_name("triangle");
+            let pipeline = vulkan::GraphicsPipelineBuilder::new(&pipeline_layout, &shaders)
+                .render_pass(&render_pass, 0)
+                .build(cache)?;
+            let overlay = TextOverlay::create(device, Some(&render_pass), color_format, cache)?;
+            (Targets::RenderPass { framebuffers: Vec::new(), render_pass }, pipeline, overlay)
+        };
+        pipeline.set_name("triangle");
+        Ok(TriangleRenderer {
+            targets,
+            target_generation: 0,
+            color_format,
+            final_layout,
+            pipeline,
+            _pipeline_layout: pipeline_layout,
+            overlay,
+            device: device.clone(),
+        })
+    }
+
+    pub fn get_color_format(&self) -> vk::Format
+    {
+        self.color_format
+    }
+
+    /// Replaces the text drawn over the triangle; empty text hides the overlay.
+    pub fn set_overlay(&mut self, text: OverlayText)
+    {
+        self.overlay.set_text(text);
+    }
+
+    /// Takes new target images when they changed, as tracked by `generation`.
+    pub fn update_targets(
+        &mut self,
+        images: &[vk::Image],
+        image_views: &[vk::ImageView],
+        extent: vk::Extent2D,
+        generation: u64) -> Result<(), vk::Result>
+    {
+        match &mut self.targets
+        {
+            Targets::RenderPass { framebuffers, render_pass } =>
+            {
+                if generation == self.target_generation && framebuffers.len() == image_views.len()
+                {
+                    return Ok(());
+                }
+                framebuffers.clear();
+                for view in image_views
+                {
+                    let framebuffer = vulkan::Framebuffer::create(&self.device, render_pass, &[*view], extent)?;
+                    if self.device.is_debug_utils_enabled()
+                    {
+                        framebuffer.set_name(&format!("triangle framebuffer {}", framebuffers.len()));
+                    }
+                    framebuffers.push(framebuffer);
+                }
+            }
+            Targets::Dynamic { images: targets, extent: target_extent } =>
+            {
+                *targets = images.iter().copied().zip(image_views.iter().copied()).collect();
+                *target_extent = extent;
+            }
+        }
+        self.target_generation = generation;
+        Ok(())
+    }
+
+    pub fn record(&self, command_buffer: vk::CommandBuffer, image_index: u32, frame_number: u64)
+    {
+        let device = self.device.get_device();
+        let clear_value = vk::ClearValue {
+            color: vk::ClearColorValue { float32: get_clear_color(frame_number) },
+        };
+        self.device.begin_label(command_buffer, "triangle");
+        self.device.insert_label(command_buffer, &format!("frame {}", frame_number));
+        match &self.targets
+        {
+            Targets::RenderPass { framebuffers, render_pass } =>
+            {
+                let framebuffer = &framebuffers[image_index as usize];
+                let render_area = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: framebuffer.get_extent() };
+                let clear_values = [clear_value];
+                let begin_info = vk::RenderPassBeginInfo::builder()
+                    .render_pass(render_pass.get_handle())
+                    .framebuffer(framebuffer.get_handle())
+                    .render_area(render_area)
+                    .clear_values(&clear_values);
+                unsafe { device.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE) };
+                self.draw(command_buffer, render_area);
+                unsafe { device.cmd_end_render_pass(command_buffer) };
+            }
+            Targets::Dynamic { images, extent } =>
+            {
+                let (image, view) = images[image_index as usize];
+                let render_area = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: *extent };
+                // Waits for the acquire semaphore, which is waited on at this stage.
+                self.transition(command_buffer, image,
+                    (vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::NONE),
+                    (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE));
+                let color_attachments = [vk::RenderingAttachmentInfo::builder()
+                    .image_view(view)
+                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .clear_value(clear_value)
+                    .build()];
+                let rendering_info = vk::RenderingInfo::builder()
+                    .render_area(render_area)
+                    .layer_count(1)
+                    .color_attachments(&color_attachments);
+                unsafe { device.cmd_begin_rendering(command_buffer, &rendering_info) };
+                self.draw(command_buffer, render_area);
+                unsafe { device.cmd_end_rendering(command_buffer) };
+                // Later users of the image, be it presentation or a readback, chain onto ALL_COMMANDS.
+                self.transition(command_buffer, image,
+                    (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE),
+                    (self.final_layout, vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::NONE));
+            }
+        }
+        self.device.end_label(command_buffer);
+    }
+
+    fn draw(&self, command_buffer: vk::CommandBuffer, render_area: vk::Rect2D)
+    {
+        let device = self.device.get_device();
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: render_area.extent.width as f32,
+            height: render_area.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.get_handle());
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+        self.overlay.record(command_buffer, render_area);
+    }
+
+    /// Records a synchronization2 layout transition of a color image; both ends are given
+    /// as layout, stages and accesses.
+    fn transition(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        src: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2))
+    {
+        let image_barriers = [vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(src.1)
+            .src_access_mask(src.2)
+            .dst_stage_mask(dst.1)
+            .dst_access_mask(dst.2)
+            .old_layout(src.0)
+            .new_layout(dst.0)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build()];
+        let dependency_info = vk::DependencyInfo::builder().image_memory_barriers(&image_barriers);
+        unsafe { self.device.get_device().cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+    }
+}
+impl Drop for TriangleRenderer
+{
+    fn drop(&mut self)
+    {
+        let _ = self.device.wait_idle();
+        if let Targets::RenderPass { framebuffers, .. } = &mut self.targets
+        {
+            framebuffers.clear();
+        }
+    }
+}
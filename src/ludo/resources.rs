@@ -0,0 +1,27 @@
+use crate::ludo::vulkan;
+use ash::vk;
+
+/// What `DeviceResources::create` builds on.
+// Read by embedders; the demo renderer adds no resources.
+#[allow(dead_code)]
+pub struct ResourceContext<'a>
+{
+    pub device: &'a vulkan::Device,
+    pub allocator: &'a vulkan::Allocator,
+    /// `None` when bindless resources are off or the device does not support them.
+    pub bindless: Option<&'a mut vulkan::BindlessTable>,
+}
+
+/// Buffers, textures, samplers and bindless registrations of an embedder, kept as CPU-side
+/// descriptions so that `Ludo` can build them again after a lost device.
+///
+/// `create` runs once the device exists and again after every loss, `release` before the
+/// device goes away; nothing created from the old device may outlive `release`, or the lost
+/// `VkDevice` stays alive with it.
+pub trait DeviceResources
+{
+    fn create(&mut self, context: &mut ResourceContext) -> Result<(), vk::Result>;
+
+    /// Drops everything `create` made; bindless registrations are dropped with the table.
+    fn release(&mut self);
+}
@@ -0,0 +1,150 @@
+use sdl2_sys::{SDL_EventType, SDL_WindowEventID};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent
+{
+    Shown,
+    Hidden,
+    Exposed,
+    Moved { x: i32, y: i32 },
+    Resized { width: i32, height: i32 },
+    Minimized,
+    Maximized,
+    Restored,
+    FocusGained,
+    FocusLost,
+    CloseRequested,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event
+{
+    Quit,
+    Window { window_id: u32, event: WindowEvent },
+    KeyDown { window_id: u32, scancode: u32, keycode: i32, modifiers: u16, repeat: bool },
+    KeyUp { window_id: u32, scancode: u32, keycode: i32, modifiers: u16 },
+    TextInput { window_id: u32, text: String },
+    MouseMotion { window_id: u32, state: u32, x: i32, y: i32, xrel: i32, yrel: i32 },
+    MouseButtonDown { window_id: u32, button: u8, clicks: u8, x: i32, y: i32 },
+    MouseButtonUp { window_id: u32, button: u8, clicks: u8, x: i32, y: i32 },
+    MouseWheel { window_id: u32, x: i32, y: i32, flipped: bool },
+    ControllerAxisMotion { which: i32, axis: u8, value: i16 },
+    ControllerButtonDown { which: i32, button: u8 },
+    ControllerButtonUp { which: i32, button: u8 },
+    ControllerAdded { device_index: i32 },
+    ControllerRemoved { which: i32 },
+    Unknown(u32),
+}
+
+impl Event
+{
+    /// Converts a raw event filled in by `SDL_PollEvent` or `SDL_WaitEventTimeout`.
+    pub(crate) fn from_sdl(raw: &sdl2_sys::SDL_Event) -> Event
+    {
+        // SAFETY: SDL always writes `type_` and the union member that matches it.
+        let event_type = unsafe { raw.type_ };
+        unsafe
+        {
+            match event_type
+            {
+                t if t == SDL_EventType::SDL_QUIT as u32 => Event::Quit,
+                t if t == SDL_EventType::SDL_WINDOWEVENT as u32 => Event::Window {
+                    window_id: raw.window.windowID,
+                    event: Event::window_event(&raw.window),
+                },
+                t if t == SDL_EventType::SDL_KEYDOWN as u32 => Event::KeyDown {
+                    window_id: raw.key.windowID,
+                    scancode: raw.key.keysym.scancode as u32,
+                    keycode: raw.key.keysym.sym,
+                    modifiers: raw.key.keysym.mod_,
+                    repeat: raw.key.repeat != 0,
+                },
+                t if t == SDL_EventType::SDL_KEYUP as u32 => Event::KeyUp {
+                    window_id: raw.key.windowID,
+                    scancode: raw.key.keysym.scancode as u32,
+                    keycode: raw.key.keysym.sym,
+                    modifiers: raw.key.keysym.mod_,
+                },
+                t if t == SDL_EventType::SDL_TEXTINPUT as u32 => Event::TextInput {
+                    window_id: raw.text.windowID,
+                    text: std::ffi::CStr::from_ptr(raw.text.text.as_ptr()).to_string_lossy().into_owned(),
+                },
+                t if t == SDL_EventType::SDL_MOUSEMOTION as u32 => Event::MouseMotion {
+                    window_id: raw.motion.windowID,
+                    state: raw.motion.state,
+                    x: raw.motion.x,
+                    y: raw.motion.y,
+                    xrel: raw.motion.xrel,
+                    yrel: raw.motion.yrel,
+                },
+                t if t == SDL_EventType::SDL_MOUSEBUTTONDOWN as u32 => Event::MouseButtonDown {
+                    window_id: raw.button.windowID,
+                    button: raw.button.button,
+                    clicks: raw.button.clicks,
+                    x: raw.button.x,
+                    y: raw.button.y,
+                },
+                t if t == SDL_EventType::SDL_MOUSEBUTTONUP as u32 => Event::MouseButtonUp {
+                    window_id: raw.button.windowID,
+                    button: raw.button.button,
+                    clicks: raw.button.clicks,
+                    x: raw.button.x,
+                    y: raw.button.y,
+                },
+                t if t == SDL_EventType::SDL_MOUSEWHEEL as u32 => Event::MouseWheel {
+                    window_id: raw.wheel.windowID,
+                    x: raw.wheel.x,
+                    y: raw.wheel.y,
+                    flipped: raw.wheel.direction
+                        == sdl2_sys::SDL_MouseWheelDirection::SDL_MOUSEWHEEL_FLIPPED as u32,
+                },
+                t if t == SDL_EventType::SDL_CONTROLLERAXISMOTION as u32 => Event::ControllerAxisMotion {
+                    which: raw.caxis.which,
+                    axis: raw.caxis.axis,
+                    value: raw.caxis.value,
+                },
+                t if t == SDL_EventType::SDL_CONTROLLERBUTTONDOWN as u32 => Event::ControllerButtonDown {
+                    which: raw.cbutton.which,
+                    button: raw.cbutton.button,
+                },
+                t if t == SDL_EventType::SDL_CONTROLLERBUTTONUP as u32 => Event::ControllerButtonUp {
+                    which: raw.cbutton.which,
+                    button: raw.cbutton.button,
+                },
+                // For added devices `which` is the joystick device index, for removed ones
+                // it is the instance id.
+                t if t == SDL_EventType::SDL_CONTROLLERDEVICEADDED as u32 => Event::ControllerAdded {
+                    device_index: raw.cdevice.which,
+                },
+                t if t == SDL_EventType::SDL_CONTROLLERDEVICEREMOVED as u32 => Event::ControllerRemoved {
+                    which: raw.cdevice.which,
+                },
+                t => Event::Unknown(t),
+            }
+        }
+    }
+
+    fn window_event(raw: &sdl2_sys::SDL_WindowEvent) -> WindowEvent
+    {
+        match raw.event
+        {
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_SHOWN as u8 => WindowEvent::Shown,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_HIDDEN as u8 => WindowEvent::Hidden,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_EXPOSED as u8 => WindowEvent::Exposed,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_MOVED as u8 =>
+                WindowEvent::Moved { x: raw.data1, y: raw.data2 },
+            // SIZE_CHANGED is sent for every size change, RESIZED only for external ones,
+            // so only the former is reported to avoid handling a resize twice.
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_SIZE_CHANGED as u8 =>
+                WindowEvent::Resized { width: raw.data1, height: raw.data2 },
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_MINIMIZED as u8 => WindowEvent::Minimized,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_MAXIMIZED as u8 => WindowEvent::Maximized,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_RESTORED as u8 => WindowEvent::Restored,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_FOCUS_GAINED as u8 => WindowEvent::FocusGained,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_FOCUS_LOST as u8 => WindowEvent::FocusLost,
+            e if e == SDL_WindowEventID::SDL_WINDOWEVENT_CLOSE as u8 => WindowEvent::CloseRequested,
+            e => WindowEvent::Other(e),
+        }
+    }
+}
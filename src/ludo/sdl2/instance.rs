@@ -1,21 +1,42 @@
 use crate::rc_string::RCString;
+use crate::ludo::sdl2::Event;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub struct Instance 
+static SDL_INITED: AtomicBool = AtomicBool::new(false);
+
+struct Context
 {
-    sdl_inited: bool,
-    vulkan_library_loaded: bool,
+    vulkan_library_loaded: Cell<bool>,
+    controllers: RefCell<Vec<*mut sdl2_sys::SDL_GameController>>,
 }
-impl Default for Instance 
+impl Drop for Context
 {
-    fn default() -> Self
+    fn drop(&mut self)
     {
-        Instance 
+        for controller in self.controllers.borrow_mut().drain(..)
+        {
+            unsafe { sdl2_sys::SDL_GameControllerClose(controller) };
+        }
+        if self.vulkan_library_loaded.get()
         {
-            sdl_inited: false,
-            vulkan_library_loaded: false,
+            unsafe { sdl2_sys::SDL_Vulkan_UnloadLibrary() };
         }
+        unsafe { sdl2_sys::SDL_Quit() };
+        SDL_INITED.store(false, Ordering::SeqCst);
     }
 }
+
+/// Handle to the initialized SDL library.
+///
+/// Every object created from SDL keeps a clone of this handle, so
+/// `SDL_Vulkan_UnloadLibrary` and `SDL_Quit` only run after the last of them is dropped.
+#[derive(Clone)]
+pub struct Instance
+{
+    context: Rc<Context>,
+}
 impl Instance
 {
     pub fn get_error() -> String
@@ -24,37 +45,45 @@ impl Instance
         let c_str = unsafe { std::ffi::CStr::from_ptr(c_buf) };
         c_str.to_str().unwrap().to_owned()
     }
-    pub fn init(&mut self, flags: u32) -> Result<(),(i32, String)>
+    pub fn init(flags: u32) -> Result<Instance, (i32, String)>
     {
-        if self.sdl_inited
+        if SDL_INITED.swap(true, Ordering::SeqCst)
         {
             panic!("SDL2 re-initialization attempt");
         }
-        let result = unsafe 
+        let result = unsafe
         {
             sdl2_sys::SDL_Init(flags)
         };
-        if result != 0 
+        if result != 0
         {
             let error = Instance::get_error();
+            SDL_INITED.store(false, Ordering::SeqCst);
             Err((result, error))
         }
-        else 
+        else
         {
-            self.sdl_inited = true;
-            Ok(())
+            let context = Context {
+                vulkan_library_loaded: Cell::new(false),
+                controllers: RefCell::new(Vec::new()),
+            };
+            Ok(Instance { context: Rc::new(context) })
         }
     }
-    pub fn load_vulkan(&mut self, path: Option<&str>) -> Result<(), (i32, String)>
+    pub fn load_vulkan(&self, path: Option<&str>) -> Result<(), (i32, String)>
     {
-        let result = if path.is_none()
+        if self.context.vulkan_library_loaded.get()
         {
-            unsafe { sdl2_sys::SDL_Vulkan_LoadLibrary(std::ptr::null_mut()) }
+            return Ok(());
+        }
+        let result = if let Some(path) = path
+        {
+            let c_path = RCString::from_rstr(path);
+            unsafe { sdl2_sys::SDL_Vulkan_LoadLibrary(c_path.get_cstr().as_ptr()) }
         }
         else
         {
-            let c_path = RCString::from_rstr(path.unwrap()).get_cstr().as_ptr();
-            unsafe { sdl2_sys::SDL_Vulkan_LoadLibrary(c_path) }
+            unsafe { sdl2_sys::SDL_Vulkan_LoadLibrary(std::ptr::null_mut()) }
         };
         if result != 0
         {
@@ -63,52 +92,70 @@ impl Instance
         }
         else
         {
-            self.vulkan_library_loaded = true;
+            self.context.vulkan_library_loaded.set(true);
             Ok(())
         }
     }
-//     pub fn release(&mut self) -> Result<(), String>
-//     {
-//         let mut released = false;
-//         extern 
-//         {
-//             pub fn SDL_Quit();
-//             pub fn SDL_Vulkan_UnloadLibrary();
-//         }
-//         if self.vulkan_library_loaded
-//         {
-//             unsafe { SDL_Vulkan_UnloadLibrary() };
-//             self.vulkan_library_loaded = false;
-//             released = true;
-//         }
-//         if self.sdl_inited
-//         {
-//             unsafe { SDL_Quit() };
-//             self.sdl_inited = false;
-//             released = true;
-//         }
-//         if !released
-//         {
-//             return Ok(())
-//         }
-//         let error = Instance::get_error();
-//         if error.is_empty()
-//         {
-//             Ok(())
-//         }
-//         else 
-//         {
-//             Err(error)
-//         }
-//     }
+    /// Returns an iterator over the pending events; it ends when the queue is empty.
+    pub fn poll_events(&self) -> EventIterator<'_>
+    {
+        EventIterator { instance: self }
+    }
+    /// Waits up to `timeout_ms` milliseconds for the next event.
+    pub fn wait_event_timeout(&self, timeout_ms: i32) -> Option<Event>
+    {
+        let mut raw = std::mem::MaybeUninit::<sdl2_sys::SDL_Event>::uninit();
+        let result = unsafe { sdl2_sys::SDL_WaitEventTimeout(raw.as_mut_ptr(), timeout_ms) };
+        if result == 0
+        {
+            return None;
+        }
+        Some(self.translate_event(unsafe { raw.assume_init_ref() }))
+    }
+    fn translate_event(&self, raw: &sdl2_sys::SDL_Event) -> Event
+    {
+        let event = Event::from_sdl(raw);
+        // Controllers only report input after they have been opened.
+        match event
+        {
+            Event::ControllerAdded { device_index } =>
+            {
+                let controller = unsafe { sdl2_sys::SDL_GameControllerOpen(device_index) };
+                if !controller.is_null()
+                {
+                    self.context.controllers.borrow_mut().push(controller);
+                }
+            }
+            Event::ControllerRemoved { which } =>
+            {
+                let controller = unsafe { sdl2_sys::SDL_GameControllerFromInstanceID(which) };
+                let mut controllers = self.context.controllers.borrow_mut();
+                if let Some(index) = controllers.iter().position(|c| *c == controller)
+                {
+                    unsafe { sdl2_sys::SDL_GameControllerClose(controllers.swap_remove(index)) };
+                }
+            }
+            _ => {}
+        }
+        event
+    }
+}
+
+pub struct EventIterator<'a>
+{
+    instance: &'a Instance,
+}
+impl Iterator for EventIterator<'_>
+{
+    type Item = Event;
+    fn next(&mut self) -> Option<Event>
+    {
+        let mut raw = std::mem::MaybeUninit::<sdl2_sys::SDL_Event>::uninit();
+        let result = unsafe { sdl2_sys::SDL_PollEvent(raw.as_mut_ptr()) };
+        if result == 0
+        {
+            return None;
+        }
+        Some(self.instance.translate_event(unsafe { raw.assume_init_ref() }))
+    }
 }
-// impl Drop for Instance {
-//     fn drop(&mut self)
-//     {
-//         let result = self.release();
-//         if result.is_err()
-//         {
-//             println!("SDL cleaned up with errors: {}", result.unwrap_err());
-//         }
-//     }
-// }
\ No newline at end of file
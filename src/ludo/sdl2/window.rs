@@ -1,119 +1,115 @@
 use crate::rc_string::RCString;
-// use crate::sdl2::Instance;
+use crate::ludo::sdl2::Instance;
+use crate::ludo::vulkan;
+use ash::vk;
+use ash::vk::Handle;
+use std::rc::Rc;
 
-// pub const SDL_WINDOW_VULKAN: u32 = 0x10000000;
-// pub const SDL_WINDOW_SHOWN: u32 = 0x00000004;
-
-pub struct Window
+struct WindowHandle
 {
-    title: RCString,
     p_window : *mut sdl2_sys::SDL_Window,
+    // Keeps SDL initialized until this window is destroyed.
+    _instance: Instance,
 }
-impl Default for Window
-{
-    fn default() -> Window
+impl Drop for WindowHandle {
+    fn drop(&mut self)
     {
-        Window { 
-            title: RCString::from_rstr(""),
-            p_window: std::ptr::null_mut() as *mut sdl2_sys::SDL_Window,
-        }
+        unsafe { sdl2_sys::SDL_DestroyWindow(self.p_window) };
+        self.p_window = std::ptr::null_mut();
     }
 }
+
+/// Handle to an SDL window created with `SDL_WINDOW_VULKAN`.
+///
+/// Surfaces created from the window keep a clone of this handle, so the window outlives them.
+#[derive(Clone)]
+pub struct Window
+{
+    handle: Rc<WindowHandle>,
+}
 impl Window
 {
-    pub fn create_window(title: &str, width : i32, height : i32) -> Result<Window, ()>
+    pub fn create_window(instance: &Instance, title: &str, width : i32, height : i32) -> Result<Window, String>
     {
-        let mut window = Window::default();
-        window.title = RCString::from_rstr(title);
-        // extern 
-        // {
-        //     fn SDL_CreateWindow(
-        //         title: *const libc::c_char, 
-        //         x : libc::c_int, 
-        //         y : libc::c_int, 
-        //         w : libc::c_int, 
-        //         h : libc::c_int, 
-        //         flags : libc::c_uint) -> *const libc::c_void;
-        // }   
-        window.p_window = unsafe { 
+        let title = RCString::from_rstr(title);
+        let p_window = unsafe {
             sdl2_sys::SDL_CreateWindow(
-                window.title.get_cstr().as_ptr(),
+                title.get_cstr().as_ptr(),
                 sdl2_sys::SDL_WINDOWPOS_UNDEFINED_MASK as libc::c_int,
-                sdl2_sys::SDL_WINDOWPOS_UNDEFINED_MASK as libc::c_int, 
-                width, 
-                height, 
-                sdl2_sys::SDL_WindowFlags::SDL_WINDOW_VULKAN as u32 
-                | sdl2_sys::SDL_WindowFlags::SDL_WINDOW_SHOWN as u32)
+                sdl2_sys::SDL_WINDOWPOS_UNDEFINED_MASK as libc::c_int,
+                width,
+                height,
+                sdl2_sys::SDL_WindowFlags::SDL_WINDOW_VULKAN as u32
+                | sdl2_sys::SDL_WindowFlags::SDL_WINDOW_SHOWN as u32
+                | sdl2_sys::SDL_WindowFlags::SDL_WINDOW_RESIZABLE as u32)
         };
-        if window.p_window.is_null()
+        if p_window.is_null()
+        {
+            Err(Instance::get_error())
+        }
+        else
         {
-            Err(())
-        } 
-        else 
+            let handle = WindowHandle { p_window, _instance: instance.clone() };
+            Ok(Window { handle: Rc::new(handle) })
+        }
+    }
+    /// Creates a `VkSurfaceKHR` for this window. The instance has to be created with the
+    /// extensions returned by `SDL_Vulkan_GetInstanceExtensions`.
+    pub fn create_surface(&self, instance: &vulkan::Instance) -> Result<vulkan::Surface, String>
+    {
+        let mut surface : sdl2_sys::VkSurfaceKHR = 0;
+        let result = unsafe {
+            sdl2_sys::SDL_Vulkan_CreateSurface(
+                self.handle.p_window,
+                instance.get_instance().handle().as_raw() as sdl2_sys::VkInstance,
+                &mut surface)
+        };
+        if result != sdl2_sys::SDL_bool::SDL_TRUE
+        {
+            return Err(Instance::get_error());
+        }
+        Ok(vulkan::Surface::from_raw(instance, vk::SurfaceKHR::from_raw(surface), self))
+    }
+    /// Replaces the text of the title bar.
+    pub fn set_title(&self, title: &str)
+    {
+        let title = RCString::from_rstr(title);
+        unsafe { sdl2_sys::SDL_SetWindowTitle(self.handle.p_window, title.get_cstr().as_ptr()) };
+    }
+    /// Returns the size of the drawable area in pixels, which differs from the window size
+    /// on high-DPI displays. This is the extent the swapchain has to use.
+    pub fn get_drawable_size(&self) -> (u32, u32)
+    {
+        let mut width : libc::c_int = 0;
+        let mut height : libc::c_int = 0;
+        unsafe { sdl2_sys::SDL_Vulkan_GetDrawableSize(self.handle.p_window, &mut width, &mut height) };
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+    /// Instance extensions SDL needs to create a surface for this window.
+    pub fn get_vulkan_extensions(&self) -> Result<Vec<RCString>, String>
+    {
+        let mut count : libc::c_uint = 0;
+        let result = unsafe {
+            sdl2_sys::SDL_Vulkan_GetInstanceExtensions(self.handle.p_window, &mut count, std::ptr::null_mut())
+        };
+        if result != sdl2_sys::SDL_bool::SDL_TRUE
+        {
+            return Err(Instance::get_error());
+        }
+        let mut names : Vec<*const libc::c_char> = vec![std::ptr::null(); count as usize];
+        let result = unsafe {
+            sdl2_sys::SDL_Vulkan_GetInstanceExtensions(self.handle.p_window, &mut count, names.as_mut_ptr())
+        };
+        if result != sdl2_sys::SDL_bool::SDL_TRUE
+        {
+            return Err(Instance::get_error());
+        }
+        let mut extensions : Vec<RCString> = Vec::with_capacity(count as usize);
+        for p_char in names.into_iter().take(count as usize)
         {
-            Ok(window)
+            let c_str = unsafe { std::ffi::CStr::from_ptr(p_char) };
+            extensions.push(RCString::from_cstr(c_str));
         }
+        Ok(extensions)
     }
-//     pub fn get_vulkan_extensions(&mut self) -> Result<Vec<RCString>, ()>
-//     {
-//         let mut count : u32 = 0;
-//         extern 
-//         {
-//             fn SDL_Vulkan_GetInstanceExtensions(
-//                 window: *const libc::c_void, 
-//                 pCount: *mut libc::c_uint, 
-//                 pNames: *mut *const libc::c_char) -> libc::c_uint;
-//         }
-//         let result = unsafe { 
-//             SDL_Vulkan_GetInstanceExtensions(self.p_window, &mut count, std::ptr::null_mut()) == 1 
-//         };
-//         if !result
-//         {
-//             return Err(());
-//         }
-//         let mut names : Vec<*const libc::c_char> = Vec::new();
-//         names.resize(count as usize, std::ptr::null());
-//         let result = unsafe { 
-//             SDL_Vulkan_GetInstanceExtensions(self.p_window, &mut count, names.as_mut_ptr()) == 1 
-//         };
-//         if !result
-//         {
-//             return Err(());
-//         }
-//         let mut extentions : Vec<RCString> = Vec::with_capacity(count as usize);
-//         for p_char in names 
-//         {
-//             let c_str = unsafe { std::ffi::CStr::from_ptr(p_char) };
-//             extentions.push(RCString::from_cstr( c_str ));
-//         }
-//         Ok(extentions)
-//     }
-//     pub fn destroy(&mut self) -> Result<(), String>
-//     {
-//         extern 
-//         {
-//             fn SDL_DestroyWindow(window: *const libc::c_void);
-//         }
-//         unsafe { SDL_DestroyWindow(self.p_window) };
-//         let result = Instance::get_error();
-//         if !result.is_empty()
-//         {
-//             Err(result)
-//         }
-//         else 
-//         {
-//             self.p_window = std::ptr::null();
-//             Ok(())
-//         }
-//     }
 }
-// impl Drop for Window {
-//     fn drop(&mut self)
-//     {
-//         let result = self.destroy();
-//         if result.is_err()
-//         {
-//             println!("SDL window drop error: {}", result.unwrap_err());
-//         }
-//     }
-// }
\ No newline at end of file
@@ -0,0 +1,5 @@
+//! SPIR-V of every shader under `shaders/`, compiled by `build.rs`.
+// Not every build uses every shader.
+#![allow(dead_code)]
+
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
@@ -1,168 +1,92 @@
 mod instance;
 pub use instance::*;
+mod surface;
+pub use surface::*;
 extern crate ash;
-// mod extensions;
-// pub use extensions::*;
+mod extensions;
+pub use extensions::*;
+mod debug;
+pub use debug::*;
+mod features;
+pub use features::*;
+mod physical_device;
+pub use physical_device::*;
+mod device_selection;
+pub use device_selection::*;
+mod device;
+pub use device::*;
+mod swapchain;
+pub use swapchain::*;
+mod sync;
+pub use sync::*;
+mod command_pool;
+pub use command_pool::*;
+mod render_pass;
+pub use render_pass::*;
+mod spirv;
+pub use spirv::*;
+mod shader_module;
+pub use shader_module::*;
+mod pipeline_layout;
+pub use pipeline_layout::*;
+mod descriptor_allocator;
+#[allow(unused_imports)] // Library API the demo renderer does not use yet.
+pub use descriptor_allocator::*;
+mod bindless;
+pub use bindless::*;
+mod pipeline_cache;
+pub use pipeline_cache::*;
+mod graphics_pipeline;
+pub use graphics_pipeline::*;
+mod barrier;
+pub use barrier::*;
+mod compute_pipeline;
+pub use compute_pipeline::*;
+mod compute;
+#[allow(unused_imports)] // Library API the demo renderer does not use yet.
+pub use compute::*;
+mod allocator;
+pub use allocator::*;
+mod buffer;
+pub use buffer::*;
+mod upload;
+pub use upload::*;
+mod texture_formats;
+pub use texture_formats::*;
+mod sampler;
+#[allow(unused_imports)] // Library API the demo renderer does not use yet.
+pub use sampler::*;
+mod texture;
+#[allow(unused_imports)] // Library API the demo renderer does not use yet.
+pub use texture::*;
+mod frames;
+pub use frames::*;
+mod offscreen;
+pub use offscreen::*;
+mod profiler;
+pub use profiler::*;
 // use crate::rc_string::RCString;
 
 
-// const VK_SUCCESS: i32 = 0;
+pub fn make_version(major: u32, minor: u32, patch: u32) -> u32
+{
+    (major << 22) | (minor << 12) | (patch)
+}
 
-// #[derive(Clone)]
-// pub struct VkPhysicalDevice 
-// {
-//     p_physical_device : *const libc::c_void,
-// }
-// impl Default for VkPhysicalDevice {
-//     fn default() -> Self 
-//     { 
-//         VkPhysicalDevice { 
-//             p_physical_device: std::ptr::null(),
-//         }
-//     }
-// }
+#[allow(dead_code)]
+pub fn make_api_version(variant: u32, major: u32, minor: u32, patch: u32) -> u32
+{
+    (variant << 29) | (major << 22) | (minor << 12) | (patch)
+}
 
-// pub fn make_version(major: u32, minor: u32, patch: u32) -> u32
-// {
-//     (major << 22) | (minor << 12) | (patch)
-// }
-
-// pub fn make_api_version(variant: u32, major: u32, minor: u32, patch: u32) -> u32
-// {
-//     (variant << 29) | (major << 22) | (minor << 12) | (patch)
-// }
-
-// #[allow(dead_code)]
-// pub fn api_version_to_string(version: u32) -> String
-// {
-//     let variant = version >> 29;
-//     let major = (version >> 22) & 0x7F;
-//     let minor = (version >> 12) & 0x3FF;
-//     let patch = version & 0xFFF;
-//     return variant.to_string() + "." +
-//         major.to_string().as_ref() + "." +
-//         minor.to_string().as_ref() + "." +
-//         patch.to_string().as_ref();
-// }
-
-// pub struct VkLayerProperties
-// {
-//     pub layer_name : RCString,
-//     pub spec_version : u32,
-//     pub implementation_version : u32,
-//     pub description : RCString,
-// }
-
-// #[allow(non_snake_case)]
-// pub fn vkEnumerateInstanceLayerProperties() -> Result<Vec<VkLayerProperties>, i32>
-// {
-//     const VK_MAX_EXTENSION_NAME_SIZE: usize = 256;
-//     const VK_MAX_DESCRIPTION_SIZE: usize = 256;
-//     #[allow(non_snake_case)]
-//     #[derive(Clone)]
-//     #[repr(C)]
-//     struct sys_VkLayerProperties
-//     {
-//         layerName : [libc::c_char; VK_MAX_EXTENSION_NAME_SIZE],
-//         specVersion : libc::c_uint,
-//         implementationVersion : libc::c_uint,
-//         description : [libc::c_char; VK_MAX_DESCRIPTION_SIZE],
-//     }
-//     impl sys_VkLayerProperties
-//     {
-//         fn new() -> sys_VkLayerProperties
-//         {
-//             sys_VkLayerProperties { 
-//                 layerName: [0; VK_MAX_EXTENSION_NAME_SIZE], 
-//                 specVersion: 0,
-//                 implementationVersion: 0,
-//                 description: [0; VK_MAX_DESCRIPTION_SIZE]
-//             }
-//         }
-//         fn to_safe(&self) -> VkLayerProperties
-//         {
-//             let c_layer_name = unsafe {
-//                 std::ffi::CStr::from_ptr(self.layerName.as_ptr())
-//             };
-//             let layer_name = RCString::from_cstr(c_layer_name);
-//             let c_desription = unsafe {
-//                 std::ffi::CStr::from_ptr(self.description.as_ptr())
-//             };
-//             let description = RCString::from_cstr(c_desription);
-//             VkLayerProperties { 
-//                 layer_name, 
-//                 spec_version: self.specVersion, 
-//                 implementation_version: self.implementationVersion, 
-//                 description }
-//         }
-//     }
-//     extern {
-//         fn vkEnumerateInstanceLayerProperties(
-//             pPropertyCount: *mut libc::c_uint, 
-//             pProperties: *mut sys_VkLayerProperties) -> libc::c_int;
-//     }
-//     let mut property_count: u32 = 0;
-//     let result = unsafe {
-//         vkEnumerateInstanceLayerProperties(
-//             &mut property_count, std::ptr::null_mut())
-//     };
-//     if result != VK_SUCCESS
-//     {
-//         return Err(result);
-//     }
-//     let mut layer_properties_vector : Vec<sys_VkLayerProperties> = Vec::new();
-//     layer_properties_vector.resize(property_count as usize, sys_VkLayerProperties::new());
-//     let result = unsafe {
-//         vkEnumerateInstanceLayerProperties(
-//             &mut property_count, layer_properties_vector.as_mut_ptr())
-//     };
-//     if result != VK_SUCCESS
-//     {
-//         return Err(result);
-//     }
-//     let mut result : Vec<VkLayerProperties> = Vec::new();
-//     for layer_properties in layer_properties_vector
-//     {
-//         result.push(layer_properties.to_safe());
-//     }
-//     Ok(result)
-// }
-
-// #[allow(non_snake_case)]
-// pub fn vkEnumeratePhysicalDevices(instance: &VkInstance) -> Result<Vec<VkPhysicalDevice>, i32>
-// {
-//     extern {
-//         fn vkEnumeratePhysicalDevices(
-//             instance: *const libc::c_void, 
-//             pPhysicalDeviceCount: *mut libc::c_uint,
-//             pPhysicalDevices: *mut *const libc::c_void) -> libc::c_int;
-//     }
-//     let mut device_count: u32 = 0;
-//     let result = unsafe {
-//         vkEnumeratePhysicalDevices(
-//             instance.p_instance, 
-//             &mut device_count, 
-//             std::ptr::null_mut())
-//     };
-//     if result != VK_SUCCESS
-//     {
-//         return Err(result);
-//     }
-//     if device_count == 0
-//     {
-//         return Ok(Vec::new());
-//     }
-//     let mut phisical_devices: Vec<VkPhysicalDevice> = Vec::new();
-//     phisical_devices.resize(device_count as usize, VkPhysicalDevice::default());
-//     let result = unsafe {
-//         vkEnumeratePhysicalDevices(
-//             instance.p_instance, 
-//             &mut device_count, 
-//             &mut phisical_devices[0].p_physical_device)
-//     };
-//     if result != VK_SUCCESS
-//     {
-//         return Err(result);
-//     }
-//     Ok(phisical_devices)
-// }
\ No newline at end of file
+pub fn api_version_to_string(version: u32) -> String
+{
+    let variant = version >> 29;
+    let major = (version >> 22) & 0x7F;
+    let minor = (version >> 12) & 0x3FF;
+    let patch = version & 0xFFF;
+    variant.to_string() + "." +
+        major.to_string().as_ref() + "." +
+        minor.to_string().as_ref() + "." +
+        patch.to_string().as_ref()
+}
@@ -0,0 +1,644 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Size of the `VkDeviceMemory` blocks allocations are carved from.
+pub const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Images at least this large get their own `VkDeviceMemory`.
+pub const DEDICATED_IMAGE_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+
+/// What the memory is used for; decides the memory type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryUsage
+{
+    /// Only accessed by the GPU; device-local.
+    GpuOnly,
+    /// Written by the CPU and read by the GPU, e.g. staging and uniform buffers.
+    Upload,
+    /// Written by the GPU and read back by the CPU.
+    Readback,
+}
+impl MemoryUsage
+{
+    fn get_required_flags(&self) -> vk::MemoryPropertyFlags
+    {
+        match self
+        {
+            MemoryUsage::GpuOnly => vk::MemoryPropertyFlags::empty(),
+            MemoryUsage::Upload | MemoryUsage::Readback => vk::MemoryPropertyFlags::HOST_VISIBLE,
+        }
+    }
+
+    fn get_preferred_flags(&self) -> vk::MemoryPropertyFlags
+    {
+        match self
+        {
+            MemoryUsage::GpuOnly => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryUsage::Upload => vk::MemoryPropertyFlags::HOST_COHERENT,
+            MemoryUsage::Readback => vk::MemoryPropertyFlags::HOST_CACHED | vk::MemoryPropertyFlags::HOST_COHERENT,
+        }
+    }
+}
+
+/// Whether the resource is a buffer (or linear image) or an optimally tiled image.
+///
+/// The two kinds never share a block, so `bufferImageGranularity` never has to be honoured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind
+{
+    Linear,
+    Optimal,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AllocationCreateInfo
+{
+    pub usage: MemoryUsage,
+    pub kind: ResourceKind,
+    /// Forces a dedicated `VkDeviceMemory` regardless of size.
+    pub dedicated: bool,
+}
+
+struct Block
+{
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped: *mut u8,
+    /// Free ranges as `(offset, size)`, sorted by offset and never adjacent.
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+    allocation_count: usize,
+}
+impl Block
+{
+    /// First fit; returns the aligned offset.
+    fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize>
+    {
+        for index in 0..self.free_ranges.len()
+        {
+            let (offset, range_size) = self.free_ranges[index];
+            let aligned = offset.next_multiple_of(alignment.max(1));
+            let padding = aligned - offset;
+            if padding + size > range_size
+            {
+                continue;
+            }
+            // The padding stays free in front, the remainder behind.
+            let remainder = range_size - padding - size;
+            let mut replacement = Vec::with_capacity(2);
+            if padding > 0
+            {
+                replacement.push((offset, padding));
+            }
+            if remainder > 0
+            {
+                replacement.push((aligned + size, remainder));
+            }
+            self.free_ranges.splice(index..index + 1, replacement);
+            self.allocation_count += 1;
+            return Some(aligned);
+        }
+        None
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize)
+    {
+        let index = self.free_ranges.partition_point(|(range_offset, _)| *range_offset < offset);
+        self.free_ranges.insert(index, (offset, size));
+        // Merge with the following range, then with the preceding one.
+        if index + 1 < self.free_ranges.len() && offset + size == self.free_ranges[index + 1].0
+        {
+            self.free_ranges[index].1 += self.free_ranges[index + 1].1;
+            self.free_ranges.remove(index + 1);
+        }
+        if index > 0 && self.free_ranges[index - 1].0 + self.free_ranges[index - 1].1 == offset
+        {
+            self.free_ranges[index - 1].1 += self.free_ranges[index].1;
+            self.free_ranges.remove(index);
+        }
+        self.allocation_count -= 1;
+    }
+
+    fn get_free_bytes(&self) -> vk::DeviceSize
+    {
+        self.free_ranges.iter().map(|(_, size)| size).sum()
+    }
+
+    fn get_largest_free_range(&self) -> vk::DeviceSize
+    {
+        self.free_ranges.iter().map(|(_, size)| *size).max().unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PoolKey
+{
+    memory_type_index: u32,
+    kind: ResourceKind,
+}
+
+#[derive(Clone, Copy)]
+enum Location
+{
+    Block(PoolKey, u64),
+    Dedicated,
+}
+
+struct BlockAllocation
+{
+    location: Location,
+    memory: vk::DeviceMemory,
+    memory_size: vk::DeviceSize,
+    offset: vk::DeviceSize,
+    mapped: *mut u8,
+}
+
+struct AllocatorState
+{
+    blocks: HashMap<PoolKey, HashMap<u64, Block>>,
+    next_block_id: u64,
+    dedicated: Vec<(vk::DeviceMemory, u32, vk::DeviceSize)>,
+    block_size: vk::DeviceSize,
+    device: Device,
+}
+impl AllocatorState
+{
+    fn allocate_memory(&self, size: vk::DeviceSize, memory_type_index: u32) -> Result<(vk::DeviceMemory, *mut u8), vk::Result>
+    {
+        let device = self.device.get_device();
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+        let memory = unsafe { device.allocate_memory(&allocate_info, None) }?;
+        let flags = self.device.get_physical_device().get_memory_types()[memory_type_index as usize].property_flags;
+        if !flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            return Ok((memory, std::ptr::null_mut()));
+        }
+        // Host-visible memory stays mapped for its whole life.
+        match unsafe { device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) }
+        {
+            Ok(mapped) => Ok((memory, mapped as *mut u8)),
+            Err(error) =>
+            {
+                unsafe { device.free_memory(memory, None) };
+                Err(error)
+            }
+        }
+    }
+
+    fn get_block_size(&self, memory_type_index: u32) -> vk::DeviceSize
+    {
+        let physical_device = self.device.get_physical_device();
+        let heap_index = physical_device.get_memory_types()[memory_type_index as usize].heap_index;
+        let heap_size = physical_device.get_memory_heaps()[heap_index as usize].size;
+        // Small heaps, like the 256 MiB BAR window, must not be taken by one or two blocks.
+        self.block_size.min(heap_size / 8).max(1)
+    }
+
+    fn allocate_from_blocks(
+        &mut self,
+        key: PoolKey,
+        requirements: &vk::MemoryRequirements) -> Result<BlockAllocation, vk::Result>
+    {
+        let pool = self.blocks.entry(key).or_default();
+        for (id, block) in pool.iter_mut()
+        {
+            if let Some(offset) = block.allocate(requirements.size, requirements.alignment)
+            {
+                return Ok(BlockAllocation {
+                    location: Location::Block(key, *id),
+                    memory: block.memory,
+                    memory_size: block.size,
+                    offset,
+                    mapped: block.mapped,
+                });
+            }
+        }
+        let block_size = self.get_block_size(key.memory_type_index).max(requirements.size);
+        let (memory, mapped) = self.allocate_memory(block_size, key.memory_type_index)?;
+        let mut block = Block {
+            memory,
+            size: block_size,
+            mapped,
+            free_ranges: vec![(0, block_size)],
+            allocation_count: 0,
+        };
+        let offset = block.allocate(requirements.size, requirements.alignment).expect("fresh block is too small");
+        let id = self.next_block_id;
+        self.next_block_id += 1;
+        if self.device.is_debug_utils_enabled()
+        {
+            self.device.set_object_name(memory, &format!("allocator block {} (memory type {})", id, key.memory_type_index));
+        }
+        self.blocks.get_mut(&key).unwrap().insert(id, block);
+        Ok(BlockAllocation { location: Location::Block(key, id), memory, memory_size: block_size, offset, mapped })
+    }
+
+    fn free(&mut self, location: Location, memory: vk::DeviceMemory, offset: vk::DeviceSize, size: vk::DeviceSize)
+    {
+        let device = self.device.get_device();
+        match location
+        {
+            Location::Dedicated =>
+            {
+                self.dedicated.retain(|(dedicated, _, _)| *dedicated != memory);
+                unsafe { device.free_memory(memory, None) };
+            }
+            Location::Block(key, id) =>
+            {
+                let pool = self.blocks.get_mut(&key).expect("allocation of an unknown pool");
+                let block = pool.get_mut(&id).expect("allocation of an unknown block");
+                block.free(offset, size);
+                let is_empty = block.allocation_count == 0;
+                // One empty block per pool is kept around, so that alternating allocations
+                // do not keep hitting vkAllocateMemory.
+                let empty_blocks = pool.values().filter(|block| block.allocation_count == 0).count();
+                if is_empty && empty_blocks > 1
+                {
+                    let block = pool.remove(&id).unwrap();
+                    unsafe { device.free_memory(block.memory, None) };
+                }
+            }
+        }
+    }
+}
+impl Drop for AllocatorState
+{
+    fn drop(&mut self)
+    {
+        // Only reached once every allocation is gone, since each of them holds the state.
+        let device = self.device.get_device();
+        for block in self.blocks.values().flat_map(|pool| pool.values())
+        {
+            unsafe { device.free_memory(block.memory, None) };
+        }
+    }
+}
+
+/// Memory usage of one heap.
+#[derive(Clone, Debug, Default)]
+pub struct HeapStats
+{
+    pub flags: vk::MemoryHeapFlags,
+    pub size: vk::DeviceSize,
+    pub block_count: usize,
+    /// Bytes allocated from Vulkan, in blocks and dedicated allocations.
+    pub allocated_bytes: vk::DeviceSize,
+    /// Bytes handed out to live allocations.
+    pub used_bytes: vk::DeviceSize,
+    pub free_bytes: vk::DeviceSize,
+    pub largest_free_range: vk::DeviceSize,
+    pub allocation_count: usize,
+    pub dedicated_count: usize,
+}
+impl HeapStats
+{
+    /// Share of the free bytes outside of the largest free range, from 0 (none) to 1.
+    pub fn get_fragmentation(&self) -> f64
+    {
+        if self.free_bytes == 0
+        {
+            return 0.0;
+        }
+        1.0 - self.largest_free_range as f64 / self.free_bytes as f64
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AllocatorStats
+{
+    pub heaps: Vec<HeapStats>,
+    pub live_allocations: usize,
+}
+impl std::fmt::Display for AllocatorStats
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        const MIB: f64 = 1024.0 * 1024.0;
+        write!(f, "{} live allocations", self.live_allocations)?;
+        for (index, heap) in self.heaps.iter().enumerate()
+        {
+            write!(f, "\n\theap {} ({:?}, {:.0} MiB): {:.1}/{:.1} MiB used in {} blocks + {} dedicated, {} allocations, {:.0}% fragmented",
+                index,
+                heap.flags,
+                heap.size as f64 / MIB,
+                heap.used_bytes as f64 / MIB,
+                heap.allocated_bytes as f64 / MIB,
+                heap.block_count,
+                heap.dedicated_count,
+                heap.allocation_count,
+                heap.get_fragmentation() * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sub-allocates `VkDeviceMemory` for buffers and images.
+///
+/// Resources of one memory type and `ResourceKind` share large blocks; big images get
+/// dedicated allocations. Cloning shares the allocator, and it lives until the last of
+/// its clones and allocations is dropped.
+#[derive(Clone)]
+pub struct Allocator
+{
+    state: Rc<RefCell<AllocatorState>>,
+}
+impl Allocator
+{
+    pub fn create(device: &Device) -> Allocator
+    {
+        Allocator::with_block_size(device, DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(device: &Device, block_size: vk::DeviceSize) -> Allocator
+    {
+        Allocator {
+            state: Rc::new(RefCell::new(AllocatorState {
+                blocks: HashMap::new(),
+                next_block_id: 0,
+                dedicated: Vec::new(),
+                block_size,
+                device: device.clone(),
+            })),
+        }
+    }
+
+    pub fn get_device(&self) -> Device
+    {
+        self.state.borrow().device.clone()
+    }
+
+    /// Memory types allowed by `type_bits` that fit `usage`, best first.
+    pub fn get_memory_types(&self, type_bits: u32, usage: MemoryUsage) -> Vec<u32>
+    {
+        let state = self.state.borrow();
+        let required = usage.get_required_flags();
+        let preferred = usage.get_preferred_flags();
+        let mut candidates : Vec<(u32, u32)> = state.device.get_physical_device().get_memory_types()
+            .iter()
+            .enumerate()
+            .filter(|(index, memory_type)| {
+                type_bits & (1 << index) != 0 && memory_type.property_flags.contains(required)
+            })
+            .map(|(index, memory_type)| {
+                let score = (memory_type.property_flags & preferred).as_raw().count_ones();
+                (index as u32, score)
+            })
+            .collect();
+        // Stable, so equally good types stay in the driver's order, which is a preference too.
+        candidates.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        candidates.into_iter().map(|(index, _)| index).collect()
+    }
+
+    pub fn allocate(&self, requirements: &vk::MemoryRequirements, info: &AllocationCreateInfo) -> Result<Allocation, vk::Result>
+    {
+        let memory_types = self.get_memory_types(requirements.memory_type_bits, info.usage);
+        if memory_types.is_empty()
+        {
+            // No memory type can hold the resource at all, which is no less fatal than a full heap.
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        let mut state = self.state.borrow_mut();
+        let mut last_error = vk::Result::ERROR_OUT_OF_DEVICE_MEMORY;
+        // Falls back to worse memory types when the better heaps are exhausted.
+        for memory_type_index in memory_types
+        {
+            let dedicated = info.dedicated || requirements.size > state.get_block_size(memory_type_index) / 2;
+            let result = if dedicated
+            {
+                state.allocate_memory(requirements.size, memory_type_index).map(|(memory, mapped)| {
+                    if state.device.is_debug_utils_enabled()
+                    {
+                        state.device.set_object_name(memory, &format!("dedicated allocation (memory type {})", memory_type_index));
+                    }
+                    state.dedicated.push((memory, memory_type_index, requirements.size));
+                    BlockAllocation {
+                        location: Location::Dedicated,
+                        memory,
+                        memory_size: requirements.size,
+                        offset: 0,
+                        mapped,
+                    }
+                })
+            }
+            else
+            {
+                let key = PoolKey { memory_type_index, kind: info.kind };
+                state.allocate_from_blocks(key, requirements)
+            };
+            match result
+            {
+                Ok(block_allocation) =>
+                {
+                    let flags = state.device.get_physical_device().get_memory_types()[memory_type_index as usize].property_flags;
+                    let mapped = block_allocation.mapped;
+                    return Ok(Allocation {
+                        memory: block_allocation.memory,
+                        offset: block_allocation.offset,
+                        size: requirements.size,
+                        memory_size: block_allocation.memory_size,
+                        coherent: flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT),
+                        mapped: if mapped.is_null() { mapped } else { unsafe { mapped.add(block_allocation.offset as usize) } },
+                        location: block_allocation.location,
+                        state: self.state.clone(),
+                    });
+                }
+                Err(error @ (vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY)) =>
+                    last_error = error,
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Allocates memory for `buffer` and binds it.
+    pub fn allocate_for_buffer(&self, buffer: vk::Buffer, usage: MemoryUsage) -> Result<Allocation, vk::Result>
+    {
+        let device = self.get_device();
+        let requirements = unsafe { device.get_device().get_buffer_memory_requirements(buffer) };
+        let info = AllocationCreateInfo { usage, kind: ResourceKind::Linear, dedicated: false };
+        let allocation = self.allocate(&requirements, &info)?;
+        unsafe { device.get_device().bind_buffer_memory(buffer, allocation.memory, allocation.offset) }?;
+        Ok(allocation)
+    }
+
+    /// Allocates memory for an optimally tiled `image` and binds it; large images get
+    /// a dedicated allocation.
+    pub fn allocate_for_image(&self, image: vk::Image, usage: MemoryUsage) -> Result<Allocation, vk::Result>
+    {
+        let device = self.get_device();
+        let requirements = unsafe { device.get_device().get_image_memory_requirements(image) };
+        let info = AllocationCreateInfo {
+            usage,
+            kind: ResourceKind::Optimal,
+            dedicated: requirements.size >= DEDICATED_IMAGE_SIZE,
+        };
+        let allocation = self.allocate(&requirements, &info)?;
+        unsafe { device.get_device().bind_image_memory(image, allocation.memory, allocation.offset) }?;
+        Ok(allocation)
+    }
+
+    pub fn get_stats(&self) -> AllocatorStats
+    {
+        let state = self.state.borrow();
+        let physical_device = state.device.get_physical_device();
+        let memory_types = physical_device.get_memory_types();
+        let mut stats = AllocatorStats {
+            heaps: physical_device.get_memory_heaps()
+                .iter()
+                .map(|heap| HeapStats { flags: heap.flags, size: heap.size, ..Default::default() })
+                .collect(),
+            live_allocations: 0,
+        };
+        for (key, pool) in &state.blocks
+        {
+            let heap = &mut stats.heaps[memory_types[key.memory_type_index as usize].heap_index as usize];
+            for block in pool.values()
+            {
+                let free_bytes = block.get_free_bytes();
+                heap.block_count += 1;
+                heap.allocated_bytes += block.size;
+                heap.used_bytes += block.size - free_bytes;
+                heap.free_bytes += free_bytes;
+                heap.largest_free_range = heap.largest_free_range.max(block.get_largest_free_range());
+                heap.allocation_count += block.allocation_count;
+            }
+        }
+        for (_, memory_type_index, size) in &state.dedicated
+        {
+            let heap = &mut stats.heaps[memory_types[*memory_type_index as usize].heap_index as usize];
+            heap.dedicated_count += 1;
+            heap.allocation_count += 1;
+            heap.allocated_bytes += size;
+            heap.used_bytes += size;
+        }
+        stats.live_allocations = stats.heaps.iter().map(|heap| heap.allocation_count).sum();
+        stats
+    }
+}
+
+/// A range of `VkDeviceMemory` handed out by an `Allocator`; returned to it on drop.
+///
+/// The resource bound to it has to be destroyed first.
+pub struct Allocation
+{
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    /// Size of the whole `memory`, which flushed ranges must not exceed.
+    memory_size: vk::DeviceSize,
+    coherent: bool,
+    mapped: *mut u8,
+    location: Location,
+    state: Rc<RefCell<AllocatorState>>,
+}
+impl Allocation
+{
+    pub fn get_memory(&self) -> vk::DeviceMemory
+    {
+        self.memory
+    }
+
+    pub fn get_offset(&self) -> vk::DeviceSize
+    {
+        self.offset
+    }
+
+    /// Host pointer to the start of the allocation, for host-visible memory only.
+    pub fn get_mapped_ptr(&self) -> Option<*mut u8>
+    {
+        (!self.mapped.is_null()).then_some(self.mapped)
+    }
+
+    fn get_atom_range(&self) -> vk::MappedMemoryRange
+    {
+        let state = self.state.borrow();
+        let atom = state.device.get_physical_device().get_properties().limits.non_coherent_atom_size.max(1);
+        let start = self.offset / atom * atom;
+        let end = (self.offset + self.size).next_multiple_of(atom);
+        // The last atom of the memory may be cut short, only `VK_WHOLE_SIZE` can reach it.
+        let size = if end > self.memory_size { vk::WHOLE_SIZE } else { end - start };
+        vk::MappedMemoryRange::builder()
+            .memory(self.memory)
+            .offset(start)
+            .size(size)
+            .build()
+    }
+
+    /// Makes host writes visible to the device; a no-op on coherent memory.
+    pub fn flush(&self) -> Result<(), vk::Result>
+    {
+        if self.coherent || self.mapped.is_null()
+        {
+            return Ok(());
+        }
+        let range = self.get_atom_range();
+        let device = self.state.borrow().device.clone();
+        unsafe { device.get_device().flush_mapped_memory_ranges(&[range]) }
+    }
+
+    /// Makes device writes visible to the host; a no-op on coherent memory.
+    pub fn invalidate(&self) -> Result<(), vk::Result>
+    {
+        if self.coherent || self.mapped.is_null()
+        {
+            return Ok(());
+        }
+        let range = self.get_atom_range();
+        let device = self.state.borrow().device.clone();
+        unsafe { device.get_device().invalidate_mapped_memory_ranges(&[range]) }
+    }
+}
+impl Drop for Allocation
+{
+    fn drop(&mut self)
+    {
+        self.state.borrow_mut().free(self.location, self.memory, self.offset, self.size);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn block(size: vk::DeviceSize) -> Block
+    {
+        Block {
+            memory: vk::DeviceMemory::null(),
+            size,
+            mapped: std::ptr::null_mut(),
+            free_ranges: vec![(0, size)],
+            allocation_count: 0,
+        }
+    }
+
+    #[test]
+    fn block_allocation_honours_alignment()
+    {
+        let mut block = block(1024);
+        assert_eq!(block.allocate(10, 1), Some(0));
+        assert_eq!(block.allocate(16, 256), Some(256));
+        assert_eq!(block.free_ranges, vec![(10, 246), (272, 752)]);
+        assert_eq!(block.allocate(2048, 1), None);
+    }
+
+    #[test]
+    fn block_free_merges_neighbours()
+    {
+        let mut block = block(300);
+        let a = block.allocate(100, 1).unwrap();
+        let b = block.allocate(100, 1).unwrap();
+        let c = block.allocate(100, 1).unwrap();
+        assert!(block.free_ranges.is_empty());
+        block.free(a, 100);
+        block.free(c, 100);
+        assert_eq!(block.get_largest_free_range(), 100);
+        block.free(b, 100);
+        assert_eq!(block.free_ranges, vec![(0, 300)]);
+        assert_eq!(block.allocation_count, 0);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+
+/// Every access flag that writes memory.
+pub const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw());
+
+/// What happened to a buffer or image since the last barrier that covers it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceState
+{
+    /// Stages and accesses of the last write, kept after barriers made it visible; a layout
+    /// transition counts as a write without either.
+    last_write: Option<(vk::PipelineStageFlags, vk::AccessFlags)>,
+    /// Stages and accesses that barriers made the last write visible to, none while pending.
+    visible_stages: vk::PipelineStageFlags,
+    visible_access: vk::AccessFlags,
+    /// Stages that read since the last write or barrier.
+    read_stages: vk::PipelineStageFlags,
+    /// Always `UNDEFINED` for buffers.
+    layout: vk::ImageLayout,
+}
+// Manual barriers for code outside the render graph; the demo renderer needs none.
+#[allow(dead_code)]
+impl ResourceState
+{
+    /// State after an access by `stage`; an empty `access` still orders later writes after
+    /// `stage`, e.g. `ALL_COMMANDS` for whatever a semaphore wait covers, and `TOP_OF_PIPE`
+    /// orders nothing.
+    pub fn new(stage: vk::PipelineStageFlags, access: vk::AccessFlags, layout: vk::ImageLayout) -> ResourceState
+    {
+        let mut state = ResourceState {
+            last_write: None,
+            visible_stages: vk::PipelineStageFlags::empty(),
+            visible_access: vk::AccessFlags::empty(),
+            read_stages: vk::PipelineStageFlags::empty(),
+            layout,
+        };
+        state.record(stage, access);
+        state
+    }
+
+    pub fn get_layout(&self) -> vk::ImageLayout
+    {
+        self.layout
+    }
+
+    /// Forgets the contents but keeps the pending accesses, for memory taken over by
+    /// another resource that aliases it.
+    pub fn discard(&mut self)
+    {
+        self.layout = vk::ImageLayout::UNDEFINED;
+    }
+
+    fn record(&mut self, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        if access.intersects(WRITE_ACCESS)
+        {
+            self.last_write = Some((stage, access & WRITE_ACCESS));
+            self.visible_stages = vk::PipelineStageFlags::empty();
+            self.visible_access = vk::AccessFlags::empty();
+            self.read_stages = vk::PipelineStageFlags::empty();
+        }
+        else if stage != vk::PipelineStageFlags::TOP_OF_PIPE
+        {
+            self.read_stages |= stage;
+        }
+    }
+
+    /// Moves on to an access by `stage` in `layout`, returning the barrier it needs first:
+    /// a memory barrier after writes not yet visible to this access or for a layout change,
+    /// an execution barrier for a write after reads, none for a read after reads.
+    pub fn transition(&mut self, stage: vk::PipelineStageFlags, access: vk::AccessFlags, layout: vk::ImageLayout) -> Option<Barrier>
+    {
+        let writes = access.intersects(WRITE_ACCESS);
+        let mut barrier = Barrier {
+            src_stage: self.read_stages,
+            dst_stage: stage,
+            src_access: vk::AccessFlags::empty(),
+            dst_access: access,
+            old_layout: self.layout,
+            new_layout: layout,
+        };
+        let mut needed = layout != self.layout || (writes && !self.read_stages.is_empty());
+        if let Some((write_stage, write_access)) = self.last_write
+        {
+            // Writes after a visible write are ordered through the reads since, above.
+            let visible = self.visible_stages.contains(stage) && self.visible_access.contains(access);
+            if self.visible_stages.is_empty() || !(writes || visible)
+            {
+                barrier.src_stage |= write_stage | self.visible_stages;
+                barrier.src_access = write_access;
+                needed = true;
+            }
+        }
+        if barrier.src_stage.is_empty()
+        {
+            barrier.src_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        }
+        if needed
+        {
+            if layout != self.layout
+            {
+                self.last_write.get_or_insert((vk::PipelineStageFlags::empty(), vk::AccessFlags::empty()));
+                self.visible_stages = stage;
+                self.visible_access = access;
+            }
+            else
+            {
+                self.visible_stages |= stage;
+                self.visible_access |= access;
+            }
+            self.read_stages = vk::PipelineStageFlags::empty();
+        }
+        self.layout = layout;
+        self.record(stage, access);
+        needed.then_some(barrier)
+    }
+}
+
+/// A dependency between two accesses, possibly with a layout transition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Barrier
+{
+    pub src_stage: vk::PipelineStageFlags,
+    pub dst_stage: vk::PipelineStageFlags,
+    pub src_access: vk::AccessFlags,
+    pub dst_access: vk::AccessFlags,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+/// Barriers collected for one `vkCmdPipelineBarrier`.
+#[derive(Default)]
+pub struct BarrierBatch
+{
+    buffer_barriers: Vec<vk::BufferMemoryBarrier>,
+    image_barriers: Vec<vk::ImageMemoryBarrier>,
+    src_stages: vk::PipelineStageFlags,
+    dst_stages: vk::PipelineStageFlags,
+}
+// Manual barriers for code outside the render graph; the demo renderer needs none.
+#[allow(dead_code)]
+impl BarrierBatch
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.buffer_barriers.is_empty() && self.image_barriers.is_empty()
+    }
+
+    pub fn add_buffer(&mut self, barrier: &Barrier, buffer: vk::Buffer)
+    {
+        self.buffer_barriers.push(vk::BufferMemoryBarrier::builder()
+            .src_access_mask(barrier.src_access)
+            .dst_access_mask(barrier.dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build());
+        self.src_stages |= barrier.src_stage;
+        self.dst_stages |= barrier.dst_stage;
+    }
+
+    pub fn add_image(&mut self, barrier: &Barrier, image: vk::Image, range: vk::ImageSubresourceRange)
+    {
+        self.image_barriers.push(vk::ImageMemoryBarrier::builder()
+            .src_access_mask(barrier.src_access)
+            .dst_access_mask(barrier.dst_access)
+            .old_layout(barrier.old_layout)
+            .new_layout(barrier.new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(range)
+            .build());
+        self.src_stages |= barrier.src_stage;
+        self.dst_stages |= barrier.dst_stage;
+    }
+
+    /// Records the barriers, if any, and starts a new batch.
+    pub fn record(&mut self, device: &Device, command_buffer: vk::CommandBuffer)
+    {
+        if self.is_empty()
+        {
+            return;
+        }
+        unsafe {
+            device.get_device().cmd_pipeline_barrier(
+                command_buffer,
+                self.src_stages,
+                self.dst_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &self.buffer_barriers,
+                &self.image_barriers);
+        }
+        *self = BarrierBatch::default();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const COMPUTE: vk::PipelineStageFlags = vk::PipelineStageFlags::COMPUTE_SHADER;
+
+    #[test]
+    fn read_after_write_needs_a_memory_barrier()
+    {
+        let mut state = ResourceState::new(COMPUTE, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::UNDEFINED);
+        let barrier = state.transition(COMPUTE, vk::AccessFlags::SHADER_READ, vk::ImageLayout::UNDEFINED).unwrap();
+        assert_eq!((barrier.src_stage, barrier.src_access), (COMPUTE, vk::AccessFlags::SHADER_WRITE));
+        assert_eq!(barrier.dst_access, vk::AccessFlags::SHADER_READ);
+        // Further reads are already covered.
+        assert_eq!(state.transition(COMPUTE, vk::AccessFlags::SHADER_READ, vk::ImageLayout::UNDEFINED), None);
+    }
+
+    #[test]
+    fn reads_by_other_stages_need_their_own_barrier()
+    {
+        let mut state = ResourceState::new(COMPUTE, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::UNDEFINED);
+        assert!(state.transition(COMPUTE, vk::AccessFlags::SHADER_READ, vk::ImageLayout::UNDEFINED).is_some());
+        let barrier = state.transition(vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ, vk::ImageLayout::UNDEFINED).unwrap();
+        assert!(barrier.src_stage.contains(COMPUTE));
+        assert_eq!(barrier.src_access, vk::AccessFlags::SHADER_WRITE);
+        assert_eq!((barrier.dst_stage, barrier.dst_access), (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ));
+        // Both readers are covered now.
+        assert_eq!(state.transition(COMPUTE, vk::AccessFlags::SHADER_READ, vk::ImageLayout::UNDEFINED), None);
+        assert_eq!(state.transition(vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ, vk::ImageLayout::UNDEFINED), None);
+
+        // The same goes for a layout transition without earlier writes.
+        let mut state = ResourceState::new(vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::empty(), vk::ImageLayout::UNDEFINED);
+        let fragment = vk::PipelineStageFlags::FRAGMENT_SHADER;
+        assert!(state.transition(fragment, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).is_some());
+        let barrier = state.transition(COMPUTE, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).unwrap();
+        assert_eq!(barrier.src_stage, fragment);
+    }
+
+    #[test]
+    fn write_after_read_needs_an_execution_barrier()
+    {
+        let mut state = ResourceState::new(vk::PipelineStageFlags::VERTEX_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::UNDEFINED);
+        let barrier = state.transition(COMPUTE, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::UNDEFINED).unwrap();
+        assert_eq!(barrier.src_stage, vk::PipelineStageFlags::VERTEX_SHADER);
+        assert_eq!(barrier.src_access, vk::AccessFlags::empty());
+        // And a write after that write needs another one.
+        assert!(state.transition(COMPUTE, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::UNDEFINED).is_some());
+    }
+
+    #[test]
+    fn layout_changes_always_need_a_barrier()
+    {
+        let mut state = ResourceState::new(vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty(), vk::ImageLayout::UNDEFINED);
+        let barrier = state.transition(COMPUTE, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::GENERAL).unwrap();
+        assert_eq!((barrier.old_layout, barrier.new_layout), (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL));
+        assert_eq!(barrier.src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        let barrier = state
+            .transition(vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .unwrap();
+        assert_eq!((barrier.src_stage, barrier.src_access), (COMPUTE, vk::AccessFlags::SHADER_WRITE));
+    }
+}
@@ -0,0 +1,409 @@
+use crate::ludo::vulkan::{DescriptorBinding, Device, PhysicalDevice};
+use ash::vk;
+use std::rc::Rc;
+
+/// Binding of the image table: `layout(set = N, binding = 0) uniform sampler2D textures[];`.
+pub const BINDLESS_IMAGE_BINDING: u32 = 0;
+/// Binding of the buffer table: `layout(set = N, binding = 1) buffer Data { ... } buffers[];`.
+pub const BINDLESS_BUFFER_BINDING: u32 = 1;
+
+/// Index of a resource in the bindless table, as shaders receive it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BindlessHandle(u32);
+#[allow(dead_code)]
+impl BindlessHandle
+{
+    pub fn get_index(&self) -> u32
+    {
+        self.0
+    }
+}
+
+/// Free indices of one table.
+///
+/// A removed index may still be read by frames in flight, so it only becomes free again
+/// after `frames_in_flight` more frames have begun.
+struct Slots
+{
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    /// Whether each acquired index is in use, so that an index is not released twice.
+    live: Vec<bool>,
+    /// Removed indices with the frame they were removed in.
+    retired: Vec<(u64, u32)>,
+}
+impl Slots
+{
+    fn new(capacity: u32) -> Slots
+    {
+        Slots { capacity, next: 0, free: Vec::new(), live: Vec::new(), retired: Vec::new() }
+    }
+
+    fn acquire(&mut self) -> Option<u32>
+    {
+        if let Some(index) = self.free.pop()
+        {
+            self.live[index as usize] = true;
+            return Some(index);
+        }
+        if self.next == self.capacity
+        {
+            return None;
+        }
+        self.next += 1;
+        self.live.push(true);
+        Some(self.next - 1)
+    }
+
+    /// Retires `index`; false when it is not in use, i.e. already released or never acquired.
+    fn release(&mut self, index: u32, frame: u64) -> bool
+    {
+        let Some(live) = self.live.get_mut(index as usize).filter(|live| **live) else { return false };
+        *live = false;
+        self.retired.push((frame, index));
+        true
+    }
+
+    fn collect(&mut self, frame: u64, frames_in_flight: u64)
+    {
+        let free = &mut self.free;
+        self.retired.retain(|(retired_in, index)| {
+            let expired = retired_in + frames_in_flight <= frame;
+            if expired
+            {
+                free.push(*index);
+            }
+            !expired
+        });
+    }
+
+    fn get_used(&self) -> u32
+    {
+        self.next - self.free.len() as u32
+    }
+}
+
+struct SetLayoutHandle
+{
+    layout: vk::DescriptorSetLayout,
+    device: Device,
+}
+impl Drop for SetLayoutHandle
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_descriptor_set_layout(self.layout, None) };
+    }
+}
+
+/// The set layout of a bindless table, kept alive by the pipeline layouts that use it.
+#[derive(Clone)]
+pub struct SharedSetLayout(Rc<SetLayoutHandle>);
+impl SharedSetLayout
+{
+    pub fn get_handle(&self) -> vk::DescriptorSetLayout
+    {
+        self.0.layout
+    }
+}
+
+/// One global descriptor set holding every sampled image and storage buffer, which shaders
+/// index with a `BindlessHandle` passed in push constants or buffers.
+///
+/// Needs the descriptor indexing features of `get_required_features`; check
+/// `is_supported` before creating the device with them.
+pub struct BindlessTable
+{
+    set: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    set_layout: SharedSetLayout,
+    images: Slots,
+    buffers: Slots,
+    frame: u64,
+    frames_in_flight: u64,
+    device: Device,
+}
+impl BindlessTable
+{
+    /// The descriptor indexing features the table relies on.
+    pub fn get_required_features() -> vk::PhysicalDeviceDescriptorIndexingFeatures
+    {
+        vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .shader_storage_buffer_array_non_uniform_indexing(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_storage_buffer_update_after_bind(true)
+            .descriptor_binding_update_unused_while_pending(true)
+            .descriptor_binding_partially_bound(true)
+            .runtime_descriptor_array(true)
+            .build()
+    }
+
+    pub fn is_supported(physical_device: &PhysicalDevice) -> bool
+    {
+        let Some(indexing) = physical_device.get_descriptor_indexing() else { return false };
+        let supported = &indexing.features;
+        let required = BindlessTable::get_required_features();
+        let flags = |features: &vk::PhysicalDeviceDescriptorIndexingFeatures| [
+            features.shader_sampled_image_array_non_uniform_indexing,
+            features.shader_storage_buffer_array_non_uniform_indexing,
+            features.descriptor_binding_sampled_image_update_after_bind,
+            features.descriptor_binding_storage_buffer_update_after_bind,
+            features.descriptor_binding_update_unused_while_pending,
+            features.descriptor_binding_partially_bound,
+            features.runtime_descriptor_array,
+        ];
+        flags(&required).iter().zip(flags(supported)).all(|(required, supported)| *required == vk::FALSE || supported == vk::TRUE)
+    }
+
+    /// Creates a table of up to `image_capacity` images and `buffer_capacity` buffers, fewer
+    /// when the device limits are lower.
+    ///
+    /// The device must have been created with `get_required_features`.
+    pub fn create(device: &Device, frames_in_flight: usize, image_capacity: u32, buffer_capacity: u32) -> Result<BindlessTable, vk::Result>
+    {
+        let Some(indexing) = device.get_physical_device().get_descriptor_indexing()
+        else
+        {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        };
+        if device.get_descriptor_indexing_features().is_none()
+        {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        let limits = &indexing.properties;
+        let image_capacity = image_capacity
+            .min(limits.max_descriptor_set_update_after_bind_sampled_images)
+            .min(limits.max_descriptor_set_update_after_bind_samplers)
+            .min(limits.max_per_stage_descriptor_update_after_bind_sampled_images)
+            .min(limits.max_per_stage_descriptor_update_after_bind_samplers);
+        let buffer_capacity = buffer_capacity
+            .min(limits.max_descriptor_set_update_after_bind_storage_buffers)
+            .min(limits.max_per_stage_descriptor_update_after_bind_storage_buffers);
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(BINDLESS_IMAGE_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(image_capacity)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(BINDLESS_BUFFER_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(buffer_capacity)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .build(),
+        ];
+        // Unused entries may hold anything, and entries the GPU does not read may change at any time.
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING; 2];
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(&binding_flags);
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&bindings)
+            .push_next(&mut binding_flags_info);
+        let layout = unsafe { device.get_device().create_descriptor_set_layout(&create_info, None) }?;
+        let set_layout = SharedSetLayout(Rc::new(SetLayoutHandle { layout, device: device.clone() }));
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, descriptor_count: image_capacity },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: buffer_capacity },
+        ];
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { device.get_device().create_descriptor_pool(&create_info, None) }?;
+        let mut table = BindlessTable {
+            set: vk::DescriptorSet::null(),
+            pool,
+            set_layout,
+            images: Slots::new(image_capacity),
+            buffers: Slots::new(buffer_capacity),
+            frame: 0,
+            frames_in_flight: frames_in_flight.max(1) as u64,
+            device: device.clone(),
+        };
+        let layouts = [table.set_layout.get_handle()];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        table.set = unsafe { device.get_device().allocate_descriptor_sets(&allocate_info) }?[0];
+        Ok(table)
+    }
+
+    /// Whether `binding` of a shader is one of the tables, so its pipeline layout can use
+    /// the table's set layout.
+    pub fn is_table_binding(binding: &DescriptorBinding) -> bool
+    {
+        // Runtime arrays only; a sized array in the same place is a different layout.
+        binding.count == 0 && match binding.binding
+        {
+            BINDLESS_IMAGE_BINDING => binding.descriptor_type == vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            BINDLESS_BUFFER_BINDING => binding.descriptor_type == vk::DescriptorType::STORAGE_BUFFER,
+            _ => false,
+        }
+    }
+
+    pub fn get_set_layout(&self) -> &SharedSetLayout
+    {
+        &self.set_layout
+    }
+
+    /// Names the set, and its layout and pool after it.
+    pub fn set_name(&self, name: &str)
+    {
+        if self.device.is_debug_utils_enabled()
+        {
+            self.device.set_object_name(self.set, name);
+            self.device.set_object_name(self.set_layout.get_handle(), &format!("{} layout", name));
+            self.device.set_object_name(self.pool, &format!("{} pool", name));
+        }
+    }
+
+    pub fn get_image_capacity(&self) -> u32
+    {
+        self.images.capacity
+    }
+
+    pub fn get_buffer_capacity(&self) -> u32
+    {
+        self.buffers.capacity
+    }
+
+    /// Starts a new frame; indices removed `frames_in_flight` frames ago are reused from now on.
+    pub fn begin_frame(&mut self)
+    {
+        self.frame += 1;
+        self.images.collect(self.frame, self.frames_in_flight);
+        self.buffers.collect(self.frame, self.frames_in_flight);
+    }
+}
+// Resources are added by whoever owns them; the demo renderer draws without any.
+#[allow(dead_code)]
+impl BindlessTable
+{
+    pub fn get_set(&self) -> vk::DescriptorSet
+    {
+        self.set
+    }
+
+    /// Number of images and buffers in the table.
+    pub fn get_usage(&self) -> (u32, u32)
+    {
+        (self.images.get_used(), self.buffers.get_used())
+    }
+
+    /// Adds an image in `layout` sampled with `sampler`; `None` when the table is full.
+    pub fn add_image(&mut self, image_view: vk::ImageView, layout: vk::ImageLayout, sampler: vk::Sampler) -> Option<BindlessHandle>
+    {
+        let index = self.images.acquire()?;
+        let image_info = [vk::DescriptorImageInfo { sampler, image_view, image_layout: layout }];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(BINDLESS_IMAGE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { self.device.get_device().update_descriptor_sets(&[write.build()], &[]) };
+        Some(BindlessHandle(index))
+    }
+
+    /// Adds `range` bytes of `buffer` from `offset`; `None` when the table is full.
+    pub fn add_buffer(&mut self, buffer: vk::Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Option<BindlessHandle>
+    {
+        let index = self.buffers.acquire()?;
+        let buffer_info = [vk::DescriptorBufferInfo { buffer, offset, range }];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(BINDLESS_BUFFER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info);
+        unsafe { self.device.get_device().update_descriptor_sets(&[write.build()], &[]) };
+        Some(BindlessHandle(index))
+    }
+
+    /// Removes an image; the resource itself must live until `frames_in_flight` more frames began.
+    ///
+    /// False, and nothing changes, when `handle` is not an image of the table; removing
+    /// twice would otherwise hand the index out to two resources.
+    pub fn remove_image(&mut self, handle: BindlessHandle) -> bool
+    {
+        self.images.release(handle.0, self.frame)
+    }
+
+    /// Removes a buffer; the resource itself must live until `frames_in_flight` more frames began.
+    ///
+    /// False, and nothing changes, when `handle` is not a buffer of the table; removing
+    /// twice would otherwise hand the index out to two resources.
+    pub fn remove_buffer(&mut self, handle: BindlessHandle) -> bool
+    {
+        self.buffers.release(handle.0, self.frame)
+    }
+}
+impl Drop for BindlessTable
+{
+    fn drop(&mut self)
+    {
+        // Frees the set too; the layout goes with the last pipeline layout using it.
+        unsafe { self.device.get_device().destroy_descriptor_pool(self.pool, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn removed_slots_wait_for_frames_in_flight()
+    {
+        let mut slots = Slots::new(2);
+        assert_eq!((slots.acquire(), slots.acquire(), slots.acquire()), (Some(0), Some(1), None));
+        assert!(slots.release(1, 5));
+        assert_eq!(slots.get_used(), 2);
+        slots.collect(6, 2);
+        assert_eq!(slots.acquire(), None);
+        slots.collect(7, 2);
+        assert_eq!(slots.get_used(), 1);
+        assert_eq!(slots.acquire(), Some(1));
+    }
+
+    #[test]
+    fn double_and_foreign_releases_are_rejected()
+    {
+        let mut slots = Slots::new(4);
+        assert_eq!(slots.acquire(), Some(0));
+        assert!(!slots.release(1, 0));
+        assert!(!slots.release(7, 0));
+        assert!(slots.release(0, 0));
+        assert!(!slots.release(0, 0));
+        slots.collect(2, 2);
+        assert_eq!(slots.free, vec![0]);
+        assert!(!slots.release(0, 2));
+        assert_eq!(slots.acquire(), Some(0));
+        assert!(slots.release(0, 2));
+    }
+
+    #[test]
+    fn only_runtime_arrays_are_table_bindings()
+    {
+        let binding = |binding, descriptor_type, count| DescriptorBinding {
+            name: String::new(),
+            set: 0,
+            binding,
+            descriptor_type,
+            count,
+        };
+        assert!(BindlessTable::is_table_binding(&binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 0)));
+        assert!(BindlessTable::is_table_binding(&binding(1, vk::DescriptorType::STORAGE_BUFFER, 0)));
+        assert!(!BindlessTable::is_table_binding(&binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 16)));
+        assert!(!BindlessTable::is_table_binding(&binding(1, vk::DescriptorType::UNIFORM_BUFFER, 0)));
+        assert!(!BindlessTable::is_table_binding(&binding(2, vk::DescriptorType::STORAGE_BUFFER, 0)));
+    }
+}
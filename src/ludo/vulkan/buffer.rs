@@ -0,0 +1,238 @@
+use crate::ludo::vulkan::{Allocation, Allocator, Device, MemoryUsage};
+use ash::vk;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut, Range};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BufferError
+{
+    /// The buffer lives in memory the host cannot map.
+    NotHostVisible,
+    /// The mapped memory is not aligned for the element type.
+    Misaligned(usize),
+    /// The element range does not fit in the buffer.
+    OutOfBounds(Range<usize>, usize),
+    Vulkan(vk::Result),
+}
+impl std::fmt::Display for BufferError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            BufferError::NotHostVisible =>
+                write!(f, "buffer memory is not host visible"),
+            BufferError::Misaligned(alignment) =>
+                write!(f, "mapped buffer memory is not aligned to {} bytes", alignment),
+            BufferError::OutOfBounds(range, len) =>
+                write!(f, "elements {:?} are out of bounds of a buffer of {} elements", range, len),
+            BufferError::Vulkan(result) =>
+                write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for BufferError {}
+impl From<vk::Result> for BufferError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        BufferError::Vulkan(result)
+    }
+}
+
+/// A `VkBuffer` of `len` elements of `T` with its memory.
+///
+/// Buffers are shared between all queue families of the device, so that uploads on the
+/// transfer queue need no ownership transfers.
+pub struct Buffer<T: bytemuck::Pod>
+{
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    len: usize,
+    usage: vk::BufferUsageFlags,
+    device: Device,
+    _element: PhantomData<T>,
+}
+// Not used by the demo renderer, which draws without vertex buffers.
+#[allow(dead_code)]
+impl<T: bytemuck::Pod> Buffer<T>
+{
+    pub fn create(
+        allocator: &Allocator,
+        len: usize,
+        usage: vk::BufferUsageFlags,
+        memory_usage: MemoryUsage) -> Result<Buffer<T>, vk::Result>
+    {
+        let device = allocator.get_device();
+        let size = (len.max(1) * std::mem::size_of::<T>()) as vk::DeviceSize;
+        let families = device.get_queue_families().get_unique();
+        let mut create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        if families.len() > 1
+        {
+            create_info = create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families);
+        }
+        let buffer = unsafe { device.get_device().create_buffer(&create_info, None) }?;
+        let mut result = Buffer {
+            buffer,
+            allocation: None,
+            len,
+            usage,
+            device,
+            _element: PhantomData,
+        };
+        // On failure the buffer is destroyed by `drop`.
+        result.allocation = Some(allocator.allocate_for_buffer(buffer, memory_usage)?);
+        Ok(result)
+    }
+
+    /// A device-local vertex buffer, filled through an `Uploader`.
+    pub fn create_vertex(allocator: &Allocator, len: usize) -> Result<Buffer<T>, vk::Result>
+    {
+        let usage = vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        Buffer::create(allocator, len, usage, MemoryUsage::GpuOnly)
+    }
+
+    /// A device-local index buffer, filled through an `Uploader`.
+    pub fn create_index(allocator: &Allocator, len: usize) -> Result<Buffer<T>, vk::Result>
+    {
+        let usage = vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        Buffer::create(allocator, len, usage, MemoryUsage::GpuOnly)
+    }
+
+    /// A host-visible uniform buffer, written through `map` every frame.
+    pub fn create_uniform(allocator: &Allocator, len: usize) -> Result<Buffer<T>, vk::Result>
+    {
+        Buffer::create(allocator, len, vk::BufferUsageFlags::UNIFORM_BUFFER, MemoryUsage::Upload)
+    }
+
+    /// A device-local storage buffer that can be uploaded to and copied from.
+    pub fn create_storage(allocator: &Allocator, len: usize) -> Result<Buffer<T>, vk::Result>
+    {
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_DST
+            | vk::BufferUsageFlags::TRANSFER_SRC;
+        Buffer::create(allocator, len, usage, MemoryUsage::GpuOnly)
+    }
+
+    pub fn get_handle(&self) -> vk::Buffer
+    {
+        self.buffer
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.buffer, name);
+    }
+
+    pub fn get_usage(&self) -> vk::BufferUsageFlags
+    {
+        self.usage
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    pub fn get_size(&self) -> vk::DeviceSize
+    {
+        (self.len * std::mem::size_of::<T>()) as vk::DeviceSize
+    }
+
+    pub fn get_allocation(&self) -> &Allocation
+    {
+        self.allocation.as_ref().unwrap()
+    }
+
+    pub fn is_host_visible(&self) -> bool
+    {
+        self.get_allocation().get_mapped_ptr().is_some()
+    }
+
+    /// Maps all elements; see `map_range`.
+    pub fn map(&mut self) -> Result<BufferMapping<'_, T>, BufferError>
+    {
+        self.map_range(0..self.len)
+    }
+
+    /// Maps the elements in `range` as a `&mut [T]`.
+    ///
+    /// The memory stays mapped for the whole life of the buffer, so this is cheap; writes
+    /// are flushed when the mapping is dropped. The GPU must not be using the range meanwhile.
+    pub fn map_range(&mut self, range: Range<usize>) -> Result<BufferMapping<'_, T>, BufferError>
+    {
+        if range.start > range.end || range.end > self.len
+        {
+            return Err(BufferError::OutOfBounds(range, self.len));
+        }
+        let allocation = self.allocation.as_ref().unwrap();
+        let mapped = allocation.get_mapped_ptr().ok_or(BufferError::NotHostVisible)?;
+        if !(mapped as usize).is_multiple_of(std::mem::align_of::<T>())
+        {
+            return Err(BufferError::Misaligned(std::mem::align_of::<T>()));
+        }
+        // Another queue or an earlier frame may have written to it.
+        allocation.invalidate()?;
+        let elements = unsafe {
+            std::slice::from_raw_parts_mut((mapped as *mut T).add(range.start), range.end - range.start)
+        };
+        Ok(BufferMapping { elements, allocation })
+    }
+
+    /// Copies `data` into a host-visible buffer, starting at element `offset`.
+    pub fn write(&mut self, offset: usize, data: &[T]) -> Result<(), BufferError>
+    {
+        let mut mapping = self.map_range(offset..offset + data.len())?;
+        mapping.copy_from_slice(data);
+        Ok(())
+    }
+}
+impl<T: bytemuck::Pod> Drop for Buffer<T>
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_buffer(self.buffer, None) };
+        self.allocation = None;
+    }
+}
+
+/// Mapped elements of a host-visible `Buffer<T>`; indexing is bounds-checked like any slice.
+pub struct BufferMapping<'a, T>
+{
+    elements: &'a mut [T],
+    allocation: &'a Allocation,
+}
+impl<T> Deref for BufferMapping<'_, T>
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T]
+    {
+        self.elements
+    }
+}
+impl<T> DerefMut for BufferMapping<'_, T>
+{
+    fn deref_mut(&mut self) -> &mut [T]
+    {
+        self.elements
+    }
+}
+impl<T> Drop for BufferMapping<'_, T>
+{
+    fn drop(&mut self)
+    {
+        // Only fails when out of memory, which the next submission will report anyway.
+        let _ = self.allocation.flush();
+    }
+}
@@ -0,0 +1,46 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+
+/// Owns a `VkCommandPool`; command buffers allocated from it are freed together with it.
+pub struct CommandPool
+{
+    pool: vk::CommandPool,
+    device: Device,
+}
+impl CommandPool
+{
+    pub fn create(device: &Device, queue_family_index: u32, flags: vk::CommandPoolCreateFlags) -> Result<CommandPool, vk::Result>
+    {
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(flags);
+        let pool = unsafe { device.get_device().create_command_pool(&create_info, None) }?;
+        Ok(CommandPool { pool, device: device.clone() })
+    }
+
+    pub fn allocate(&self, level: vk::CommandBufferLevel, count: u32) -> Result<Vec<vk::CommandBuffer>, vk::Result>
+    {
+        let allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(self.pool)
+            .level(level)
+            .command_buffer_count(count);
+        unsafe { self.device.get_device().allocate_command_buffers(&allocate_info) }
+    }
+
+    pub fn free(&self, command_buffers: &[vk::CommandBuffer])
+    {
+        unsafe { self.device.get_device().free_command_buffers(self.pool, command_buffers) };
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.pool, name);
+    }
+}
+impl Drop for CommandPool
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_command_pool(self.pool, None) };
+    }
+}
@@ -0,0 +1,483 @@
+use crate::ludo::vulkan::{BarrierBatch, CommandPool, Compute, ComputePipeline, Device, Fence, PipelineLayout, Queue, ResourceState};
+use ash::vk;
+use std::collections::HashMap;
+
+/// How a dispatch uses a buffer or image.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access
+{
+    Read,
+    Write,
+    ReadWrite,
+}
+impl Access
+{
+    fn get_flags(&self) -> vk::AccessFlags
+    {
+        match self
+        {
+            Access::Read => vk::AccessFlags::SHADER_READ,
+            Access::Write => vk::AccessFlags::SHADER_WRITE,
+            Access::ReadWrite => vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        }
+    }
+}
+
+/// Records compute work into a command buffer, inserting the buffer and image barriers
+/// between dispatches that their declared accesses need.
+///
+/// Resources are tracked from their first use in the command buffer: a resource used for the
+/// first time is assumed to be ready, unless `import_buffer` or `import_image` says which
+/// earlier work still has to finish. `release_buffer` and `release_image` hand resources on
+/// to whatever comes after, e.g. a copy or the host reading the result.
+///
+/// ```ignore
+/// commands.bind(&pipeline, &layout, &[set]);
+/// commands.use_buffer(input, Access::Read).use_buffer(output, Access::Write);
+/// commands.dispatch_invocations([count, 1, 1]);
+/// commands.release_buffer(output, vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ);
+/// ```
+pub struct ComputeCommands
+{
+    command_buffer: vk::CommandBuffer,
+    buffers: HashMap<vk::Buffer, ResourceState>,
+    images: HashMap<vk::Image, ResourceState>,
+    barriers: BarrierBatch,
+    local_size: [u32; 3],
+    device: Device,
+}
+// Compute is library API the demo renderer does not dispatch yet.
+#[allow(dead_code)]
+impl ComputeCommands
+{
+    /// Records into `command_buffer`, which is already begun.
+    pub fn new(device: &Device, command_buffer: vk::CommandBuffer) -> ComputeCommands
+    {
+        ComputeCommands {
+            command_buffer,
+            buffers: HashMap::new(),
+            images: HashMap::new(),
+            barriers: BarrierBatch::default(),
+            local_size: [1, 1, 1],
+            device: device.clone(),
+        }
+    }
+
+    pub fn get_command_buffer(&self) -> vk::CommandBuffer
+    {
+        self.command_buffer
+    }
+
+    /// Opens a labeled region for debuggers and validation messages, closed by `end_label`.
+    pub fn begin_label(&mut self, name: &str) -> &mut Self
+    {
+        self.device.begin_label(self.command_buffer, name);
+        self
+    }
+
+    pub fn end_label(&mut self) -> &mut Self
+    {
+        self.device.end_label(self.command_buffer);
+        self
+    }
+
+    /// Declares the last use of `buffer` before this command buffer, e.g. a transfer write.
+    pub fn import_buffer(&mut self, buffer: vk::Buffer, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        self.buffers.insert(buffer, ResourceState::new(stage, access, vk::ImageLayout::UNDEFINED));
+    }
+
+    /// Declares the layout and last use of `image` before this command buffer.
+    pub fn import_image(&mut self, image: vk::Image, layout: vk::ImageLayout, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        self.images.insert(image, ResourceState::new(stage, access, layout));
+    }
+
+    /// Declares that the next dispatch accesses `buffer`.
+    pub fn use_buffer(&mut self, buffer: vk::Buffer, access: Access) -> &mut Self
+    {
+        self.add_buffer_barrier(buffer, vk::PipelineStageFlags::COMPUTE_SHADER, access.get_flags());
+        self
+    }
+
+    /// Declares that the next dispatch accesses `range` of `image` in `layout`: `GENERAL`
+    /// for storage images, `SHADER_READ_ONLY_OPTIMAL` for sampled ones.
+    pub fn use_image(&mut self, image: vk::Image, range: vk::ImageSubresourceRange, layout: vk::ImageLayout, access: Access) -> &mut Self
+    {
+        self.add_image_barrier(image, range, layout, vk::PipelineStageFlags::COMPUTE_SHADER, access.get_flags());
+        self
+    }
+
+    pub fn bind(&mut self, pipeline: &ComputePipeline, layout: &PipelineLayout, descriptor_sets: &[vk::DescriptorSet])
+    {
+        let device = self.device.get_device();
+        unsafe {
+            device.cmd_bind_pipeline(self.command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.get_handle());
+            if !descriptor_sets.is_empty()
+            {
+                device.cmd_bind_descriptor_sets(
+                    self.command_buffer, vk::PipelineBindPoint::COMPUTE, layout.get_handle(), 0, descriptor_sets, &[]);
+            }
+        }
+        self.local_size = pipeline.get_local_size();
+    }
+
+    /// Sets the push constants of `layout` from its start.
+    pub fn push_constants<T: bytemuck::Pod>(&mut self, layout: &PipelineLayout, data: &T)
+    {
+        let range = layout.get_push_constant_range().expect("pipeline layout has no push constants");
+        unsafe {
+            self.device.get_device().cmd_push_constants(
+                self.command_buffer, layout.get_handle(), range.stage_flags, 0, bytemuck::bytes_of(data));
+        }
+    }
+
+    /// Dispatches `groups` workgroups of the bound pipeline after the barriers it needs.
+    pub fn dispatch(&mut self, groups: [u32; 3])
+    {
+        self.flush_barriers();
+        unsafe { self.device.get_device().cmd_dispatch(self.command_buffer, groups[0], groups[1], groups[2]) };
+    }
+
+    /// Dispatches enough workgroups of the bound pipeline to cover `invocations`.
+    pub fn dispatch_invocations(&mut self, invocations: [u32; 3])
+    {
+        let groups = [0, 1, 2].map(|axis| invocations[axis].div_ceil(self.local_size[axis]));
+        self.dispatch(groups);
+    }
+
+    /// Makes the compute work on `buffer` visible to `access` in `stage`.
+    pub fn release_buffer(&mut self, buffer: vk::Buffer, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        self.add_buffer_barrier(buffer, stage, access);
+        self.flush_barriers();
+    }
+
+    /// Makes the compute work on `image` visible to `access` in `stage`, moving it to `layout`.
+    pub fn release_image(
+        &mut self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags)
+    {
+        self.add_image_barrier(image, range, layout, stage, access);
+        self.flush_barriers();
+    }
+
+    fn add_buffer_barrier(&mut self, buffer: vk::Buffer, stage: vk::PipelineStageFlags, access: vk::AccessFlags)
+    {
+        let barrier = match self.buffers.get_mut(&buffer)
+        {
+            Some(state) => state.transition(stage, access, vk::ImageLayout::UNDEFINED),
+            None =>
+            {
+                self.buffers.insert(buffer, ResourceState::new(stage, access, vk::ImageLayout::UNDEFINED));
+                None
+            }
+        };
+        if let Some(barrier) = barrier
+        {
+            self.barriers.add_buffer(&barrier, buffer);
+        }
+    }
+
+    fn add_image_barrier(
+        &mut self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags)
+    {
+        // An image never seen before has undefined contents, which a transition may discard.
+        let state = self.images
+            .entry(image)
+            .or_insert(ResourceState::new(vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty(), vk::ImageLayout::UNDEFINED));
+        if let Some(barrier) = state.transition(stage, access, layout)
+        {
+            self.barriers.add_image(&barrier, image, range);
+        }
+    }
+
+    fn flush_barriers(&mut self)
+    {
+        self.barriers.record(&self.device, self.command_buffer);
+    }
+}
+
+/// Submits compute work to the compute queue: a dedicated, asynchronous one where the
+/// device has it, the graphics queue otherwise.
+///
+/// Buffers, textures, offscreen targets and render graph resources are created `CONCURRENT`
+/// across the device's queue families whenever it uses more than one, so nothing needs an
+/// ownership transfer. Swapchain images are the exception: they are only shared between the
+/// graphics and present families. Ordering against graphics work is up to the semaphores
+/// given to `submit`.
+pub struct ComputeContext
+{
+    queue: Queue<Compute>,
+    in_flight: Vec<(vk::CommandBuffer, Fence)>,
+    idle: Vec<(vk::CommandBuffer, Fence)>,
+    command_pool: CommandPool,
+    device: Device,
+}
+// Compute is library API the demo renderer does not dispatch yet.
+#[allow(dead_code)]
+impl ComputeContext
+{
+    pub fn create(device: &Device) -> Result<ComputeContext, vk::Result>
+    {
+        let queue = device.get_compute_queue();
+        let command_pool = CommandPool::create(
+            device,
+            queue.get_family_index(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER | vk::CommandPoolCreateFlags::TRANSIENT)?;
+        command_pool.set_name("compute commands");
+        Ok(ComputeContext {
+            queue,
+            in_flight: Vec::new(),
+            idle: Vec::new(),
+            command_pool,
+            device: device.clone(),
+        })
+    }
+
+    /// Whether work runs on a queue of its own, next to the graphics work.
+    pub fn is_async(&self) -> bool
+    {
+        self.device.has_dedicated_compute_queue()
+    }
+
+    pub fn get_queue(&self) -> Queue<Compute>
+    {
+        self.queue
+    }
+
+    /// Records work with `record` and submits it, after `wait_semaphores` were signalled
+    /// and signalling `signal_semaphores` when done.
+    pub fn submit<F>(
+        &mut self,
+        wait_semaphores: &[(vk::Semaphore, vk::PipelineStageFlags)],
+        signal_semaphores: &[vk::Semaphore],
+        record: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(&mut ComputeCommands) -> Result<(), vk::Result>
+    {
+        self.reclaim()?;
+        let (command_buffer, fence) = match self.idle.pop()
+        {
+            Some(idle) => idle,
+            None => (self.command_pool.allocate(vk::CommandBufferLevel::PRIMARY, 1)?[0], Fence::create(&self.device, false)?),
+        };
+        let device = self.device.get_device();
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let recorded = unsafe { device.begin_command_buffer(command_buffer, &begin_info) }
+            .and_then(|_| record(&mut ComputeCommands::new(&self.device, command_buffer)))
+            .and_then(|_| unsafe { device.end_command_buffer(command_buffer) });
+        if let Err(error) = recorded
+        {
+            unsafe { device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty()) }?;
+            self.idle.push((command_buffer, fence));
+            return Err(error);
+        }
+
+        let (semaphores, stages): (Vec<_>, Vec<_>) = wait_semaphores.iter().copied().unzip();
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&semaphores)
+            .wait_dst_stage_mask(&stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(signal_semaphores);
+        unsafe { device.queue_submit(self.queue.get_handle(), &[submit_info.build()], fence.get_handle()) }?;
+        self.in_flight.push((command_buffer, fence));
+        Ok(())
+    }
+
+    /// Submits work recorded by `record` and waits for it, e.g. before reading results back.
+    pub fn run<F>(&mut self, record: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(&mut ComputeCommands) -> Result<(), vk::Result>
+    {
+        self.submit(&[], &[], record)?;
+        self.wait_idle()
+    }
+
+    /// Blocks until all submitted work has completed.
+    pub fn wait_idle(&mut self) -> Result<(), vk::Result>
+    {
+        for (_, fence) in &self.in_flight
+        {
+            fence.wait(u64::MAX)?;
+        }
+        self.reclaim()
+    }
+
+    /// Recycles the command buffers of finished submissions.
+    fn reclaim(&mut self) -> Result<(), vk::Result>
+    {
+        let mut index = 0;
+        while index < self.in_flight.len()
+        {
+            if self.in_flight[index].1.is_signaled()?
+            {
+                let (command_buffer, fence) = self.in_flight.swap_remove(index);
+                fence.reset()?;
+                self.idle.push((command_buffer, fence));
+            }
+            else
+            {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+}
+impl Drop for ComputeContext
+{
+    fn drop(&mut self)
+    {
+        // Command buffers must not be pending when their pool is destroyed.
+        for (_, fence) in &self.in_flight
+        {
+            let _ = fence.wait(u64::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::ludo::golden::get_test_device;
+    use crate::ludo::shaders::{PARTICLES_COMP, PREFIX_SUM_COMP};
+    use crate::ludo::vulkan::{Allocator, Buffer, DescriptorAllocator, MemoryUsage, ShaderModule};
+
+    /// Points `set` at `buffers`, in binding order.
+    fn write_storage_buffers(device: &Device, set: vk::DescriptorSet, buffers: &[vk::Buffer])
+    {
+        let infos: Vec<_> = buffers
+            .iter()
+            .map(|buffer| [vk::DescriptorBufferInfo { buffer: *buffer, offset: 0, range: vk::WHOLE_SIZE }])
+            .collect();
+        let writes: Vec<_> = infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(info)
+                .build())
+            .collect();
+        unsafe { device.get_device().update_descriptor_sets(&writes, &[]) };
+    }
+
+    #[test]
+    fn prefix_sum_matches_the_cpu()
+    {
+        let Some(test) = get_test_device() else { return };
+        let device = test.get_device();
+        let allocator = Allocator::create(device);
+        let shader = ShaderModule::from_bytes(device, PREFIX_SUM_COMP).unwrap();
+        let layout = PipelineLayout::from_shaders(device, &[&shader]).unwrap();
+        let pipeline = ComputePipeline::create(&layout, &shader, None).unwrap();
+
+        let values: Vec<u32> = (0..1000u32).map(|i| i.wrapping_mul(2654435761) % 100).collect();
+        let mut buffers: Vec<Buffer<u32>> = (0..2)
+            .map(|_| Buffer::create(&allocator, values.len(), vk::BufferUsageFlags::STORAGE_BUFFER, MemoryUsage::Readback).unwrap())
+            .collect();
+        buffers[0].write(0, &values).unwrap();
+        let handles = [buffers[0].get_handle(), buffers[1].get_handle()];
+
+        let mut descriptors = DescriptorAllocator::create(device, 1);
+        let sets = [
+            descriptors.allocate_for(&layout, 0).unwrap(),
+            descriptors.allocate_for(&layout, 0).unwrap(),
+        ];
+        write_storage_buffers(device, sets[0], &handles);
+        write_storage_buffers(device, sets[1], &[handles[1], handles[0]]);
+
+        let mut context = ComputeContext::create(device).unwrap();
+        let mut steps = 0;
+        context.run(|commands| {
+            let mut offset = 1;
+            while offset < values.len() as u32
+            {
+                let (src, dst) = (handles[steps % 2], handles[(steps + 1) % 2]);
+                commands.bind(&pipeline, &layout, &[sets[steps % 2]]);
+                commands.push_constants(&layout, &[offset, values.len() as u32]);
+                commands.use_buffer(src, Access::Read).use_buffer(dst, Access::Write);
+                commands.dispatch_invocations([values.len() as u32, 1, 1]);
+                offset *= 2;
+                steps += 1;
+            }
+            commands.release_buffer(handles[steps % 2], vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ);
+            Ok(())
+        }).unwrap();
+
+        let expected: Vec<u32> = values.iter().scan(0, |sum, value| { *sum += value; Some(*sum) }).collect();
+        assert_eq!(&buffers[steps % 2].map().unwrap()[..], &expected[..]);
+        test.assert_no_errors();
+    }
+
+    #[test]
+    fn particles_bounce_off_the_floor()
+    {
+        let Some(test) = get_test_device() else { return };
+        let device = test.get_device();
+        let allocator = Allocator::create(device);
+        let shader = ShaderModule::from_bytes(device, PARTICLES_COMP).unwrap();
+        let layout = PipelineLayout::from_shaders(device, &[&shader]).unwrap();
+        let pipeline = ComputePipeline::create(&layout, &shader, None).unwrap();
+        assert_eq!(pipeline.get_local_size(), [64, 1, 1]);
+
+        // Position and velocity of each particle.
+        let mut particles: Vec<[f32; 4]> = (0..100).map(|i| [i as f32, 1.0 + i as f32 * 0.01, 0.5, 0.0]).collect();
+        let mut buffer: Buffer<[f32; 4]> =
+            Buffer::create(&allocator, particles.len(), vk::BufferUsageFlags::STORAGE_BUFFER, MemoryUsage::Readback).unwrap();
+        buffer.write(0, &particles).unwrap();
+        let mut descriptors = DescriptorAllocator::create(device, 1);
+        let set = descriptors.allocate_for(&layout, 0).unwrap();
+        write_storage_buffers(device, set, &[buffer.get_handle()]);
+
+        let (delta_time, gravity, steps) = (0.05f32, 9.81f32, 20);
+        let mut context = ComputeContext::create(device).unwrap();
+        context.run(|commands| {
+            commands.bind(&pipeline, &layout, &[set]);
+            for _ in 0..steps
+            {
+                commands.push_constants(&layout, &[delta_time.to_bits(), gravity.to_bits(), particles.len() as u32]);
+                commands.use_buffer(buffer.get_handle(), Access::ReadWrite);
+                commands.dispatch_invocations([particles.len() as u32, 1, 1]);
+            }
+            commands.release_buffer(buffer.get_handle(), vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ);
+            Ok(())
+        }).unwrap();
+
+        for _ in 0..steps
+        {
+            for particle in &mut particles
+            {
+                particle[3] -= gravity * delta_time;
+                particle[0] += particle[2] * delta_time;
+                particle[1] += particle[3] * delta_time;
+                if particle[1] < 0.0
+                {
+                    particle[1] = -particle[1];
+                    particle[3] = -particle[3];
+                }
+            }
+        }
+        let mapping = buffer.map().unwrap();
+        for (actual, expected) in mapping.iter().zip(&particles)
+        {
+            assert!(actual.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-3), "{:?} != {:?}", actual, expected);
+            assert!(actual[1] >= 0.0);
+        }
+        test.assert_no_errors();
+    }
+}
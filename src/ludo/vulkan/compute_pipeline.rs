@@ -0,0 +1,66 @@
+use crate::ludo::vulkan::{Device, PipelineCache, PipelineError, PipelineLayout, ShaderModule};
+use ash::vk;
+
+/// A `VkPipeline` running one compute shader, with the workgroup size it was written for.
+pub struct ComputePipeline
+{
+    pipeline: vk::Pipeline,
+    local_size: [u32; 3],
+    device: Device,
+}
+// Created by users of `ComputeContext`; the demo renderer does not dispatch yet.
+#[allow(dead_code)]
+impl ComputePipeline
+{
+    /// Creates the pipeline of `shader`, whose layout usually comes from
+    /// `PipelineLayout::from_shaders(device, &[&shader])`.
+    pub fn create(layout: &PipelineLayout, shader: &ShaderModule, cache: Option<&PipelineCache>) -> Result<ComputePipeline, PipelineError>
+    {
+        if shader.get_stage() != vk::ShaderStageFlags::COMPUTE || layout.get_stages() != vk::ShaderStageFlags::COMPUTE
+        {
+            return Err(PipelineError::WrongStages(shader.get_stage() | layout.get_stages()));
+        }
+        let device = layout.get_device();
+        let create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(shader.get_stage_info())
+            .layout(layout.get_handle());
+        let cache = cache.map_or(vk::PipelineCache::null(), PipelineCache::get_handle);
+        let pipelines = unsafe {
+            device.get_device().create_compute_pipelines(cache, &[create_info.build()], None)
+        }.map_err(|(_, error)| error)?;
+        Ok(ComputePipeline {
+            pipeline: pipelines[0],
+            local_size: shader.get_reflection().local_size.unwrap_or([1, 1, 1]),
+            device: device.clone(),
+        })
+    }
+
+    pub fn get_handle(&self) -> vk::Pipeline
+    {
+        self.pipeline
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.pipeline, name);
+    }
+
+    /// Invocations per workgroup in each dimension, as declared by the shader.
+    pub fn get_local_size(&self) -> [u32; 3]
+    {
+        self.local_size
+    }
+
+    /// Workgroups needed to cover `invocations`, rounding up; shaders check their bounds.
+    pub fn get_group_count(&self, invocations: [u32; 3]) -> [u32; 3]
+    {
+        [0, 1, 2].map(|axis| invocations[axis].div_ceil(self.local_size[axis]))
+    }
+}
+impl Drop for ComputePipeline
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_pipeline(self.pipeline, None) };
+    }
+}
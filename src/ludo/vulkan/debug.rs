@@ -0,0 +1,292 @@
+use crate::ludo::vulkan::Instance;
+use ash::extensions::ext;
+use ash::vk;
+use std::ffi::CStr;
+use std::sync::Arc;
+
+pub const VALIDATION_LAYER_NAME: &str = "VK_LAYER_KHRONOS_validation";
+
+#[derive(Clone, Debug)]
+pub struct DebugMessage
+{
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub message_id_name: String,
+    pub message_id_number: i32,
+    pub message: String,
+    pub object_names: Vec<String>,
+}
+#[cfg(test)]
+impl DebugMessage
+{
+    pub fn is_error(&self) -> bool
+    {
+        self.severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    }
+}
+impl std::fmt::Display for DebugMessage
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "[{:?}][{:?}] {} ({:#x}): {}",
+            self.severity, self.message_type, self.message_id_name, self.message_id_number, self.message)?;
+        if !self.object_names.is_empty()
+        {
+            write!(f, " [objects: {}]", self.object_names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives the messages reported through `VK_EXT_debug_utils`.
+///
+/// Drivers may invoke the callback from any thread, hence the `Send + Sync` bound.
+pub trait DebugSink: Send + Sync
+{
+    fn log(&self, message: &DebugMessage);
+}
+
+/// Default sink, prints every message to stderr.
+pub struct StderrSink;
+impl DebugSink for StderrSink
+{
+    fn log(&self, message: &DebugMessage)
+    {
+        eprintln!("{}", message);
+    }
+}
+
+/// Keeps every message, so tests can inspect them or fail on validation errors.
+#[cfg(test)]
+#[derive(Default)]
+pub struct CollectingSink
+{
+    messages: std::sync::Mutex<Vec<DebugMessage>>,
+}
+#[cfg(test)]
+impl CollectingSink
+{
+    pub fn get_messages(&self) -> Vec<DebugMessage>
+    {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub fn get_errors(&self) -> Vec<DebugMessage>
+    {
+        self.messages.lock().unwrap().iter().filter(|message| message.is_error()).cloned().collect()
+    }
+
+    /// Lists every error message collected so far, one per line.
+    pub fn check_no_errors(&self) -> Result<(), String>
+    {
+        let errors = self.get_errors();
+        if errors.is_empty()
+        {
+            return Ok(());
+        }
+        let lines : Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        Err(format!("{} validation error(s):\n{}", errors.len(), lines.join("\n")))
+    }
+
+    /// Panics listing every error message collected so far.
+    pub fn assert_no_errors(&self)
+    {
+        if let Err(errors) = self.check_no_errors()
+        {
+            panic!("{}", errors);
+        }
+    }
+}
+#[cfg(test)]
+impl DebugSink for CollectingSink
+{
+    fn log(&self, message: &DebugMessage)
+    {
+        self.messages.lock().unwrap().push(message.clone());
+    }
+}
+
+/// Owns a `VkDebugUtilsMessengerEXT` that forwards every message to a `DebugSink`.
+pub struct DebugMessenger
+{
+    messenger: vk::DebugUtilsMessengerEXT,
+    loader: ext::DebugUtils,
+    // Passed to the callback as user data, freed after the messenger is destroyed.
+    p_sink: *mut Arc<dyn DebugSink>,
+    _instance: Instance,
+}
+impl DebugMessenger
+{
+    /// The instance has to be created with `VK_EXT_debug_utils` enabled.
+    pub fn create(
+        instance: &Instance,
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        sink: Arc<dyn DebugSink>) -> Result<DebugMessenger, vk::Result>
+    {
+        let loader = ext::DebugUtils::new(instance.get_entry(), instance.get_instance());
+        let p_sink = Box::into_raw(Box::new(sink));
+        let create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(severity)
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE)
+            .pfn_user_callback(Some(debug_callback))
+            .user_data(p_sink as *mut libc::c_void);
+        let result = unsafe { loader.create_debug_utils_messenger(&create_info, None) };
+        match result
+        {
+            Ok(messenger) => Ok(DebugMessenger {
+                messenger,
+                loader,
+                p_sink,
+                _instance: instance.clone(),
+            }),
+            Err(error) =>
+            {
+                drop(unsafe { Box::from_raw(p_sink) });
+                Err(error)
+            }
+        }
+    }
+}
+impl Drop for DebugMessenger
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.loader.destroy_debug_utils_messenger(self.messenger, None);
+            drop(Box::from_raw(self.p_sink));
+        }
+    }
+}
+
+unsafe fn get_str(p_str: *const libc::c_char) -> String
+{
+    if p_str.is_null()
+    {
+        String::new()
+    }
+    else
+    {
+        CStr::from_ptr(p_str).to_string_lossy().into_owned()
+    }
+}
+
+unsafe extern "system" fn debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut libc::c_void) -> vk::Bool32
+{
+    if p_callback_data.is_null() || p_user_data.is_null()
+    {
+        return vk::FALSE;
+    }
+    let callback_data = &*p_callback_data;
+    let sink = &*(p_user_data as *const Arc<dyn DebugSink>);
+    let mut object_names : Vec<String> = Vec::with_capacity(callback_data.object_count as usize);
+    if !callback_data.p_objects.is_null()
+    {
+        let objects = std::slice::from_raw_parts(callback_data.p_objects, callback_data.object_count as usize);
+        for object in objects
+        {
+            let name = get_str(object.p_object_name);
+            object_names.push(if name.is_empty()
+            {
+                format!("{:?} {:#x}", object.object_type, object.object_handle)
+            }
+            else
+            {
+                format!("{:?} {:#x} \"{}\"", object.object_type, object.object_handle, name)
+            });
+        }
+    }
+    let message = DebugMessage {
+        severity: message_severity,
+        message_type,
+        message_id_name: get_str(callback_data.p_message_id_name),
+        message_id_number: callback_data.message_id_number,
+        message: get_str(callback_data.p_message),
+        object_names,
+    };
+    // Unwinding into the driver is undefined behaviour, so a panicking sink is contained here.
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sink.log(&message)));
+    vk::FALSE
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Calls `debug_callback` the way the loader would, with `sink` as user data.
+    fn report(sink: &Arc<dyn DebugSink>, severity: vk::DebugUtilsMessageSeverityFlagsEXT, message: &CStr, objects: &[vk::DebugUtilsObjectNameInfoEXT])
+    {
+        let callback_data = vk::DebugUtilsMessengerCallbackDataEXT::builder()
+            .message_id_name(c"VUID-test")
+            .message_id_number(0x1234)
+            .message(message)
+            .objects(objects);
+        let p_sink = sink as *const Arc<dyn DebugSink> as *mut libc::c_void;
+        let result = unsafe { debug_callback(severity, vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION, &*callback_data, p_sink) };
+        assert_eq!(result, vk::FALSE);
+    }
+
+    #[test]
+    fn callback_forwards_messages_to_the_sink()
+    {
+        let collecting = Arc::new(CollectingSink::default());
+        let sink: Arc<dyn DebugSink> = collecting.clone();
+        let objects = [
+            vk::DebugUtilsObjectNameInfoEXT::builder()
+                .object_type(vk::ObjectType::BUFFER)
+                .object_handle(0x10)
+                .object_name(c"vertices")
+                .build(),
+            vk::DebugUtilsObjectNameInfoEXT { object_type: vk::ObjectType::IMAGE, object_handle: 0x20, ..Default::default() },
+        ];
+        report(&sink, vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, c"slow path", &[]);
+        report(&sink, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"bad usage", &objects);
+
+        let messages = collecting.get_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!((messages[1].message_id_name.as_str(), messages[1].message_id_number), ("VUID-test", 0x1234));
+        assert_eq!(messages[1].message, "bad usage");
+        assert_eq!(messages[1].object_names, ["BUFFER 0x10 \"vertices\"", "IMAGE 0x20"]);
+        let errors = collecting.get_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(collecting.check_no_errors().unwrap_err().contains("bad usage"));
+    }
+
+    #[test]
+    #[should_panic(expected = "1 validation error(s)")]
+    fn assert_no_errors_fails_on_errors()
+    {
+        let sink = CollectingSink::default();
+        sink.log(&DebugMessage {
+            severity: vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            message_type: vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            message_id_name: String::new(),
+            message_id_number: 0,
+            message: "bad usage".to_owned(),
+            object_names: Vec::new(),
+        });
+        sink.assert_no_errors();
+    }
+
+    #[test]
+    fn panicking_sinks_do_not_unwind_into_the_driver()
+    {
+        struct PanickingSink;
+        impl DebugSink for PanickingSink
+        {
+            fn log(&self, _: &DebugMessage)
+            {
+                panic!("sink failed");
+            }
+        }
+        let sink: Arc<dyn DebugSink> = Arc::new(PanickingSink);
+        report(&sink, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"bad usage", &[]);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ludo::vulkan::{Device, PipelineLayout};
+use ash::vk;
+
+/// Descriptors of each type a pool holds per set it is sized for.
+const POOL_RATIOS: [(vk::DescriptorType, f32); 11] = [
+    (vk::DescriptorType::SAMPLER, 0.5),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 1.0),
+    (vk::DescriptorType::STORAGE_TEXEL_BUFFER, 1.0),
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 0.5),
+];
+const FIRST_POOL_SETS: u32 = 64;
+const MAX_POOL_SETS: u32 = 4096;
+
+fn get_pool_sizes(sets: u32) -> Vec<vk::DescriptorPoolSize>
+{
+    POOL_RATIOS
+        .iter()
+        .map(|(ty, ratio)| vk::DescriptorPoolSize {
+            ty: *ty,
+            descriptor_count: ((sets as f32 * ratio).ceil() as u32).max(1),
+        })
+        .collect()
+}
+
+/// Pools a frame slot allocated from since it was last recycled.
+#[derive(Default)]
+struct FramePools
+{
+    /// Pools that ran out of space.
+    full: Vec<vk::DescriptorPool>,
+    active: Option<vk::DescriptorPool>,
+}
+
+/// Hands out descriptor sets that live for one frame.
+///
+/// Every frame slot allocates from its own pools, growing by a new and larger pool whenever
+/// the current one is exhausted. `begin_frame` resets the pools of a slot at once and keeps
+/// them for reuse, so no set is ever freed on its own.
+pub struct DescriptorAllocator
+{
+    frames: Vec<FramePools>,
+    current: usize,
+    free_pools: Vec<vk::DescriptorPool>,
+    pool_count: usize,
+    next_pool_sets: u32,
+    /// Debug name of the pools, numbered in creation order.
+    name: String,
+    device: Device,
+}
+// The demo pipeline has no descriptor sets, so only other users allocate them.
+#[allow(dead_code)]
+impl DescriptorAllocator
+{
+    pub fn create(device: &Device, frames_in_flight: usize) -> DescriptorAllocator
+    {
+        DescriptorAllocator {
+            frames: (0..frames_in_flight.max(1)).map(|_| FramePools::default()).collect(),
+            current: 0,
+            free_pools: Vec::new(),
+            pool_count: 0,
+            next_pool_sets: FIRST_POOL_SETS,
+            name: "descriptor allocator".to_owned(),
+            device: device.clone(),
+        }
+    }
+
+    /// Recycles the sets of `frame_slot` and makes it the slot later allocations belong to.
+    ///
+    /// Only call it once the fence of the slot was waited on, as `FramesInFlight::draw` does
+    /// before recording; the sets must no longer be in use by the GPU.
+    pub fn begin_frame(&mut self, frame_slot: usize) -> Result<(), vk::Result>
+    {
+        self.current = frame_slot % self.frames.len();
+        let frame = &mut self.frames[self.current];
+        for pool in frame.full.drain(..).chain(frame.active.take())
+        {
+            unsafe { self.device.get_device().reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty()) }?;
+            self.free_pools.push(pool);
+        }
+        Ok(())
+    }
+
+    /// Allocates a set of `layout` that stays valid until its frame slot is recycled.
+    pub fn allocate(&mut self, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, vk::Result>
+    {
+        let layouts = [layout];
+        // A fresh pool always fits one set, so the second attempt only fails for real.
+        for _ in 0..2
+        {
+            let pool = match self.frames[self.current].active
+            {
+                Some(pool) => pool,
+                None => self.get_pool()?,
+            };
+            self.frames[self.current].active = Some(pool);
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+            match unsafe { self.device.get_device().allocate_descriptor_sets(&allocate_info) }
+            {
+                Ok(sets) => return Ok(sets[0]),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) =>
+                {
+                    let frame = &mut self.frames[self.current];
+                    frame.full.extend(frame.active.take());
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+    }
+
+    /// Allocates set number `set` of a pipeline layout.
+    pub fn allocate_for(&mut self, layout: &PipelineLayout, set: u32) -> Result<vk::DescriptorSet, vk::Result>
+    {
+        self.allocate(layout.get_set_layouts()[set as usize])
+    }
+
+    /// Names the pools created from now on.
+    pub fn set_name(&mut self, name: &str)
+    {
+        self.name = name.to_owned();
+    }
+
+    /// Number of pools created so far, in use or not.
+    pub fn get_pool_count(&self) -> usize
+    {
+        self.pool_count
+    }
+
+    fn get_pool(&mut self) -> Result<vk::DescriptorPool, vk::Result>
+    {
+        if let Some(pool) = self.free_pools.pop()
+        {
+            return Ok(pool);
+        }
+        let pool_sizes = get_pool_sizes(self.next_pool_sets);
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(self.next_pool_sets)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { self.device.get_device().create_descriptor_pool(&create_info, None) }?;
+        if self.device.is_debug_utils_enabled()
+        {
+            self.device.set_object_name(pool, &format!("{} pool {}", self.name, self.pool_count));
+        }
+        self.pool_count += 1;
+        self.next_pool_sets = (self.next_pool_sets * 2).min(MAX_POOL_SETS);
+        Ok(pool)
+    }
+}
+impl Drop for DescriptorAllocator
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        let frame_pools = self.frames.iter().flat_map(|frame| frame.full.iter().chain(frame.active.iter()));
+        for pool in frame_pools.chain(self.free_pools.iter())
+        {
+            unsafe { device.destroy_descriptor_pool(*pool, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn pool_sizes_scale_with_sets()
+    {
+        let sizes = get_pool_sizes(64);
+        assert_eq!(sizes.len(), POOL_RATIOS.len());
+        let count = |ty| sizes.iter().find(|size| size.ty == ty).unwrap().descriptor_count;
+        assert_eq!(count(vk::DescriptorType::COMBINED_IMAGE_SAMPLER), 256);
+        assert_eq!(count(vk::DescriptorType::SAMPLER), 32);
+        // Every type gets at least one descriptor, however small the pool.
+        assert!(get_pool_sizes(1).iter().all(|size| size.descriptor_count >= 1));
+    }
+}
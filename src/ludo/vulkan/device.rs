@@ -0,0 +1,476 @@
+use crate::rc_string::RCString;
+use crate::ludo::vulkan::{PhysicalDevice, Surface};
+use ash::extensions::ext;
+use ash::vk;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Marker types for `Queue`, so work can only be submitted to a queue of the right kind.
+pub enum Graphics {}
+pub enum Present {}
+pub enum Compute {}
+pub enum Transfer {}
+
+pub struct Queue<K>
+{
+    handle: vk::Queue,
+    family_index: u32,
+    _kind: PhantomData<K>,
+}
+impl<K> Clone for Queue<K>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+impl<K> Copy for Queue<K> {}
+impl<K> Queue<K>
+{
+    pub fn get_handle(&self) -> vk::Queue
+    {
+        self.handle
+    }
+
+    pub fn get_family_index(&self) -> u32
+    {
+        self.family_index
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueFamilyIndices
+{
+    pub graphics: Option<u32>,
+    pub present: Option<u32>,
+    pub compute: Option<u32>,
+    pub transfer: Option<u32>,
+}
+impl QueueFamilyIndices
+{
+    /// Prefers families dedicated to compute and transfer, falling back to the graphics family.
+    pub fn find(physical_device: &PhysicalDevice, surface: Option<&Surface>) -> QueueFamilyIndices
+    {
+        let families = physical_device.get_queue_families();
+        let find_family = |include: vk::QueueFlags, exclude: vk::QueueFlags| families
+            .iter()
+            .position(|family| family.queue_count > 0
+                && family.queue_flags.contains(include)
+                && !family.queue_flags.intersects(exclude))
+            .map(|index| index as u32);
+
+        let graphics = find_family(vk::QueueFlags::GRAPHICS, vk::QueueFlags::empty());
+        let present = surface.and_then(|surface| {
+            let supports = |index: u32| physical_device.get_surface_support(index, surface).unwrap_or(false);
+            // Presenting from the graphics family avoids ownership transfers of swapchain images.
+            graphics
+                .filter(|index| supports(*index))
+                .or_else(|| (0..families.len() as u32).find(|index| supports(*index)))
+        });
+        let compute = find_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS)
+            .or_else(|| find_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::empty()));
+        // Graphics and compute queues implicitly support transfer operations.
+        let transfer = find_family(vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            .or_else(|| find_family(vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS))
+            .or(compute)
+            .or(graphics);
+        QueueFamilyIndices { graphics, present, compute, transfer }
+    }
+
+    pub fn get_unique(&self) -> Vec<u32>
+    {
+        let mut unique : Vec<u32> = Vec::with_capacity(4);
+        for index in [self.graphics, self.present, self.compute, self.transfer].into_iter().flatten()
+        {
+            if !unique.contains(&index)
+            {
+                unique.push(index);
+            }
+        }
+        unique
+    }
+}
+
+#[derive(Default)]
+pub struct DeviceCreateInfo<'a>
+{
+    pub enabled_extension_names: Vec<RCString>,
+    /// Extensions that are enabled only when the device supports them.
+    pub optional_extension_names: Vec<RCString>,
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    /// Enables dynamic rendering, for render passes without `VkRenderPass`: the core feature
+    /// on Vulkan 1.3, `VK_KHR_dynamic_rendering` before.
+    pub dynamic_rendering: bool,
+    /// Enables the Vulkan 1.2 `timelineSemaphore` feature.
+    pub timeline_semaphore: bool,
+    /// Enables the Vulkan 1.3 `synchronization2` feature.
+    pub synchronization2: bool,
+    /// Enables `VK_EXT_descriptor_indexing` with these of its features.
+    pub descriptor_indexing: Option<vk::PhysicalDeviceDescriptorIndexingFeatures>,
+    /// When set, a queue able to present to this surface is created.
+    pub surface: Option<&'a Surface>,
+}
+
+#[derive(Debug)]
+pub enum DeviceError
+{
+    ExtensionsNotPresent(Vec<String>),
+    FeaturesNotPresent(Vec<&'static str>),
+    NoGraphicsQueue,
+    NoPresentQueue,
+    CreationFailed(vk::Result),
+}
+impl std::fmt::Display for DeviceError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            DeviceError::ExtensionsNotPresent(names) =>
+                write!(f, "device extensions {} are not supported", names.join(", ")),
+            DeviceError::FeaturesNotPresent(names) =>
+                write!(f, "device features {} are not supported", names.join(", ")),
+            DeviceError::NoGraphicsQueue =>
+                write!(f, "device has no graphics queue family"),
+            DeviceError::NoPresentQueue =>
+                write!(f, "device has no queue family able to present to the surface"),
+            DeviceError::CreationFailed(result) =>
+                write!(f, "vkCreateDevice failed ({})", result),
+        }
+    }
+}
+impl std::error::Error for DeviceError {}
+
+struct DeviceHandle
+{
+    device: ash::Device,
+    queue_families: QueueFamilyIndices,
+    enabled_extension_names: Vec<RCString>,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    dynamic_rendering: bool,
+    timeline_semaphore: bool,
+    synchronization2: bool,
+    descriptor_indexing: Option<vk::PhysicalDeviceDescriptorIndexingFeatures>,
+    /// Loaded when the instance has `VK_EXT_debug_utils`.
+    debug_utils: Option<ext::DebugUtils>,
+    // Keeps the instance alive until the device is destroyed.
+    physical_device: PhysicalDevice,
+}
+impl Drop for DeviceHandle
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+        }
+        println!("Vulkan device was destroyed.");
+    }
+}
+
+/// Handle to a created `VkDevice`.
+///
+/// Objects created from the device keep a clone of this handle, so `vkDestroyDevice` only
+/// runs after the last of them is destroyed, and always before `vkDestroyInstance`.
+#[derive(Clone)]
+pub struct Device
+{
+    handle: Rc<DeviceHandle>,
+}
+impl Device
+{
+    pub fn create(physical_device: &PhysicalDevice, info: &DeviceCreateInfo) -> Result<Device, DeviceError>
+    {
+        let mut missing_features = physical_device.get_missing_features(&info.enabled_features);
+        let modern_features = physical_device.get_modern_features();
+        if info.timeline_semaphore && !modern_features.timeline_semaphore
+        {
+            missing_features.push("timelineSemaphore");
+        }
+        if info.synchronization2 && !modern_features.synchronization2
+        {
+            missing_features.push("synchronization2");
+        }
+        if !missing_features.is_empty()
+        {
+            return Err(DeviceError::FeaturesNotPresent(missing_features));
+        }
+        let mut required_extension_names = info.enabled_extension_names.clone();
+        if info.dynamic_rendering && !modern_features.dynamic_rendering
+        {
+            // Plus what it depends on before Vulkan 1.2; naming them again on 1.2 is harmless.
+            required_extension_names.extend([
+                vk::KhrDynamicRenderingFn::name(),
+                vk::KhrDepthStencilResolveFn::name(),
+                vk::KhrCreateRenderpass2Fn::name(),
+                vk::KhrMultiviewFn::name(),
+                vk::KhrMaintenance2Fn::name(),
+            ].map(RCString::from_cstr));
+        }
+        if info.descriptor_indexing.is_some()
+        {
+            required_extension_names.extend([
+                vk::ExtDescriptorIndexingFn::name(),
+                vk::KhrMaintenance3Fn::name(),
+            ].map(RCString::from_cstr));
+        }
+        let enabled_extension_names = crate::ludo::vulkan::select_extensions(
+            physical_device.get_extensions(),
+            &required_extension_names,
+            &info.optional_extension_names)
+            .map_err(DeviceError::ExtensionsNotPresent)?;
+
+        let queue_families = QueueFamilyIndices::find(physical_device, info.surface);
+        if queue_families.graphics.is_none()
+        {
+            return Err(DeviceError::NoGraphicsQueue);
+        }
+        if info.surface.is_some() && queue_families.present.is_none()
+        {
+            return Err(DeviceError::NoPresentQueue);
+        }
+
+        let queue_priorities = [1.0f32];
+        let queue_create_infos : Vec<vk::DeviceQueueCreateInfo> = queue_families
+            .get_unique()
+            .into_iter()
+            .map(|family_index| vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(family_index)
+                .queue_priorities(&queue_priorities)
+                .build())
+            .collect();
+        let extension_names : Vec<*const libc::c_char> = enabled_extension_names
+            .iter()
+            .map(|name| name.get_cstr().as_ptr())
+            .collect();
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::builder()
+            .dynamic_rendering(true);
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&queue_create_infos)
+            .enabled_extension_names(&extension_names)
+            .enabled_features(&info.enabled_features);
+        if info.dynamic_rendering
+        {
+            device_create_info = device_create_info.push_next(&mut dynamic_rendering_features);
+        }
+        let mut timeline_semaphore_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::builder()
+            .timeline_semaphore(true);
+        if info.timeline_semaphore
+        {
+            device_create_info = device_create_info.push_next(&mut timeline_semaphore_features);
+        }
+        let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::builder()
+            .synchronization2(true);
+        if info.synchronization2
+        {
+            device_create_info = device_create_info.push_next(&mut synchronization2_features);
+        }
+        let mut descriptor_indexing_features = info.descriptor_indexing.unwrap_or_default();
+        descriptor_indexing_features.p_next = std::ptr::null_mut();
+        if info.descriptor_indexing.is_some()
+        {
+            device_create_info = device_create_info.push_next(&mut descriptor_indexing_features);
+        }
+        let instance = physical_device.get_instance();
+        let debug_utils = instance
+            .get_enabled_extensions()
+            .iter()
+            .any(|name| name.get_cstr() == ext::DebugUtils::name())
+            .then(|| ext::DebugUtils::new(instance.get_entry(), instance.get_instance()));
+        let instance = instance.get_instance();
+        let device = unsafe { instance.create_device(physical_device.get_handle(), &device_create_info, None) }
+            .map_err(DeviceError::CreationFailed)?;
+        let handle = DeviceHandle {
+            device,
+            queue_families,
+            enabled_extension_names,
+            enabled_features: info.enabled_features,
+            dynamic_rendering: info.dynamic_rendering,
+            timeline_semaphore: info.timeline_semaphore,
+            synchronization2: info.synchronization2,
+            descriptor_indexing: info.descriptor_indexing.map(|features| vk::PhysicalDeviceDescriptorIndexingFeatures {
+                p_next: std::ptr::null_mut(),
+                ..features
+            }),
+            debug_utils,
+            physical_device: physical_device.clone(),
+        };
+        let device = Device { handle: Rc::new(handle) };
+        if device.is_debug_utils_enabled()
+        {
+            device.set_object_name(device.get_device().handle(), physical_device.get_name());
+            device.set_object_name(device.get_graphics_queue().get_handle(), "graphics queue");
+            if let Some(queue) = device.get_present_queue()
+            {
+                device.set_object_name(queue.get_handle(), "present queue");
+            }
+            if device.has_dedicated_compute_queue()
+            {
+                device.set_object_name(device.get_compute_queue().get_handle(), "compute queue");
+            }
+            if device.has_dedicated_transfer_queue()
+            {
+                device.set_object_name(device.get_transfer_queue().get_handle(), "transfer queue");
+            }
+        }
+        Ok(device)
+    }
+
+    pub fn get_device(&self) -> &ash::Device
+    {
+        &self.handle.device
+    }
+
+    pub fn get_physical_device(&self) -> &PhysicalDevice
+    {
+        &self.handle.physical_device
+    }
+
+    pub fn get_queue_families(&self) -> &QueueFamilyIndices
+    {
+        &self.handle.queue_families
+    }
+
+    pub fn get_enabled_extensions(&self) -> &[RCString]
+    {
+        &self.handle.enabled_extension_names
+    }
+
+    pub fn get_enabled_features(&self) -> &vk::PhysicalDeviceFeatures
+    {
+        &self.handle.enabled_features
+    }
+
+    pub fn is_dynamic_rendering_enabled(&self) -> bool
+    {
+        self.handle.dynamic_rendering
+    }
+
+    pub fn is_timeline_semaphore_enabled(&self) -> bool
+    {
+        self.handle.timeline_semaphore
+    }
+
+    pub fn is_synchronization2_enabled(&self) -> bool
+    {
+        self.handle.synchronization2
+    }
+
+    /// Same as `PhysicalDevice::get_api_version`.
+    pub fn get_api_version(&self) -> u32
+    {
+        self.handle.physical_device.get_api_version()
+    }
+
+    /// The `VK_EXT_descriptor_indexing` features the device was created with, if any.
+    pub fn get_descriptor_indexing_features(&self) -> Option<&vk::PhysicalDeviceDescriptorIndexingFeatures>
+    {
+        self.handle.descriptor_indexing.as_ref()
+    }
+
+    fn get_queue<K>(&self, family_index: u32) -> Queue<K>
+    {
+        Queue {
+            handle: unsafe { self.handle.device.get_device_queue(family_index, 0) },
+            family_index,
+            _kind: PhantomData,
+        }
+    }
+
+    pub fn get_graphics_queue(&self) -> Queue<Graphics>
+    {
+        self.get_queue(self.handle.queue_families.graphics.unwrap())
+    }
+
+    /// `None` when the device was created without a surface.
+    pub fn get_present_queue(&self) -> Option<Queue<Present>>
+    {
+        self.handle.queue_families.present.map(|index| self.get_queue(index))
+    }
+
+    pub fn get_compute_queue(&self) -> Queue<Compute>
+    {
+        let families = &self.handle.queue_families;
+        self.get_queue(families.compute.or(families.graphics).unwrap())
+    }
+
+    pub fn get_transfer_queue(&self) -> Queue<Transfer>
+    {
+        let families = &self.handle.queue_families;
+        self.get_queue(families.transfer.or(families.graphics).unwrap())
+    }
+
+    /// Whether compute work runs on a queue family without graphics support.
+    pub fn has_dedicated_compute_queue(&self) -> bool
+    {
+        let families = &self.handle.queue_families;
+        families.compute.is_some() && families.compute != families.graphics
+    }
+
+    /// Whether transfers run on a queue family other than the graphics one.
+    pub fn has_dedicated_transfer_queue(&self) -> bool
+    {
+        let families = &self.handle.queue_families;
+        families.transfer.is_some() && families.transfer != families.graphics
+    }
+
+    pub fn wait_idle(&self) -> Result<(), vk::Result>
+    {
+        unsafe { self.handle.device.device_wait_idle() }
+    }
+
+    /// Whether names and labels reach `VK_EXT_debug_utils`; without it they are ignored, so
+    /// callers only need this to skip building names they would pass.
+    pub fn is_debug_utils_enabled(&self) -> bool
+    {
+        self.handle.debug_utils.is_some()
+    }
+
+    /// Names `handle` in validation messages and captures, with `vkSetDebugUtilsObjectNameEXT`.
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str)
+    {
+        let debug_utils = match &self.handle.debug_utils
+        {
+            Some(debug_utils) => debug_utils,
+            None => return,
+        };
+        let name = CString::new(name.replace('\0', "")).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+        // Only fails when out of host memory, which is no reason to stop rendering.
+        let _ = unsafe { debug_utils.set_debug_utils_object_name(self.handle.device.handle(), &name_info) };
+    }
+
+    /// Opens a labeled region of `command_buffer`, closed by `end_label`; regions nest.
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str)
+    {
+        if let Some(debug_utils) = &self.handle.debug_utils
+        {
+            let name = CString::new(name.replace('\0', "")).unwrap();
+            let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+            unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer)
+    {
+        if let Some(debug_utils) = &self.handle.debug_utils
+        {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    /// Marks a single point of `command_buffer`.
+    pub fn insert_label(&self, command_buffer: vk::CommandBuffer, name: &str)
+    {
+        if let Some(debug_utils) = &self.handle.debug_utils
+        {
+            let name = CString::new(name.replace('\0', "")).unwrap();
+            let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+            unsafe { debug_utils.cmd_insert_debug_utils_label(command_buffer, &label) };
+        }
+    }
+}
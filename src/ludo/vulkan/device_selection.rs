@@ -0,0 +1,240 @@
+use crate::rc_string::RCString;
+use crate::ludo::vulkan::{PhysicalDevice, Surface};
+use ash::vk;
+
+/// How the physical device is chosen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelection
+{
+    /// The suitable device with the highest score.
+    Best,
+    /// The device at this position in `vkEnumeratePhysicalDevices` order.
+    Index(usize),
+    /// The first device whose name contains this string, ignoring case.
+    Name(String),
+}
+impl DeviceSelection
+{
+    /// A number selects by index, anything else by name.
+    pub fn parse(value: &str) -> DeviceSelection
+    {
+        match value.trim().parse::<usize>()
+        {
+            Ok(index) => DeviceSelection::Index(index),
+            Err(_) => DeviceSelection::Name(value.trim().to_owned()),
+        }
+    }
+
+    /// Whether the device at `index` named `name` is the one selected; `Best` matches none.
+    pub fn matches(&self, index: usize, name: &str) -> bool
+    {
+        match self
+        {
+            DeviceSelection::Best => false,
+            DeviceSelection::Index(selected) => *selected == index,
+            DeviceSelection::Name(selected) => name.to_lowercase().contains(&selected.to_lowercase()),
+        }
+    }
+}
+
+pub struct DeviceRequirements<'a>
+{
+    pub extensions: Vec<RCString>,
+    pub features: vk::PhysicalDeviceFeatures,
+    pub queue_flags: vk::QueueFlags,
+    /// When set, some queue family has to be able to present to this surface.
+    pub surface: Option<&'a Surface>,
+}
+impl Default for DeviceRequirements<'_>
+{
+    fn default() -> Self
+    {
+        DeviceRequirements {
+            extensions: Vec::new(),
+            features: vk::PhysicalDeviceFeatures::default(),
+            queue_flags: vk::QueueFlags::GRAPHICS,
+            surface: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceSelectionError
+{
+    NoDevices,
+    NotFound(DeviceSelection),
+    Unsuitable(String, Vec<String>),
+    NoSuitableDevice(Vec<(String, Vec<String>)>),
+}
+impl std::fmt::Display for DeviceSelectionError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            DeviceSelectionError::NoDevices =>
+                write!(f, "failed to find physical devices"),
+            DeviceSelectionError::NotFound(selection) =>
+                write!(f, "no physical device matches {:?}", selection),
+            DeviceSelectionError::Unsuitable(name, reasons) =>
+                write!(f, "physical device {} is not suitable: {}", name, reasons.join(", ")),
+            DeviceSelectionError::NoSuitableDevice(devices) =>
+            {
+                write!(f, "no suitable physical device")?;
+                for (name, reasons) in devices
+                {
+                    write!(f, "; {}: {}", name, reasons.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for DeviceSelectionError {}
+
+/// Lists why `device` cannot satisfy `requirements`; empty when it can.
+pub fn get_unsuitability_reasons(device: &PhysicalDevice, requirements: &DeviceRequirements) -> Vec<String>
+{
+    let mut reasons : Vec<String> = Vec::new();
+    for extension in &requirements.extensions
+    {
+        if !device.supports_extension(extension.get_cstr())
+        {
+            reasons.push(format!("missing extension {}", extension.get_rstr()));
+        }
+    }
+    for feature in device.get_missing_features(&requirements.features)
+    {
+        reasons.push(format!("missing feature {}", feature));
+    }
+    if !device.get_queue_families().iter().any(|family| family.queue_flags.contains(requirements.queue_flags))
+    {
+        reasons.push(format!("no queue family with {:?}", requirements.queue_flags));
+    }
+    if let Some(surface) = requirements.surface
+    {
+        if !device.supports_surface(surface)
+        {
+            reasons.push("cannot present to the surface".to_owned());
+        }
+    }
+    reasons
+}
+
+/// Ranks devices by type first, then by the amount of device-local memory.
+pub fn score_physical_device(device: &PhysicalDevice) -> u64
+{
+    get_score(device.get_device_type(), device.get_device_local_memory())
+}
+
+/// A CPU implementation reports system memory as device-local, so the type has to dominate.
+fn get_score(device_type: vk::PhysicalDeviceType, device_local_memory: vk::DeviceSize) -> u64
+{
+    let type_score : u64 = match device_type
+    {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    };
+    let memory_mib = (device_local_memory >> 20).min(u32::MAX as u64);
+    (type_score << 32) | memory_mib
+}
+
+pub fn pick_physical_device(
+    devices: &[PhysicalDevice],
+    requirements: &DeviceRequirements,
+    selection: &DeviceSelection) -> Result<PhysicalDevice, DeviceSelectionError>
+{
+    if devices.is_empty()
+    {
+        return Err(DeviceSelectionError::NoDevices);
+    }
+    if *selection != DeviceSelection::Best
+    {
+        // An explicitly chosen device is never replaced by another one.
+        let device = devices
+            .iter()
+            .find(|device| selection.matches(device.get_index(), device.get_name()))
+            .ok_or_else(|| DeviceSelectionError::NotFound(selection.clone()))?;
+        let reasons = get_unsuitability_reasons(device, requirements);
+        if !reasons.is_empty()
+        {
+            return Err(DeviceSelectionError::Unsuitable(device.get_name().to_owned(), reasons));
+        }
+        return Ok(device.clone());
+    }
+    let mut rejected : Vec<(String, Vec<String>)> = Vec::new();
+    let mut best : Option<(&PhysicalDevice, u64)> = None;
+    for device in devices
+    {
+        let reasons = get_unsuitability_reasons(device, requirements);
+        if !reasons.is_empty()
+        {
+            rejected.push((device.get_name().to_owned(), reasons));
+            continue;
+        }
+        let score = score_physical_device(device);
+        if best.is_none_or(|(_, best_score)| score > best_score)
+        {
+            best = Some((device, score));
+        }
+    }
+    match best
+    {
+        Some((device, _)) => Ok(device.clone()),
+        None => Err(DeviceSelectionError::NoSuitableDevice(rejected)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const GIB: vk::DeviceSize = 1 << 30;
+
+    #[test]
+    fn numbers_select_by_index_and_anything_else_by_name()
+    {
+        assert_eq!(DeviceSelection::parse(" 1 "), DeviceSelection::Index(1));
+        assert_eq!(DeviceSelection::parse("-1"), DeviceSelection::Name("-1".to_owned()));
+        assert_eq!(DeviceSelection::parse(" llvmpipe"), DeviceSelection::Name("llvmpipe".to_owned()));
+
+        let index = DeviceSelection::Index(1);
+        assert!(index.matches(1, "llvmpipe (LLVM 17.0.6, 256 bits)"));
+        assert!(!index.matches(0, "1"));
+        let name = DeviceSelection::parse("LLVMpipe");
+        assert!(name.matches(0, "llvmpipe (LLVM 17.0.6, 256 bits)"));
+        assert!(!name.matches(1, "AMD Radeon RX 7600 (RADV NAVI33)"));
+        assert!(!DeviceSelection::Best.matches(0, "llvmpipe"));
+    }
+
+    #[test]
+    fn device_type_outranks_memory()
+    {
+        // lavapipe reports the whole system memory as device-local.
+        let mut devices = [
+            ("llvmpipe", get_score(vk::PhysicalDeviceType::CPU, 64 * GIB)),
+            ("integrated", get_score(vk::PhysicalDeviceType::INTEGRATED_GPU, GIB / 2)),
+            ("discrete", get_score(vk::PhysicalDeviceType::DISCRETE_GPU, 8 * GIB)),
+            ("virtual", get_score(vk::PhysicalDeviceType::VIRTUAL_GPU, 16 * GIB)),
+            ("other", get_score(vk::PhysicalDeviceType::OTHER, 128 * GIB)),
+        ];
+        devices.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        let names: Vec<&str> = devices.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["discrete", "integrated", "virtual", "llvmpipe", "other"]);
+    }
+
+    #[test]
+    fn memory_breaks_ties_between_devices_of_a_type()
+    {
+        let small = get_score(vk::PhysicalDeviceType::DISCRETE_GPU, 4 * GIB);
+        let large = get_score(vk::PhysicalDeviceType::DISCRETE_GPU, 12 * GIB);
+        assert!(large > small);
+        // Memory beyond what the score can hold does not spill into the type.
+        let huge = get_score(vk::PhysicalDeviceType::CPU, u64::MAX);
+        assert!(huge < get_score(vk::PhysicalDeviceType::VIRTUAL_GPU, 0));
+    }
+}
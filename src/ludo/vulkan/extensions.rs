@@ -1,81 +1,111 @@
 use crate::rc_string::RCString;
+use ash::vk;
 
+#[derive(Clone)]
 pub struct ExtensionProperties
 {
     pub extension_name: RCString,
+    #[allow(dead_code)]
     pub spec_version: u32,
 }
 
-pub fn get_available_extensions(layer_name: Option<RCString>) -> Result<Vec<ExtensionProperties>, i32>
+pub fn get_available_extensions(entry: &ash::Entry, layer_name: Option<&RCString>) -> Result<Vec<ExtensionProperties>, vk::Result>
 {
-    const VK_MAX_EXTENSION_NAME_SIZE: usize = 256;
-    #[allow(non_snake_case)]
-    #[derive(Clone)]
-    #[repr(C)]
-    struct sys_VkExtensionProperties
+    let c_layer_name = layer_name.map(|name| name.get_cstr());
+    let extension_properties_vector = entry.enumerate_instance_extension_properties(c_layer_name)?;
+    let mut result : Vec<ExtensionProperties> = Vec::with_capacity(extension_properties_vector.len());
+    for extension_properties in extension_properties_vector
     {
-        extensionName: [libc::c_char; VK_MAX_EXTENSION_NAME_SIZE],
-        specVersion: libc::c_uint,
+        let c_extension_name = unsafe {
+            std::ffi::CStr::from_ptr(extension_properties.extension_name.as_ptr())
+        };
+        result.push(ExtensionProperties {
+            extension_name: RCString::from_cstr(c_extension_name),
+            spec_version: extension_properties.spec_version,
+        });
     }
-    impl sys_VkExtensionProperties
+    Ok(result)
+}
+
+/// Splits `required` and `optional` extension names against the `available` ones.
+///
+/// Returns the names to enable (all required ones followed by the optional ones that are
+/// available, without duplicates) or the list of required names that are missing.
+pub fn select_extensions(
+    available: &[ExtensionProperties],
+    required: &[RCString],
+    optional: &[RCString]) -> Result<Vec<RCString>, Vec<String>>
+{
+    let is_available = |name: &RCString| available
+        .iter()
+        .any(|extension| extension.extension_name.get_cstr() == name.get_cstr());
+    let missing : Vec<String> = required
+        .iter()
+        .filter(|name| !is_available(name))
+        .map(|name| name.get_rstr().to_owned())
+        .collect();
+    if !missing.is_empty()
     {
-        fn new() -> sys_VkExtensionProperties
-        {
-            sys_VkExtensionProperties { 
-                extensionName: [0; VK_MAX_EXTENSION_NAME_SIZE], 
-                specVersion: 0 
-            }
-        }
-        fn to_safe(&self) -> ExtensionProperties
+        return Err(missing);
+    }
+    let mut enabled : Vec<RCString> = Vec::with_capacity(required.len() + optional.len());
+    for name in required.iter().chain(optional.iter().filter(|name| is_available(name)))
+    {
+        if !enabled.iter().any(|enabled_name| enabled_name.get_cstr() == name.get_cstr())
         {
-            let c_extension_name = unsafe {
-                std::ffi::CStr::from_ptr(self.extensionName.as_ptr())
-            };
-            let extension_name = RCString::from_cstr(c_extension_name);
-            ExtensionProperties { 
-                extension_name, 
-                spec_version: self.specVersion
-            }
+            enabled.push(name.clone());
         }
     }
-    extern {
-        fn vkEnumerateInstanceExtensionProperties(
-            pLayerName: *const libc::c_char,
-            pPropertyCount: *mut libc::c_uint, 
-            pProperties: *mut sys_VkExtensionProperties) -> libc::c_int;
+    Ok(enabled)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<RCString>
+    {
+        names.iter().map(|name| RCString::from_rstr(name)).collect()
+    }
+
+    fn available(names: &[&str]) -> Vec<ExtensionProperties>
+    {
+        names.iter()
+            .map(|name| ExtensionProperties { extension_name: RCString::from_rstr(name), spec_version: 1 })
+            .collect()
     }
-    let c_layer_name = match layer_name {
-        Some(name) => name.get_cstr().as_ptr(),
-        None => std::ptr::null(),
-    };
-    let mut property_count : u32 = 0;
-    let result = unsafe {
-        vkEnumerateInstanceExtensionProperties(
-            c_layer_name, 
-            &mut property_count, 
-            std::ptr::null_mut()) 
-    };
-    const VK_SUCCESS: i32 = 0;
-    if result != VK_SUCCESS
+
+    fn to_strings(names: &[RCString]) -> Vec<&str>
     {
-        return Err(result);
+        names.iter().map(|name| name.get_rstr()).collect()
     }
-    let mut extension_properties_vector : Vec<sys_VkExtensionProperties> = Vec::new();
-    extension_properties_vector.resize(property_count as usize, sys_VkExtensionProperties::new());
-    let result = unsafe {
-        vkEnumerateInstanceExtensionProperties(
-            c_layer_name,
-            &mut property_count, 
-            extension_properties_vector.as_mut_ptr())
-    };
-    if result != VK_SUCCESS
+
+    #[test]
+    fn missing_required_extensions_are_all_reported()
     {
-        return Err(result);
+        let available = available(&["VK_KHR_surface"]);
+        let result = select_extensions(&available, &names(&["VK_KHR_surface", "VK_KHR_xcb_surface", "VK_EXT_debug_utils"]), &[]);
+        assert_eq!(result.err(), Some(vec!["VK_KHR_xcb_surface".to_owned(), "VK_EXT_debug_utils".to_owned()]));
     }
-    let mut result : Vec<ExtensionProperties> = Vec::new();
-    for extension_properties in extension_properties_vector
+
+    #[test]
+    fn absent_optional_extensions_are_dropped()
     {
-        result.push(extension_properties.to_safe());
+        let available = available(&["VK_KHR_surface", "VK_EXT_debug_utils"]);
+        let enabled = select_extensions(&available,
+            &names(&["VK_KHR_surface"]),
+            &names(&["VK_KHR_portability_enumeration", "VK_EXT_debug_utils"])).ok().unwrap();
+        assert_eq!(to_strings(&enabled), vec!["VK_KHR_surface", "VK_EXT_debug_utils"]);
     }
-    Ok(result)
-}
\ No newline at end of file
+
+    #[test]
+    fn duplicates_are_enabled_once()
+    {
+        let available = available(&["VK_KHR_surface", "VK_EXT_debug_utils"]);
+        let enabled = select_extensions(&available,
+            &names(&["VK_KHR_surface", "VK_EXT_debug_utils", "VK_KHR_surface"]),
+            &names(&["VK_EXT_debug_utils", "VK_EXT_debug_utils"])).ok().unwrap();
+        assert_eq!(to_strings(&enabled), vec!["VK_KHR_surface", "VK_EXT_debug_utils"]);
+    }
+}
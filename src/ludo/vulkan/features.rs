@@ -0,0 +1,127 @@
+use ash::vk;
+
+const FEATURE_COUNT: usize = 55;
+const _: () = assert!(std::mem::size_of::<vk::PhysicalDeviceFeatures>() == FEATURE_COUNT * std::mem::size_of::<vk::Bool32>());
+
+/// Names of the `VkPhysicalDeviceFeatures` members, in declaration order.
+const FEATURE_NAMES: [&str; FEATURE_COUNT] = [
+    "robustBufferAccess",
+    "fullDrawIndexUint32",
+    "imageCubeArray",
+    "independentBlend",
+    "geometryShader",
+    "tessellationShader",
+    "sampleRateShading",
+    "dualSrcBlend",
+    "logicOp",
+    "multiDrawIndirect",
+    "drawIndirectFirstInstance",
+    "depthClamp",
+    "depthBiasClamp",
+    "fillModeNonSolid",
+    "depthBounds",
+    "wideLines",
+    "largePoints",
+    "alphaToOne",
+    "multiViewport",
+    "samplerAnisotropy",
+    "textureCompressionETC2",
+    "textureCompressionASTC_LDR",
+    "textureCompressionBC",
+    "occlusionQueryPrecise",
+    "pipelineStatisticsQuery",
+    "vertexPipelineStoresAndAtomics",
+    "fragmentStoresAndAtomics",
+    "shaderTessellationAndGeometryPointSize",
+    "shaderImageGatherExtended",
+    "shaderStorageImageExtendedFormats",
+    "shaderStorageImageMultisample",
+    "shaderStorageImageReadWithoutFormat",
+    "shaderStorageImageWriteWithoutFormat",
+    "shaderUniformBufferArrayDynamicIndexing",
+    "shaderSampledImageArrayDynamicIndexing",
+    "shaderStorageBufferArrayDynamicIndexing",
+    "shaderStorageImageArrayDynamicIndexing",
+    "shaderClipDistance",
+    "shaderCullDistance",
+    "shaderFloat64",
+    "shaderInt64",
+    "shaderInt16",
+    "shaderResourceResidency",
+    "shaderResourceMinLod",
+    "sparseBinding",
+    "sparseResidencyBuffer",
+    "sparseResidencyImage2D",
+    "sparseResidencyImage3D",
+    "sparseResidency2Samples",
+    "sparseResidency4Samples",
+    "sparseResidency8Samples",
+    "sparseResidency16Samples",
+    "sparseResidencyAliased",
+    "variableMultisampleRate",
+    "inheritedQueries",
+];
+
+fn features_as_array(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32; FEATURE_COUNT]
+{
+    // SAFETY: VkPhysicalDeviceFeatures is a #[repr(C)] struct of exactly FEATURE_COUNT VkBool32 members.
+    unsafe { &*(features as *const vk::PhysicalDeviceFeatures as *const [vk::Bool32; FEATURE_COUNT]) }
+}
+
+/// Returns the names of the features enabled in `required` that are not in `available`.
+pub fn get_missing_features(
+    required: &vk::PhysicalDeviceFeatures,
+    available: &vk::PhysicalDeviceFeatures) -> Vec<&'static str>
+{
+    let required = features_as_array(required);
+    let available = features_as_array(available);
+    FEATURE_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| required[*index] != vk::FALSE && available[*index] == vk::FALSE)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn missing_features_are_named()
+    {
+        let required = vk::PhysicalDeviceFeatures {
+            robust_buffer_access: vk::TRUE,
+            geometry_shader: vk::TRUE,
+            sampler_anisotropy: vk::TRUE,
+            inherited_queries: vk::TRUE,
+            ..Default::default()
+        };
+        let available = vk::PhysicalDeviceFeatures {
+            geometry_shader: vk::TRUE,
+            shader_float64: vk::TRUE,
+            ..Default::default()
+        };
+        assert_eq!(get_missing_features(&required, &available), ["robustBufferAccess", "samplerAnisotropy", "inheritedQueries"]);
+    }
+
+    #[test]
+    fn nothing_is_missing_when_nothing_is_required()
+    {
+        let required = vk::PhysicalDeviceFeatures::default();
+        assert!(get_missing_features(&required, &vk::PhysicalDeviceFeatures::default()).is_empty());
+    }
+
+    #[test]
+    fn names_follow_the_member_order()
+    {
+        // Each member on its own has to map to its own name, first and last included.
+        let features = vk::PhysicalDeviceFeatures { robust_buffer_access: vk::TRUE, ..Default::default() };
+        assert_eq!(get_missing_features(&features, &vk::PhysicalDeviceFeatures::default()), ["robustBufferAccess"]);
+        let features = vk::PhysicalDeviceFeatures { texture_compression_bc: vk::TRUE, ..Default::default() };
+        assert_eq!(get_missing_features(&features, &vk::PhysicalDeviceFeatures::default()), ["textureCompressionBC"]);
+        let features = vk::PhysicalDeviceFeatures { inherited_queries: vk::TRUE, ..Default::default() };
+        assert_eq!(get_missing_features(&features, &vk::PhysicalDeviceFeatures::default()), ["inheritedQueries"]);
+    }
+}
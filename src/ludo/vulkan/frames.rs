@@ -0,0 +1,268 @@
+use crate::ludo::vulkan::{
+    CommandPool, Device, Fence, Graphics, Present, Queue, Semaphore, Swapchain, SwapchainStatus, TimelineSemaphore,
+};
+use ash::vk;
+
+struct Frame
+{
+    command_buffer: vk::CommandBuffer,
+    image_available: Semaphore,
+    /// Signalled by the last submission from this slot; `None` on the timeline path.
+    in_flight: Option<Fence>,
+    /// Timeline value the last submission from this slot signals.
+    timeline_value: u64,
+}
+
+/// What a recording callback gets to know about the frame it records.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameInfo
+{
+    /// Slot in `0..frames_in_flight` whose resources this frame uses.
+    pub frame_slot: usize,
+    /// Index of the acquired swapchain image.
+    pub image_index: u32,
+    /// Number of frames submitted before this one.
+    pub frame_number: u64,
+}
+
+/// Per-frame command buffers, semaphores and fences for up to N frames in flight.
+///
+/// Each frame waits for the fence of the frame that used its slot N frames earlier, acquires
+/// a swapchain image, records, submits and presents. The semaphores signalled for presentation
+/// are kept per swapchain image, since an image can only be presented once it was acquired again.
+///
+/// Devices with timeline semaphores and synchronization2 track all slots with one timeline
+/// semaphore and submit with `vkQueueSubmit2`; others get a fence per slot.
+pub struct FramesInFlight
+{
+    frames: Vec<Frame>,
+    render_finished: Vec<Semaphore>,
+    /// Counts finished frames, so frame `n` signals `n + 1`.
+    timeline: Option<TimelineSemaphore>,
+    current: usize,
+    frame_number: u64,
+    // Freed after the command buffers' fences have been waited on in `drop`.
+    _command_pool: CommandPool,
+    device: Device,
+}
+impl FramesInFlight
+{
+    pub fn create(device: &Device, frames_in_flight: usize) -> Result<FramesInFlight, vk::Result>
+    {
+        let frames_in_flight = frames_in_flight.max(1);
+        let command_pool = CommandPool::create(
+            device,
+            device.get_graphics_queue().get_family_index(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
+        let command_buffers = command_pool.allocate(vk::CommandBufferLevel::PRIMARY, frames_in_flight as u32)?;
+        let timeline = match device.is_timeline_semaphore_enabled() && device.is_synchronization2_enabled()
+        {
+            true => Some(TimelineSemaphore::create(device, 0)?),
+            false => None,
+        };
+        let mut frames : Vec<Frame> = Vec::with_capacity(frames_in_flight);
+        for command_buffer in command_buffers
+        {
+            frames.push(Frame {
+                command_buffer,
+                image_available: Semaphore::create(device)?,
+                // Signaled, so the first wait on every slot returns immediately.
+                in_flight: match timeline
+                {
+                    Some(_) => None,
+                    None => Some(Fence::create(device, true)?),
+                },
+                timeline_value: 0,
+            });
+        }
+        if device.is_debug_utils_enabled()
+        {
+            command_pool.set_name("frames in flight");
+            if let Some(timeline) = &timeline
+            {
+                timeline.set_name("frames in flight timeline");
+            }
+            for (slot, frame) in frames.iter().enumerate()
+            {
+                device.set_object_name(frame.command_buffer, &format!("frame {} commands", slot));
+                frame.image_available.set_name(&format!("frame {} image available", slot));
+                if let Some(fence) = &frame.in_flight
+                {
+                    fence.set_name(&format!("frame {} in flight", slot));
+                }
+            }
+        }
+        Ok(FramesInFlight {
+            frames,
+            render_finished: Vec::new(),
+            timeline,
+            current: 0,
+            frame_number: 0,
+            _command_pool: command_pool,
+            device: device.clone(),
+        })
+    }
+
+    pub fn get_frame_number(&self) -> u64
+    {
+        self.frame_number
+    }
+
+    /// Continues the numbering of an earlier instance, e.g. one lost with its device; only
+    /// before the first frame.
+    pub fn set_frame_number(&mut self, frame_number: u64)
+    {
+        self.frame_number = frame_number;
+    }
+
+    fn wait_for(&self, frame: &Frame) -> Result<(), vk::Result>
+    {
+        match (&self.timeline, &frame.in_flight)
+        {
+            (Some(timeline), _) => timeline.wait(frame.timeline_value, u64::MAX),
+            (None, Some(fence)) => fence.wait(u64::MAX),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Draws one frame into `swapchain`, with `record` filling the command buffer between
+    /// `vkBeginCommandBuffer` and `vkEndCommandBuffer`.
+    ///
+    /// Returns `false` when nothing was drawn because the swapchain went out of date; it is
+    /// rebuilt by the next `Swapchain::prepare`. That also happens after `record` failed, to
+    /// give back the image acquired for it.
+    pub fn draw<F>(
+        &mut self,
+        swapchain: &mut Swapchain,
+        graphics_queue: Queue<Graphics>,
+        present_queue: Queue<Present>,
+        record: F) -> Result<bool, vk::Result>
+    where
+        F: FnOnce(vk::CommandBuffer, FrameInfo) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let frame = &self.frames[self.current];
+        self.wait_for(frame)?;
+
+        let (image_index, status) = swapchain.acquire_next_image(frame.image_available.get_handle(), vk::Fence::null())?;
+        if status == SwapchainStatus::OutOfDate
+        {
+            return Ok(false);
+        }
+        while self.render_finished.len() < swapchain.get_images().len()
+        {
+            let semaphore = Semaphore::create(&self.device)?;
+            if self.device.is_debug_utils_enabled()
+            {
+                semaphore.set_name(&format!("image {} render finished", self.render_finished.len()));
+            }
+            self.render_finished.push(semaphore);
+        }
+
+        let command_buffer = frame.command_buffer;
+        let info = FrameInfo {
+            frame_slot: self.current,
+            image_index,
+            frame_number: self.frame_number,
+        };
+        if let Err(error) = FramesInFlight::record_commands(device, command_buffer, info, record)
+        {
+            // The acquire still signals `image_available`, which must be waited on before it
+            // can be signalled again; the image goes back with the rebuilt swapchain.
+            let wait_semaphores = [frame.image_available.get_handle()];
+            let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
+            let submit_info = vk::SubmitInfo::builder()
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&wait_stages);
+            // Failing as well changes nothing about the error to report.
+            let _ = unsafe { device.queue_submit(graphics_queue.get_handle(), &[submit_info.build()], vk::Fence::null()) };
+            swapchain.mark_out_of_date();
+            return Err(error);
+        }
+
+        let signal_semaphores = [self.render_finished[image_index as usize].get_handle()];
+        match (&self.timeline, &frame.in_flight)
+        {
+            (Some(timeline), _) =>
+            {
+                let timeline_value = self.frame_number + 1;
+                let wait_infos = [vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(frame.image_available.get_handle())
+                    .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+                    .build()];
+                let signal_infos = [
+                    vk::SemaphoreSubmitInfo::builder()
+                        .semaphore(signal_semaphores[0])
+                        .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .build(),
+                    vk::SemaphoreSubmitInfo::builder()
+                        .semaphore(timeline.get_handle())
+                        .value(timeline_value)
+                        .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .build(),
+                ];
+                let command_buffer_infos = [vk::CommandBufferSubmitInfo::builder().command_buffer(command_buffer).build()];
+                let submit_info = vk::SubmitInfo2::builder()
+                    .wait_semaphore_infos(&wait_infos)
+                    .command_buffer_infos(&command_buffer_infos)
+                    .signal_semaphore_infos(&signal_infos);
+                unsafe { device.queue_submit2(graphics_queue.get_handle(), &[submit_info.build()], vk::Fence::null()) }?;
+                self.frames[self.current].timeline_value = timeline_value;
+            }
+            (None, in_flight) =>
+            {
+                let wait_semaphores = [frame.image_available.get_handle()];
+                let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+                let command_buffers = [command_buffer];
+                let submit_info = vk::SubmitInfo::builder()
+                    .wait_semaphores(&wait_semaphores)
+                    .wait_dst_stage_mask(&wait_stages)
+                    .command_buffers(&command_buffers)
+                    .signal_semaphores(&signal_semaphores);
+                // Reset right before the submit that signals it again: had recording failed
+                // after a reset, the next wait on this slot would never return.
+                let fence = in_flight.as_ref().unwrap();
+                fence.reset()?;
+                let fence = fence.get_handle();
+                unsafe { device.queue_submit(graphics_queue.get_handle(), &[submit_info.build()], fence) }?;
+            }
+        }
+
+        swapchain.present(present_queue, image_index, &signal_semaphores)?;
+        self.current = (self.current + 1) % self.frames.len();
+        self.frame_number += 1;
+        Ok(true)
+    }
+
+    fn record_commands<F>(device: &ash::Device, command_buffer: vk::CommandBuffer, info: FrameInfo, record: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(vk::CommandBuffer, FrameInfo) -> Result<(), vk::Result>
+    {
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+        }
+        record(command_buffer, info)?;
+        unsafe { device.end_command_buffer(command_buffer) }
+    }
+
+        /// Waits until every submitted frame has finished executing.
+    pub fn wait_idle(&self) -> Result<(), vk::Result>
+    {
+        for frame in &self.frames
+        {
+            self.wait_for(frame)?;
+        }
+        Ok(())
+    }
+}
+impl Drop for FramesInFlight
+{
+    fn drop(&mut self)
+    {
+        // Semaphores may still be waited on by a pending present, which no fence covers.
+        let _ = self.device.wait_idle();
+    }
+}
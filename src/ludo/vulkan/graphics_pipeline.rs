@@ -0,0 +1,513 @@
+use crate::ludo::vulkan::{Device, PipelineCache, PipelineLayout, RenderPass, ShaderModule};
+use ash::vk;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PipelineError
+{
+    /// The vertex shader reads a location no vertex attribute provides.
+    MissingVertexAttribute(u32, String),
+    /// A vertex attribute refers to a binding that was not declared.
+    UnknownVertexBinding(u32),
+    /// The device cannot render with this many samples per pixel.
+    SampleCountNotSupported(vk::SampleCountFlags),
+    /// Dynamic rendering was asked for on a device created without it.
+    DynamicRenderingNotEnabled,
+    /// Neither a render pass nor dynamic rendering attachments were given.
+    NoRenderTarget,
+    /// The number of blend states differs from the number of color attachments.
+    BlendStateCount(usize, usize),
+    /// The shaders do not fit the kind of pipeline, e.g. a vertex shader for a compute pipeline.
+    WrongStages(vk::ShaderStageFlags),
+    Vulkan(vk::Result),
+}
+impl std::fmt::Display for PipelineError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            PipelineError::MissingVertexAttribute(location, name) =>
+                write!(f, "vertex input \"{}\" at location {} has no vertex attribute", name, location),
+            PipelineError::UnknownVertexBinding(binding) =>
+                write!(f, "vertex binding {} is not declared", binding),
+            PipelineError::SampleCountNotSupported(samples) =>
+                write!(f, "{:?} samples per pixel are not supported", samples),
+            PipelineError::DynamicRenderingNotEnabled =>
+                write!(f, "dynamic rendering is not enabled on the device"),
+            PipelineError::NoRenderTarget =>
+                write!(f, "pipeline has neither a render pass nor dynamic rendering attachments"),
+            PipelineError::BlendStateCount(states, attachments) =>
+                write!(f, "{} blend states for {} color attachments", states, attachments),
+            PipelineError::WrongStages(stages) =>
+                write!(f, "{:?} stages do not make this kind of pipeline", stages),
+            PipelineError::Vulkan(result) =>
+                write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for PipelineError {}
+impl From<vk::Result> for PipelineError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        PipelineError::Vulkan(result)
+    }
+}
+
+/// Color blending of one attachment.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum BlendState
+{
+    /// Writes the source color as is.
+    Opaque,
+    /// `src * src.a + dst * (1 - src.a)`.
+    Alpha,
+    /// `src + dst * (1 - src.a)`, for colors already multiplied by their alpha.
+    Premultiplied,
+    /// `src * src.a + dst`.
+    Additive,
+    Custom(vk::PipelineColorBlendAttachmentState),
+}
+impl BlendState
+{
+    fn get_attachment_state(&self) -> vk::PipelineColorBlendAttachmentState
+    {
+        let blend = |src_color: vk::BlendFactor, dst_color: vk::BlendFactor| vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        };
+        match self
+        {
+            BlendState::Opaque => vk::PipelineColorBlendAttachmentState {
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+                ..Default::default()
+            },
+            BlendState::Alpha => blend(vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendState::Premultiplied => blend(vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendState::Additive => vk::PipelineColorBlendAttachmentState {
+                dst_alpha_blend_factor: vk::BlendFactor::ONE,
+                ..blend(vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE)
+            },
+            BlendState::Custom(state) => *state,
+        }
+    }
+}
+
+/// Attachment formats of a pipeline used with dynamic rendering instead of a `VkRenderPass`.
+#[derive(Clone, Debug, Default)]
+pub struct RenderingFormats
+{
+    pub color: Vec<vk::Format>,
+    pub depth: Option<vk::Format>,
+    pub stencil: Option<vk::Format>,
+}
+
+enum RenderTarget<'a>
+{
+    None,
+    RenderPass(&'a RenderPass, u32),
+    Dynamic(RenderingFormats),
+}
+impl RenderTarget<'_>
+{
+    /// Sample counts `limits` allow for every attachment the target has.
+    fn get_supported_samples(&self, limits: &vk::PhysicalDeviceLimits) -> vk::SampleCountFlags
+    {
+        let (color, depth, stencil) = match self
+        {
+            RenderTarget::None => (false, false, false),
+            RenderTarget::RenderPass(..) => (true, false, false),
+            RenderTarget::Dynamic(formats) => (!formats.color.is_empty(), formats.depth.is_some(), formats.stencil.is_some()),
+        };
+        [
+            (color, limits.framebuffer_color_sample_counts),
+            (depth, limits.framebuffer_depth_sample_counts),
+            (stencil, limits.framebuffer_stencil_sample_counts),
+        ]
+            .into_iter()
+            .filter(|(used, _)| *used)
+            .map(|(_, counts)| counts)
+            .reduce(|all, counts| all & counts)
+            .unwrap_or(limits.framebuffer_no_attachments_sample_counts)
+    }
+}
+
+/// Describes a graphics pipeline; everything not set has the usual default: triangle
+/// lists, no culling, no depth test, opaque color and dynamic viewport and scissor.
+///
+/// ```ignore
+/// let pipeline = GraphicsPipelineBuilder::new(&layout, &[&vertex_shader, &fragment_shader])
+///     .vertex_inputs_interleaved(0)
+///     .depth_test(vk::CompareOp::LESS, true)
+///     .samples(vk::SampleCountFlags::TYPE_4)
+///     .dynamic_rendering(RenderingFormats { color: vec![format], depth: Some(depth_format), stencil: None })
+///     .build(Some(&cache))?;
+/// ```
+pub struct GraphicsPipelineBuilder<'a>
+{
+    layout: &'a PipelineLayout,
+    shaders: Vec<&'a ShaderModule>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    primitive_restart: bool,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    depth_bias: Option<(f32, f32)>,
+    depth_test: Option<(vk::CompareOp, bool)>,
+    stencil: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    blend_states: Option<Vec<BlendState>>,
+    dynamic_states: Vec<vk::DynamicState>,
+    samples: vk::SampleCountFlags,
+    min_sample_shading: Option<f32>,
+    alpha_to_coverage: bool,
+    target: RenderTarget<'a>,
+}
+// Every fixed-function option is API; the demo pipelines only set a few of them.
+#[allow(dead_code)]
+impl<'a> GraphicsPipelineBuilder<'a>
+{
+    pub fn new(layout: &'a PipelineLayout, shaders: &[&'a ShaderModule]) -> GraphicsPipelineBuilder<'a>
+    {
+        GraphicsPipelineBuilder {
+            layout,
+            shaders: shaders.to_vec(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_bias: None,
+            depth_test: None,
+            stencil: None,
+            blend_states: None,
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            samples: vk::SampleCountFlags::TYPE_1,
+            min_sample_shading: None,
+            alpha_to_coverage: false,
+            target: RenderTarget::None,
+        }
+    }
+
+    pub fn vertex_binding(mut self, binding: u32, stride: u32, input_rate: vk::VertexInputRate) -> Self
+    {
+        self.vertex_bindings.push(vk::VertexInputBindingDescription { binding, stride, input_rate });
+        self
+    }
+
+    pub fn vertex_attribute(mut self, location: u32, binding: u32, format: vk::Format, offset: u32) -> Self
+    {
+        self.vertex_attributes.push(vk::VertexInputAttributeDescription { location, binding, format, offset });
+        self
+    }
+
+    /// Feeds all inputs of the vertex shader from one per-vertex buffer at `binding`, tightly
+    /// packed in location order with the formats the shader declares them with.
+    pub fn vertex_inputs_interleaved(mut self, binding: u32) -> Self
+    {
+        let mut offset = 0;
+        for input in self.layout.get_vertex_inputs()
+        {
+            // Matrices and arrays take one attribute per location.
+            let location_size = input.width / 8 * input.components;
+            for index in 0..input.locations
+            {
+                self.vertex_attributes.push(vk::VertexInputAttributeDescription {
+                    location: input.location + index,
+                    binding,
+                    format: input.get_format(),
+                    offset,
+                });
+                offset += location_size;
+            }
+        }
+        self.vertex_binding(binding, offset, vk::VertexInputRate::VERTEX)
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology, primitive_restart: bool) -> Self
+    {
+        self.topology = topology;
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self
+    {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self
+    {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    /// Constant and slope-scaled depth bias, e.g. against shadow acne.
+    pub fn depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self
+    {
+        self.depth_bias = Some((constant_factor, slope_factor));
+        self
+    }
+
+    pub fn depth_test(mut self, compare_op: vk::CompareOp, write: bool) -> Self
+    {
+        self.depth_test = Some((compare_op, write));
+        self
+    }
+
+    pub fn stencil_test(mut self, front: vk::StencilOpState, back: vk::StencilOpState) -> Self
+    {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    /// Blending of every color attachment; `Opaque` for each by default.
+    pub fn blend(mut self, blend_states: &[BlendState]) -> Self
+    {
+        self.blend_states = Some(blend_states.to_vec());
+        self
+    }
+
+    /// Adds state set by commands instead; viewport and scissor always are.
+    pub fn dynamic_state(mut self, state: vk::DynamicState) -> Self
+    {
+        if !self.dynamic_states.contains(&state)
+        {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self
+    {
+        self.samples = samples;
+        self
+    }
+
+    /// Shades at least this fraction of the samples separately; needs the `sampleRateShading` feature.
+    pub fn sample_shading(mut self, min_sample_shading: f32) -> Self
+    {
+        self.min_sample_shading = Some(min_sample_shading);
+        self
+    }
+
+    pub fn alpha_to_coverage(mut self, enable: bool) -> Self
+    {
+        self.alpha_to_coverage = enable;
+        self
+    }
+
+    pub fn render_pass(mut self, render_pass: &'a RenderPass, subpass: u32) -> Self
+    {
+        self.target = RenderTarget::RenderPass(render_pass, subpass);
+        self
+    }
+
+    /// Renders with `vkCmdBeginRendering` into attachments of these formats.
+    pub fn dynamic_rendering(mut self, formats: RenderingFormats) -> Self
+    {
+        self.target = RenderTarget::Dynamic(formats);
+        self
+    }
+
+    fn validate(&self, device: &Device) -> Result<usize, PipelineError>
+    {
+        for input in self.layout.get_vertex_inputs()
+        {
+            for location in input.location..input.location + input.locations
+            {
+                if !self.vertex_attributes.iter().any(|attribute| attribute.location == location)
+                {
+                    return Err(PipelineError::MissingVertexAttribute(location, input.name.clone()));
+                }
+            }
+        }
+        for attribute in &self.vertex_attributes
+        {
+            if !self.vertex_bindings.iter().any(|binding| binding.binding == attribute.binding)
+            {
+                return Err(PipelineError::UnknownVertexBinding(attribute.binding));
+            }
+        }
+        let color_attachments = match &self.target
+        {
+            RenderTarget::None => return Err(PipelineError::NoRenderTarget),
+            // `RenderPass` only makes single color attachment passes.
+            RenderTarget::RenderPass(..) => 1,
+            RenderTarget::Dynamic(formats) =>
+            {
+                if !device.is_dynamic_rendering_enabled()
+                {
+                    return Err(PipelineError::DynamicRenderingNotEnabled);
+                }
+                formats.color.len()
+            }
+        };
+        let limits = &device.get_physical_device().get_properties().limits;
+        if !self.target.get_supported_samples(limits).contains(self.samples)
+        {
+            return Err(PipelineError::SampleCountNotSupported(self.samples));
+        }
+        if let Some(blend_states) = &self.blend_states
+        {
+            if blend_states.len() != color_attachments
+            {
+                return Err(PipelineError::BlendStateCount(blend_states.len(), color_attachments));
+            }
+        }
+        Ok(color_attachments)
+    }
+
+    /// Checks the description against the shaders and the device and compiles it,
+    /// through `cache` when given.
+    pub fn build(&self, cache: Option<&PipelineCache>) -> Result<GraphicsPipeline, PipelineError>
+    {
+        let device = self.layout.get_device();
+        let color_attachments = self.validate(device)?;
+
+        let stages: Vec<_> = self.shaders.iter().map(|shader| shader.get_stage_info()).collect();
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&self.vertex_bindings)
+            .vertex_attribute_descriptions(&self.vertex_attributes);
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let (depth_bias_constant, depth_bias_slope) = self.depth_bias.unwrap_or((0.0, 0.0));
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_bias_enable(self.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias_constant)
+            .depth_bias_slope_factor(depth_bias_slope)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(self.samples)
+            .sample_shading_enable(self.min_sample_shading.is_some())
+            .min_sample_shading(self.min_sample_shading.unwrap_or(0.0))
+            .alpha_to_coverage_enable(self.alpha_to_coverage);
+        let (depth_compare, depth_write) = self.depth_test.unwrap_or((vk::CompareOp::ALWAYS, false));
+        let (stencil_front, stencil_back) = self.stencil.unwrap_or_default();
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth_test.is_some())
+            .depth_write_enable(depth_write)
+            .depth_compare_op(depth_compare)
+            .stencil_test_enable(self.stencil.is_some())
+            .front(stencil_front)
+            .back(stencil_back)
+            .max_depth_bounds(1.0);
+        let blend_attachments: Vec<_> = match &self.blend_states
+        {
+            Some(blend_states) => blend_states.iter().map(BlendState::get_attachment_state).collect(),
+            None => vec![BlendState::Opaque.get_attachment_state(); color_attachments],
+        };
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&blend_attachments);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&self.dynamic_states);
+
+        let mut create_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(self.layout.get_handle());
+        let mut rendering_info;
+        match &self.target
+        {
+            RenderTarget::RenderPass(render_pass, subpass) =>
+            {
+                create_info = create_info.render_pass(render_pass.get_handle()).subpass(*subpass);
+            }
+            RenderTarget::Dynamic(formats) =>
+            {
+                rendering_info = vk::PipelineRenderingCreateInfo::builder()
+                    .color_attachment_formats(&formats.color)
+                    .depth_attachment_format(formats.depth.unwrap_or(vk::Format::UNDEFINED))
+                    .stencil_attachment_format(formats.stencil.unwrap_or(vk::Format::UNDEFINED));
+                create_info = create_info.push_next(&mut rendering_info);
+            }
+            RenderTarget::None => unreachable!(),
+        }
+        let cache = cache.map_or(vk::PipelineCache::null(), PipelineCache::get_handle);
+        let pipelines = unsafe {
+            device.get_device().create_graphics_pipelines(cache, &[create_info.build()], None)
+        }.map_err(|(_, error)| error)?;
+        Ok(GraphicsPipeline { pipeline: pipelines[0], device: device.clone() })
+    }
+}
+
+pub struct GraphicsPipeline
+{
+    pipeline: vk::Pipeline,
+    device: Device,
+}
+impl GraphicsPipeline
+{
+    pub fn get_handle(&self) -> vk::Pipeline
+    {
+        self.pipeline
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.pipeline, name);
+    }
+}
+impl Drop for GraphicsPipeline
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn only_present_attachments_limit_the_sample_count()
+    {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4 | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4,
+            framebuffer_stencil_sample_counts: vk::SampleCountFlags::TYPE_1,
+            framebuffer_no_attachments_sample_counts: vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_2,
+            ..Default::default()
+        };
+        let samples = |color: bool, depth: bool, stencil: bool| {
+            let formats = RenderingFormats {
+                color: if color { vec![vk::Format::R8G8B8A8_UNORM] } else { Vec::new() },
+                depth: depth.then_some(vk::Format::D32_SFLOAT),
+                stencil: stencil.then_some(vk::Format::S8_UINT),
+            };
+            RenderTarget::Dynamic(formats).get_supported_samples(&limits)
+        };
+        assert_eq!(samples(true, false, false), limits.framebuffer_color_sample_counts);
+        assert_eq!(samples(true, true, false), vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4);
+        assert_eq!(samples(false, true, true), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(samples(false, false, false), limits.framebuffer_no_attachments_sample_counts);
+    }
+}
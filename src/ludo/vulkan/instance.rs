@@ -1,5 +1,7 @@
 use crate::rc_string::RCString;
-use ash::Entry;
+use crate::ludo::vulkan::{get_available_extensions, select_extensions, ExtensionProperties};
+use ash::vk;
+use std::rc::Rc;
 
 pub struct ApplicationInfo
 {
@@ -7,6 +9,7 @@ pub struct ApplicationInfo
     pub application_version: u32,
     pub engine_name: RCString,
     pub engine_version: u32,
+    /// Highest API version to use; lowered to what the loader supports when it is older.
     pub api_version: u32,
 }
 
@@ -16,139 +19,196 @@ pub struct InstanceCreateInfo
     pub application_info: ApplicationInfo,
     pub enabled_layer_names: Vec<RCString>,
     pub enabled_extension_names: Vec<RCString>,
+    /// Extensions that are enabled only when the loader reports them.
+    pub optional_extension_names: Vec<RCString>,
 }
 
-pub struct Instance
+#[derive(Debug)]
+pub enum InstanceError
 {
-    pub instance_info: InstanceCreateInfo,
-    p_instance: *const libc::c_void,
+    LoadingFailed(String),
+    LayerNotPresent(vk::Result, String),
+    ExtensionsNotPresent(vk::Result, Vec<String>),
+    EnumerationFailed(vk::Result),
+    CreationFailed(vk::Result),
 }
-impl Default for Instance {
-    fn default() -> Self 
-    { 
+impl std::fmt::Display for InstanceError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            InstanceError::LoadingFailed(error) =>
+                write!(f, "failed to load the Vulkan library: {}", error),
+            InstanceError::LayerNotPresent(result, name) =>
+                write!(f, "layer {} is not present ({})", name, result),
+            InstanceError::ExtensionsNotPresent(result, names) =>
+                write!(f, "extensions {} are not present ({})", names.join(", "), result),
+            InstanceError::EnumerationFailed(result) =>
+                write!(f, "failed to query the loader's version, layers or extensions ({})", result),
+            InstanceError::CreationFailed(result) =>
+                write!(f, "vkCreateInstance failed ({})", result),
+        }
+    }
+}
+impl std::error::Error for InstanceError {}
+
+impl Default for InstanceCreateInfo {
+    fn default() -> Self
+    {
         let application_info = ApplicationInfo{
             application_name : RCString::from_rstr(""),
-            application_version : crate::vulkan::make_version(1, 0, 0),
+            application_version : crate::ludo::vulkan::make_version(1, 0, 0),
             engine_name : RCString::from_rstr("No Engine"),
-            engine_version : crate::vulkan::make_version(1, 0, 0),
-            api_version : crate::vulkan::make_api_version(0, 1, 0, 0),
+            engine_version : crate::ludo::vulkan::make_version(1, 0, 0),
+            api_version : vk::API_VERSION_1_3,
         };
-        Instance { 
-            p_instance: std::ptr::null(),
-            instance_info: InstanceCreateInfo {
-                flags : 0,
-                application_info : application_info,
-                enabled_layer_names : Vec::new(),
-                enabled_extension_names : Vec::new(),
-            }
+        InstanceCreateInfo {
+            flags : 0,
+            application_info,
+            enabled_layer_names : Vec::new(),
+            enabled_extension_names : Vec::new(),
+            optional_extension_names : Vec::new(),
         }
     }
 }
+
+struct InstanceHandle
+{
+    // `entry` owns the loaded library, so it has to outlive `instance`.
+    instance: ash::Instance,
+    entry: ash::Entry,
+    api_version: u32,
+    enabled_extension_names: Vec<RCString>,
+}
+impl Drop for InstanceHandle
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.instance.destroy_instance(None) };
+        println!("Vulkan instance was destroyed.");
+    }
+}
+
+/// Handle to a created `VkInstance`.
+///
+/// Objects created from the instance (devices, surfaces, messengers) keep a clone of this
+/// handle, so `vkDestroyInstance` only runs after the last of them has been destroyed.
+#[derive(Clone)]
+pub struct Instance
+{
+    handle: Rc<InstanceHandle>,
+}
 impl Instance {
-    pub fn create(&mut self) -> Result<(),i32>
+    pub fn create(info: &InstanceCreateInfo) -> Result<Instance, InstanceError>
     {
-        let entry = unsafe { ash::Entry::load().unwrap()};
+        let entry = unsafe { ash::Entry::load() }
+            .map_err(|error| InstanceError::LoadingFailed(error.to_string()))?;
 
-        //entry.create_instance(create_info, allocation_callbacks)
+        // A 1.0 loader refuses any higher version with VK_ERROR_INCOMPATIBLE_DRIVER.
+        let loader_version = entry.try_enumerate_instance_version()
+            .map_err(InstanceError::EnumerationFailed)?
+            .unwrap_or(vk::API_VERSION_1_0);
+        let api_version = info.application_info.api_version.min(loader_version);
+        Instance::check_layers(&entry, &info.enabled_layer_names)?;
+        let enabled_extension_names = Instance::select_extensions(&entry, info)?;
+        let mut flags = vk::InstanceCreateFlags::from_raw(info.flags);
+        if enabled_extension_names.iter().any(|name| name.get_cstr() == vk::KhrPortabilityEnumerationFn::name())
+        {
+            flags |= vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR;
+        }
 
-        Ok(())
-        // #[allow(non_snake_case)]
-        // #[repr(C)]
-        // pub struct VkApplicationInfo
-        // {
-        //     sType : libc::c_uint,
-        //     pNext : *const libc::c_void,
-        //     pApplicationName : *const libc::c_char,
-        //     applicationVersion : libc::c_uint,
-        //     pEngineName : *const libc::c_char,
-        //     engineVersion : libc::c_uint,
-        //     apiVersion : libc::c_uint,
-        // }
-        // #[allow(non_snake_case)]
-        // #[repr(C)]
-        // pub struct VkInstanceCreateInfo
-        // {
-        //     sType : libc::c_uint,
-        //     pNext : *const libc::c_void,
-        //     flags : libc::c_uint,
-        //     pApplicationInfo : *const VkApplicationInfo,
-        //     enabledLayerCount : libc::c_uint,
-        //     ppEnabledLayerNames : *const *const libc::c_char,
-        //     enabledExtensionCount : libc::c_uint,
-        //     ppEnabledExtensionNames : *const *const libc::c_char,
-        // }
-        // extern {
-        //     fn vkCreateInstance(
-        //         pCreateInfo: *const VkInstanceCreateInfo, 
-        //         pAllocator: *const libc::c_void, 
-        //         pInstance: *mut *const libc::c_void) -> libc::c_int;
-        // }
-        // const VK_STRUCTURE_TYPE_APPLICATION_INFO: u32 = 0;
-        // let info = &self.instance_info;
-        // let application_info = VkApplicationInfo{
-        //     sType: VK_STRUCTURE_TYPE_APPLICATION_INFO,
-        //     pNext: std::ptr::null(), 
-        //     pApplicationName: info.application_info.application_name.get_cstr().as_ptr(),
-        //     applicationVersion: info.application_info.application_version,
-        //     pEngineName: info.application_info.engine_name.get_cstr().as_ptr(),
-        //     engineVersion: info.application_info.engine_version,
-        //     apiVersion: info.application_info.api_version
-        // };
-        // let mut layer_names : Vec<*const libc::c_char> = Vec::with_capacity(info.enabled_layer_names.len());
-        // for layer_name in &info.enabled_layer_names 
-        // {
-        //     layer_names.push(layer_name.get_cstr().as_ptr());
-        // }
-        // let mut extension_names : Vec<*const libc::c_char> = Vec::with_capacity(info.enabled_extension_names.len());
-        // for extension_name in &info.enabled_extension_names
-        // {
-        //     extension_names  .push(extension_name.get_cstr().as_ptr());
-        // }
-        // const VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO: u32 = 1;
-        // let instance_create_info = VkInstanceCreateInfo {
-        //     sType: VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO, 
-        //     pNext: std::ptr::null(),
-        //     flags: 0,
-        //     pApplicationInfo: &application_info,
-        //     enabledLayerCount: info.enabled_layer_names.len() as u32,
-        //     ppEnabledLayerNames: layer_names.as_ptr(),
-        //     enabledExtensionCount: info.enabled_extension_names.len() as u32,
-        //     ppEnabledExtensionNames: extension_names.as_ptr(),
-        // };
-        // let result = unsafe {
-        //     vkCreateInstance(&instance_create_info, std::ptr::null(), &mut self.p_instance)
-        // };
-        // const VK_SUCCESS: i32 = 0;
-        // if result == VK_SUCCESS
-        // {
-        //     Ok(())
-        // }
-        // else 
-        // {
-        //     Err(result)
-        // }
+        let application_info = vk::ApplicationInfo::builder()
+            .application_name(info.application_info.application_name.get_cstr())
+            .application_version(info.application_info.application_version)
+            .engine_name(info.application_info.engine_name.get_cstr())
+            .engine_version(info.application_info.engine_version)
+            .api_version(api_version);
+        let layer_names : Vec<*const libc::c_char> = info.enabled_layer_names
+            .iter()
+            .map(|name| name.get_cstr().as_ptr())
+            .collect();
+        let extension_names : Vec<*const libc::c_char> = enabled_extension_names
+            .iter()
+            .map(|name| name.get_cstr().as_ptr())
+            .collect();
+        let instance_create_info = vk::InstanceCreateInfo::builder()
+            .flags(flags)
+            .application_info(&application_info)
+            .enabled_layer_names(&layer_names)
+            .enabled_extension_names(&extension_names);
+
+        let instance = unsafe { entry.create_instance(&instance_create_info, None) }
+            .map_err(InstanceError::CreationFailed)?;
+        let handle = InstanceHandle { instance, entry, api_version, enabled_extension_names };
+        Ok(Instance { handle: Rc::new(handle) })
     }
-    // pub fn destroy(&mut self)
-    // {
-    //     if self.p_instance.is_null()
-    //     {
-    //         return;
-    //     }
-    //     extern {
-    //         fn vkDestroyInstance(instance: *const libc::c_void, pAllocator: *const libc::c_void);
-    //     }
-    //     unsafe {
-    //         vkDestroyInstance(self.p_instance, std::ptr::null());
-    //     };
-    //     self.p_instance = std::ptr::null();
-    //     println!("Vulkan instance was destroyed.");
-    // }
-}
-// impl Drop for Instance {
-//     fn drop(&mut self)
-//     {
-//         self.destroy();
-//     }
-// }
 
+    pub fn get_entry(&self) -> &ash::Entry
+    {
+        &self.handle.entry
+    }
+
+    pub fn get_instance(&self) -> &ash::Instance
+    {
+        &self.handle.instance
+    }
+
+    /// The API version the instance was created with, as negotiated with the loader.
+    ///
+    /// Devices are only used up to this version, whatever they support themselves.
+    pub fn get_api_version(&self) -> u32
+    {
+        self.handle.api_version
+    }
+
+    /// Required extensions followed by the optional ones that were available.
+    pub fn get_enabled_extensions(&self) -> &[RCString]
+    {
+        &self.handle.enabled_extension_names
+    }
+
+    pub fn is_extension_enabled(&self, name: &std::ffi::CStr) -> bool
+    {
+        self.handle.enabled_extension_names.iter().any(|enabled| enabled.get_cstr() == name)
+    }
+
+    fn check_layers(entry: &ash::Entry, layer_names: &[RCString]) -> Result<(), InstanceError>
+    {
+        if layer_names.is_empty()
+        {
+            return Ok(());
+        }
+        let available = entry.enumerate_instance_layer_properties()
+            .map_err(InstanceError::EnumerationFailed)?;
+        for name in layer_names
+        {
+            let present = available.iter().any(|layer| {
+                let layer_name = unsafe { std::ffi::CStr::from_ptr(layer.layer_name.as_ptr()) };
+                layer_name == name.get_cstr()
+            });
+            if !present
+            {
+                return Err(InstanceError::LayerNotPresent(
+                    vk::Result::ERROR_LAYER_NOT_PRESENT,
+                    name.get_rstr().to_owned()));
+            }
+        }
+        Ok(())
+    }
 
+    fn select_extensions(entry: &ash::Entry, info: &InstanceCreateInfo) -> Result<Vec<RCString>, InstanceError>
+    {
+        // Layers may provide extensions of their own, so they are searched as well.
+        let mut available : Vec<ExtensionProperties> = get_available_extensions(entry, None)
+            .map_err(InstanceError::EnumerationFailed)?;
+        for layer_name in &info.enabled_layer_names
+        {
+            let layer_extensions = get_available_extensions(entry, Some(layer_name))
+                .map_err(InstanceError::EnumerationFailed)?;
+            available.extend(layer_extensions);
+        }
+        select_extensions(&available, &info.enabled_extension_names, &info.optional_extension_names)
+            .map_err(|missing| InstanceError::ExtensionsNotPresent(vk::Result::ERROR_EXTENSION_NOT_PRESENT, missing))
+    }
+}
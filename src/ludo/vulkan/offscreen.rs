@@ -0,0 +1,254 @@
+use crate::ludo::vulkan::{Allocation, Allocator, Device, MemoryUsage};
+use ash::vk;
+
+/// Color formats tried for offscreen rendering, in order of preference.
+///
+/// Both are required to be renderable by most drivers, lavapipe included; the BGRA
+/// fallback is swizzled back to RGBA on readback.
+pub const OFFSCREEN_FORMATS: [vk::Format; 2] = [vk::Format::R8G8B8A8_SRGB, vk::Format::B8G8R8A8_SRGB];
+
+/// A device-local color image with a host-visible buffer it is copied into for readback.
+///
+/// Works without a window or surface; the image is expected to be left in
+/// `TRANSFER_SRC_OPTIMAL` by the pass that renders into it.
+pub struct OffscreenTarget
+{
+    image: vk::Image,
+    image_memory: Option<Allocation>,
+    image_view: vk::ImageView,
+    readback_buffer: vk::Buffer,
+    readback_memory: Option<Allocation>,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    device: Device,
+}
+impl OffscreenTarget
+{
+    /// Picks the first of `OFFSCREEN_FORMATS` the device can render to and copy from.
+    pub fn choose_format(device: &Device) -> Option<vk::Format>
+    {
+        let features = vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_SRC;
+        OFFSCREEN_FORMATS
+            .iter()
+            .copied()
+            .find(|format| device.get_physical_device().supports_format(*format, features))
+    }
+
+    pub fn create(allocator: &Allocator, extent: vk::Extent2D, format: vk::Format) -> Result<OffscreenTarget, vk::Result>
+    {
+        let mut target = OffscreenTarget {
+            image: vk::Image::null(),
+            image_memory: None,
+            image_view: vk::ImageView::null(),
+            readback_buffer: vk::Buffer::null(),
+            readback_memory: None,
+            extent,
+            format,
+            device: allocator.get_device(),
+        };
+        // Anything created before a failure is released by `drop`.
+        target.create_image(allocator)?;
+        target.create_readback_buffer(allocator)?;
+        Ok(target)
+    }
+
+    fn create_image(&mut self, allocator: &Allocator) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let families = self.device.get_queue_families().get_unique();
+        let mut create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(self.format)
+            .extent(vk::Extent3D { width: self.extent.width, height: self.extent.height, depth: 1 })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        if families.len() > 1
+        {
+            create_info = create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families);
+        }
+        self.image = unsafe { device.create_image(&create_info, None) }?;
+        self.image_memory = Some(allocator.allocate_for_image(self.image, MemoryUsage::GpuOnly)?);
+
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(self.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(self.format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        self.image_view = unsafe { device.create_image_view(&view_info, None) }?;
+        Ok(())
+    }
+
+    fn create_readback_buffer(&mut self, allocator: &Allocator) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let families = self.device.get_queue_families().get_unique();
+        let mut create_info = vk::BufferCreateInfo::builder()
+            .size(self.get_readback_size())
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        if families.len() > 1
+        {
+            create_info = create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families);
+        }
+        self.readback_buffer = unsafe { device.create_buffer(&create_info, None) }?;
+        self.readback_memory = Some(allocator.allocate_for_buffer(self.readback_buffer, MemoryUsage::Readback)?);
+        Ok(())
+    }
+
+    pub fn get_image(&self) -> vk::Image
+    {
+        self.image
+    }
+
+    pub fn get_image_view(&self) -> vk::ImageView
+    {
+        self.image_view
+    }
+
+    /// Names the image, and its view and readback buffer after it.
+    pub fn set_name(&self, name: &str)
+    {
+        if self.device.is_debug_utils_enabled()
+        {
+            self.device.set_object_name(self.image, name);
+            self.device.set_object_name(self.image_view, &format!("{} view", name));
+            self.device.set_object_name(self.readback_buffer, &format!("{} readback", name));
+        }
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D
+    {
+        self.extent
+    }
+
+    pub fn get_format(&self) -> vk::Format
+    {
+        self.format
+    }
+
+    /// Size in bytes of one tightly packed RGBA8 frame.
+    pub fn get_readback_size(&self) -> vk::DeviceSize
+    {
+        self.extent.width as vk::DeviceSize * self.extent.height as vk::DeviceSize * 4
+    }
+
+    /// Records the copy of the rendered image into the readback buffer.
+    ///
+    /// Must follow the rendering in the same submission, with the image already
+    /// in `TRANSFER_SRC_OPTIMAL`.
+    pub fn record_readback(&self, command_buffer: vk::CommandBuffer)
+    {
+        let device = self.device.get_device();
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(subresource_range);
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D { width: self.extent.width, height: self.extent.height, depth: 1 },
+        };
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.readback_buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier.build()]);
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback_buffer,
+                &[region]);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier.build()],
+                &[]);
+        }
+    }
+
+    /// Reads the last copied frame as tightly packed RGBA8 rows, top row first.
+    ///
+    /// The submission that recorded `record_readback` must have completed.
+    pub fn read_pixels(&self) -> Result<Vec<u8>, vk::Result>
+    {
+        let memory = self.readback_memory.as_ref().expect("readback memory is not allocated");
+        let data = memory.get_mapped_ptr().expect("readback memory is not host visible");
+        memory.invalidate()?;
+        let mut pixels = vec![0u8; self.get_readback_size() as usize];
+        unsafe { std::ptr::copy_nonoverlapping(data as *const u8, pixels.as_mut_ptr(), pixels.len()) };
+        if self.format == vk::Format::B8G8R8A8_SRGB || self.format == vk::Format::B8G8R8A8_UNORM
+        {
+            for pixel in pixels.chunks_exact_mut(4)
+            {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(pixels)
+    }
+}
+impl Drop for OffscreenTarget
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        unsafe {
+            device.destroy_buffer(self.readback_buffer, None);
+            device.destroy_image_view(self.image_view, None);
+            device.destroy_image(self.image, None);
+        }
+        // Memory goes back to the allocator only after the resources bound to it are gone.
+        self.readback_memory = None;
+        self.image_memory = None;
+    }
+}
@@ -0,0 +1,267 @@
+use crate::rc_string::RCString;
+use crate::ludo::vulkan::{get_missing_features, ExtensionProperties, Instance, Surface};
+use ash::vk;
+use std::ffi::CStr;
+
+/// A `VkPhysicalDevice` together with everything queried about it at enumeration time.
+#[derive(Clone)]
+pub struct PhysicalDevice
+{
+    handle: vk::PhysicalDevice,
+    index: usize,
+    name: String,
+    properties: vk::PhysicalDeviceProperties,
+    features: vk::PhysicalDeviceFeatures,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    queue_families: Vec<vk::QueueFamilyProperties>,
+    extensions: Vec<ExtensionProperties>,
+    /// Only queried when both `VK_EXT_descriptor_indexing` and
+    /// `VK_KHR_get_physical_device_properties2` are available.
+    descriptor_indexing: Option<DescriptorIndexing>,
+    /// The lower of the device's and the instance's API version.
+    api_version: u32,
+    modern_features: ModernFeatures,
+    instance: Instance,
+}
+
+/// Features and limits of `VK_EXT_descriptor_indexing`, with their `pNext` chains cleared.
+#[derive(Clone, Copy)]
+pub struct DescriptorIndexing
+{
+    pub features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    pub properties: vk::PhysicalDeviceDescriptorIndexingProperties,
+}
+/// Core features of Vulkan 1.2 and 1.3 that replace their 1.0 counterparts, as far as the
+/// device supports them within the negotiated API version.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModernFeatures
+{
+    /// Vulkan 1.2 `timelineSemaphore`.
+    pub timeline_semaphore: bool,
+    /// Vulkan 1.3 `synchronization2`.
+    pub synchronization2: bool,
+    /// Vulkan 1.3 `dynamicRendering`.
+    pub dynamic_rendering: bool,
+}
+impl ModernFeatures
+{
+    /// Whether the renderer can leave render passes, binary fences and 1.0 barriers behind.
+    pub fn is_complete(&self) -> bool
+    {
+        self.timeline_semaphore && self.synchronization2 && self.dynamic_rendering
+    }
+}
+
+impl PhysicalDevice
+{
+    pub fn enumerate(instance: &Instance) -> Result<Vec<PhysicalDevice>, vk::Result>
+    {
+        let ash_instance = instance.get_instance();
+        let handles = unsafe { ash_instance.enumerate_physical_devices() }?;
+        let mut physical_devices : Vec<PhysicalDevice> = Vec::with_capacity(handles.len());
+        for (index, handle) in handles.into_iter().enumerate()
+        {
+            let properties = unsafe { ash_instance.get_physical_device_properties(handle) };
+            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+            let extensions: Vec<ExtensionProperties> = unsafe { ash_instance.enumerate_device_extension_properties(handle) }?
+                .iter()
+                .map(|extension| ExtensionProperties {
+                    extension_name: RCString::from_cstr(unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) }),
+                    spec_version: extension.spec_version,
+                })
+                .collect();
+            let supports_descriptor_indexing = extensions
+                .iter()
+                .any(|extension| extension.extension_name.get_cstr() == vk::ExtDescriptorIndexingFn::name());
+            let descriptor_indexing = if supports_descriptor_indexing
+            {
+                PhysicalDevice::query_descriptor_indexing(instance, handle)
+            }
+            else
+            {
+                None
+            };
+            let api_version = properties.api_version.min(instance.get_api_version());
+            physical_devices.push(PhysicalDevice {
+                handle,
+                index,
+                name,
+                properties,
+                features: unsafe { ash_instance.get_physical_device_features(handle) },
+                memory_properties: unsafe { ash_instance.get_physical_device_memory_properties(handle) },
+                queue_families: unsafe { ash_instance.get_physical_device_queue_family_properties(handle) },
+                extensions,
+                descriptor_indexing,
+                api_version,
+                modern_features: PhysicalDevice::query_modern_features(instance, handle, api_version),
+                instance: instance.clone(),
+            });
+        }
+        Ok(physical_devices)
+    }
+
+    fn query_descriptor_indexing(instance: &Instance, handle: vk::PhysicalDevice) -> Option<DescriptorIndexing>
+    {
+        if !instance.is_extension_enabled(vk::KhrGetPhysicalDeviceProperties2Fn::name())
+        {
+            return None;
+        }
+        let loader = ash::extensions::khr::GetPhysicalDeviceProperties2::new(instance.get_entry(), instance.get_instance());
+        let mut features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        unsafe {
+            let mut features2 = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features);
+            loader.get_physical_device_features2(handle, &mut features2);
+            let mut properties2 = vk::PhysicalDeviceProperties2::builder().push_next(&mut properties);
+            loader.get_physical_device_properties2(handle, &mut properties2);
+        }
+        features.p_next = std::ptr::null_mut();
+        properties.p_next = std::ptr::null_mut();
+        Some(DescriptorIndexing { features, properties })
+    }
+
+    fn query_modern_features(instance: &Instance, handle: vk::PhysicalDevice, api_version: u32) -> ModernFeatures
+    {
+        // `vkGetPhysicalDeviceFeatures2` is core since 1.1, which any 1.2 device implies.
+        if api_version < vk::API_VERSION_1_2
+        {
+            return ModernFeatures::default();
+        }
+        let mut timeline_semaphore = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::builder().push_next(&mut timeline_semaphore);
+        if api_version >= vk::API_VERSION_1_3
+        {
+            features2 = features2.push_next(&mut synchronization2).push_next(&mut dynamic_rendering);
+        }
+        unsafe { instance.get_instance().get_physical_device_features2(handle, &mut features2) };
+        ModernFeatures {
+            timeline_semaphore: timeline_semaphore.timeline_semaphore == vk::TRUE,
+            synchronization2: synchronization2.synchronization2 == vk::TRUE,
+            dynamic_rendering: dynamic_rendering.dynamic_rendering == vk::TRUE,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::PhysicalDevice
+    {
+        self.handle
+    }
+
+    pub fn get_instance(&self) -> &Instance
+    {
+        &self.instance
+    }
+
+    /// Position of the device in `vkEnumeratePhysicalDevices` order.
+    pub fn get_index(&self) -> usize
+    {
+        self.index
+    }
+
+    pub fn get_name(&self) -> &str
+    {
+        &self.name
+    }
+
+    pub fn get_device_type(&self) -> vk::PhysicalDeviceType
+    {
+        self.properties.device_type
+    }
+
+    /// The API version the device is used with: its own, capped by the instance's.
+    pub fn get_api_version(&self) -> u32
+    {
+        self.api_version
+    }
+
+    pub fn get_modern_features(&self) -> &ModernFeatures
+    {
+        &self.modern_features
+    }
+
+    pub fn get_properties(&self) -> &vk::PhysicalDeviceProperties
+    {
+        &self.properties
+    }
+
+    pub fn get_features(&self) -> &vk::PhysicalDeviceFeatures
+    {
+        &self.features
+    }
+
+    pub fn get_memory_heaps(&self) -> &[vk::MemoryHeap]
+    {
+        &self.memory_properties.memory_heaps[..self.memory_properties.memory_heap_count as usize]
+    }
+
+    pub fn get_memory_types(&self) -> &[vk::MemoryType]
+    {
+        &self.memory_properties.memory_types[..self.memory_properties.memory_type_count as usize]
+    }
+
+    /// Total size of the device-local heaps in bytes.
+    pub fn get_device_local_memory(&self) -> vk::DeviceSize
+    {
+        self.get_memory_heaps()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    pub fn get_format_properties(&self, format: vk::Format) -> vk::FormatProperties
+    {
+        unsafe {
+            self.instance.get_instance().get_physical_device_format_properties(self.handle, format)
+        }
+    }
+
+    /// Whether optimally tiled images of `format` support all of `features`.
+    pub fn supports_format(&self, format: vk::Format, features: vk::FormatFeatureFlags) -> bool
+    {
+        self.get_format_properties(format).optimal_tiling_features.contains(features)
+    }
+
+    pub fn get_queue_families(&self) -> &[vk::QueueFamilyProperties]
+    {
+        &self.queue_families
+    }
+
+    pub fn get_extensions(&self) -> &[ExtensionProperties]
+    {
+        &self.extensions
+    }
+
+    pub fn supports_extension(&self, name: &CStr) -> bool
+    {
+        self.extensions.iter().any(|extension| extension.extension_name.get_cstr() == name)
+    }
+
+    pub fn get_descriptor_indexing(&self) -> Option<&DescriptorIndexing>
+    {
+        self.descriptor_indexing.as_ref()
+    }
+
+    pub fn get_missing_features(&self, required: &vk::PhysicalDeviceFeatures) -> Vec<&'static str>
+    {
+        get_missing_features(required, &self.features)
+    }
+
+    pub fn get_surface_support(&self, queue_family_index: u32, surface: &Surface) -> Result<bool, vk::Result>
+    {
+        unsafe {
+            surface.get_loader().get_physical_device_surface_support(
+                self.handle, queue_family_index, surface.get_handle())
+        }
+    }
+
+    /// Whether any queue family of the device can present to `surface`.
+    pub fn supports_surface(&self, surface: &Surface) -> bool
+    {
+        (0..self.queue_families.len() as u32)
+            .any(|index| self.get_surface_support(index, surface).unwrap_or(false))
+    }
+}
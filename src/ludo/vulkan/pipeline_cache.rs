@@ -0,0 +1,263 @@
+use crate::ludo::vulkan::{Device, PhysicalDevice};
+use ash::vk;
+use std::path::{Path, PathBuf};
+
+const FILE_MAGIC: &[u8; 8] = b"LUDOPSO1";
+/// Magic, vendor and device id, driver version, pipeline cache UUID, data size and checksum.
+const FILE_HEADER_SIZE: usize = 8 + 4 + 4 + 4 + vk::UUID_SIZE + 8 + 8;
+/// Size of the header Vulkan itself puts in front of pipeline cache data, version one.
+const VULKAN_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Identifies the device and driver a pipeline cache was written by.
+///
+/// Drivers only accept cache data they produced themselves, and some crash on data that
+/// merely looks right, so caches are checked against this key before Vulkan sees them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineCacheKey
+{
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub uuid: [u8; vk::UUID_SIZE],
+}
+impl PipelineCacheKey
+{
+    pub fn from_physical_device(physical_device: &PhysicalDevice) -> PipelineCacheKey
+    {
+        let properties = physical_device.get_properties();
+        PipelineCacheKey {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            uuid: properties.pipeline_cache_uuid,
+        }
+    }
+
+    /// File name of the cache, unique per device and driver build.
+    pub fn get_file_name(&self) -> String
+    {
+        let uuid: String = self.uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("pipelines-{:04x}-{:04x}-{}.bin", self.vendor_id, self.device_id, uuid)
+    }
+
+    /// Wraps cache data from `vkGetPipelineCacheData` for writing to disk.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(FILE_HEADER_SIZE + data.len());
+        bytes.extend_from_slice(FILE_MAGIC);
+        bytes.extend_from_slice(&self.vendor_id.to_le_bytes());
+        bytes.extend_from_slice(&self.device_id.to_le_bytes());
+        bytes.extend_from_slice(&self.driver_version.to_le_bytes());
+        bytes.extend_from_slice(&self.uuid);
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&get_checksum(data).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Returns the cache data of a file written by `encode`, if it is intact and was
+    /// written for this very device and driver.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], String>
+    {
+        if bytes.len() < FILE_HEADER_SIZE || &bytes[..8] != FILE_MAGIC
+        {
+            return Err("not a pipeline cache file".to_string());
+        }
+        let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let read_u64 = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let key = PipelineCacheKey {
+            vendor_id: read_u32(8),
+            device_id: read_u32(12),
+            driver_version: read_u32(16),
+            uuid: bytes[20..20 + vk::UUID_SIZE].try_into().unwrap(),
+        };
+        if key != *self
+        {
+            return Err(format!(
+                "written by device {:04x}:{:04x} driver {:#x}, not {:04x}:{:04x} driver {:#x}",
+                key.vendor_id, key.device_id, key.driver_version,
+                self.vendor_id, self.device_id, self.driver_version));
+        }
+        let size = read_u64(20 + vk::UUID_SIZE);
+        let data = &bytes[FILE_HEADER_SIZE..];
+        if data.len() as u64 != size || get_checksum(data) != read_u64(28 + vk::UUID_SIZE)
+        {
+            return Err("data is truncated or corrupted".to_string());
+        }
+        self.check_vulkan_header(data)?;
+        Ok(data)
+    }
+
+    /// Checks the header of the data itself, in case the file was tampered with.
+    fn check_vulkan_header(&self, data: &[u8]) -> Result<(), String>
+    {
+        if data.is_empty()
+        {
+            return Ok(());
+        }
+        if data.len() < VULKAN_HEADER_SIZE
+        {
+            return Err("data has no Vulkan header".to_string());
+        }
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let header_size = read_u32(0) as usize;
+        let header_version = read_u32(4);
+        if header_size < VULKAN_HEADER_SIZE
+            || header_size > data.len()
+            || header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            || read_u32(8) != self.vendor_id
+            || read_u32(12) != self.device_id
+            || data[16..16 + vk::UUID_SIZE] != self.uuid
+        {
+            return Err("Vulkan header does not match the device".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a; only needs to catch truncated and corrupted files.
+fn get_checksum(data: &[u8]) -> u64
+{
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// A `VkPipelineCache` that is loaded from and saved to a file per device.
+///
+/// A missing, foreign or corrupted file is reported and replaced by an empty cache;
+/// it never fails creation.
+pub struct PipelineCache
+{
+    cache: vk::PipelineCache,
+    key: PipelineCacheKey,
+    path: Option<PathBuf>,
+    device: Device,
+}
+impl PipelineCache
+{
+    /// Loads the cache of `device` from `directory`, where `save` and `drop` write it back;
+    /// without a directory the cache only lives in memory.
+    pub fn create(device: &Device, directory: Option<&Path>) -> Result<PipelineCache, vk::Result>
+    {
+        let key = PipelineCacheKey::from_physical_device(device.get_physical_device());
+        let path = directory.map(|directory| directory.join(key.get_file_name()));
+        let file = path.as_ref().and_then(|path| std::fs::read(path).ok());
+        let initial_data = match &file
+        {
+            Some(bytes) => match key.decode(bytes)
+            {
+                Ok(data) => data,
+                Err(reason) =>
+                {
+                    println!("Ignoring pipeline cache {}: {}", path.as_ref().unwrap().display(), reason);
+                    &[]
+                }
+            },
+            None => &[],
+        };
+        let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(initial_data);
+        let cache = unsafe { device.get_device().create_pipeline_cache(&create_info, None) }?;
+        if !initial_data.is_empty()
+        {
+            println!("Pipeline cache loaded: {} bytes", initial_data.len());
+        }
+        Ok(PipelineCache { cache, key, path, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::PipelineCache
+    {
+        self.cache
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.cache, name);
+    }
+
+    pub fn get_data(&self) -> Result<Vec<u8>, vk::Result>
+    {
+        unsafe { self.device.get_device().get_pipeline_cache_data(self.cache) }
+    }
+
+    /// Writes the cache to its file, through a temporary file so a crash cannot leave half of it.
+    pub fn save(&self) -> std::io::Result<()>
+    {
+        let Some(path) = &self.path else { return Ok(()) };
+        let data = self.get_data().map_err(std::io::Error::other)?;
+        if let Some(directory) = path.parent()
+        {
+            std::fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, self.key.encode(&data))?;
+        std::fs::rename(&temporary, path)
+    }
+}
+impl Drop for PipelineCache
+{
+    fn drop(&mut self)
+    {
+        if let Err(error) = self.save()
+        {
+            println!("Pipeline cache was not saved: {}", error);
+        }
+        unsafe { self.device.get_device().destroy_pipeline_cache(self.cache, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const KEY: PipelineCacheKey = PipelineCacheKey {
+        vendor_id: 0x10de,
+        device_id: 0x2684,
+        driver_version: 0x8a0e_0000,
+        uuid: [7; vk::UUID_SIZE],
+    };
+
+    fn get_vulkan_data(key: &PipelineCacheKey) -> Vec<u8>
+    {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(VULKAN_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&key.vendor_id.to_le_bytes());
+        data.extend_from_slice(&key.device_id.to_le_bytes());
+        data.extend_from_slice(&key.uuid);
+        data.extend_from_slice(b"driver blob");
+        data
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let data = get_vulkan_data(&KEY);
+        assert_eq!(KEY.decode(&KEY.encode(&data)), Ok(&data[..]));
+        assert_eq!(KEY.decode(&KEY.encode(&[])), Ok(&[][..]));
+    }
+
+    #[test]
+    fn foreign_caches_are_rejected()
+    {
+        let other_driver = PipelineCacheKey { driver_version: KEY.driver_version + 1, ..KEY };
+        let file = other_driver.encode(&get_vulkan_data(&other_driver));
+        assert!(KEY.decode(&file).unwrap_err().contains("written by device"));
+
+        // A file header claiming to be ours around another device's data.
+        let other_device = PipelineCacheKey { device_id: 0x1234, ..KEY };
+        let file = KEY.encode(&get_vulkan_data(&other_device));
+        assert!(KEY.decode(&file).unwrap_err().contains("Vulkan header"));
+        assert_ne!(KEY.get_file_name(), other_device.get_file_name());
+    }
+
+    #[test]
+    fn damaged_files_are_rejected()
+    {
+        let mut file = KEY.encode(&get_vulkan_data(&KEY));
+        assert!(KEY.decode(&file[..file.len() - 1]).is_err());
+        *file.last_mut().unwrap() ^= 1;
+        assert!(KEY.decode(&file).unwrap_err().contains("corrupted"));
+        assert!(KEY.decode(b"LUDO").is_err());
+        assert!(KEY.decode(&[0; FILE_HEADER_SIZE]).is_err());
+    }
+}
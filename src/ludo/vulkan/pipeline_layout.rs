@@ -0,0 +1,269 @@
+use crate::ludo::vulkan::{BindlessTable, Device, InterfaceVariable, ShaderError, ShaderModule, SharedSetLayout};
+use ash::vk;
+use std::collections::BTreeMap;
+
+/// One binding of a descriptor set layout, merged over all stages that use it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetLayoutBinding
+{
+    pub name: String,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+/// A `VkPipelineLayout` and its descriptor set layouts, derived from reflected shaders.
+///
+/// Set numbers without bindings get empty layouts, so `get_set_layouts()[n]` is always set `n`.
+pub struct PipelineLayout
+{
+    layout: vk::PipelineLayout,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    set_bindings: Vec<Vec<SetLayoutBinding>>,
+    push_constant_range: Option<vk::PushConstantRange>,
+    vertex_inputs: Vec<InterfaceVariable>,
+    stages: vk::ShaderStageFlags,
+    /// The set taken by a bindless table, whose layout is not ours to destroy.
+    bindless: Option<(u32, SharedSetLayout)>,
+    device: Device,
+}
+impl PipelineLayout
+{
+    /// Links the stages of one pipeline: checks that each stage reads what the previous one
+    /// writes and that shared bindings agree, then creates the layouts.
+    pub fn from_shaders(device: &Device, shaders: &[&ShaderModule]) -> Result<PipelineLayout, ShaderError>
+    {
+        PipelineLayout::create(device, shaders, None)
+    }
+
+    /// Same as `from_shaders`, for shaders that declare the tables of `table` as runtime
+    /// arrays; the set they are in uses the table's layout, and may hold nothing else.
+    #[allow(dead_code)] // The demo pipelines do not index the bindless table.
+    pub fn from_shaders_bindless(device: &Device, shaders: &[&ShaderModule], table: &BindlessTable) -> Result<PipelineLayout, ShaderError>
+    {
+        PipelineLayout::create(device, shaders, Some(table))
+    }
+
+    fn create(device: &Device, shaders: &[&ShaderModule], table: Option<&BindlessTable>) -> Result<PipelineLayout, ShaderError>
+    {
+        let mut shaders = shaders.to_vec();
+        // Stage bits are in pipeline order, vertex first.
+        shaders.sort_by_key(|shader| shader.get_stage().as_raw());
+        let mut stages = vk::ShaderStageFlags::empty();
+        for shader in &shaders
+        {
+            if stages.intersects(shader.get_stage())
+            {
+                return Err(ShaderError::LayoutMismatch(format!("{:?} stage is given twice", shader.get_stage())));
+            }
+            stages |= shader.get_stage();
+        }
+        if stages.contains(vk::ShaderStageFlags::COMPUTE) && stages != vk::ShaderStageFlags::COMPUTE
+        {
+            return Err(ShaderError::LayoutMismatch("compute and graphics stages in one pipeline".to_string()));
+        }
+        check_interfaces(&shaders)?;
+
+        let mut sets: BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>> = BTreeMap::new();
+        let mut push_constants: Option<(u32, u32, vk::ShaderStageFlags)> = None;
+        let mut bindless_set = None;
+        for shader in &shaders
+        {
+            let reflection = shader.get_reflection();
+            for binding in &reflection.descriptor_bindings
+            {
+                if table.is_some() && BindlessTable::is_table_binding(binding)
+                {
+                    if let Some(previous) = bindless_set.filter(|set| *set != binding.set)
+                    {
+                        return Err(ShaderError::LayoutMismatch(format!(
+                            "bindless tables are in both set {} and set {}", previous, binding.set)));
+                    }
+                    bindless_set = Some(binding.set);
+                }
+                else if binding.count == 0
+                {
+                    return Err(ShaderError::LayoutMismatch(format!(
+                        "\"{}\" at set {} binding {} is a runtime-sized array", binding.name, binding.set, binding.binding)));
+                }
+                let merged = sets.entry(binding.set).or_default().entry(binding.binding).or_insert(SetLayoutBinding {
+                    name: binding.name.clone(),
+                    binding: binding.binding,
+                    descriptor_type: binding.descriptor_type,
+                    count: binding.count,
+                    stages: vk::ShaderStageFlags::empty(),
+                });
+                if merged.descriptor_type != binding.descriptor_type || merged.count != binding.count
+                {
+                    return Err(ShaderError::LayoutMismatch(format!(
+                        "set {} binding {} is {:?}[{}] in {:?} but {:?}[{}] in {:?}",
+                        binding.set, binding.binding,
+                        merged.descriptor_type, merged.count, merged.stages,
+                        binding.descriptor_type, binding.count, reflection.stage)));
+                }
+                merged.stages |= reflection.stage;
+            }
+            // One range for all stages keeps `vkCmdPushConstants` calls simple.
+            if let Some((offset, size)) = reflection.push_constants
+            {
+                push_constants = Some(match push_constants
+                {
+                    Some((start, end, stages)) => (start.min(offset), end.max(offset + size), stages | reflection.stage),
+                    None => (offset, offset + size, reflection.stage),
+                });
+            }
+        }
+        if let Some(set) = bindless_set
+        {
+            let bindings = &sets[&set];
+            if let Some(other) = bindings.values().find(|binding| binding.count != 0)
+            {
+                return Err(ShaderError::LayoutMismatch(format!(
+                    "\"{}\" at set {} binding {} shares the set of the bindless tables", other.name, set, other.binding)));
+            }
+        }
+        let vertex_inputs = shaders
+            .iter()
+            .find(|shader| shader.get_stage() == vk::ShaderStageFlags::VERTEX)
+            .map(|shader| shader.get_reflection().inputs.clone())
+            .unwrap_or_default();
+
+        let set_count = sets.keys().next_back().map_or(0, |set| set + 1);
+        let mut layout = PipelineLayout {
+            layout: vk::PipelineLayout::null(),
+            set_layouts: Vec::new(),
+            set_bindings: (0..set_count)
+                .map(|set| sets.get(&set).map(|bindings| bindings.values().cloned().collect()).unwrap_or_default())
+                .collect(),
+            push_constant_range: push_constants.map(|(start, end, stages)| vk::PushConstantRange {
+                stage_flags: stages,
+                offset: start,
+                size: end - start,
+            }),
+            vertex_inputs,
+            stages,
+            bindless: bindless_set.zip(table.map(|table| table.get_set_layout().clone())),
+            device: device.clone(),
+        };
+        // Anything created before a failure is released by `drop`.
+        layout.create_layouts()?;
+        Ok(layout)
+    }
+
+    fn create_layouts(&mut self) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        for (set, bindings) in self.set_bindings.iter().enumerate()
+        {
+            if let Some((_, shared)) = self.bindless.as_ref().filter(|(bindless_set, _)| *bindless_set as usize == set)
+            {
+                self.set_layouts.push(shared.get_handle());
+                continue;
+            }
+            let layout_bindings: Vec<_> = bindings
+                .iter()
+                .map(|binding| vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+                    .build())
+                .collect();
+            let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+            self.set_layouts.push(unsafe { device.create_descriptor_set_layout(&create_info, None) }?);
+        }
+        let push_constant_ranges: Vec<_> = self.push_constant_range.iter().copied().collect();
+        let create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&self.set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        self.layout = unsafe { device.create_pipeline_layout(&create_info, None) }?;
+        Ok(())
+    }
+
+    pub fn get_handle(&self) -> vk::PipelineLayout
+    {
+        self.layout
+    }
+
+    /// Names the layout, and the set layouts it owns after it.
+    pub fn set_name(&self, name: &str)
+    {
+        if !self.device.is_debug_utils_enabled()
+        {
+            return;
+        }
+        self.device.set_object_name(self.layout, name);
+        for (set, set_layout) in self.set_layouts.iter().enumerate()
+        {
+            if self.bindless.as_ref().is_none_or(|(bindless_set, _)| *bindless_set as usize != set)
+            {
+                self.device.set_object_name(*set_layout, &format!("{} set {}", name, set));
+            }
+        }
+    }
+
+    pub fn get_set_layouts(&self) -> &[vk::DescriptorSetLayout]
+    {
+        &self.set_layouts
+    }
+
+    pub fn get_push_constant_range(&self) -> Option<vk::PushConstantRange>
+    {
+        self.push_constant_range
+    }
+
+    /// Inputs of the vertex stage, by location; empty for compute pipelines.
+    pub fn get_vertex_inputs(&self) -> &[InterfaceVariable]
+    {
+        &self.vertex_inputs
+    }
+
+    pub fn get_stages(&self) -> vk::ShaderStageFlags
+    {
+        self.stages
+    }
+
+    pub fn get_device(&self) -> &Device
+    {
+        &self.device
+    }
+}
+impl Drop for PipelineLayout
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        unsafe {
+            device.destroy_pipeline_layout(self.layout, None);
+            for (set, set_layout) in self.set_layouts.iter().enumerate()
+            {
+                if self.bindless.as_ref().is_some_and(|(bindless_set, _)| *bindless_set as usize == set)
+                {
+                    continue;
+                }
+                device.destroy_descriptor_set_layout(*set_layout, None);
+            }
+        }
+    }
+}
+
+/// Checks each stage against the one before it, where their interfaces line up one to one.
+///
+/// Tessellation and geometry stages read per-vertex arrays, so their inputs are not compared.
+fn check_interfaces(shaders: &[&ShaderModule]) -> Result<(), ShaderError>
+{
+    let arrayed_inputs = vk::ShaderStageFlags::TESSELLATION_CONTROL
+        | vk::ShaderStageFlags::TESSELLATION_EVALUATION
+        | vk::ShaderStageFlags::GEOMETRY;
+    for pair in shaders.windows(2)
+    {
+        let (previous, next) = (pair[0].get_reflection(), pair[1].get_reflection());
+        if arrayed_inputs.contains(next.stage) || next.stage == vk::ShaderStageFlags::COMPUTE
+        {
+            continue;
+        }
+        next.check_interface(previous).map_err(ShaderError::InterfaceMismatch)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,648 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+use std::fmt::{Display, Formatter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Counters of a pipeline-statistics query; results come back in the order of their bits.
+const STATISTICS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw());
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics
+{
+    pub vertices: u64,
+    pub vertex_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_invocations: u64,
+    pub compute_invocations: u64,
+}
+impl PipelineStatistics
+{
+    fn from_results(results: [u64; 5]) -> PipelineStatistics
+    {
+        PipelineStatistics {
+            vertices: results[0],
+            vertex_invocations: results[1],
+            clipping_primitives: results[2],
+            fragment_invocations: results[3],
+            compute_invocations: results[4],
+        }
+    }
+}
+
+/// GPU time of one scope of a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeTiming
+{
+    pub name: String,
+    /// Nesting level, 0 for scopes recorded outside any other.
+    pub depth: usize,
+    /// Start relative to the first scope of the frame.
+    pub start_ms: f64,
+    pub gpu_ms: f64,
+    /// Only for top-level scopes, and only with `pipelineStatisticsQuery` enabled.
+    pub statistics: Option<PipelineStatistics>,
+}
+
+/// Where the time of a frame went, as far as the profiler saw it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameTimings
+{
+    pub frame_number: u64,
+    /// Time from `begin_frame` to `end_frame`.
+    pub cpu_ms: f64,
+    /// Time from the start of the first scope to the end of the last one.
+    pub gpu_ms: f64,
+    pub scopes: Vec<ScopeTiming>,
+}
+impl Display for FrameTimings
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "frame {}: CPU {:.3} ms, GPU {:.3} ms", self.frame_number, self.cpu_ms, self.gpu_ms)?;
+        for scope in &self.scopes
+        {
+            write!(f, "\n{:indent$}{}: {:.3} ms", "", scope.name, scope.gpu_ms, indent = 2 + 2 * scope.depth)?;
+            if let Some(statistics) = &scope.statistics
+            {
+                write!(f, " ({} vertices, {} fragment and {} compute invocations)",
+                    statistics.vertices, statistics.fragment_invocations, statistics.compute_invocations)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Timeline an event is shown on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceTrack
+{
+    Cpu,
+    Gpu,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent
+{
+    pub name: String,
+    pub track: TraceTrack,
+    /// Microseconds since the profiler was created.
+    pub start_us: f64,
+    pub duration_us: f64,
+    pub frame_number: u64,
+    pub statistics: Option<PipelineStatistics>,
+}
+
+fn escape_json(text: &str) -> String
+{
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars()
+    {
+        match c
+        {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Events recorded between `GpuProfiler::start_session` and `GpuProfiler::take_session`.
+#[derive(Clone, Debug, Default)]
+pub struct TraceSession
+{
+    pub events: Vec<TraceEvent>,
+}
+impl TraceSession
+{
+    /// The session in the Chrome trace event format, for `chrome://tracing` or Perfetto,
+    /// with the CPU and GPU timelines as two threads of one process.
+    pub fn to_chrome_trace(&self) -> String
+    {
+        let mut json = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{\"name\":\"CPU\"}},\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":2,\"args\":{\"name\":\"GPU\"}}");
+        for event in &self.events
+        {
+            let (category, thread) = match event.track
+            {
+                TraceTrack::Cpu => ("cpu", 1),
+                TraceTrack::Gpu => ("gpu", 2),
+            };
+            write!(json, ",\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"frame\":{}",
+                escape_json(&event.name), category, thread, event.start_us, event.duration_us, event.frame_number).unwrap();
+            if let Some(statistics) = &event.statistics
+            {
+                write!(json, ",\"vertices\":{},\"vertex_invocations\":{},\"clipping_primitives\":{},\"fragment_invocations\":{},\"compute_invocations\":{}",
+                    statistics.vertices,
+                    statistics.vertex_invocations,
+                    statistics.clipping_primitives,
+                    statistics.fragment_invocations,
+                    statistics.compute_invocations).unwrap();
+            }
+            json.push_str("}}");
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+
+    pub fn write_chrome_trace(&self, path: &Path) -> std::io::Result<()>
+    {
+        std::fs::write(path, self.to_chrome_trace())
+    }
+}
+
+struct Scope
+{
+    name: String,
+    depth: usize,
+    /// Whether a pipeline-statistics query with the scope's index was recorded.
+    statistics: bool,
+}
+
+/// Queries of one frame slot, and what was recorded into them.
+struct FrameQueries
+{
+    timestamps: vk::QueryPool,
+    statistics: vk::QueryPool,
+    scopes: Vec<Scope>,
+    frame_number: u64,
+    cpu_start_us: f64,
+    cpu_duration_us: f64,
+    /// Recorded and not read back yet.
+    pending: bool,
+}
+
+/// Measures frames with timestamp and pipeline-statistics queries around named scopes,
+/// next to CPU markers.
+///
+/// Each frame slot has its own query pools, so results are read back when the slot comes
+/// around again: `begin_frame` must only be called once the slot's fence was waited on, as in
+/// the recording callback of `FramesInFlight::draw`. Pipeline statistics need the
+/// `pipelineStatisticsQuery` feature and cover only top-level scopes, since queries of one
+/// type cannot nest.
+///
+/// GPU events are placed on the CPU timeline by assuming the first measured scope started
+/// when the CPU finished recording its frame; the clocks themselves are not calibrated.
+pub struct GpuProfiler
+{
+    frames: Vec<FrameQueries>,
+    current: Option<usize>,
+    /// Number of the frame begun last, kept after `end_frame` for the CPU markers around it.
+    frame_number: u64,
+    /// Scopes begun and not yet ended; `None` for those dropped because the pool was full.
+    open_scopes: Vec<Option<usize>>,
+    open_markers: Vec<(String, f64)>,
+    max_scopes: u32,
+    has_statistics: bool,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    origin: Instant,
+    /// A timestamp and the CPU time in microseconds it is taken to correspond to.
+    gpu_origin: Option<(u64, f64)>,
+    last_timings: Option<FrameTimings>,
+    session: Option<TraceSession>,
+    device: Device,
+}
+impl GpuProfiler
+{
+    /// Whether the graphics queue can write timestamps.
+    pub fn is_supported(device: &Device) -> bool
+    {
+        let family = device.get_graphics_queue().get_family_index();
+        device.get_physical_device().get_queue_families()[family as usize].timestamp_valid_bits > 0
+    }
+
+    /// A profiler for up to `max_scopes` scopes per frame, whose pools are reused after
+    /// `frames_in_flight` frames.
+    pub fn create(device: &Device, frames_in_flight: usize, max_scopes: u32) -> Result<GpuProfiler, vk::Result>
+    {
+        if !GpuProfiler::is_supported(device)
+        {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        let family = device.get_graphics_queue().get_family_index();
+        let valid_bits = device.get_physical_device().get_queue_families()[family as usize].timestamp_valid_bits;
+        let mut profiler = GpuProfiler {
+            frames: Vec::new(),
+            current: None,
+            frame_number: 0,
+            open_scopes: Vec::new(),
+            open_markers: Vec::new(),
+            max_scopes,
+            has_statistics: device.get_enabled_features().pipeline_statistics_query == vk::TRUE,
+            timestamp_period: device.get_physical_device().get_properties().limits.timestamp_period as f64,
+            timestamp_mask: if valid_bits >= 64 { u64::MAX } else { (1 << valid_bits) - 1 },
+            origin: Instant::now(),
+            gpu_origin: None,
+            last_timings: None,
+            session: None,
+            device: device.clone(),
+        };
+        // Pools created before a failure are destroyed by `drop`.
+        for _ in 0..frames_in_flight.max(1)
+        {
+            let create_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(2 * max_scopes);
+            let timestamps = unsafe { device.get_device().create_query_pool(&create_info, None) }?;
+            profiler.frames.push(FrameQueries {
+                timestamps,
+                statistics: vk::QueryPool::null(),
+                scopes: Vec::new(),
+                frame_number: 0,
+                cpu_start_us: 0.0,
+                cpu_duration_us: 0.0,
+                pending: false,
+            });
+            if profiler.has_statistics
+            {
+                let create_info = vk::QueryPoolCreateInfo::builder()
+                    .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                    .query_count(max_scopes)
+                    .pipeline_statistics(STATISTICS);
+                profiler.frames.last_mut().unwrap().statistics = unsafe { device.get_device().create_query_pool(&create_info, None) }?;
+            }
+        }
+        if device.is_debug_utils_enabled()
+        {
+            for (slot, frame) in profiler.frames.iter().enumerate()
+            {
+                device.set_object_name(frame.timestamps, &format!("profiler timestamps {}", slot));
+                if frame.statistics != vk::QueryPool::null()
+                {
+                    device.set_object_name(frame.statistics, &format!("profiler statistics {}", slot));
+                }
+            }
+        }
+        Ok(profiler)
+    }
+
+    pub fn has_statistics(&self) -> bool
+    {
+        self.has_statistics
+    }
+
+    /// The most recent frame that was read back.
+    pub fn get_last_timings(&self) -> Option<&FrameTimings>
+    {
+        self.last_timings.as_ref()
+    }
+
+    /// Starts collecting trace events, dropping those of an earlier session.
+    pub fn start_session(&mut self)
+    {
+        self.session = Some(TraceSession::default());
+    }
+
+    /// Ends the session and returns its events; call `flush` first for the last frames.
+    pub fn take_session(&mut self) -> Option<TraceSession>
+    {
+        self.session.take()
+    }
+
+    fn get_cpu_time_us(&self) -> f64
+    {
+        self.origin.elapsed().as_secs_f64() * 1e6
+    }
+
+    fn add_event(&mut self, event: TraceEvent)
+    {
+        if let Some(session) = &mut self.session
+        {
+            session.events.push(event);
+        }
+    }
+
+    /// Reads back the results of the frame recorded last into `frame_slot`, then resets its
+    /// queries for the frame being recorded into `command_buffer`.
+    pub fn begin_frame(&mut self, command_buffer: vk::CommandBuffer, frame_slot: usize, frame_number: u64) -> Result<(), vk::Result>
+    {
+        let slot = frame_slot % self.frames.len();
+        self.resolve(slot)?;
+        let device = self.device.get_device();
+        let frame = &mut self.frames[slot];
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, frame.timestamps, 0, 2 * self.max_scopes);
+            if self.has_statistics
+            {
+                device.cmd_reset_query_pool(command_buffer, frame.statistics, 0, self.max_scopes);
+            }
+        }
+        frame.scopes.clear();
+        frame.frame_number = frame_number;
+        frame.cpu_start_us = self.origin.elapsed().as_secs_f64() * 1e6;
+        self.current = Some(slot);
+        self.frame_number = frame_number;
+        self.open_scopes.clear();
+        Ok(())
+    }
+
+    /// Starts a GPU scope; scopes nest, and beyond `max_scopes` per frame they are ignored.
+    pub fn begin_scope(&mut self, command_buffer: vk::CommandBuffer, name: &str)
+    {
+        let slot = match self.current
+        {
+            Some(slot) => slot,
+            None => return,
+        };
+        let frame = &mut self.frames[slot];
+        if frame.scopes.len() >= self.max_scopes as usize
+        {
+            self.open_scopes.push(None);
+            return;
+        }
+        let index = frame.scopes.len() as u32;
+        let depth = self.open_scopes.len();
+        let statistics = self.has_statistics && depth == 0;
+        let device = self.device.get_device();
+        unsafe {
+            device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, frame.timestamps, 2 * index);
+            if statistics
+            {
+                device.cmd_begin_query(command_buffer, frame.statistics, index, vk::QueryControlFlags::empty());
+            }
+        }
+        frame.scopes.push(Scope { name: name.to_owned(), depth, statistics });
+        self.open_scopes.push(Some(index as usize));
+    }
+
+    /// Ends the innermost open scope.
+    pub fn end_scope(&mut self, command_buffer: vk::CommandBuffer)
+    {
+        let (slot, index) = match (self.current, self.open_scopes.pop())
+        {
+            (Some(slot), Some(Some(index))) => (slot, index),
+            _ => return,
+        };
+        let frame = &self.frames[slot];
+        let device = self.device.get_device();
+        unsafe {
+            if frame.scopes[index].statistics
+            {
+                device.cmd_end_query(command_buffer, frame.statistics, index as u32);
+            }
+            device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, frame.timestamps, 2 * index as u32 + 1);
+        }
+    }
+
+    /// Finishes the frame begun by `begin_frame`; its results are read once its slot is reused.
+    pub fn end_frame(&mut self)
+    {
+        let slot = match self.current.take()
+        {
+            Some(slot) => slot,
+            None => return,
+        };
+        let now = self.get_cpu_time_us();
+        let frame = &mut self.frames[slot];
+        debug_assert!(self.open_scopes.is_empty(), "GPU scopes were left open");
+        frame.cpu_duration_us = now - frame.cpu_start_us;
+        frame.pending = true;
+        let event = TraceEvent {
+            name: format!("frame {}", frame.frame_number),
+            track: TraceTrack::Cpu,
+            start_us: frame.cpu_start_us,
+            duration_us: frame.cpu_duration_us,
+            frame_number: frame.frame_number,
+            statistics: None,
+        };
+        self.add_event(event);
+    }
+
+    /// Starts a CPU marker; markers nest and only show up in trace sessions, tagged with the
+    /// frame begun last when they end.
+    pub fn begin_cpu(&mut self, name: &str)
+    {
+        let now = self.get_cpu_time_us();
+        self.open_markers.push((name.to_owned(), now));
+    }
+
+    pub fn end_cpu(&mut self)
+    {
+        if let Some((name, start_us)) = self.open_markers.pop()
+        {
+            let frame_number = self.frame_number;
+            let duration_us = self.get_cpu_time_us() - start_us;
+            self.add_event(TraceEvent { name, track: TraceTrack::Cpu, start_us, duration_us, frame_number, statistics: None });
+        }
+    }
+
+    /// Reads back every frame still pending; the device must be idle.
+    pub fn flush(&mut self) -> Result<(), vk::Result>
+    {
+        for slot in 0..self.frames.len()
+        {
+            self.resolve(slot)?;
+        }
+        Ok(())
+    }
+
+    fn resolve(&mut self, slot: usize) -> Result<(), vk::Result>
+    {
+        let frame = &mut self.frames[slot];
+        if !frame.pending
+        {
+            return Ok(());
+        }
+        frame.pending = false;
+        let device = self.device.get_device();
+        let mut timestamps = vec![0u64; 2 * frame.scopes.len()];
+        if !frame.scopes.is_empty()
+        {
+            let result = unsafe {
+                device.get_query_pool_results(frame.timestamps, 0, timestamps.len() as u32, &mut timestamps, vk::QueryResultFlags::TYPE_64)
+            };
+            match result
+            {
+                Ok(()) => {}
+                // Only possible when the frame was never submitted.
+                Err(vk::Result::NOT_READY) => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+        let mut statistics = vec![None; frame.scopes.len()];
+        for index in (0..frame.scopes.len()).filter(|index| frame.scopes[*index].statistics)
+        {
+            let mut results = [[0u64; 5]];
+            let result = unsafe {
+                device.get_query_pool_results(frame.statistics, index as u32, 1, &mut results, vk::QueryResultFlags::TYPE_64)
+            };
+            match result
+            {
+                Ok(()) => statistics[index] = Some(PipelineStatistics::from_results(results[0])),
+                Err(vk::Result::NOT_READY) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mask = self.timestamp_mask;
+        let to_ms = |ticks: u64| (ticks & mask) as f64 * self.timestamp_period / 1e6;
+        let frame_start = timestamps.iter().step_by(2).copied().min().unwrap_or(0);
+        let frame_end = timestamps.iter().skip(1).step_by(2).copied().max().unwrap_or(frame_start);
+        let (origin_ticks, origin_us) = *self.gpu_origin.get_or_insert((frame_start, frame.cpu_start_us + frame.cpu_duration_us));
+        let mut events = Vec::new();
+        let scopes: Vec<ScopeTiming> = frame.scopes
+            .iter()
+            .enumerate()
+            .map(|(index, scope)| {
+                let (begin, end) = (timestamps[2 * index], timestamps[2 * index + 1]);
+                let timing = ScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    start_ms: to_ms(begin.wrapping_sub(frame_start)),
+                    gpu_ms: to_ms(end.wrapping_sub(begin)),
+                    statistics: statistics[index],
+                };
+                events.push(TraceEvent {
+                    name: scope.name.clone(),
+                    track: TraceTrack::Gpu,
+                    start_us: origin_us + to_ms(begin.wrapping_sub(origin_ticks)) * 1e3,
+                    duration_us: timing.gpu_ms * 1e3,
+                    frame_number: frame.frame_number,
+                    statistics: timing.statistics,
+                });
+                timing
+            })
+            .collect();
+        self.last_timings = Some(FrameTimings {
+            frame_number: frame.frame_number,
+            cpu_ms: frame.cpu_duration_us / 1e3,
+            gpu_ms: to_ms(frame_end.wrapping_sub(frame_start)),
+            scopes,
+        });
+        for event in events
+        {
+            self.add_event(event);
+        }
+        Ok(())
+    }
+}
+impl Drop for GpuProfiler
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        for frame in &self.frames
+        {
+            unsafe {
+                device.destroy_query_pool(frame.timestamps, None);
+                if frame.statistics != vk::QueryPool::null()
+                {
+                    device.destroy_query_pool(frame.statistics, None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::ludo::golden::get_test_device;
+    use crate::ludo::headless::Headless;
+    use crate::ludo::vulkan::Allocator;
+
+    #[test]
+    fn chrome_trace_has_both_tracks()
+    {
+        let statistics = PipelineStatistics { vertices: 3, fragment_invocations: 120, ..Default::default() };
+        let session = TraceSession {
+            events: vec![
+                TraceEvent {
+                    name: "frame 7".to_owned(),
+                    track: TraceTrack::Cpu,
+                    start_us: 1000.0,
+                    duration_us: 250.5,
+                    frame_number: 7,
+                    statistics: None,
+                },
+                TraceEvent {
+                    name: "tri\"angle".to_owned(),
+                    track: TraceTrack::Gpu,
+                    start_us: 1250.5,
+                    duration_us: 42.0,
+                    frame_number: 7,
+                    statistics: Some(statistics),
+                },
+            ],
+        };
+        let json = session.to_chrome_trace();
+        assert!(json.starts_with("{\"displayTimeUnit\":\"ms\",\"traceEvents\":["));
+        assert!(json.contains("\"args\":{\"name\":\"GPU\"}"));
+        assert!(json.contains("{\"name\":\"frame 7\",\"cat\":\"cpu\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":1000.000,\"dur\":250.500,\"args\":{\"frame\":7}}"));
+        assert!(json.contains("\"name\":\"tri\\\"angle\",\"cat\":\"gpu\""));
+        assert!(json.contains("\"vertices\":3,"));
+        assert!(json.contains("\"fragment_invocations\":120,"));
+        assert!(json.trim_end().ends_with("]}"));
+    }
+
+    #[test]
+    fn timings_are_reported_per_scope()
+    {
+        let timings = FrameTimings {
+            frame_number: 12,
+            cpu_ms: 1.5,
+            gpu_ms: 0.25,
+            scopes: vec![
+                ScopeTiming { name: "scene".to_owned(), depth: 0, start_ms: 0.0, gpu_ms: 0.25, statistics: None },
+                ScopeTiming { name: "shadows".to_owned(), depth: 1, start_ms: 0.0, gpu_ms: 0.125, statistics: None },
+            ],
+        };
+        assert_eq!(timings.to_string(), "frame 12: CPU 1.500 ms, GPU 0.250 ms\n  scene: 0.250 ms\n    shadows: 0.125 ms");
+    }
+
+    #[test]
+    fn frames_are_read_back_when_their_slot_comes_around()
+    {
+        let Some(test) = get_test_device() else { return };
+        let device = test.get_device();
+        if !GpuProfiler::is_supported(device)
+        {
+            return println!("skipped, no timestamps on the graphics queue");
+        }
+        let allocator = Allocator::create(device);
+        let mut headless = Headless::create(&allocator, vk::Extent2D { width: 16, height: 16 }).unwrap();
+        let mut profiler = GpuProfiler::create(device, 1, 4).unwrap();
+        profiler.start_session();
+        for _ in 0..2
+        {
+            profiler.begin_cpu("render");
+            headless.render(|command_buffer, frame_number| {
+                profiler.begin_frame(command_buffer, 0, frame_number)?;
+                profiler.begin_scope(command_buffer, "outer");
+                profiler.begin_scope(command_buffer, "inner");
+                profiler.end_scope(command_buffer);
+                profiler.end_scope(command_buffer);
+                profiler.end_frame();
+                Ok(())
+            }).unwrap();
+            profiler.end_cpu();
+        }
+        // The second frame read back the first one, flushing reads back the second.
+        assert_eq!(profiler.get_last_timings().unwrap().frame_number, 0);
+        profiler.flush().unwrap();
+        let timings = profiler.get_last_timings().unwrap();
+        assert_eq!(timings.frame_number, 1);
+        let names: Vec<(&str, usize)> = timings.scopes.iter().map(|scope| (scope.name.as_str(), scope.depth)).collect();
+        assert_eq!(names, [("outer", 0), ("inner", 1)]);
+        assert!(timings.scopes.iter().all(|scope| scope.gpu_ms >= 0.0 && scope.gpu_ms < 1000.0));
+
+        let session = profiler.take_session().unwrap();
+        let count = |track| session.events.iter().filter(|event| event.track == track).count();
+        assert_eq!((count(TraceTrack::Cpu), count(TraceTrack::Gpu)), (4, 4));
+        // Markers ending after `end_frame` still belong to the frame.
+        let markers: Vec<u64> = session.events.iter().filter(|event| event.name == "render").map(|event| event.frame_number).collect();
+        assert_eq!(markers, [0, 1]);
+        test.assert_no_errors();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+
+pub struct RenderPass
+{
+    render_pass: vk::RenderPass,
+    device: Device,
+}
+impl RenderPass
+{
+    /// A single subpass writing one cleared color attachment that ends up in `final_layout`.
+    pub fn create_color(device: &Device, color_format: vk::Format, final_layout: vk::ImageLayout) -> Result<RenderPass, vk::Result>
+    {
+        let attachments = [vk::AttachmentDescription::builder()
+            .format(color_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(final_layout)
+            .build()];
+        let color_references = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let subpasses = [vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_references)
+            .build()];
+        // Makes the layout transition wait for the acquire semaphore, which is waited on
+        // at the color attachment output stage.
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        }];
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+        let render_pass = unsafe { device.get_device().create_render_pass(&create_info, None) }?;
+        Ok(RenderPass { render_pass, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::RenderPass
+    {
+        self.render_pass
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.render_pass, name);
+    }
+}
+impl Drop for RenderPass
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_render_pass(self.render_pass, None) };
+    }
+}
+
+pub struct Framebuffer
+{
+    framebuffer: vk::Framebuffer,
+    extent: vk::Extent2D,
+    device: Device,
+}
+impl Framebuffer
+{
+    pub fn create(
+        device: &Device,
+        render_pass: &RenderPass,
+        attachments: &[vk::ImageView],
+        extent: vk::Extent2D) -> Result<Framebuffer, vk::Result>
+    {
+        let create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass.get_handle())
+            .attachments(attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { device.get_device().create_framebuffer(&create_info, None) }?;
+        Ok(Framebuffer { framebuffer, extent, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::Framebuffer
+    {
+        self.framebuffer
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.framebuffer, name);
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D
+    {
+        self.extent
+    }
+}
+impl Drop for Framebuffer
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_framebuffer(self.framebuffer, None) };
+    }
+}
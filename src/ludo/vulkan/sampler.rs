@@ -0,0 +1,179 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Create parameters of a sampler; equal descriptions share one `VkSampler`.
+///
+/// Floats are compared by their bits, so e.g. `0.0` and `-0.0` are different keys.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc
+{
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    pub mip_lod_bias: f32,
+    /// Maximum anisotropy; clamped to the device limit, ignored without the feature.
+    pub max_anisotropy: Option<f32>,
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: vk::BorderColor,
+}
+impl Default for SamplerDesc
+{
+    /// Trilinear, repeating, all mip levels.
+    fn default() -> Self
+    {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            mip_lod_bias: 0.0,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        }
+    }
+}
+impl SamplerDesc
+{
+    fn get_key(&self) -> SamplerKey
+    {
+        SamplerKey {
+            filters: (self.mag_filter, self.min_filter, self.mipmap_mode),
+            address_modes: (self.address_mode_u, self.address_mode_v, self.address_mode_w),
+            mip_lod_bias: self.mip_lod_bias.to_bits(),
+            max_anisotropy: self.max_anisotropy.map(f32::to_bits),
+            compare_op: self.compare_op,
+            lod_range: (self.min_lod.to_bits(), self.max_lod.to_bits()),
+            border_color: self.border_color,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SamplerKey
+{
+    filters: (vk::Filter, vk::Filter, vk::SamplerMipmapMode),
+    address_modes: (vk::SamplerAddressMode, vk::SamplerAddressMode, vk::SamplerAddressMode),
+    mip_lod_bias: u32,
+    max_anisotropy: Option<u32>,
+    compare_op: Option<vk::CompareOp>,
+    lod_range: (u32, u32),
+    border_color: vk::BorderColor,
+}
+
+struct SamplerHandle
+{
+    sampler: vk::Sampler,
+    device: Device,
+}
+impl Drop for SamplerHandle
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_sampler(self.sampler, None) };
+    }
+}
+
+#[derive(Clone)]
+pub struct Sampler
+{
+    handle: Rc<SamplerHandle>,
+}
+// Bound by whoever draws with a texture; the demo renderer samples none yet.
+#[allow(dead_code)]
+impl Sampler
+{
+    pub fn create(device: &Device, desc: &SamplerDesc) -> Result<Sampler, vk::Result>
+    {
+        let anisotropy = desc.max_anisotropy
+            .filter(|_| device.get_enabled_features().sampler_anisotropy == vk::TRUE)
+            .map(|value| value.min(device.get_physical_device().get_properties().limits.max_sampler_anisotropy));
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .mipmap_mode(desc.mipmap_mode)
+            .address_mode_u(desc.address_mode_u)
+            .address_mode_v(desc.address_mode_v)
+            .address_mode_w(desc.address_mode_w)
+            .mip_lod_bias(desc.mip_lod_bias)
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1.0))
+            .compare_enable(desc.compare_op.is_some())
+            .compare_op(desc.compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+            .min_lod(desc.min_lod)
+            .max_lod(desc.max_lod)
+            .border_color(desc.border_color);
+        let sampler = unsafe { device.get_device().create_sampler(&create_info, None) }?;
+        Ok(Sampler { handle: Rc::new(SamplerHandle { sampler, device: device.clone() }) })
+    }
+
+    pub fn get_handle(&self) -> vk::Sampler
+    {
+        self.handle.sampler
+    }
+
+    /// Names the sampler for every texture sharing it.
+    pub fn set_name(&self, name: &str)
+    {
+        self.handle.device.set_object_name(self.handle.sampler, name);
+    }
+}
+
+/// Hands out one shared `Sampler` per distinct `SamplerDesc`.
+///
+/// Samplers are cheap to share and devices limit how many may exist at once
+/// (`maxSamplerAllocationCount`, as low as 4000), so textures should not own theirs.
+pub struct SamplerCache
+{
+    samplers: RefCell<HashMap<SamplerKey, Sampler>>,
+    device: Device,
+}
+// Used with `Texture`, which the demo renderer does not load yet.
+#[allow(dead_code)]
+impl SamplerCache
+{
+    pub fn new(device: &Device) -> SamplerCache
+    {
+        SamplerCache { samplers: RefCell::new(HashMap::new()), device: device.clone() }
+    }
+
+    pub fn get(&self, desc: &SamplerDesc) -> Result<Sampler, vk::Result>
+    {
+        let key = desc.get_key();
+        if let Some(sampler) = self.samplers.borrow().get(&key)
+        {
+            return Ok(sampler.clone());
+        }
+        let sampler = Sampler::create(&self.device, desc)?;
+        self.samplers.borrow_mut().insert(key, sampler.clone());
+        Ok(sampler)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.samplers.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.samplers.borrow().is_empty()
+    }
+
+    /// Drops the cache's references; samplers still in use live on until released.
+    pub fn clear(&self)
+    {
+        self.samplers.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::ludo::vulkan::{read_words, Device, ShaderReflection, SpirvError};
+use ash::vk;
+use std::ffi::CString;
+
+#[derive(Debug)]
+pub enum ShaderError
+{
+    Io(std::io::Error),
+    Spirv(SpirvError),
+    /// A stage reads something the previous stage does not write, or with a different type.
+    InterfaceMismatch(String),
+    /// Two stages declare the same descriptor binding differently.
+    LayoutMismatch(String),
+    Vulkan(vk::Result),
+}
+impl std::fmt::Display for ShaderError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            ShaderError::Io(error) =>
+                write!(f, "{}", error),
+            ShaderError::Spirv(error) =>
+                write!(f, "{}", error),
+            ShaderError::InterfaceMismatch(message) =>
+                write!(f, "shader interface mismatch: {}", message),
+            ShaderError::LayoutMismatch(message) =>
+                write!(f, "shader layout mismatch: {}", message),
+            ShaderError::Vulkan(result) =>
+                write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for ShaderError {}
+impl From<std::io::Error> for ShaderError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        ShaderError::Io(error)
+    }
+}
+impl From<SpirvError> for ShaderError
+{
+    fn from(error: SpirvError) -> Self
+    {
+        ShaderError::Spirv(error)
+    }
+}
+impl From<vk::Result> for ShaderError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        ShaderError::Vulkan(result)
+    }
+}
+
+/// A `VkShaderModule` with the reflection of one of its entry points.
+pub struct ShaderModule
+{
+    module: vk::ShaderModule,
+    reflection: ShaderReflection,
+    entry_point: CString,
+    device: Device,
+}
+impl ShaderModule
+{
+    /// Loads SPIR-V code and reflects its first entry point.
+    pub fn from_bytes(device: &Device, bytes: &[u8]) -> Result<ShaderModule, ShaderError>
+    {
+        ShaderModule::from_bytes_with_entry_point(device, bytes, None)
+    }
+
+    pub fn from_bytes_with_entry_point(
+        device: &Device,
+        bytes: &[u8],
+        entry_point: Option<&str>) -> Result<ShaderModule, ShaderError>
+    {
+        let code = read_words(bytes)?;
+        let reflection = ShaderReflection::reflect(&code, entry_point)?;
+        let entry_point = CString::new(reflection.entry_point.clone())
+            .map_err(|_| SpirvError::NoEntryPoint(reflection.entry_point.clone()))?;
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+        let module = unsafe { device.get_device().create_shader_module(&create_info, None) }?;
+        Ok(ShaderModule { module, reflection, entry_point, device: device.clone() })
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.module, name);
+    }
+
+    pub fn get_reflection(&self) -> &ShaderReflection
+    {
+        &self.reflection
+    }
+
+    pub fn get_stage(&self) -> vk::ShaderStageFlags
+    {
+        self.reflection.stage
+    }
+
+    /// Stage description for pipeline creation; borrows the entry point name from `self`.
+    pub fn get_stage_info(&self) -> vk::PipelineShaderStageCreateInfo
+    {
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(self.reflection.stage)
+            .module(self.module)
+            .name(&self.entry_point)
+            .build()
+    }
+}
+impl Drop for ShaderModule
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_shader_module(self.module, None) };
+    }
+}
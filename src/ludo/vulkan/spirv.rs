@@ -0,0 +1,845 @@
+use ash::vk;
+use std::collections::HashMap;
+
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SpirvError
+{
+    /// The code is not a whole number of 32-bit words.
+    Misaligned(usize),
+    /// The code is too short for a header or does not start with the SPIR-V magic number.
+    BadMagic,
+    /// An instruction runs past the end of the code, or has a zero length.
+    Truncated(usize),
+    /// The module has no entry point, or none with the requested name.
+    NoEntryPoint(String),
+    /// The module uses something reflection does not understand.
+    Unsupported(String),
+}
+impl std::fmt::Display for SpirvError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            SpirvError::Misaligned(len) =>
+                write!(f, "SPIR-V code of {} bytes is not a whole number of words", len),
+            SpirvError::BadMagic =>
+                write!(f, "SPIR-V magic number is missing"),
+            SpirvError::Truncated(word) =>
+                write!(f, "SPIR-V instruction at word {} is truncated", word),
+            SpirvError::NoEntryPoint(name) if name.is_empty() =>
+                write!(f, "SPIR-V module has no entry point"),
+            SpirvError::NoEntryPoint(name) =>
+                write!(f, "SPIR-V module has no entry point named \"{}\"", name),
+            SpirvError::Unsupported(message) =>
+                write!(f, "unsupported SPIR-V: {}", message),
+        }
+    }
+}
+impl std::error::Error for SpirvError {}
+
+/// Checks the size and magic number of SPIR-V `bytes` and returns them as native-endian words.
+pub fn read_words(bytes: &[u8]) -> Result<Vec<u32>, SpirvError>
+{
+    if !bytes.len().is_multiple_of(4)
+    {
+        return Err(SpirvError::Misaligned(bytes.len()));
+    }
+    let mut words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    if words.len() < 5
+    {
+        return Err(SpirvError::BadMagic);
+    }
+    // The magic number tells the endianness the module was written in.
+    if words[0] == SPIRV_MAGIC.swap_bytes()
+    {
+        for word in &mut words
+        {
+            *word = word.swap_bytes();
+        }
+    }
+    if words[0] != SPIRV_MAGIC
+    {
+        return Err(SpirvError::BadMagic);
+    }
+    Ok(words)
+}
+
+/// Numeric type of the components of an interface variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarKind
+{
+    Float,
+    Int,
+    Uint,
+}
+
+/// A shader input or output with a `Location`, such as a vertex attribute or a varying.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceVariable
+{
+    pub name: String,
+    pub location: u32,
+    pub kind: ScalarKind,
+    /// Bits per component.
+    pub width: u32,
+    /// Components of one location, 1 to 4.
+    pub components: u32,
+    /// Consecutive locations taken, e.g. 4 for a `mat4` or a `vec4[4]`.
+    pub locations: u32,
+}
+impl InterfaceVariable
+{
+    /// The vertex attribute format that matches this variable one to one.
+    pub fn get_format(&self) -> vk::Format
+    {
+        let formats = match (self.kind, self.width)
+        {
+            (ScalarKind::Float, 32) =>
+                [vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT, vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT],
+            (ScalarKind::Int, 32) =>
+                [vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT],
+            (ScalarKind::Uint, 32) =>
+                [vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT],
+            (ScalarKind::Float, 16) =>
+                [vk::Format::R16_SFLOAT, vk::Format::R16G16_SFLOAT, vk::Format::R16G16B16_SFLOAT, vk::Format::R16G16B16A16_SFLOAT],
+            (ScalarKind::Float, 64) =>
+                [vk::Format::R64_SFLOAT, vk::Format::R64G64_SFLOAT, vk::Format::R64G64B64_SFLOAT, vk::Format::R64G64B64A64_SFLOAT],
+            _ => return vk::Format::UNDEFINED,
+        };
+        formats[(self.components.clamp(1, 4) - 1) as usize]
+    }
+}
+
+/// A resource bound through a descriptor set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding
+{
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Array size; 0 for a runtime-sized array.
+    pub count: u32,
+}
+
+/// Everything the pipeline needs to know about one entry point of a SPIR-V module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderReflection
+{
+    pub entry_point: String,
+    pub stage: vk::ShaderStageFlags,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    /// Offset and size of the push constant block, if there is one.
+    pub push_constants: Option<(u32, u32)>,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    /// Workgroup size of a compute shader.
+    pub local_size: Option<[u32; 3]>,
+}
+
+mod op
+{
+    pub const NAME: u32 = 5;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const EXECUTION_MODE: u32 = 16;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const CONSTANT_COMPOSITE: u32 = 44;
+    pub const SPEC_CONSTANT: u32 = 50;
+    pub const SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+    pub const EXECUTION_MODE_ID: u32 = 331;
+    pub const TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+}
+
+mod decoration
+{
+    #[cfg(test)]
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage_class
+{
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const OUTPUT: u32 = 3;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Debug)]
+enum Type
+{
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+/// How an execution mode gives the workgroup size: as literals, or as ids of constants.
+#[derive(Clone, Copy)]
+enum LocalSize
+{
+    Literal([u32; 3]),
+    Id([u32; 3]),
+}
+
+struct EntryPoint
+{
+    execution_model: u32,
+    function: u32,
+    name: String,
+    interface: Vec<u32>,
+}
+
+/// The instructions of a module that reflection cares about, indexed by result id.
+#[derive(Default)]
+struct Module
+{
+    names: HashMap<u32, String>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    constant_composites: HashMap<u32, Vec<u32>>,
+    variables: Vec<(u32, u32, u32)>,
+    entry_points: Vec<EntryPoint>,
+    local_sizes: HashMap<u32, LocalSize>,
+}
+
+fn read_string(words: &[u32]) -> (String, usize)
+{
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate()
+    {
+        for byte in word.to_le_bytes()
+        {
+            if byte == 0
+            {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+impl Module
+{
+    fn parse(words: &[u32]) -> Result<Module, SpirvError>
+    {
+        let mut module = Module::default();
+        let mut position = 5;
+        while position < words.len()
+        {
+            let length = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+            if length == 0 || position + length > words.len()
+            {
+                return Err(SpirvError::Truncated(position));
+            }
+            let operands = &words[position + 1..position + length];
+            module.add_instruction(opcode, operands)
+                .ok_or(SpirvError::Truncated(position))?;
+            position += length;
+        }
+        Ok(module)
+    }
+
+    /// Records one instruction; `None` if it has fewer operands than its opcode requires.
+    fn add_instruction(&mut self, opcode: u32, operands: &[u32]) -> Option<()>
+    {
+        let operand = |index: usize| operands.get(index).copied();
+        match opcode
+        {
+            op::NAME =>
+            {
+                self.names.insert(operand(0)?, read_string(operands.get(1..)?).0);
+            }
+            op::ENTRY_POINT =>
+            {
+                let (name, name_words) = read_string(operands.get(2..)?);
+                self.entry_points.push(EntryPoint {
+                    execution_model: operand(0)?,
+                    function: operand(1)?,
+                    name,
+                    interface: operands.get(2 + name_words..)?.to_vec(),
+                });
+            }
+            op::EXECUTION_MODE | op::EXECUTION_MODE_ID =>
+            {
+                let size = || Some([operand(2)?, operand(3)?, operand(4)?]);
+                match operand(1)?
+                {
+                    EXECUTION_MODE_LOCAL_SIZE => self.local_sizes.insert(operand(0)?, LocalSize::Literal(size()?)),
+                    EXECUTION_MODE_LOCAL_SIZE_ID => self.local_sizes.insert(operand(0)?, LocalSize::Id(size()?)),
+                    _ => None,
+                };
+            }
+            op::DECORATE =>
+            {
+                self.decorations.insert((operand(0)?, operand(1)?), operand(2).unwrap_or(0));
+            }
+            op::MEMBER_DECORATE =>
+            {
+                self.member_decorations.insert((operand(0)?, operand(1)?, operand(2)?), operand(3).unwrap_or(0));
+            }
+            op::TYPE_BOOL => self.add_type(operand(0)?, Type::Bool),
+            op::TYPE_INT => self.add_type(operand(0)?, Type::Int { width: operand(1)?, signed: operand(2)? != 0 }),
+            op::TYPE_FLOAT => self.add_type(operand(0)?, Type::Float { width: operand(1)? }),
+            op::TYPE_VECTOR => self.add_type(operand(0)?, Type::Vector { component: operand(1)?, count: operand(2)? }),
+            op::TYPE_MATRIX => self.add_type(operand(0)?, Type::Matrix { column: operand(1)?, count: operand(2)? }),
+            op::TYPE_IMAGE => self.add_type(operand(0)?, Type::Image { dim: operand(2)?, sampled: operand(6)? }),
+            op::TYPE_SAMPLER => self.add_type(operand(0)?, Type::Sampler),
+            op::TYPE_SAMPLED_IMAGE => self.add_type(operand(0)?, Type::SampledImage),
+            op::TYPE_ARRAY => self.add_type(operand(0)?, Type::Array { element: operand(1)?, length: operand(2)? }),
+            op::TYPE_RUNTIME_ARRAY => self.add_type(operand(0)?, Type::RuntimeArray { element: operand(1)? }),
+            op::TYPE_STRUCT => self.add_type(operand(0)?, Type::Struct { members: operands.get(1..)?.to_vec() }),
+            op::TYPE_POINTER => self.add_type(operand(0)?, Type::Pointer { pointee: operand(2)? }),
+            op::TYPE_ACCELERATION_STRUCTURE => self.add_type(operand(0)?, Type::AccelerationStructure),
+            // Array lengths are 32-bit integer constants; spec constants count with their default.
+            op::CONSTANT | op::SPEC_CONSTANT =>
+            {
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            op::CONSTANT_COMPOSITE | op::SPEC_CONSTANT_COMPOSITE =>
+            {
+                self.constant_composites.insert(operand(1)?, operands.get(2..)?.to_vec());
+            }
+            op::VARIABLE =>
+            {
+                self.variables.push((operand(0)?, operand(1)?, operand(2)?));
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn add_type(&mut self, id: u32, ty: Type)
+    {
+        self.types.insert(id, ty);
+    }
+
+    /// Workgroup size of `function`; a constant decorated as the `WorkgroupSize` built-in
+    /// overrides the execution modes of every entry point.
+    fn get_local_size(&self, function: u32) -> Option<[u32; 3]>
+    {
+        let built_in = self.decorations
+            .iter()
+            .find(|((_, decoration), value)| *decoration == decoration::BUILT_IN && **value == BUILT_IN_WORKGROUP_SIZE)
+            .and_then(|((id, _), _)| self.constant_composites.get(id));
+        let ids = match (built_in, self.local_sizes.get(&function))
+        {
+            (Some(components), _) => components.get(..3)?.try_into().ok()?,
+            (None, Some(LocalSize::Literal(size))) => return Some(*size),
+            (None, Some(LocalSize::Id(ids))) => *ids,
+            (None, None) => return None,
+        };
+        let size: [Option<u32>; 3] = ids.map(|id: u32| self.constants.get(&id).copied());
+        Some([size[0]?, size[1]?, size[2]?])
+    }
+
+    fn get_type(&self, id: u32) -> Result<&Type, SpirvError>
+    {
+        self.types.get(&id).ok_or_else(|| SpirvError::Unsupported(format!("type %{} is not declared", id)))
+    }
+
+    fn get_decoration(&self, id: u32, decoration: u32) -> Option<u32>
+    {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    fn get_name(&self, id: u32) -> String
+    {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn get_array_length(&self, length_id: u32) -> Result<u32, SpirvError>
+    {
+        self.constants
+            .get(&length_id)
+            .copied()
+            .ok_or_else(|| SpirvError::Unsupported(format!("array length %{} is not a constant", length_id)))
+    }
+
+    /// Size in bytes of a type inside a block, following its `Offset`, `ArrayStride` and `MatrixStride`.
+    fn get_size(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, SpirvError>
+    {
+        let size = match self.get_type(id)?
+        {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => self.get_size(*component, None)? * count,
+            Type::Matrix { column, count } => match matrix_stride
+            {
+                Some(stride) => stride * count,
+                None => self.get_size(*column, None)? * count,
+            },
+            Type::Array { element, length } =>
+            {
+                let length = self.get_array_length(*length)?;
+                match self.get_decoration(id, decoration::ARRAY_STRIDE)
+                {
+                    Some(stride) => stride * length,
+                    None => self.get_size(*element, matrix_stride)? * length,
+                }
+            }
+            Type::RuntimeArray { .. } => 0,
+            Type::Struct { members } =>
+            {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate()
+                {
+                    let index = index as u32;
+                    let offset = self.member_decorations.get(&(id, index, decoration::OFFSET)).copied().unwrap_or(size);
+                    let stride = self.member_decorations.get(&(id, index, decoration::MATRIX_STRIDE)).copied();
+                    size = size.max(offset + self.get_size(*member, stride)?);
+                }
+                size
+            }
+            ty => return Err(SpirvError::Unsupported(format!("{:?} inside a block", ty))),
+        };
+        Ok(size)
+    }
+
+    /// Strips arrays off a descriptor type, multiplying their lengths; 0 for runtime arrays.
+    fn get_descriptor_element(&self, mut id: u32) -> Result<(u32, u32), SpirvError>
+    {
+        let mut count = 1;
+        loop
+        {
+            match self.get_type(id)?
+            {
+                Type::Array { element, length } =>
+                {
+                    count *= self.get_array_length(*length)?;
+                    id = *element;
+                }
+                Type::RuntimeArray { element } =>
+                {
+                    count = 0;
+                    id = *element;
+                }
+                _ => return Ok((id, count)),
+            }
+        }
+    }
+
+    fn get_descriptor_type(&self, id: u32, storage_class: u32) -> Result<vk::DescriptorType, SpirvError>
+    {
+        let descriptor_type = match (storage_class, self.get_type(id)?)
+        {
+            (storage_class::STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (storage_class::UNIFORM, _) if self.get_decoration(id, decoration::BUFFER_BLOCK).is_some() =>
+                vk::DescriptorType::STORAGE_BUFFER,
+            (storage_class::UNIFORM, _) => vk::DescriptorType::UNIFORM_BUFFER,
+            (_, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (_, Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (_, Type::Image { dim: DIM_SUBPASS_DATA, .. }) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (_, Type::Image { dim: DIM_BUFFER, sampled: 1 }) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (_, Type::Image { dim: DIM_BUFFER, .. }) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (_, Type::Image { sampled: 1, .. }) => vk::DescriptorType::SAMPLED_IMAGE,
+            (_, Type::Image { .. }) => vk::DescriptorType::STORAGE_IMAGE,
+            (_, Type::AccelerationStructure) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (_, ty) => return Err(SpirvError::Unsupported(format!("descriptor of type {:?}", ty))),
+        };
+        Ok(descriptor_type)
+    }
+
+    /// Scalar kind, width, components per location and locations of an interface type.
+    fn get_interface_layout(&self, id: u32) -> Result<(ScalarKind, u32, u32, u32), SpirvError>
+    {
+        let layout = match self.get_type(id)?
+        {
+            Type::Float { width } => (ScalarKind::Float, *width, 1, 1),
+            Type::Int { width, signed: true } => (ScalarKind::Int, *width, 1, 1),
+            Type::Int { width, signed: false } => (ScalarKind::Uint, *width, 1, 1),
+            Type::Vector { component, count } =>
+            {
+                let (kind, width, _, _) = self.get_interface_layout(*component)?;
+                // 64-bit three- and four-component vectors take two locations.
+                let locations = if width == 64 && *count > 2 { 2 } else { 1 };
+                (kind, width, *count, locations)
+            }
+            Type::Matrix { column, count } =>
+            {
+                let (kind, width, components, locations) = self.get_interface_layout(*column)?;
+                (kind, width, components, locations * count)
+            }
+            Type::Array { element, length } =>
+            {
+                let (kind, width, components, locations) = self.get_interface_layout(*element)?;
+                (kind, width, components, locations * self.get_array_length(*length)?)
+            }
+            ty => return Err(SpirvError::Unsupported(format!("interface variable of type {:?}", ty))),
+        };
+        Ok(layout)
+    }
+
+    fn reflect(&self, entry_point: Option<&str>) -> Result<ShaderReflection, SpirvError>
+    {
+        let entry = match entry_point
+        {
+            Some(name) => self.entry_points.iter().find(|entry| entry.name == name),
+            None => self.entry_points.first(),
+        }.ok_or_else(|| SpirvError::NoEntryPoint(entry_point.unwrap_or_default().to_string()))?;
+        let stage = get_stage(entry.execution_model)
+            .ok_or_else(|| SpirvError::Unsupported(format!("execution model {}", entry.execution_model)))?;
+
+        let mut reflection = ShaderReflection {
+            entry_point: entry.name.clone(),
+            stage,
+            descriptor_bindings: Vec::new(),
+            push_constants: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            local_size: self.get_local_size(entry.function),
+        };
+        for &(pointer_type, id, storage) in &self.variables
+        {
+            let pointee = match self.get_type(pointer_type)?
+            {
+                Type::Pointer { pointee } => *pointee,
+                ty => return Err(SpirvError::Unsupported(format!("variable of non-pointer type {:?}", ty))),
+            };
+            match storage
+            {
+                storage_class::UNIFORM_CONSTANT | storage_class::UNIFORM | storage_class::STORAGE_BUFFER =>
+                {
+                    let (Some(set), Some(binding)) = (
+                        self.get_decoration(id, decoration::DESCRIPTOR_SET),
+                        self.get_decoration(id, decoration::BINDING)) else { continue };
+                    let (element, count) = self.get_descriptor_element(pointee)?;
+                    let name = match self.get_name(id)
+                    {
+                        // Blocks declared without an instance name are only named by their type.
+                        name if name.is_empty() => self.get_name(element),
+                        name => name,
+                    };
+                    reflection.descriptor_bindings.push(DescriptorBinding {
+                        name,
+                        set,
+                        binding,
+                        descriptor_type: self.get_descriptor_type(element, storage)?,
+                        count,
+                    });
+                }
+                storage_class::PUSH_CONSTANT =>
+                {
+                    let size = self.get_size(pointee, None)?;
+                    let offset = match self.get_type(pointee)?
+                    {
+                        Type::Struct { members } => (0..members.len() as u32)
+                            .filter_map(|index| self.member_decorations.get(&(pointee, index, decoration::OFFSET)))
+                            .min()
+                            .copied()
+                            .unwrap_or(0),
+                        _ => 0,
+                    };
+                    reflection.push_constants = Some((offset, size - offset));
+                }
+                storage_class::INPUT | storage_class::OUTPUT if entry.interface.contains(&id) =>
+                {
+                    // Built-ins, alone or in a block like `gl_PerVertex`, have no location.
+                    let Some(location) = self.get_decoration(id, decoration::LOCATION) else { continue };
+                    let (kind, width, components, locations) = self.get_interface_layout(pointee)?;
+                    let variable = InterfaceVariable { name: self.get_name(id), location, kind, width, components, locations };
+                    if storage == storage_class::INPUT
+                    {
+                        reflection.inputs.push(variable);
+                    }
+                    else
+                    {
+                        reflection.outputs.push(variable);
+                    }
+                }
+                _ => {}
+            }
+        }
+        reflection.descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        reflection.inputs.sort_by_key(|variable| variable.location);
+        reflection.outputs.sort_by_key(|variable| variable.location);
+        Ok(reflection)
+    }
+}
+
+fn get_stage(execution_model: u32) -> Option<vk::ShaderStageFlags>
+{
+    let stage = match execution_model
+    {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        _ => return None,
+    };
+    Some(stage)
+}
+
+impl ShaderReflection
+{
+    /// Reflects the entry point called `entry_point`, or the first one, of validated SPIR-V `words`.
+    pub fn reflect(words: &[u32], entry_point: Option<&str>) -> Result<ShaderReflection, SpirvError>
+    {
+        Module::parse(words)?.reflect(entry_point)
+    }
+
+    /// Checks that every input of `self` is written by a matching output of the `previous` stage.
+    ///
+    /// Following the Vulkan interface matching rules, an output may have more components
+    /// than the input that reads it, but not fewer, and their component types must agree.
+    pub fn check_interface(&self, previous: &ShaderReflection) -> Result<(), String>
+    {
+        for input in &self.inputs
+        {
+            let describe = |variable: &InterfaceVariable, stage: vk::ShaderStageFlags| format!(
+                "{:?} {} \"{}\" at location {} ({:?}{} x{})",
+                stage,
+                if stage == self.stage { "input" } else { "output" },
+                variable.name,
+                variable.location,
+                variable.kind,
+                variable.width,
+                variable.components);
+            let output = previous.outputs.iter().find(|output| output.location == input.location);
+            match output
+            {
+                None => return Err(format!(
+                    "{} is not written by the {:?} stage", describe(input, self.stage), previous.stage)),
+                Some(output) if output.kind != input.kind
+                    || output.width != input.width
+                    || output.components < input.components
+                    || output.locations != input.locations =>
+                    return Err(format!(
+                        "{} does not match {}", describe(input, self.stage), describe(output, previous.stage))),
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::ludo::shaders::{PARTICLES_COMP, PREFIX_SUM_COMP, TRIANGLE_FRAG, TRIANGLE_VERT};
+
+    fn reflect(bytes: &[u8]) -> ShaderReflection
+    {
+        ShaderReflection::reflect(&read_words(bytes).unwrap(), None).unwrap()
+    }
+
+    #[test]
+    fn code_is_validated()
+    {
+        assert_eq!(read_words(&TRIANGLE_VERT[..TRIANGLE_VERT.len() - 1]), Err(SpirvError::Misaligned(TRIANGLE_VERT.len() - 1)));
+        assert_eq!(read_words(&[0; 20]), Err(SpirvError::BadMagic));
+        let big_endian: Vec<u8> = TRIANGLE_VERT.chunks_exact(4).flat_map(|word| word.iter().rev().copied()).collect();
+        assert_eq!(read_words(&big_endian), read_words(TRIANGLE_VERT));
+        let words = read_words(TRIANGLE_VERT).unwrap();
+        assert_eq!(ShaderReflection::reflect(&words, Some("other")), Err(SpirvError::NoEntryPoint("other".to_string())));
+    }
+
+    #[test]
+    fn triangle_interfaces_are_reflected()
+    {
+        let vertex = reflect(TRIANGLE_VERT);
+        assert_eq!(vertex.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(vertex.entry_point, "main");
+        assert!(vertex.inputs.is_empty());
+        assert_eq!(vertex.outputs.len(), 1);
+        // The checked-in binaries are stripped of debug names.
+        assert_eq!(vertex.outputs[0].location, 0);
+        assert_eq!((vertex.outputs[0].kind, vertex.outputs[0].components), (ScalarKind::Float, 3));
+        assert!(vertex.descriptor_bindings.is_empty());
+        assert_eq!(vertex.push_constants, None);
+
+        let fragment = reflect(TRIANGLE_FRAG);
+        assert_eq!(fragment.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(fragment.outputs[0].get_format(), vk::Format::R32G32B32A32_SFLOAT);
+        assert_eq!(fragment.check_interface(&vertex), Ok(()));
+    }
+
+    #[test]
+    fn compute_shaders_are_reflected()
+    {
+        let prefix_sum = reflect(PREFIX_SUM_COMP);
+        assert_eq!(prefix_sum.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(prefix_sum.local_size, Some([64, 1, 1]));
+        let bindings: Vec<_> = prefix_sum.descriptor_bindings
+            .iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type, binding.count))
+            .collect();
+        assert_eq!(bindings, [
+            (0, 0, vk::DescriptorType::STORAGE_BUFFER, 1),
+            (0, 1, vk::DescriptorType::STORAGE_BUFFER, 1),
+        ]);
+        assert_eq!(prefix_sum.push_constants, Some((0, 8)));
+        assert_eq!(reflect(PARTICLES_COMP).push_constants, Some((0, 12)));
+    }
+
+    #[test]
+    fn interface_mismatches_are_reported()
+    {
+        let vertex = reflect(TRIANGLE_VERT);
+        let mut fragment = reflect(TRIANGLE_FRAG);
+        fragment.inputs[0].components = 4;
+        assert!(fragment.check_interface(&vertex).unwrap_err().contains("does not match"));
+        fragment.inputs[0].location = 1;
+        assert!(fragment.check_interface(&vertex).unwrap_err().contains("is not written"));
+    }
+
+    /// Assembles a compute shader with a uniform block, a sampled image array,
+    /// a storage buffer and push constants.
+    fn assemble_resources() -> Vec<u32>
+    {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+        let mut emit = |opcode: u32, operands: &[u32]| {
+            words.push(((operands.len() as u32 + 1) << 16) | opcode);
+            words.extend_from_slice(operands);
+        };
+        // "main" as a literal string.
+        emit(op::ENTRY_POINT, &[5, 1, u32::from_le_bytes(*b"main"), 0]);
+        emit(op::EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 8, 4, 1]);
+        emit(op::NAME, &[20, u32::from_le_bytes(*b"ligh"), u32::from_le_bytes(*b"ts\0\0")]);
+        emit(op::DECORATE, &[20, decoration::DESCRIPTOR_SET, 1]);
+        emit(op::DECORATE, &[20, decoration::BINDING, 2]);
+        emit(op::DECORATE, &[11, decoration::BLOCK]);
+        emit(op::MEMBER_DECORATE, &[11, 0, decoration::OFFSET, 0]);
+        emit(op::MEMBER_DECORATE, &[11, 1, decoration::OFFSET, 16]);
+        emit(op::MEMBER_DECORATE, &[11, 1, decoration::MATRIX_STRIDE, 16]);
+        emit(op::DECORATE, &[21, decoration::DESCRIPTOR_SET, 0]);
+        emit(op::DECORATE, &[21, decoration::BINDING, 0]);
+        emit(op::DECORATE, &[22, decoration::DESCRIPTOR_SET, 0]);
+        emit(op::DECORATE, &[22, decoration::BINDING, 1]);
+        emit(op::DECORATE, &[15, decoration::BLOCK]);
+        emit(op::MEMBER_DECORATE, &[15, 0, decoration::OFFSET, 0]);
+        emit(op::DECORATE, &[16, decoration::BLOCK]);
+        emit(op::MEMBER_DECORATE, &[16, 0, decoration::OFFSET, 64]);
+        emit(op::TYPE_FLOAT, &[2, 32]);
+        emit(op::TYPE_VECTOR, &[3, 2, 4]);
+        emit(op::TYPE_MATRIX, &[4, 3, 4]);
+        emit(op::TYPE_INT, &[5, 32, 0]);
+        emit(op::CONSTANT, &[5, 6, 4]);
+        emit(op::TYPE_STRUCT, &[11, 3, 4]);
+        emit(op::TYPE_POINTER, &[12, storage_class::UNIFORM, 11]);
+        emit(op::VARIABLE, &[12, 20, storage_class::UNIFORM]);
+        emit(op::TYPE_IMAGE, &[7, 2, 1, 0, 0, 0, 1, 0]);
+        emit(op::TYPE_SAMPLED_IMAGE, &[8, 7]);
+        emit(op::TYPE_ARRAY, &[9, 8, 6]);
+        emit(op::TYPE_POINTER, &[10, storage_class::UNIFORM_CONSTANT, 9]);
+        emit(op::VARIABLE, &[10, 21, storage_class::UNIFORM_CONSTANT]);
+        emit(op::TYPE_RUNTIME_ARRAY, &[14, 2]);
+        emit(op::TYPE_STRUCT, &[15, 14]);
+        emit(op::TYPE_POINTER, &[17, storage_class::STORAGE_BUFFER, 15]);
+        emit(op::VARIABLE, &[17, 22, storage_class::STORAGE_BUFFER]);
+        emit(op::TYPE_STRUCT, &[16, 3]);
+        emit(op::TYPE_POINTER, &[18, storage_class::PUSH_CONSTANT, 16]);
+        emit(op::VARIABLE, &[18, 23, storage_class::PUSH_CONSTANT]);
+        words
+    }
+
+    /// A compute entry point `main` with the given instructions and three uint constants
+    /// 16, 2 and 1 as ids 10 to 12.
+    fn assemble_compute(instructions: &[(u32, &[u32])]) -> Vec<u32>
+    {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0600, 0, 100, 0];
+        let mut emit = |opcode: u32, operands: &[u32]| {
+            words.push(((operands.len() as u32 + 1) << 16) | opcode);
+            words.extend_from_slice(operands);
+        };
+        emit(op::ENTRY_POINT, &[5, 1, u32::from_le_bytes(*b"main"), 0]);
+        for (opcode, operands) in instructions
+        {
+            emit(*opcode, operands);
+        }
+        emit(op::TYPE_INT, &[2, 32, 0]);
+        emit(op::CONSTANT, &[2, 10, 16]);
+        emit(op::CONSTANT, &[2, 11, 2]);
+        emit(op::SPEC_CONSTANT, &[2, 12, 1]);
+        words
+    }
+
+    #[test]
+    fn workgroup_sizes_are_reflected_from_ids_and_built_ins()
+    {
+        let local_size_id = assemble_compute(&[(op::EXECUTION_MODE_ID, &[1, EXECUTION_MODE_LOCAL_SIZE_ID, 10, 11, 12])]);
+        assert_eq!(ShaderReflection::reflect(&local_size_id, None).unwrap().local_size, Some([16, 2, 1]));
+
+        // The built-in wins over the literal size, as in GLSL with a specialized local_size_x_id.
+        let built_in = assemble_compute(&[
+            (op::EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 1, 1, 1]),
+            (op::DECORATE, &[20, decoration::BUILT_IN, BUILT_IN_WORKGROUP_SIZE]),
+            (op::SPEC_CONSTANT_COMPOSITE, &[3, 20, 12, 10, 11]),
+        ]);
+        assert_eq!(ShaderReflection::reflect(&built_in, None).unwrap().local_size, Some([1, 16, 2]));
+
+        let missing_constant = assemble_compute(&[(op::EXECUTION_MODE_ID, &[1, EXECUTION_MODE_LOCAL_SIZE_ID, 10, 13, 12])]);
+        assert_eq!(ShaderReflection::reflect(&missing_constant, None).unwrap().local_size, None);
+    }
+
+    #[test]
+    fn resources_are_reflected()
+    {
+        let reflection = ShaderReflection::reflect(&assemble_resources(), None).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(reflection.local_size, Some([8, 4, 1]));
+        let bindings: Vec<_> = reflection.descriptor_bindings
+            .iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type, binding.count))
+            .collect();
+        assert_eq!(bindings, vec![
+            (0, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+            (0, 1, vk::DescriptorType::STORAGE_BUFFER, 1),
+            (1, 2, vk::DescriptorType::UNIFORM_BUFFER, 1),
+        ]);
+        assert_eq!(reflection.descriptor_bindings[2].name, "lights");
+        assert_eq!(reflection.push_constants, Some((64, 16)));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ludo::sdl2;
+use crate::ludo::vulkan::Instance;
+use ash::extensions::khr;
+use ash::vk;
+use std::rc::Rc;
+
+struct SurfaceHandle
+{
+    surface: vk::SurfaceKHR,
+    loader: khr::Surface,
+    _window: sdl2::Window,
+    _instance: Instance,
+}
+impl Drop for SurfaceHandle
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.loader.destroy_surface(self.surface, None) };
+    }
+}
+
+/// Handle to a `VkSurfaceKHR`.
+///
+/// The surface keeps clones of the instance and window handles, so it is always destroyed
+/// before either of them. Swapchains keep a clone of the surface in turn.
+#[derive(Clone)]
+pub struct Surface
+{
+    handle: Rc<SurfaceHandle>,
+}
+impl Surface
+{
+    pub(crate) fn from_raw(instance: &Instance, surface: vk::SurfaceKHR, window: &sdl2::Window) -> Surface
+    {
+        let loader = khr::Surface::new(instance.get_entry(), instance.get_instance());
+        let handle = SurfaceHandle {
+            surface,
+            loader,
+            _window: window.clone(),
+            _instance: instance.clone(),
+        };
+        Surface { handle: Rc::new(handle) }
+    }
+
+    pub fn get_handle(&self) -> vk::SurfaceKHR
+    {
+        self.handle.surface
+    }
+
+    pub fn get_loader(&self) -> &khr::Surface
+    {
+        &self.handle.loader
+    }
+
+    pub fn get_capabilities(&self, physical_device: vk::PhysicalDevice) -> Result<vk::SurfaceCapabilitiesKHR, vk::Result>
+    {
+        unsafe { self.handle.loader.get_physical_device_surface_capabilities(physical_device, self.handle.surface) }
+    }
+
+    pub fn get_formats(&self, physical_device: vk::PhysicalDevice) -> Result<Vec<vk::SurfaceFormatKHR>, vk::Result>
+    {
+        unsafe { self.handle.loader.get_physical_device_surface_formats(physical_device, self.handle.surface) }
+    }
+
+    pub fn get_present_modes(&self, physical_device: vk::PhysicalDevice) -> Result<Vec<vk::PresentModeKHR>, vk::Result>
+    {
+        unsafe { self.handle.loader.get_physical_device_surface_present_modes(physical_device, self.handle.surface) }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::ludo::vulkan::{Device, Present, Queue, Surface};
+use ash::extensions::khr;
+use ash::vk;
+
+/// What the swapchain should look like; every list is in order of preference and the
+/// first supported entry wins.
+#[derive(Clone, Debug)]
+pub struct SwapchainPreferences
+{
+    pub formats: Vec<vk::SurfaceFormatKHR>,
+    pub present_modes: Vec<vk::PresentModeKHR>,
+    /// Clamped to the limits reported by the surface.
+    pub image_count: u32,
+}
+impl Default for SwapchainPreferences
+{
+    fn default() -> Self
+    {
+        SwapchainPreferences {
+            formats: vec![
+                vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+                vk::SurfaceFormatKHR { format: vk::Format::R8G8B8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+            ],
+            present_modes: vec![vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            image_count: 3,
+        }
+    }
+}
+
+/// Result of acquiring or presenting a swapchain image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapchainStatus
+{
+    Optimal,
+    /// The image can still be used, but the swapchain is rebuilt on the next `prepare`.
+    Suboptimal,
+    /// Nothing was acquired or presented; the swapchain is rebuilt on the next `prepare`.
+    OutOfDate,
+}
+
+/// Owns a `VkSwapchainKHR` with its images and image views.
+///
+/// The swapchain is rebuilt lazily by `prepare` after it went out of date, became
+/// suboptimal or the window was resized, and stays paused while the drawable area is empty.
+pub struct Swapchain
+{
+    loader: khr::Swapchain,
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    format: vk::SurfaceFormatKHR,
+    extent: vk::Extent2D,
+    /// Drawable extent of the last rebuild; `extent` may differ from it, e.g. when the
+    /// surface reports its own current extent or clamps the size.
+    drawable_extent: vk::Extent2D,
+    preferences: SwapchainPreferences,
+    out_of_date: bool,
+    generation: u64,
+    /// Debug name, given to every rebuilt swapchain and its images.
+    name: String,
+    surface: Surface,
+    device: Device,
+}
+impl Swapchain
+{
+    pub fn create(
+        device: &Device,
+        surface: &Surface,
+        drawable_extent: vk::Extent2D,
+        preferences: SwapchainPreferences) -> Result<Swapchain, vk::Result>
+    {
+        let loader = khr::Swapchain::new(
+            device.get_physical_device().get_instance().get_instance(), device.get_device());
+        let mut swapchain = Swapchain {
+            loader,
+            swapchain: vk::SwapchainKHR::null(),
+            images: Vec::new(),
+            image_views: Vec::new(),
+            format: vk::SurfaceFormatKHR::default(),
+            extent: vk::Extent2D::default(),
+            drawable_extent: vk::Extent2D::default(),
+            preferences,
+            out_of_date: true,
+            generation: 0,
+            name: "swapchain".to_owned(),
+            surface: surface.clone(),
+            device: device.clone(),
+        };
+        swapchain.prepare(drawable_extent)?;
+        Ok(swapchain)
+    }
+
+    /// Makes the swapchain usable for `drawable_extent`, rebuilding it when needed.
+    ///
+    /// Returns `false` while there is nothing to draw to, for example when the window is
+    /// minimized; rendering should be skipped until it returns `true` again.
+    pub fn prepare(&mut self, drawable_extent: vk::Extent2D) -> Result<bool, vk::Result>
+    {
+        if drawable_extent.width == 0 || drawable_extent.height == 0
+        {
+            return Ok(false);
+        }
+        if drawable_extent != self.drawable_extent
+        {
+            self.out_of_date = true;
+        }
+        if self.out_of_date || self.swapchain == vk::SwapchainKHR::null()
+        {
+            let physical_device = self.device.get_physical_device().get_handle();
+            let capabilities = self.surface.get_capabilities(physical_device)?;
+            let extent = Swapchain::choose_extent(&capabilities, drawable_extent);
+            if extent.width == 0 || extent.height == 0
+            {
+                return Ok(false);
+            }
+            self.rebuild(&capabilities, extent)?;
+            self.drawable_extent = drawable_extent;
+        }
+        Ok(true)
+    }
+
+    /// Forces a rebuild on the next `prepare`, e.g. after a resize event.
+    pub fn mark_out_of_date(&mut self)
+    {
+        self.out_of_date = true;
+    }
+
+    fn choose_format(&self, available: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR
+    {
+        // A single UNDEFINED entry means the surface has no preference at all.
+        if available.len() == 1 && available[0].format == vk::Format::UNDEFINED
+        {
+            if let Some(format) = self.preferences.formats.first()
+            {
+                return *format;
+            }
+        }
+        self.preferences.formats
+            .iter()
+            .find(|preferred| available.contains(preferred))
+            .or_else(|| available.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn choose_present_mode(&self, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR
+    {
+        // FIFO is the only mode every implementation has to support.
+        self.preferences.present_modes
+            .iter()
+            .find(|preferred| available.contains(preferred))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+
+    fn choose_extent(capabilities: &vk::SurfaceCapabilitiesKHR, drawable_extent: vk::Extent2D) -> vk::Extent2D
+    {
+        if capabilities.current_extent.width != u32::MAX
+        {
+            return capabilities.current_extent;
+        }
+        vk::Extent2D {
+            width: drawable_extent.width.clamp(
+                capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+            height: drawable_extent.height.clamp(
+                capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+        }
+    }
+
+    fn choose_image_count(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> u32
+    {
+        let mut image_count = self.preferences.image_count.max(capabilities.min_image_count);
+        if capabilities.max_image_count > 0
+        {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
+        image_count
+    }
+
+    fn rebuild(&mut self, capabilities: &vk::SurfaceCapabilitiesKHR, extent: vk::Extent2D) -> Result<(), vk::Result>
+    {
+        // Images of the old swapchain may still be in use by submitted frames.
+        self.device.wait_idle()?;
+
+        let physical_device = self.device.get_physical_device().get_handle();
+        let format = self.choose_format(&self.surface.get_formats(physical_device)?);
+        let present_mode = self.choose_present_mode(&self.surface.get_present_modes(physical_device)?);
+        let image_count = self.choose_image_count(capabilities);
+        let composite_alpha = [
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            vk::CompositeAlphaFlagsKHR::INHERIT,
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+        ]
+            .into_iter()
+            .find(|flag| capabilities.supported_composite_alpha.contains(*flag))
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+        let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        if capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::TRANSFER_DST)
+        {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_DST;
+        }
+
+        let families = self.device.get_queue_families();
+        let queue_family_indices = [families.graphics.unwrap(), families.present.unwrap_or(families.graphics.unwrap())];
+        let old_swapchain = self.swapchain;
+        let mut create_info = vk::SwapchainCreateInfoKHR::builder()
+            .surface(self.surface.get_handle())
+            .min_image_count(image_count)
+            .image_format(format.format)
+            .image_color_space(format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_usage(image_usage)
+            .pre_transform(capabilities.current_transform)
+            .composite_alpha(composite_alpha)
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(old_swapchain);
+        create_info = if queue_family_indices[0] != queue_family_indices[1]
+        {
+            create_info
+                .image_sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_family_indices)
+        }
+        else
+        {
+            create_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
+        let swapchain = unsafe { self.loader.create_swapchain(&create_info, None) }?;
+
+        self.destroy_image_views();
+        if old_swapchain != vk::SwapchainKHR::null()
+        {
+            unsafe { self.loader.destroy_swapchain(old_swapchain, None) };
+        }
+        self.swapchain = swapchain;
+        self.format = format;
+        self.extent = extent;
+        self.images = unsafe { self.loader.get_swapchain_images(swapchain) }?;
+        for image in &self.images
+        {
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(*image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format.format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            let view = unsafe { self.device.get_device().create_image_view(&view_info, None) }?;
+            self.image_views.push(view);
+        }
+        self.apply_name();
+        self.out_of_date = false;
+        self.generation += 1;
+        println!("Swapchain built: {}x{}, {:?}, {:?}, {} images",
+            extent.width, extent.height, format.format, present_mode, self.images.len());
+        Ok(())
+    }
+
+    /// Acquires the next image, signalling `semaphore` once it can be written.
+    ///
+    /// Returns the image index together with the status; with `OutOfDate` no image was
+    /// acquired and the index is meaningless.
+    pub fn acquire_next_image(&mut self, semaphore: vk::Semaphore, fence: vk::Fence) -> Result<(u32, SwapchainStatus), vk::Result>
+    {
+        let result = unsafe { self.loader.acquire_next_image(self.swapchain, u64::MAX, semaphore, fence) };
+        match result
+        {
+            Ok((index, false)) => Ok((index, SwapchainStatus::Optimal)),
+            Ok((index, true)) =>
+            {
+                self.out_of_date = true;
+                Ok((index, SwapchainStatus::Suboptimal))
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) =>
+            {
+                self.out_of_date = true;
+                Ok((0, SwapchainStatus::OutOfDate))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn present(&mut self, queue: Queue<Present>, image_index: u32, wait_semaphores: &[vk::Semaphore]) -> Result<SwapchainStatus, vk::Result>
+    {
+        let swapchains = [self.swapchain];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+        let result = unsafe { self.loader.queue_present(queue.get_handle(), &present_info) };
+        match result
+        {
+            Ok(false) => Ok(SwapchainStatus::Optimal),
+            Ok(true) =>
+            {
+                self.out_of_date = true;
+                Ok(SwapchainStatus::Suboptimal)
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) =>
+            {
+                self.out_of_date = true;
+                Ok(SwapchainStatus::OutOfDate)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Names the swapchain and its images, also after every rebuild.
+    pub fn set_name(&mut self, name: &str)
+    {
+        self.name = name.to_owned();
+        self.apply_name();
+    }
+
+    fn apply_name(&self)
+    {
+        if !self.device.is_debug_utils_enabled() || self.swapchain == vk::SwapchainKHR::null()
+        {
+            return;
+        }
+        self.device.set_object_name(self.swapchain, &self.name);
+        for (index, (image, view)) in self.images.iter().zip(&self.image_views).enumerate()
+        {
+            self.device.set_object_name(*image, &format!("{} image {}", self.name, index));
+            self.device.set_object_name(*view, &format!("{} view {}", self.name, index));
+        }
+    }
+
+    pub fn get_images(&self) -> &[vk::Image]
+    {
+        &self.images
+    }
+
+    pub fn get_image_views(&self) -> &[vk::ImageView]
+    {
+        &self.image_views
+    }
+
+    pub fn get_format(&self) -> vk::SurfaceFormatKHR
+    {
+        self.format
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D
+    {
+        self.extent
+    }
+
+    /// Incremented on every rebuild, so dependent objects can tell when to recreate themselves.
+    pub fn get_generation(&self) -> u64
+    {
+        self.generation
+    }
+
+    fn destroy_image_views(&mut self)
+    {
+        for view in self.image_views.drain(..)
+        {
+            unsafe { self.device.get_device().destroy_image_view(view, None) };
+        }
+    }
+}
+impl Drop for Swapchain
+{
+    fn drop(&mut self)
+    {
+        let _ = self.device.wait_idle();
+        self.destroy_image_views();
+        if self.swapchain != vk::SwapchainKHR::null()
+        {
+            unsafe { self.loader.destroy_swapchain(self.swapchain, None) };
+        }
+    }
+}
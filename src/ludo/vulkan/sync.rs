@@ -0,0 +1,146 @@
+use crate::ludo::vulkan::Device;
+use ash::vk;
+
+pub struct Semaphore
+{
+    semaphore: vk::Semaphore,
+    device: Device,
+}
+impl Semaphore
+{
+    pub fn create(device: &Device) -> Result<Semaphore, vk::Result>
+    {
+        let create_info = vk::SemaphoreCreateInfo::builder();
+        let semaphore = unsafe { device.get_device().create_semaphore(&create_info, None) }?;
+        Ok(Semaphore { semaphore, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::Semaphore
+    {
+        self.semaphore
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.semaphore, name);
+    }
+}
+impl Drop for Semaphore
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_semaphore(self.semaphore, None) };
+    }
+}
+
+pub struct Fence
+{
+    fence: vk::Fence,
+    device: Device,
+}
+impl Fence
+{
+    pub fn create(device: &Device, signaled: bool) -> Result<Fence, vk::Result>
+    {
+        let flags = if signaled { vk::FenceCreateFlags::SIGNALED } else { vk::FenceCreateFlags::empty() };
+        let create_info = vk::FenceCreateInfo::builder().flags(flags);
+        let fence = unsafe { device.get_device().create_fence(&create_info, None) }?;
+        Ok(Fence { fence, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::Fence
+    {
+        self.fence
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.fence, name);
+    }
+
+    pub fn wait(&self, timeout: u64) -> Result<(), vk::Result>
+    {
+        unsafe { self.device.get_device().wait_for_fences(&[self.fence], true, timeout) }
+    }
+
+    pub fn reset(&self) -> Result<(), vk::Result>
+    {
+        unsafe { self.device.get_device().reset_fences(&[self.fence]) }
+    }
+
+    pub fn is_signaled(&self) -> Result<bool, vk::Result>
+    {
+        unsafe { self.device.get_device().get_fence_status(self.fence) }
+    }
+}
+impl Drop for Fence
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_fence(self.fence, None) };
+    }
+}
+
+/// A semaphore with a 64-bit counter that submissions advance, from Vulkan 1.2.
+///
+/// Needs `Device::is_timeline_semaphore_enabled`; one of them replaces a fence per submission.
+pub struct TimelineSemaphore
+{
+    semaphore: vk::Semaphore,
+    device: Device,
+}
+impl TimelineSemaphore
+{
+    pub fn create(device: &Device, initial_value: u64) -> Result<TimelineSemaphore, vk::Result>
+    {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_info);
+        let semaphore = unsafe { device.get_device().create_semaphore(&create_info, None) }?;
+        Ok(TimelineSemaphore { semaphore, device: device.clone() })
+    }
+
+    pub fn get_handle(&self) -> vk::Semaphore
+    {
+        self.semaphore
+    }
+
+    pub fn set_name(&self, name: &str)
+    {
+        self.device.set_object_name(self.semaphore, name);
+    }
+
+    /// The signal of `value` for a submission, e.g. for `Uploader::submit`.
+    #[allow(dead_code)] // Nothing in the demo renderer uploads yet.
+    pub fn get_signal(&self, value: u64) -> TimelineSignal
+    {
+        TimelineSignal { semaphore: self.semaphore, value }
+    }
+
+    /// Blocks until the counter reaches `value`.
+    pub fn wait(&self, value: u64, timeout: u64) -> Result<(), vk::Result>
+    {
+        let semaphores = [self.semaphore];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe { self.device.get_device().wait_semaphores(&wait_info, timeout) }
+    }
+}
+impl Drop for TimelineSemaphore
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.device.get_device().destroy_semaphore(self.semaphore, None) };
+    }
+}
+
+/// A value a timeline semaphore is set to when a submission completes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimelineSignal
+{
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+}
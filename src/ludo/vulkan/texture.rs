@@ -0,0 +1,662 @@
+use crate::ludo::vulkan::{
+    decode_compressed, downsample_rgba8, get_decoded_format, get_format_block, is_compressed, is_srgb, Allocation,
+    Allocator, BufferError, CommandPool, Device, Fence, MemoryUsage, Uploader,
+};
+use ash::vk;
+use std::ops::Range;
+use std::path::Path;
+
+/// How the color channels of PNG and JPEG images are to be interpreted.
+///
+/// Albedo and UI images are `Srgb` and get decoded to linear values by the sampler;
+/// normal maps, roughness and other data are `Linear`. KTX2 files carry their own format.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace
+{
+    Srgb,
+    Linear,
+}
+impl ColorSpace
+{
+    fn get_rgba8_format(self) -> vk::Format
+    {
+        match self
+        {
+            ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+            ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError
+{
+    Io(std::io::Error),
+    /// The file is not a valid PNG, JPEG or KTX2 image, or uses a feature that is not supported.
+    Decode(String),
+    /// The device cannot sample the format and there is no CPU fallback for it.
+    FormatNotSupported(vk::Format),
+    Upload(BufferError),
+    Vulkan(vk::Result),
+}
+impl std::fmt::Display for TextureError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            TextureError::Io(error) =>
+                write!(f, "{}", error),
+            TextureError::Decode(message) =>
+                write!(f, "cannot decode image: {}", message),
+            TextureError::FormatNotSupported(format) =>
+                write!(f, "format {:?} is not supported by the device", format),
+            TextureError::Upload(error) =>
+                write!(f, "{}", error),
+            TextureError::Vulkan(result) =>
+                write!(f, "{}", result),
+        }
+    }
+}
+impl std::error::Error for TextureError {}
+impl From<std::io::Error> for TextureError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        TextureError::Io(error)
+    }
+}
+impl From<BufferError> for TextureError
+{
+    fn from(error: BufferError) -> Self
+    {
+        TextureError::Upload(error)
+    }
+}
+impl From<vk::Result> for TextureError
+{
+    fn from(result: vk::Result) -> Self
+    {
+        TextureError::Vulkan(result)
+    }
+}
+
+/// A decoded 2D image: tightly packed texel data of one or more mip levels, largest first.
+pub struct TextureData
+{
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub levels: Vec<Vec<u8>>,
+}
+// Texture loading is library API the demo renderer does not use yet.
+#[allow(dead_code)]
+impl TextureData
+{
+    /// Loads a PNG, JPEG or KTX2 file, recognized by its contents rather than its extension.
+    pub fn from_file(path: &Path, color_space: ColorSpace) -> Result<TextureData, TextureError>
+    {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n")
+        {
+            TextureData::from_png(&bytes, color_space)
+        }
+        else if bytes.starts_with(&[0xff, 0xd8, 0xff])
+        {
+            TextureData::from_jpeg(&bytes, color_space)
+        }
+        else if bytes.starts_with(b"\xabKTX 20\xbb\r\n\x1a\n")
+        {
+            TextureData::from_ktx2(&bytes)
+        }
+        else
+        {
+            Err(TextureError::Decode(format!("{} is not a PNG, JPEG or KTX2 file", path.display())))
+        }
+    }
+
+    /// Decodes a PNG of any color type and bit depth to RGBA8.
+    pub fn from_png(bytes: &[u8], color_space: ColorSpace) -> Result<TextureData, TextureError>
+    {
+        let decode_error = |error: png::DecodingError| TextureError::Decode(error.to_string());
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(decode_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+        buffer.truncate(info.buffer_size());
+        let channels = match info.color_type
+        {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed =>
+                return Err(TextureError::Decode("indexed PNG was not expanded".to_string())),
+        };
+        Ok(TextureData {
+            width: info.width,
+            height: info.height,
+            format: color_space.get_rgba8_format(),
+            levels: vec![expand_to_rgba8(&buffer, channels)],
+        })
+    }
+
+    /// Decodes a baseline or progressive JPEG to RGBA8.
+    pub fn from_jpeg(bytes: &[u8], color_space: ColorSpace) -> Result<TextureData, TextureError>
+    {
+        let mut decoder = jpeg_decoder::Decoder::new(bytes);
+        let pixels = decoder.decode().map_err(|error| TextureError::Decode(error.to_string()))?;
+        let info = decoder.info().unwrap();
+        let pixels = match info.pixel_format
+        {
+            jpeg_decoder::PixelFormat::L8 => expand_to_rgba8(&pixels, 1),
+            // Big-endian samples; the high byte is all an 8-bit texture can keep.
+            jpeg_decoder::PixelFormat::L16 =>
+                expand_to_rgba8(&pixels.chunks_exact(2).map(|sample| sample[0]).collect::<Vec<_>>(), 1),
+            jpeg_decoder::PixelFormat::RGB24 => expand_to_rgba8(&pixels, 3),
+            jpeg_decoder::PixelFormat::CMYK32 =>
+                return Err(TextureError::Decode("CMYK JPEG images are not supported".to_string())),
+        };
+        Ok(TextureData {
+            width: info.width as u32,
+            height: info.height as u32,
+            format: color_space.get_rgba8_format(),
+            levels: vec![pixels],
+        })
+    }
+
+    /// Reads the mip levels of a 2D KTX2 texture in its stored format.
+    ///
+    /// Supercompressed files (Basis Universal, zstd) would need a transcoder and are rejected,
+    /// as are arrays, cube maps and 3D textures.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<TextureData, TextureError>
+    {
+        let reader = ktx2::Reader::new(bytes).map_err(|error| TextureError::Decode(error.to_string()))?;
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme
+        {
+            return Err(TextureError::Decode(format!("supercompression {:?} is not supported", scheme)));
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1
+        {
+            return Err(TextureError::Decode("only 2D KTX2 textures are supported".to_string()));
+        }
+        let format = header.format
+            .map(|format| vk::Format::from_raw(format.value() as i32))
+            .ok_or_else(|| TextureError::Decode("KTX2 file has no Vulkan format".to_string()))?;
+        let block = get_format_block(format).ok_or(TextureError::FormatNotSupported(format))?;
+        let width = header.pixel_width;
+        let height = header.pixel_height.max(1);
+        let mut levels = Vec::new();
+        for (index, level) in reader.levels().enumerate()
+        {
+            let expected = block.get_image_size((width >> index).max(1), (height >> index).max(1));
+            if level.data.len() < expected
+            {
+                return Err(TextureError::Decode(format!("mip level {} is truncated", index)));
+            }
+            levels.push(level.data[..expected].to_vec());
+        }
+        if levels.is_empty()
+        {
+            return Err(TextureError::Decode("KTX2 file has no mip levels".to_string()));
+        }
+        Ok(TextureData { width, height, format, levels })
+    }
+
+    fn get_level_extent(&self, level: u32) -> vk::Extent2D
+    {
+        vk::Extent2D { width: (self.width >> level).max(1), height: (self.height >> level).max(1) }
+    }
+
+    /// Number of levels of a full mip chain down to 1x1.
+    pub fn get_full_mip_count(&self) -> u32
+    {
+        u32::BITS - self.width.max(self.height).max(1).leading_zeros()
+    }
+
+    /// Decodes block-compressed levels on the CPU, if the format has a decoder.
+    fn decode(&self) -> Option<TextureData>
+    {
+        let format = get_decoded_format(self.format)?;
+        let mut levels = Vec::new();
+        for (index, level) in self.levels.iter().enumerate()
+        {
+            let extent = self.get_level_extent(index as u32);
+            levels.push(decode_compressed(self.format, extent.width, extent.height, level)?);
+        }
+        Some(TextureData { width: self.width, height: self.height, format, levels })
+    }
+
+    /// Whether `generate_mips` can filter the format; it only knows 8-bit RGBA and BGRA.
+    fn can_generate_mips(&self) -> bool
+    {
+        matches!(self.format,
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB)
+    }
+
+    /// Appends levels down to 1x1 by box filtering the last one, in linear space for sRGB.
+    fn generate_mips(&mut self)
+    {
+        let mut extent = self.get_level_extent(self.levels.len() as u32 - 1);
+        while self.levels.len() < self.get_full_mip_count() as usize
+        {
+            let (width, height, pixels) =
+                downsample_rgba8(extent.width, extent.height, self.levels.last().unwrap(), is_srgb(self.format));
+            extent = vk::Extent2D { width, height };
+            self.levels.push(pixels);
+        }
+    }
+}
+
+fn expand_to_rgba8(pixels: &[u8], channels: usize) -> Vec<u8>
+{
+    if channels == 4
+    {
+        return pixels.to_vec();
+    }
+    let mut rgba = Vec::with_capacity(pixels.len() / channels * 4);
+    for texel in pixels.chunks_exact(channels)
+    {
+        match channels
+        {
+            1 => rgba.extend_from_slice(&[texel[0], texel[0], texel[0], 255]),
+            2 => rgba.extend_from_slice(&[texel[0], texel[0], texel[0], texel[1]]),
+            _ => rgba.extend_from_slice(&[texel[0], texel[1], texel[2], 255]),
+        }
+    }
+    rgba
+}
+
+/// A sampled 2D image with a full or file-provided mip chain, in `SHADER_READ_ONLY_OPTIMAL`.
+///
+/// Compressed formats the device cannot sample are decoded on the CPU when a decoder
+/// exists (BC1-BC7 and ASTC LDR); missing mip levels of uncompressed images are generated
+/// with blits, or on the CPU when the format cannot be linearly blitted.
+pub struct Texture
+{
+    image: vk::Image,
+    image_memory: Option<Allocation>,
+    image_view: vk::ImageView,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    device: Device,
+}
+// Texture loading is library API the demo renderer does not use yet.
+#[allow(dead_code)]
+impl Texture
+{
+    pub fn from_file(
+        allocator: &Allocator,
+        uploader: &mut Uploader,
+        path: &Path,
+        color_space: ColorSpace) -> Result<Texture, TextureError>
+    {
+        Texture::create(allocator, uploader, TextureData::from_file(path, color_space)?)
+    }
+
+    /// Uploads `data` and waits until the texture is ready to be sampled.
+    pub fn create(allocator: &Allocator, uploader: &mut Uploader, data: TextureData) -> Result<Texture, TextureError>
+    {
+        let device = allocator.get_device();
+        let physical_device = device.get_physical_device();
+        let mut data = if physical_device.supports_format(data.format, vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            data
+        }
+        else
+        {
+            data.decode()
+                .filter(|decoded| physical_device.supports_format(decoded.format, vk::FormatFeatureFlags::SAMPLED_IMAGE))
+                .ok_or(TextureError::FormatNotSupported(data.format))?
+        };
+        let block = get_format_block(data.format).ok_or(TextureError::FormatNotSupported(data.format))?;
+
+        // Compressed images keep the levels they came with; they cannot be blitted into.
+        let mut generate_with_blits = false;
+        if !is_compressed(data.format) && data.levels.len() < data.get_full_mip_count() as usize
+        {
+            let blit_features = vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+            if physical_device.supports_format(data.format, blit_features)
+            {
+                generate_with_blits = true;
+            }
+            // Anything else keeps the levels it came with.
+            else if data.can_generate_mips()
+            {
+                data.generate_mips();
+            }
+        }
+        let mip_levels = if generate_with_blits { data.get_full_mip_count() } else { data.levels.len() as u32 };
+
+        let mut texture = Texture {
+            image: vk::Image::null(),
+            image_memory: None,
+            image_view: vk::ImageView::null(),
+            format: data.format,
+            extent: vk::Extent2D { width: data.width, height: data.height },
+            mip_levels,
+            device: device.clone(),
+        };
+        // Anything created before a failure is released by `drop`.
+        texture.create_image(allocator, generate_with_blits)?;
+
+        let all_levels = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: mip_levels,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        uploader.transition_image(
+            texture.image, all_levels, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
+        for (index, level) in data.levels.iter().enumerate()
+        {
+            uploader.upload_image(texture.image, block, index as u32, data.get_level_extent(index as u32), level)?;
+        }
+        uploader.flush()?;
+
+        // The transfer queue may not support blits or shader stages; the image is shared
+        // between all queue families, so the graphics queue can simply take over.
+        let uploaded_levels = data.levels.len() as u32;
+        texture.submit_graphics(|device, command_buffer| {
+            let mut blit_sources = 0..0;
+            if generate_with_blits
+            {
+                texture.record_blits(device, command_buffer, uploaded_levels);
+                blit_sources = uploaded_levels - 1..mip_levels - 1;
+            }
+            texture.record_shader_read_transition(device, command_buffer, blit_sources);
+        })?;
+        Ok(texture)
+    }
+
+    fn create_image(&mut self, allocator: &Allocator, generate_with_blits: bool) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let mut usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        if generate_with_blits
+        {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let families = self.device.get_queue_families().get_unique();
+        let mut create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(self.format)
+            .extent(vk::Extent3D { width: self.extent.width, height: self.extent.height, depth: 1 })
+            .mip_levels(self.mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        if families.len() > 1
+        {
+            create_info = create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families);
+        }
+        self.image = unsafe { device.create_image(&create_info, None) }?;
+        self.image_memory = Some(allocator.allocate_for_image(self.image, MemoryUsage::GpuOnly)?);
+
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(self.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(self.format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: self.mip_levels,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        self.image_view = unsafe { device.create_image_view(&view_info, None) }?;
+        Ok(())
+    }
+
+    /// Records `record` into a one-time command buffer and waits for it on the graphics queue.
+    fn submit_graphics<F: FnOnce(&ash::Device, vk::CommandBuffer)>(&self, record: F) -> Result<(), vk::Result>
+    {
+        let device = self.device.get_device();
+        let queue = self.device.get_graphics_queue();
+        let command_pool = CommandPool::create(
+            &self.device, queue.get_family_index(), vk::CommandPoolCreateFlags::TRANSIENT)?;
+        let command_buffer = command_pool.allocate(vk::CommandBufferLevel::PRIMARY, 1)?[0];
+        let fence = Fence::create(&self.device, false)?;
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { device.begin_command_buffer(command_buffer, &begin_info) }?;
+        record(device, command_buffer);
+        unsafe { device.end_command_buffer(command_buffer) }?;
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        unsafe { device.queue_submit(queue.get_handle(), &[submit_info.build()], fence.get_handle()) }?;
+        fence.wait(u64::MAX)
+    }
+
+    fn get_level_range(&self, base_mip_level: u32, level_count: u32) -> vk::ImageSubresourceRange
+    {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level,
+            level_count,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    /// Fills levels from `first_level` on by halving the previous one with a linear blit.
+    ///
+    /// Leaves every level but the last in `TRANSFER_SRC_OPTIMAL`.
+    fn record_blits(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, first_level: u32)
+    {
+        for level in first_level..self.mip_levels
+        {
+            let source = level - 1;
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image)
+                .subresource_range(self.get_level_range(source, 1));
+            let get_corner = |level: u32| vk::Offset3D {
+                x: (self.extent.width >> level).max(1) as i32,
+                y: (self.extent.height >> level).max(1) as i32,
+                z: 1,
+            };
+            let get_layers = |level: u32| vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: level,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+            let blit = vk::ImageBlit {
+                src_subresource: get_layers(source),
+                src_offsets: [vk::Offset3D::default(), get_corner(source)],
+                dst_subresource: get_layers(level),
+                dst_offsets: [vk::Offset3D::default(), get_corner(level)],
+            };
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[barrier.build()]);
+                device.cmd_blit_image(
+                    command_buffer,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR);
+            }
+        }
+    }
+
+    /// Moves all levels to `SHADER_READ_ONLY_OPTIMAL`; `blit_sources` are the levels
+    /// `record_blits` left in `TRANSFER_SRC_OPTIMAL`, the others are in `TRANSFER_DST_OPTIMAL`.
+    fn record_shader_read_transition(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        blit_sources: Range<u32>)
+    {
+        let get_barrier = |levels: Range<u32>, old_layout: vk::ImageLayout, src_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(old_layout)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image)
+                .subresource_range(self.get_level_range(levels.start, levels.end - levels.start))
+                .build()
+        };
+        let ranges = [
+            (0..blit_sources.start, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::AccessFlags::TRANSFER_WRITE),
+            (blit_sources.clone(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::AccessFlags::TRANSFER_READ),
+            (blit_sources.end.max(blit_sources.start)..self.mip_levels,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::AccessFlags::TRANSFER_WRITE),
+        ];
+        let barriers: Vec<_> = ranges
+            .into_iter()
+            .filter(|(levels, _, _)| !levels.is_empty())
+            .map(|(levels, old_layout, src_access)| get_barrier(levels, old_layout, src_access))
+            .collect();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers);
+        }
+    }
+
+    pub fn get_image(&self) -> vk::Image
+    {
+        self.image
+    }
+
+    pub fn get_image_view(&self) -> vk::ImageView
+    {
+        self.image_view
+    }
+
+    /// Names the image, and its view after it.
+    pub fn set_name(&self, name: &str)
+    {
+        if self.device.is_debug_utils_enabled()
+        {
+            self.device.set_object_name(self.image, name);
+            self.device.set_object_name(self.image_view, &format!("{} view", name));
+        }
+    }
+
+    pub fn get_format(&self) -> vk::Format
+    {
+        self.format
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D
+    {
+        self.extent
+    }
+
+    pub fn get_mip_levels(&self) -> u32
+    {
+        self.mip_levels
+    }
+}
+impl Drop for Texture
+{
+    fn drop(&mut self)
+    {
+        let device = self.device.get_device();
+        unsafe {
+            device.destroy_image_view(self.image_view, None);
+            device.destroy_image(self.image, None);
+        }
+        // Memory goes back to the allocator only after the image bound to it is gone.
+        self.image_memory = None;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn encode_png(width: u32, height: u32, color_type: png::ColorType, pixels: &[u8]) -> Vec<u8>
+    {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn png_is_expanded_to_rgba8()
+    {
+        let bytes = encode_png(2, 1, png::ColorType::Rgb, &[10, 20, 30, 40, 50, 60]);
+        let data = TextureData::from_png(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!((data.width, data.height, data.format), (2, 1, vk::Format::R8G8B8A8_UNORM));
+        assert_eq!(data.levels, vec![vec![10, 20, 30, 255, 40, 50, 60, 255]]);
+
+        let bytes = encode_png(1, 1, png::ColorType::GrayscaleAlpha, &[7, 9]);
+        let data = TextureData::from_png(&bytes, ColorSpace::Srgb).unwrap();
+        assert_eq!(data.format, vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(data.levels, vec![vec![7, 7, 7, 9]]);
+    }
+
+    #[test]
+    fn generated_mips_reach_one_texel()
+    {
+        let mut data = TextureData {
+            width: 5,
+            height: 2,
+            format: vk::Format::R8G8B8A8_UNORM,
+            levels: vec![vec![100; 5 * 2 * 4]],
+        };
+        assert_eq!(data.get_full_mip_count(), 3);
+        data.generate_mips();
+        let sizes: Vec<_> = data.levels.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![40, 8, 4]);
+        assert_eq!(data.levels[2], vec![100; 4]);
+
+        // Other formats of four bytes per texel would come out as garbage.
+        for format in [vk::Format::R32_SFLOAT, vk::Format::A2B10G10R10_UNORM_PACK32, vk::Format::B10G11R11_UFLOAT_PACK32]
+        {
+            assert!(!TextureData { format, width: 1, height: 1, levels: Vec::new() }.can_generate_mips());
+        }
+        assert!(TextureData { format: vk::Format::B8G8R8A8_SRGB, ..data }.can_generate_mips());
+    }
+
+    #[test]
+    fn unknown_files_are_rejected()
+    {
+        assert!(matches!(TextureData::from_ktx2(b"not a texture"), Err(TextureError::Decode(_))));
+        assert!(matches!(TextureData::from_png(b"\x89PNG", ColorSpace::Srgb), Err(TextureError::Decode(_))));
+    }
+}
@@ -0,0 +1,508 @@
+use ash::vk;
+
+mod astc;
+mod bc6h;
+mod bc7;
+
+/// Size of one texel block: 1x1 for plain formats, e.g. 4x4 for BC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatBlock
+{
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u32,
+}
+impl FormatBlock
+{
+    const fn new(width: u32, height: u32, bytes: u32) -> FormatBlock
+    {
+        FormatBlock { width, height, bytes }
+    }
+
+    /// Bytes of a tightly packed `width` x `height` image.
+    pub fn get_image_size(&self, width: u32, height: u32) -> usize
+    {
+        (width.div_ceil(self.width) * height.div_ceil(self.height) * self.bytes) as usize
+    }
+}
+
+/// Block layout of the formats textures can be loaded in.
+pub fn get_format_block(format: vk::Format) -> Option<FormatBlock>
+{
+    let block = match format
+    {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => FormatBlock::new(1, 1, 1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB => FormatBlock::new(1, 1, 2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB => FormatBlock::new(1, 1, 4),
+        vk::Format::R16G16B16A16_SFLOAT => FormatBlock::new(1, 1, 8),
+        vk::Format::R32G32B32A32_SFLOAT => FormatBlock::new(1, 1, 16),
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => FormatBlock::new(4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::ASTC_4X4_UNORM_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK => FormatBlock::new(4, 4, 16),
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => FormatBlock::new(5, 5, 16),
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => FormatBlock::new(6, 6, 16),
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => FormatBlock::new(8, 8, 16),
+        _ => return None,
+    };
+    Some(block)
+}
+
+pub fn is_compressed(format: vk::Format) -> bool
+{
+    get_format_block(format).is_some_and(|block| block.width > 1)
+}
+
+pub fn is_srgb(format: vk::Format) -> bool
+{
+    matches!(format,
+        vk::Format::R8_SRGB
+        | vk::Format::R8G8_SRGB
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK
+        | vk::Format::ASTC_5X5_SRGB_BLOCK
+        | vk::Format::ASTC_6X6_SRGB_BLOCK
+        | vk::Format::ASTC_8X8_SRGB_BLOCK)
+}
+
+/// The uncompressed format a block-compressed format decodes to on the CPU, if it can be decoded.
+///
+/// Everything decodes to RGBA8 except BC6H, which keeps its HDR range as RGBA16F.
+pub fn get_decoded_format(format: vk::Format) -> Option<vk::Format>
+{
+    match format
+    {
+        vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK
+        | vk::Format::ASTC_5X5_SRGB_BLOCK
+        | vk::Format::ASTC_6X6_SRGB_BLOCK
+        | vk::Format::ASTC_8X8_SRGB_BLOCK => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::ASTC_4X4_UNORM_BLOCK
+        | vk::Format::ASTC_5X5_UNORM_BLOCK
+        | vk::Format::ASTC_6X6_UNORM_BLOCK
+        | vk::Format::ASTC_8X8_UNORM_BLOCK => Some(vk::Format::R8G8B8A8_UNORM),
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => Some(vk::Format::R16G16B16A16_SFLOAT),
+        _ => None,
+    }
+}
+
+/// Reads the fields of a 128-bit block from its lowest bit up, as BC6H, BC7 and ASTC lay them out.
+struct BlockBits
+{
+    bits: u128,
+    position: u32,
+}
+impl BlockBits
+{
+    fn new(block: &[u8]) -> BlockBits
+    {
+        BlockBits { bits: u128::from_le_bytes(block[..16].try_into().unwrap()), position: 0 }
+    }
+
+    /// The next `count` bits; past the end of the block they read as zeros.
+    fn read(&mut self, count: u32) -> u32
+    {
+        let value = if count == 0 || self.position >= 128
+        {
+            0
+        }
+        else
+        {
+            ((self.bits >> self.position) & ((1u128 << count) - 1)) as u32
+        };
+        self.position += count;
+        value
+    }
+}
+
+/// Builds blocks field by field for the decoder tests.
+#[cfg(test)]
+#[derive(Default)]
+struct BlockWriter
+{
+    bits: u128,
+    position: u32,
+}
+#[cfg(test)]
+impl BlockWriter
+{
+    fn write(&mut self, value: u64, count: u32) -> &mut BlockWriter
+    {
+        self.bits |= (value as u128 & ((1u128 << count) - 1)) << self.position;
+        self.position += count;
+        self
+    }
+
+    fn get_bytes(&self) -> [u8; 16]
+    {
+        self.bits.to_le_bytes()
+    }
+}
+
+/// Interpolation weights of 2-, 3- and 4-bit indices, shared by BC6H and BC7.
+const WEIGHTS_2: [u8; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u8; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u8; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Two-subset partitions of BC6H and BC7; bit i is the subset of texel i.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Texel holding the implicit high bit of the indices of the second subset of each two-subset partition.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Three-subset partitions of BC7; bits 2i and 2i+1 are the subset of texel i.
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+fn unpack_565(color: u16) -> [u8; 3]
+{
+    let r = ((color >> 11) & 0x1f) as u32;
+    let g = ((color >> 5) & 0x3f) as u32;
+    let b = (color & 0x1f) as u32;
+    [((r * 255 + 15) / 31) as u8, ((g * 255 + 31) / 63) as u8, ((b * 255 + 15) / 31) as u8]
+}
+
+/// The 16 RGBA texels of a BC1 color block; `opaque` forces the four-color mode, as in BC2/BC3.
+fn decode_bc1_colors(block: &[u8], opaque: bool) -> [[u8; 4]; 16]
+{
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (unpack_565(c0), unpack_565(c1));
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+    let mut palette = [[e0[0], e0[1], e0[2], 255], [e1[0], e1[1], e1[2], 255], [0; 4], [0; 4]];
+    if c0 > c1 || opaque
+    {
+        for channel in 0..3
+        {
+            palette[2][channel] = mix(e0[channel], e1[channel], 2, 1);
+            palette[3][channel] = mix(e0[channel], e1[channel], 1, 2);
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    }
+    else
+    {
+        for channel in 0..3
+        {
+            palette[2][channel] = mix(e0[channel], e1[channel], 1, 1);
+        }
+        palette[2][3] = 255;
+        // The fourth entry is transparent black.
+    }
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0u8; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate()
+    {
+        *value = palette[((indices >> (2 * texel)) & 3) as usize];
+    }
+    texels
+}
+
+/// The 16 values of a BC4 block, which is also the alpha block of BC3 and each half of BC5.
+fn decode_bc4_values(block: &[u8]) -> [u8; 16]
+{
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 > a1
+    {
+        for index in 1..7
+        {
+            palette[index + 1] = ((7 - index as u32) * a0 + index as u32 * a1) / 7;
+        }
+    }
+    else
+    {
+        for index in 1..5
+        {
+            palette[index + 1] = ((5 - index as u32) * a0 + index as u32 * a1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+    let mut bits = 0u64;
+    for (shift, byte) in block[2..8].iter().enumerate()
+    {
+        bits |= (*byte as u64) << (8 * shift);
+    }
+    let mut values = [0u8; 16];
+    for (texel, value) in values.iter_mut().enumerate()
+    {
+        *value = palette[((bits >> (3 * texel)) & 7) as usize] as u8;
+    }
+    values
+}
+
+/// The texels of one block in the format `get_decoded_format` gives, row by row.
+fn decode_block(format: vk::Format, block: &[u8]) -> Vec<u8>
+{
+    let texels = match format
+    {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK =>
+        {
+            let mut texels = decode_bc1_colors(block, false);
+            // Without alpha, the transparent entry is plain black.
+            texels.iter_mut().for_each(|texel| texel[3] = 255);
+            texels
+        }
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => decode_bc1_colors(block, false),
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK =>
+        {
+            let mut texels = decode_bc1_colors(&block[8..16], true);
+            for (texel, value) in texels.iter_mut().enumerate()
+            {
+                let nibble = (block[texel / 2] >> (4 * (texel % 2))) & 0xf;
+                value[3] = nibble * 17;
+            }
+            texels
+        }
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK =>
+        {
+            let mut texels = decode_bc1_colors(&block[8..16], true);
+            let alpha = decode_bc4_values(&block[0..8]);
+            for (value, alpha) in texels.iter_mut().zip(alpha)
+            {
+                value[3] = alpha;
+            }
+            texels
+        }
+        vk::Format::BC4_UNORM_BLOCK =>
+        {
+            decode_bc4_values(block).map(|red| [red, 0, 0, 255])
+        }
+        vk::Format::BC5_UNORM_BLOCK =>
+        {
+            let red = decode_bc4_values(&block[0..8]);
+            let green = decode_bc4_values(&block[8..16]);
+            let mut texels = [[0u8; 4]; 16];
+            for texel in 0..16
+            {
+                texels[texel] = [red[texel], green[texel], 0, 255];
+            }
+            texels
+        }
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK =>
+        {
+            let texels = bc6h::decode_block(block, format == vk::Format::BC6H_SFLOAT_BLOCK);
+            return texels.iter().flatten().flat_map(|channel| channel.to_le_bytes()).collect();
+        }
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => bc7::decode_block(block),
+        _ =>
+        {
+            let size = get_format_block(format).unwrap();
+            return astc::decode_block(block, size.width, size.height, is_srgb(format)).as_flattened().to_vec();
+        }
+    };
+    texels.as_flattened().to_vec()
+}
+
+/// Decodes one image to tightly packed texels in the format `get_decoded_format` gives.
+pub fn decode_compressed(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Option<Vec<u8>>
+{
+    let texel_bytes = get_format_block(get_decoded_format(format)?)?.bytes as usize;
+    let block_info = get_format_block(format)?;
+    if data.len() < block_info.get_image_size(width, height)
+    {
+        return None;
+    }
+    let blocks_wide = width.div_ceil(block_info.width);
+    let mut pixels = vec![0u8; (width * height) as usize * texel_bytes];
+    for (index, block) in data.chunks_exact(block_info.bytes as usize).enumerate()
+    {
+        let (block_x, block_y) = (index as u32 % blocks_wide, index as u32 / blocks_wide);
+        if block_y * block_info.height >= height
+        {
+            break;
+        }
+        let texels = decode_block(format, block);
+        for (texel, value) in texels.chunks_exact(texel_bytes).enumerate()
+        {
+            let x = block_x * block_info.width + texel as u32 % block_info.width;
+            let y = block_y * block_info.height + texel as u32 / block_info.width;
+            // Blocks on the right and bottom edges may stick out of the image.
+            if x < width && y < height
+            {
+                let offset = (y * width + x) as usize * texel_bytes;
+                pixels[offset..offset + texel_bytes].copy_from_slice(value);
+            }
+        }
+    }
+    Some(pixels)
+}
+
+/// Halves an RGBA8 image with a box filter; sRGB data is averaged in linear space.
+pub fn downsample_rgba8(width: u32, height: u32, pixels: &[u8], srgb: bool) -> (u32, u32, Vec<u8>)
+{
+    let (new_width, new_height) = ((width / 2).max(1), (height / 2).max(1));
+    let to_linear = |value: u8| {
+        let value = value as f32 / 255.0;
+        if !srgb { value }
+        else if value <= 0.04045 { value / 12.92 }
+        else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+    let from_linear = |value: f32| {
+        let value = if !srgb { value }
+            else if value <= 0.0031308 { value * 12.92 }
+            else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    let mut result = vec![0u8; (new_width * new_height * 4) as usize];
+    for y in 0..new_height
+    {
+        for x in 0..new_width
+        {
+            let mut sum = [0.0f32; 4];
+            let mut count = 0.0;
+            for source_y in (y * 2)..(y * 2 + 2).min(height)
+            {
+                for source_x in (x * 2)..(x * 2 + 2).min(width)
+                {
+                    let offset = ((source_y * width + source_x) * 4) as usize;
+                    for channel in 0..3
+                    {
+                        sum[channel] += to_linear(pixels[offset + channel]);
+                    }
+                    sum[3] += pixels[offset + 3] as f32 / 255.0;
+                    count += 1.0;
+                }
+            }
+            let offset = ((y * new_width + x) * 4) as usize;
+            for channel in 0..3
+            {
+                result[offset + channel] = from_linear(sum[channel] / count);
+            }
+            result[offset + 3] = (sum[3] / count * 255.0).round() as u8;
+        }
+    }
+    (new_width, new_height, result)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn bc1_decodes_endpoints_and_interpolation()
+    {
+        // Endpoints white and black, texels cycling through the four palette entries.
+        let block = [0xff, 0xff, 0x00, 0x00, 0b11100100, 0b11100100, 0b11100100, 0b11100100];
+        let pixels = decode_compressed(vk::Format::BC1_RGB_UNORM_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(&pixels[0..16], &[255, 255, 255, 255, 0, 0, 0, 255, 170, 170, 170, 255, 85, 85, 85, 255]);
+    }
+
+    #[test]
+    fn bc1_crops_partial_blocks()
+    {
+        let block = [0xff, 0xff, 0x00, 0x00, 0, 0, 0, 0];
+        let pixels = decode_compressed(vk::Format::BC1_RGBA_UNORM_BLOCK, 2, 3, &block).unwrap();
+        assert_eq!(pixels.len(), 2 * 3 * 4);
+        assert!(pixels.iter().all(|value| *value == 255));
+    }
+
+    #[test]
+    fn unsupported_formats_are_not_decoded()
+    {
+        assert!(decode_compressed(vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK, 4, 4, &[0; 16]).is_none());
+        assert!(decode_compressed(vk::Format::BC4_SNORM_BLOCK, 4, 4, &[0; 8]).is_none());
+    }
+
+    #[test]
+    fn bc6h_bc7_and_astc_decode_through_the_fallback()
+    {
+        let cases = [
+            (vk::Format::BC7_SRGB_BLOCK, vk::Format::R8G8B8A8_SRGB),
+            (vk::Format::BC6H_UFLOAT_BLOCK, vk::Format::R16G16B16A16_SFLOAT),
+            (vk::Format::ASTC_4X4_UNORM_BLOCK, vk::Format::R8G8B8A8_UNORM),
+            (vk::Format::ASTC_6X6_SRGB_BLOCK, vk::Format::R8G8B8A8_SRGB),
+            (vk::Format::ASTC_8X8_UNORM_BLOCK, vk::Format::R8G8B8A8_UNORM),
+        ];
+        for (format, decoded) in cases
+        {
+            assert_eq!(get_decoded_format(format), Some(decoded));
+            let texel_bytes = get_format_block(decoded).unwrap().bytes as usize;
+            // 10x10 texels need partial blocks at the edges of every block size.
+            let data = vec![0u8; get_format_block(format).unwrap().get_image_size(10, 10)];
+            let pixels = decode_compressed(format, 10, 10, &data).unwrap();
+            assert_eq!(pixels.len(), 10 * 10 * texel_bytes, "{:?}", format);
+        }
+        // An all-zero ASTC block is reserved and decodes to magenta.
+        let pixels = decode_compressed(vk::Format::ASTC_4X4_UNORM_BLOCK, 4, 4, &[0; 16]).unwrap();
+        assert_eq!(&pixels[0..4], &[255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn downsample_averages_in_linear_space()
+    {
+        let pixels = [0, 0, 0, 255, 255, 255, 255, 255];
+        let (width, height, linear) = downsample_rgba8(2, 1, &pixels, false);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(linear, vec![128, 128, 128, 255]);
+        let (_, _, srgb) = downsample_rgba8(2, 1, &pixels, true);
+        assert_eq!(srgb, vec![188, 188, 188, 255]);
+    }
+}
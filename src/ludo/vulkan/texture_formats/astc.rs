@@ -0,0 +1,593 @@
+use super::BlockBits;
+
+/// What the LDR profile decodes malformed blocks and HDR content to.
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Quantization levels of color endpoints, from the finest.
+const COLOR_LEVELS: [u32; 17] = [256, 192, 160, 128, 96, 80, 64, 48, 40, 32, 24, 20, 16, 12, 10, 8, 6];
+
+struct BlockMode
+{
+    grid_width: u32,
+    grid_height: u32,
+    dual_plane: bool,
+    weight_levels: u32,
+}
+
+/// Interprets the low 11 bits of a block; `None` for the reserved layouts.
+fn decode_block_mode(mode: u32) -> Option<BlockMode>
+{
+    let bit = |index: u32| (mode >> index) & 1;
+    let a = (mode >> 5) & 3;
+    let b = (mode >> 7) & 3;
+    let mut high_precision = bit(9) == 1;
+    let mut dual_plane = bit(10) == 1;
+    let (grid_width, grid_height, range);
+    if mode & 3 != 0
+    {
+        range = (bit(1) << 2) | (bit(0) << 1) | bit(4);
+        (grid_width, grid_height) = match (mode >> 2) & 3
+        {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+            _ => (bit(7) + 2, a + 2),
+        };
+    }
+    else
+    {
+        range = (bit(3) << 2) | (bit(2) << 1) | bit(4);
+        if mode & 0xf == 0
+        {
+            return None;
+        }
+        (grid_width, grid_height) = match b
+        {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            3 if a == 0 => (6, 10),
+            3 if a == 1 => (10, 6),
+            2 =>
+            {
+                high_precision = false;
+                dual_plane = false;
+                (a + 6, ((mode >> 9) & 3) + 6)
+            }
+            _ => return None,
+        };
+    }
+    const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+    let weight_levels = WEIGHT_LEVELS[(range - 2 + 6 * high_precision as u32) as usize];
+    Some(BlockMode { grid_width, grid_height, dual_plane, weight_levels })
+}
+
+/// Splits a level count into its trit or quint factor (1 when there is none) and its bit count.
+fn get_encoding(levels: u32) -> (u32, u32)
+{
+    let base = if levels.is_multiple_of(3) { 3 } else if levels.is_multiple_of(5) { 5 } else { 1 };
+    (base, (levels / base).trailing_zeros())
+}
+
+/// Bits an integer sequence of `count` values with `levels` levels takes.
+fn get_sequence_bits(levels: u32, count: usize) -> u32
+{
+    let count = count as u32;
+    let (base, bits) = get_encoding(levels);
+    bits * count + match base
+    {
+        3 => (8 * count).div_ceil(5),
+        5 => (7 * count).div_ceil(3),
+        _ => 0,
+    }
+}
+
+/// The five trits packed into 8 bits.
+fn decode_trits(packed: u32) -> [u32; 5]
+{
+    let bits = |low: u32, count: u32| (packed >> low) & ((1 << count) - 1);
+    let (c, t4, t3);
+    if bits(2, 3) == 7
+    {
+        c = (bits(5, 3) << 2) | bits(0, 2);
+        (t4, t3) = (2, 2);
+    }
+    else
+    {
+        c = bits(0, 5);
+        (t4, t3) = if bits(5, 2) == 3 { (2, bits(7, 1)) } else { (bits(7, 1), bits(5, 2)) };
+    }
+    let c_bit = |index: u32| (c >> index) & 1;
+    let (t2, t1, t0);
+    if c & 3 == 3
+    {
+        (t2, t1) = (2, c_bit(4));
+        t0 = (c_bit(3) << 1) | (c_bit(2) & !c_bit(3) & 1);
+    }
+    else if (c >> 2) & 3 == 3
+    {
+        (t2, t1, t0) = (2, 2, c & 3);
+    }
+    else
+    {
+        (t2, t1) = (c_bit(4), (c >> 2) & 3);
+        t0 = (c_bit(1) << 1) | (c_bit(0) & !c_bit(1) & 1);
+    }
+    [t0, t1, t2, t3, t4]
+}
+
+/// The three quints packed into 7 bits.
+fn decode_quints(packed: u32) -> [u32; 3]
+{
+    let bits = |low: u32, count: u32| (packed >> low) & ((1 << count) - 1);
+    if bits(1, 2) == 3 && bits(5, 2) == 0
+    {
+        let not_q0 = !packed & 1;
+        let q2 = (bits(0, 1) << 2) | ((bits(4, 1) & not_q0) << 1) | (bits(3, 1) & not_q0);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if bits(1, 2) == 3
+    {
+        (4, (bits(3, 2) << 3) | ((!bits(5, 2) & 3) << 1) | bits(0, 1))
+    }
+    else
+    {
+        (bits(5, 2), bits(0, 5))
+    };
+    let (q1, q0) = if c & 7 == 5 { (4, c >> 3) } else { (c >> 3, c & 7) };
+    [q0, q1, q2]
+}
+
+/// Reads an integer sequence as `(low bits, trit or quint)` pairs; bits past its end read as zeros.
+fn read_sequence(data: u128, start: u32, levels: u32, count: usize) -> Vec<(u32, u32)>
+{
+    let end = start + get_sequence_bits(levels, count);
+    let data = if end >= 128 { data } else { data & ((1u128 << end) - 1) };
+    let mut bits = BlockBits { bits: data, position: start };
+    let (base, bit_count) = get_encoding(levels);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count
+    {
+        match base
+        {
+            3 =>
+            {
+                let mut low = [0; 5];
+                let mut packed = 0;
+                for (index, (shift, width)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].into_iter().enumerate()
+                {
+                    low[index] = bits.read(bit_count);
+                    packed |= bits.read(width) << shift;
+                }
+                values.extend(low.into_iter().zip(decode_trits(packed)));
+            }
+            5 =>
+            {
+                let mut low = [0; 3];
+                let mut packed = 0;
+                for (index, (shift, width)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate()
+                {
+                    low[index] = bits.read(bit_count);
+                    packed |= bits.read(width) << shift;
+                }
+                values.extend(low.into_iter().zip(decode_quints(packed)));
+            }
+            _ => values.push((bits.read(bit_count), 0)),
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+/// Repeats the `from` bits of `value` until they fill `to` bits.
+fn replicate(value: u32, from: u32, to: u32) -> u32
+{
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to
+    {
+        result = (result << from) | value;
+        filled += from;
+    }
+    result >> (filled - to)
+}
+
+/// Maps a quantized color endpoint value to 0-255.
+fn unquantize_color((low, digit): (u32, u32), levels: u32) -> i32
+{
+    let (base, bit_count) = get_encoding(levels);
+    if base == 1
+    {
+        return replicate(low, bit_count, 8) as i32;
+    }
+    let bit = |index: u32| (low >> index) & 1;
+    let (b, c, d, e, f) = (bit(1), bit(2), bit(3), bit(4), bit(5));
+    let (scale, pattern) = match (base, bit_count)
+    {
+        (3, 1) => (204, 0),
+        (3, 2) => (93, b * 0x116),
+        (3, 3) => (44, c * 0x10a + b * 0x85),
+        (3, 4) => (22, d * 0x104 + c * 0x82 + b * 0x41),
+        (3, 5) => (11, e * 0x102 + d * 0x81 + c * 0x40 + b * 0x20),
+        (3, _) => (5, f * 0x101 + e * 0x80 + d * 0x40 + c * 0x20 + b * 0x10),
+        (_, 1) => (113, 0),
+        (_, 2) => (54, b * 0x10c),
+        (_, 3) => (26, c * 0x105 + b * 0x82),
+        (_, 4) => (13, d * 0x102 + c * 0x81 + b * 0x40),
+        (_, _) => (6, e * 0x101 + d * 0x80 + c * 0x40 + b * 0x20),
+    };
+    let mask = if low & 1 == 1 { 0x1ff } else { 0 };
+    let value = (digit * scale + pattern) ^ mask;
+    ((mask & 0x80) | (value >> 2)) as i32
+}
+
+/// Maps a quantized weight to 0-64.
+fn unquantize_weight((low, digit): (u32, u32), levels: u32) -> u32
+{
+    let (base, bit_count) = get_encoding(levels);
+    let value = match (base, bit_count)
+    {
+        (1, _) => replicate(low, bit_count, 6),
+        (3, 0) => return [0, 32, 64][digit as usize],
+        (5, 0) => return [0, 16, 32, 48, 64][digit as usize],
+        _ =>
+        {
+            let bit = |index: u32| (low >> index) & 1;
+            let (b, c) = (bit(1), bit(2));
+            let (scale, pattern) = match (base, bit_count)
+            {
+                (3, 1) => (50, 0),
+                (3, 2) => (23, b * 0x45),
+                (3, _) => (11, c * 0x42 + b * 0x21),
+                (_, 1) => (28, 0),
+                (_, _) => (13, b * 0x43),
+            };
+            let mask = if low & 1 == 1 { 0x7f } else { 0 };
+            let value = (digit * scale + pattern) ^ mask;
+            (mask & 0x20) | (value >> 2)
+        }
+    };
+    if value > 32 { value + 1 } else { value }
+}
+
+fn bit_transfer_signed(a: &mut i32, b: &mut i32)
+{
+    *b >>= 1;
+    *b |= *a & 0x80;
+    *a >>= 1;
+    *a &= 0x3f;
+    if *a & 0x20 != 0
+    {
+        *a -= 0x40;
+    }
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4]
+{
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// The RGBA endpoint pair of an LDR color endpoint mode; `None` for the HDR ones.
+fn decode_endpoints(mode: u32, values: &[i32]) -> Option<[[i32; 4]; 2]>
+{
+    let mut v = [0i32; 8];
+    v[..values.len()].copy_from_slice(values);
+    let endpoints = match mode
+    {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 =>
+        {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 =>
+        {
+            let [v0, v1, v2, v3, ..] = &mut v;
+            bit_transfer_signed(v1, v0);
+            bit_transfer_signed(v3, v2);
+            let l1 = *v0 + *v1;
+            [[*v0, *v0, *v0, *v2], [l1, l1, l1, *v2 + *v3]]
+        }
+        6 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, 255], [v[0], v[1], v[2], 255]],
+        8 | 12 =>
+        {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            let (e0, e1) = ([v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]);
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] { [e0, e1] } else { [blue_contract(e1), blue_contract(e0)] }
+        }
+        9 | 13 =>
+        {
+            for pair in 0..4
+            {
+                let [base, offset] = &mut v[2 * pair..2 * pair + 2] else { unreachable!() };
+                bit_transfer_signed(offset, base);
+            }
+            let alpha = if mode == 13 { [v[6], v[6] + v[7]] } else { [255, 255] };
+            let e0 = [v[0], v[2], v[4], alpha[0]];
+            let e1 = [v[0] + v[1], v[2] + v[3], v[4] + v[5], alpha[1]];
+            if v[1] + v[3] + v[5] >= 0 { [e0, e1] } else { [blue_contract(e1), blue_contract(e0)] }
+        }
+        10 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, v[4]], [v[0], v[1], v[2], v[5]]],
+        _ => return None,
+    };
+    Some(endpoints.map(|endpoint| endpoint.map(|channel| channel.clamp(0, 255))))
+}
+
+fn hash52(mut p: u32) -> u32
+{
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// The partition of texel (x, y), as the specification's `select_partition` computes it.
+fn select_partition(seed: u32, mut x: u32, mut y: u32, partitions: u32, small_block: bool) -> usize
+{
+    if small_block
+    {
+        x <<= 1;
+        y <<= 1;
+    }
+    let seed = seed + (partitions - 1) * 1024;
+    let rnum = hash52(seed);
+    let mut seeds = [0u32; 12];
+    for (index, value) in seeds.iter_mut().take(8).enumerate()
+    {
+        *value = (rnum >> (4 * index)) & 0xf;
+    }
+    seeds[8] = (rnum >> 18) & 0xf;
+    seeds[9] = (rnum >> 22) & 0xf;
+    seeds[10] = (rnum >> 26) & 0xf;
+    seeds[11] = rnum.rotate_left(2) & 0xf;
+    let (sh1, sh2) = if seed & 1 == 1
+    {
+        (if seed & 2 != 0 { 4 } else { 5 }, if partitions == 3 { 6 } else { 5 })
+    }
+    else
+    {
+        (if partitions == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 })
+    };
+    let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+    for (index, value) in seeds.iter_mut().enumerate()
+    {
+        let shift = if index >= 8 { sh3 } else if index % 2 == 0 { sh1 } else { sh2 };
+        *value = (*value * *value) >> shift;
+    }
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3f;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3f;
+    let c = if partitions < 3 { 0 } else { (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3f };
+    let d = if partitions < 4 { 0 } else { (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3f };
+    if a >= b && a >= c && a >= d { 0 } else if b >= c && b >= d { 1 } else if c >= d { 2 } else { 3 }
+}
+
+/// Bilinearly samples the weight grid at texel (x, y) of the block.
+fn infill(weights: &[u32], mode: &BlockMode, plane: usize, block: (u32, u32), x: u32, y: u32) -> i32
+{
+    let planes = 1 + mode.dual_plane as usize;
+    let ds = (1024 + block.0 / 2) / (block.0 - 1);
+    let dt = (1024 + block.1 / 2) / (block.1 - 1);
+    let gs = (ds * x * (mode.grid_width - 1) + 32) >> 6;
+    let gt = (dt * y * (mode.grid_height - 1) + 32) >> 6;
+    let (fs, ft) = ((gs & 0xf) as i32, (gt & 0xf) as i32);
+    let w11 = (fs * ft + 8) >> 4;
+    let (w10, w01, w00) = (ft - w11, fs - w11, 16 - fs - ft + w11);
+    let base = (gs >> 4) + (gt >> 4) * mode.grid_width;
+    // Weights past the grid only ever get a factor of zero.
+    let weight = |index: u32| weights.get(index as usize * planes + plane).copied().unwrap_or(0) as i32;
+    (weight(base) * w00 + weight(base + 1) * w01 + weight(base + mode.grid_width) * w10
+        + weight(base + mode.grid_width + 1) * w11 + 8) >> 4
+}
+
+fn to_unorm8(value: i32, srgb: bool) -> u8
+{
+    if srgb { (value >> 8) as u8 } else { ((value * 255 + 32767) / 65535) as u8 }
+}
+
+fn decode_void_extent(data: u128, texels: usize, srgb: bool) -> Option<Vec<[u8; 4]>>
+{
+    if (data >> 9) & 1 == 1
+    {
+        return None;
+    }
+    let color: [u8; 4] = std::array::from_fn(|channel| to_unorm8((data >> (64 + 16 * channel)) as u16 as i32, srgb));
+    Some(vec![color; texels])
+}
+
+fn decode(block: &[u8], width: u32, height: u32, srgb: bool) -> Option<Vec<[u8; 4]>>
+{
+    let data = BlockBits::new(block).bits;
+    let get = |low: u32, count: u32| ((data >> low) as u32) & ((1 << count) - 1);
+    let texels = (width * height) as usize;
+    if get(0, 9) == 0x1fc
+    {
+        return decode_void_extent(data, texels, srgb);
+    }
+    let mode = decode_block_mode(get(0, 11))?;
+    let planes = 1 + mode.dual_plane as usize;
+    let weight_count = (mode.grid_width * mode.grid_height) as usize * planes;
+    let weight_bits = get_sequence_bits(mode.weight_levels, weight_count);
+    if mode.grid_width > width || mode.grid_height > height || weight_count > 64 || !(24..=96).contains(&weight_bits)
+    {
+        return None;
+    }
+    let partitions = get(11, 2) + 1;
+    if partitions == 4 && mode.dual_plane
+    {
+        return None;
+    }
+
+    // Everything below the weights that the fields at the bottom of the block leave over is color data.
+    let mut end = 128 - weight_bits;
+    let (color_start, endpoint_modes) = if partitions == 1
+    {
+        (17, vec![get(13, 4)])
+    }
+    else
+    {
+        let field = get(23, 6);
+        if field & 3 == 0
+        {
+            (29, vec![field >> 2; partitions as usize])
+        }
+        else
+        {
+            let extra_bits = 3 * partitions - 4;
+            end -= extra_bits;
+            let combined = (field >> 2) | (get(end, extra_bits) << 4);
+            let class = (field & 3) - 1;
+            let modes = (0..partitions)
+                .map(|partition| {
+                    let class = class + ((combined >> partition) & 1);
+                    (class << 2) | ((combined >> (partitions + 2 * partition)) & 3)
+                })
+                .collect();
+            (29, modes)
+        }
+    };
+    let plane_channel = if mode.dual_plane
+    {
+        end -= 2;
+        Some(get(end, 2) as usize)
+    }
+    else
+    {
+        None
+    };
+
+    let value_count: usize = endpoint_modes.iter().map(|mode| 2 * ((mode >> 2) as usize + 1)).sum();
+    if value_count > 18
+    {
+        return None;
+    }
+    let available = end.checked_sub(color_start)?;
+    let color_levels = *COLOR_LEVELS.iter().find(|levels| get_sequence_bits(**levels, value_count) <= available)?;
+    let values: Vec<i32> = read_sequence(data, color_start, color_levels, value_count)
+        .into_iter()
+        .map(|value| unquantize_color(value, color_levels))
+        .collect();
+    let mut endpoints = Vec::with_capacity(endpoint_modes.len());
+    let mut remaining = values.as_slice();
+    for endpoint_mode in endpoint_modes
+    {
+        let (current, rest) = remaining.split_at(2 * ((endpoint_mode >> 2) as usize + 1));
+        endpoints.push(decode_endpoints(endpoint_mode, current)?);
+        remaining = rest;
+    }
+    // Weights are stored from the top of the block down.
+    let weights: Vec<u32> = read_sequence(data.reverse_bits(), 0, mode.weight_levels, weight_count)
+        .into_iter()
+        .map(|weight| unquantize_weight(weight, mode.weight_levels))
+        .collect();
+
+    let seed = get(13, 10);
+    let mut result = Vec::with_capacity(texels);
+    for texel in 0..texels as u32
+    {
+        let (x, y) = (texel % width, texel / width);
+        let partition = if partitions == 1 { 0 } else { select_partition(seed, x, y, partitions, texels < 31) };
+        let [e0, e1] = endpoints[partition];
+        let plane_weights = [
+            infill(&weights, &mode, 0, (width, height), x, y),
+            if mode.dual_plane { infill(&weights, &mode, 1, (width, height), x, y) } else { 0 },
+        ];
+        let color: [u8; 4] = std::array::from_fn(|channel| {
+            let weight = plane_weights[(plane_channel == Some(channel)) as usize];
+            // sRGB endpoints are expanded to the middle of their 8-bit step rather than replicated.
+            let srgb = srgb && channel < 3;
+            let expand = |value: i32| (value << 8) | if srgb { 0x80 } else { value };
+            let value = (expand(e0[channel]) * (64 - weight) + expand(e1[channel]) * weight + 32) / 64;
+            to_unorm8(value, srgb)
+        });
+        result.push(color);
+    }
+    Some(result)
+}
+
+/// The RGBA texels of a `width` x `height` ASTC LDR block, row by row; invalid blocks and
+/// HDR content decode to magenta.
+pub fn decode_block(block: &[u8], width: u32, height: u32, srgb: bool) -> Vec<[u8; 4]>
+{
+    decode(block, width, height, srgb).unwrap_or_else(|| vec![ERROR_COLOR; (width * height) as usize])
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::BlockWriter;
+
+    #[test]
+    fn void_extent_blocks_are_one_color()
+    {
+        let mut block = BlockWriter::default();
+        block.write(0x1fc, 9).write(6, 3).write(u64::MAX, 52);
+        block.write(0xffff, 16).write(0x8000, 16).write(0, 16).write(0xffff, 16);
+        assert_eq!(decode_block(&block.get_bytes(), 4, 4, false), vec![[255, 128, 0, 255]; 16]);
+    }
+
+    #[test]
+    fn direct_rgb_endpoints_are_interpolated_by_the_weights()
+    {
+        // A 4x4 grid of 2-bit weights, one partition with RGB direct endpoints black and white.
+        let mut block = BlockWriter::default();
+        block.write(0x042, 11).write(0, 2).write(8, 4);
+        for value in [0, 255, 0, 255, 0, 255]
+        {
+            block.write(value, 8);
+        }
+        let mut weights = BlockWriter::default();
+        for texel in 0..16
+        {
+            weights.write(texel % 4, 2);
+        }
+        let bits = BlockBits::new(&block.get_bytes()).bits | BlockBits::new(&weights.get_bytes()).bits.reverse_bits();
+        let texels = decode_block(&bits.to_le_bytes(), 4, 4, false);
+        for (texel, value) in texels.iter().enumerate()
+        {
+            let expected = [0, 84, 171, 255][texel % 4];
+            assert_eq!(*value, [expected, expected, expected, 255]);
+        }
+    }
+
+    #[test]
+    fn reserved_blocks_decode_to_the_error_color()
+    {
+        assert_eq!(decode_block(&[0; 16], 6, 6, true), vec![ERROR_COLOR; 36]);
+    }
+
+    #[test]
+    fn trits_and_quints_cover_every_combination()
+    {
+        let mut trits: Vec<[u32; 5]> = (0..256).map(decode_trits).collect();
+        trits.sort();
+        trits.dedup();
+        assert_eq!(trits.len(), 243);
+        assert!(trits.iter().flatten().all(|trit| *trit < 3));
+        let mut quints: Vec<[u32; 3]> = (0..128).map(decode_quints).collect();
+        quints.sort();
+        quints.dedup();
+        assert_eq!(quints.len(), 125);
+        assert!(quints.iter().flatten().all(|quint| *quint < 5));
+    }
+
+    #[test]
+    fn unquantized_values_are_spread_evenly()
+    {
+        let mut colors: Vec<i32> = (0..2).flat_map(|low| (0..3).map(move |trit| unquantize_color((low, trit), 6))).collect();
+        colors.sort();
+        assert_eq!(colors, [0, 51, 102, 153, 204, 255]);
+        let mut weights: Vec<u32> = (0..2).flat_map(|low| (0..5).map(move |quint| unquantize_weight((low, quint), 10))).collect();
+        weights.sort();
+        assert_eq!(weights, [0, 7, 14, 21, 28, 36, 43, 50, 57, 64]);
+    }
+}
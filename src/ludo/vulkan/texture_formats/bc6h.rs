@@ -0,0 +1,267 @@
+use super::{BlockBits, ANCHORS_2, PARTITIONS_2, WEIGHTS_3, WEIGHTS_4};
+
+// Endpoint fields, in the order the BC6H specification names them: W and X are the first
+// pair, Y and Z the second one; D is the partition.
+const RW: usize = 0;
+const RX: usize = 1;
+const RY: usize = 2;
+const RZ: usize = 3;
+const GW: usize = 4;
+const GX: usize = 5;
+const GY: usize = 6;
+const GZ: usize = 7;
+const BW: usize = 8;
+const BX: usize = 9;
+const BY: usize = 10;
+const BZ: usize = 11;
+const D: usize = 12;
+
+/// Layout of one of the fourteen BC6H modes.
+struct Mode
+{
+    /// 5-bit value of the mode bits; 2-bit for the first two modes.
+    bits: u32,
+    regions: usize,
+    /// Bits of the W endpoints.
+    precision: u32,
+    /// Bits of the other endpoints of each channel, stored relative to W when `transformed`.
+    delta_bits: [u32; 3],
+    transformed: bool,
+    /// Runs of `(field, lowest bit, count)`, in block order after the mode bits; the high bits
+    /// of the last two modes are stored reversed, one bit per run.
+    layout: &'static [(usize, u32, u32)],
+}
+
+const MODES: [Mode; 14] = [
+    Mode { bits: 0x00, regions: 2, precision: 10, delta_bits: [5, 5, 5], transformed: true, layout: &[
+        (GY, 4, 1), (BY, 4, 1), (BZ, 4, 1), (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 5), (GZ, 4, 1),
+        (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5),
+        (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x01, regions: 2, precision: 7, delta_bits: [6, 6, 6], transformed: true, layout: &[
+        (GY, 5, 1), (GZ, 4, 1), (GZ, 5, 1), (RW, 0, 7), (BZ, 0, 1), (BZ, 1, 1), (BY, 4, 1), (GW, 0, 7),
+        (BY, 5, 1), (BZ, 2, 1), (GY, 4, 1), (BW, 0, 7), (BZ, 3, 1), (BZ, 5, 1), (BZ, 4, 1), (RX, 0, 6),
+        (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 6), (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6), (D, 0, 5)] },
+    Mode { bits: 0x02, regions: 2, precision: 11, delta_bits: [5, 4, 4], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 5), (RW, 10, 1), (GY, 0, 4), (GX, 0, 4), (GW, 10, 1),
+        (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 4), (BW, 10, 1), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1),
+        (RZ, 0, 5), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x06, regions: 2, precision: 11, delta_bits: [4, 5, 4], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 4), (RW, 10, 1), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5),
+        (GW, 10, 1), (GZ, 0, 4), (BX, 0, 4), (BW, 10, 1), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 4), (BZ, 0, 1),
+        (BZ, 2, 1), (RZ, 0, 4), (GY, 4, 1), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x0a, regions: 2, precision: 11, delta_bits: [4, 4, 5], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 4), (RW, 10, 1), (BY, 4, 1), (GY, 0, 4), (GX, 0, 4),
+        (GW, 10, 1), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BW, 10, 1), (BY, 0, 4), (RY, 0, 4), (BZ, 1, 1),
+        (BZ, 2, 1), (RZ, 0, 4), (BZ, 4, 1), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x0e, regions: 2, precision: 9, delta_bits: [5, 5, 5], transformed: true, layout: &[
+        (RW, 0, 9), (BY, 4, 1), (GW, 0, 9), (GY, 4, 1), (BW, 0, 9), (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1),
+        (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5),
+        (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x12, regions: 2, precision: 8, delta_bits: [6, 5, 5], transformed: true, layout: &[
+        (RW, 0, 8), (GZ, 4, 1), (BY, 4, 1), (GW, 0, 8), (BZ, 2, 1), (GY, 4, 1), (BW, 0, 8), (BZ, 3, 1),
+        (BZ, 4, 1), (RX, 0, 6), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1),
+        (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6), (D, 0, 5)] },
+    Mode { bits: 0x16, regions: 2, precision: 8, delta_bits: [5, 6, 5], transformed: true, layout: &[
+        (RW, 0, 8), (BZ, 0, 1), (BY, 4, 1), (GW, 0, 8), (GY, 5, 1), (GY, 4, 1), (BW, 0, 8), (GZ, 5, 1),
+        (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1),
+        (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x1a, regions: 2, precision: 8, delta_bits: [5, 5, 6], transformed: true, layout: &[
+        (RW, 0, 8), (BZ, 1, 1), (BY, 4, 1), (GW, 0, 8), (BY, 5, 1), (GY, 4, 1), (BW, 0, 8), (BZ, 5, 1),
+        (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 6),
+        (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1), (D, 0, 5)] },
+    Mode { bits: 0x1e, regions: 2, precision: 6, delta_bits: [6, 6, 6], transformed: false, layout: &[
+        (RW, 0, 6), (GZ, 4, 1), (BZ, 0, 1), (BZ, 1, 1), (BY, 4, 1), (GW, 0, 6), (GY, 5, 1), (BY, 5, 1),
+        (BZ, 2, 1), (GY, 4, 1), (BW, 0, 6), (GZ, 5, 1), (BZ, 3, 1), (BZ, 5, 1), (BZ, 4, 1), (RX, 0, 6),
+        (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 6), (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6), (D, 0, 5)] },
+    Mode { bits: 0x03, regions: 1, precision: 10, delta_bits: [10, 10, 10], transformed: false, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 10), (GX, 0, 10), (BX, 0, 10)] },
+    Mode { bits: 0x07, regions: 1, precision: 11, delta_bits: [9, 9, 9], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 9), (RW, 10, 1), (GX, 0, 9), (GW, 10, 1), (BX, 0, 9),
+        (BW, 10, 1)] },
+    Mode { bits: 0x0b, regions: 1, precision: 12, delta_bits: [8, 8, 8], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 8), (RW, 11, 1), (RW, 10, 1), (GX, 0, 8),
+        (GW, 11, 1), (GW, 10, 1), (BX, 0, 8), (BW, 11, 1), (BW, 10, 1)] },
+    Mode { bits: 0x0f, regions: 1, precision: 16, delta_bits: [4, 4, 4], transformed: true, layout: &[
+        (RW, 0, 10), (GW, 0, 10), (BW, 0, 10),
+        (RX, 0, 4), (RW, 15, 1), (RW, 14, 1), (RW, 13, 1), (RW, 12, 1), (RW, 11, 1), (RW, 10, 1),
+        (GX, 0, 4), (GW, 15, 1), (GW, 14, 1), (GW, 13, 1), (GW, 12, 1), (GW, 11, 1), (GW, 10, 1),
+        (BX, 0, 4), (BW, 15, 1), (BW, 14, 1), (BW, 13, 1), (BW, 12, 1), (BW, 11, 1), (BW, 10, 1)] },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32
+{
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Expands an endpoint to the 16-bit range interpolation works in.
+fn unquantize(value: i32, precision: u32, signed: bool) -> i32
+{
+    if !signed
+    {
+        if precision >= 15 || value == 0
+        {
+            value
+        }
+        else if value == (1 << precision) - 1
+        {
+            0xffff
+        }
+        else
+        {
+            ((value << 16) + 0x8000) >> precision
+        }
+    }
+    else
+    {
+        if precision >= 16
+        {
+            return value;
+        }
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0
+        {
+            0
+        }
+        else if magnitude >= (1 << (precision - 1)) - 1
+        {
+            0x7fff
+        }
+        else
+        {
+            ((magnitude << 15) + 0x4000) >> (precision - 1)
+        };
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+/// Scales an interpolated value to the bits of a half float.
+fn finish_unquantize(value: i32, signed: bool) -> u16
+{
+    if !signed
+    {
+        ((value * 31) >> 6) as u16
+    }
+    else if value < 0
+    {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    }
+    else
+    {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+/// The 16 texels of a BC6H block as RGBA16F bits with an alpha of 1; reserved modes decode to black.
+pub fn decode_block(block: &[u8], signed: bool) -> [[u16; 4]; 16]
+{
+    const ONE: u16 = 0x3c00;
+    let mut bits = BlockBits::new(block);
+    let mut mode_bits = bits.read(2);
+    if mode_bits > 1
+    {
+        mode_bits |= bits.read(3) << 2;
+    }
+    let Some(mode) = MODES.iter().find(|mode| mode.bits == mode_bits) else { return [[0, 0, 0, ONE]; 16] };
+
+    let mut fields = [0i32; 13];
+    for &(field, low, count) in mode.layout
+    {
+        fields[field] |= (bits.read(count) as i32) << low;
+    }
+    let partition = fields[D] as usize;
+    // W, X, Y and Z of each channel.
+    let mut endpoints = [[0i32; 4]; 3];
+    for channel in 0..3
+    {
+        let channel_fields = &fields[channel * 4..channel * 4 + 4];
+        let mask = (1 << mode.precision) - 1;
+        endpoints[channel][0] = channel_fields[0];
+        for endpoint in 1..mode.regions * 2
+        {
+            let value = channel_fields[endpoint];
+            endpoints[channel][endpoint] = if mode.transformed
+            {
+                (channel_fields[0] + sign_extend(value, mode.delta_bits[channel])) & mask
+            }
+            else
+            {
+                value
+            };
+        }
+        if signed
+        {
+            for endpoint in endpoints[channel].iter_mut()
+            {
+                *endpoint = sign_extend(*endpoint, mode.precision);
+            }
+        }
+        for endpoint in endpoints[channel].iter_mut()
+        {
+            *endpoint = unquantize(*endpoint, mode.precision, signed);
+        }
+    }
+
+    let index_bits = if mode.regions == 1 { 4 } else { 3 };
+    let mut texels = [[0u16; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate()
+    {
+        let region = if mode.regions == 1 { 0 } else { ((PARTITIONS_2[partition] >> texel) & 1) as usize };
+        let anchor = texel == 0 || (mode.regions == 2 && texel == ANCHORS_2[partition] as usize);
+        let index = bits.read(index_bits - anchor as u32) as usize;
+        let weight = if mode.regions == 1 { WEIGHTS_4[index] } else { WEIGHTS_3[index] } as i32;
+        for channel in 0..3
+        {
+            let (e0, e1) = (endpoints[channel][region * 2], endpoints[channel][region * 2 + 1]);
+            let interpolated = ((64 - weight) * e0 + weight * e1 + 32) >> 6;
+            value[channel] = finish_unquantize(interpolated, signed);
+        }
+        value[3] = ONE;
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::BlockWriter;
+
+    #[test]
+    fn mode_11_spans_the_unsigned_range()
+    {
+        // One region from 0 to the largest 10-bit endpoint, with texel i at index i.
+        let mut block = BlockWriter::default();
+        block.write(0b00011, 5).write(0, 30).write(0x3fffffff, 30).write(0, 3);
+        for index in 1..16
+        {
+            block.write(index, 4);
+        }
+        let texels = decode_block(&block.get_bytes(), false);
+        assert_eq!(texels[0], [0, 0, 0, 0x3c00]);
+        assert_eq!(texels[15], [0x7bff, 0x7bff, 0x7bff, 0x3c00]);
+        assert!(texels.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+    }
+
+    #[test]
+    fn mode_1_applies_the_deltas_per_region()
+    {
+        // Red W = 100 with deltas X = +5, Y = -1 and Z = +2; green and blue stay 0.
+        let mut block = BlockWriter::default();
+        block.write(0, 2).write(0, 3).write(100, 10).write(0, 20).write(5, 5).write(0, 1).write(0, 4);
+        block.write(0, 5).write(0, 1).write(0, 4).write(0, 5).write(0, 1).write(0, 4).write(0x1f, 5);
+        block.write(0, 1).write(2, 5).write(0, 1).write(0, 5);
+        let texels = decode_block(&block.get_bytes(), false);
+        // Partition 0 puts the two right columns in region 1.
+        assert_eq!(texels[0], [3115, 0, 0, 0x3c00]);
+        assert_eq!(texels[2], [3084, 0, 0, 0x3c00]);
+    }
+
+    #[test]
+    fn reserved_modes_decode_to_black()
+    {
+        let mut block = BlockWriter::default();
+        block.write(0b10011, 5);
+        assert_eq!(decode_block(&block.get_bytes(), true), [[0, 0, 0, 0x3c00]; 16]);
+    }
+}
@@ -0,0 +1,243 @@
+use super::{BlockBits, ANCHORS_2, PARTITIONS_2, PARTITIONS_3, WEIGHTS_2, WEIGHTS_3, WEIGHTS_4};
+
+/// Layout of one of the eight BC7 modes.
+struct Mode
+{
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// One p-bit per endpoint.
+    endpoint_p_bits: bool,
+    /// One p-bit per subset, shared by both of its endpoints.
+    shared_p_bits: bool,
+    index_bits: u32,
+    /// Bits of the second index set of modes 4 and 5, 0 for the others.
+    secondary_index_bits: u32,
+}
+
+const MODES: [Mode; 8] = [
+    Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0,
+        endpoint_p_bits: true, shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0,
+        endpoint_p_bits: false, shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+    Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0,
+        endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0,
+        endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6,
+        endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8,
+        endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7,
+        endpoint_p_bits: true, shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5,
+        endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+/// Texels holding the implicit high bit of the indices of the second and the third subset of a
+/// three-subset partition.
+const ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+fn get_subset(subsets: usize, partition: usize, texel: usize) -> usize
+{
+    match subsets
+    {
+        2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+        3 => ((PARTITIONS_3[partition] >> (2 * texel)) & 3) as usize,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, texel: usize) -> bool
+{
+    texel == 0
+        || (subsets == 2 && texel == ANCHORS_2[partition] as usize)
+        || (subsets == 3 && (texel == ANCHORS_3[0][partition] as usize || texel == ANCHORS_3[1][partition] as usize))
+}
+
+fn interpolate(e0: u8, e1: u8, index: u32, bits: u32) -> u8
+{
+    let weight = match bits
+    {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    } as u32;
+    (((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+/// The 16 RGBA texels of a BC7 block; reserved modes decode to transparent black.
+pub fn decode_block(block: &[u8]) -> [[u8; 4]; 16]
+{
+    let mut bits = BlockBits::new(block);
+    let Some(mode_index) = (0..8).find(|_| bits.read(1) == 1) else { return [[0; 4]; 16] };
+    let mode = &MODES[mode_index];
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Endpoint pairs of each subset, channel by channel.
+    let channel_bits = [mode.color_bits, mode.color_bits, mode.color_bits, mode.alpha_bits];
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    for (channel, channel_bits) in channel_bits.into_iter().enumerate()
+    {
+        for endpoint in 0..mode.subsets * 2
+        {
+            endpoints[endpoint / 2][endpoint % 2][channel] = bits.read(channel_bits);
+        }
+    }
+    let mut p_bits = [[0u32; 2]; 3];
+    if mode.endpoint_p_bits
+    {
+        for endpoint in 0..mode.subsets * 2
+        {
+            p_bits[endpoint / 2][endpoint % 2] = bits.read(1);
+        }
+    }
+    else if mode.shared_p_bits
+    {
+        for subset_p_bits in p_bits.iter_mut().take(mode.subsets)
+        {
+            *subset_p_bits = [bits.read(1); 2];
+        }
+    }
+    let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    let mut colors = [[[0u8; 4]; 2]; 3];
+    for subset in 0..mode.subsets
+    {
+        for endpoint in 0..2
+        {
+            for (channel, channel_bits) in channel_bits.into_iter().enumerate()
+            {
+                if channel_bits == 0
+                {
+                    colors[subset][endpoint][channel] = 255;
+                    continue;
+                }
+                let mut value = endpoints[subset][endpoint][channel];
+                let mut precision = channel_bits;
+                if has_p_bits
+                {
+                    value = (value << 1) | p_bits[subset][endpoint];
+                    precision += 1;
+                }
+                // Replicates the high bits into the low ones to reach 8 bits.
+                value <<= 8 - precision;
+                colors[subset][endpoint][channel] = (value | (value >> precision)) as u8;
+            }
+        }
+    }
+
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate()
+    {
+        let anchor = is_anchor(mode.subsets, partition, texel);
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0
+    {
+        for (texel, index) in secondary_indices.iter_mut().enumerate()
+        {
+            *index = bits.read(mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    let mut texels = [[0u8; 4]; 16];
+    for (texel, value) in texels.iter_mut().enumerate()
+    {
+        let [e0, e1] = colors[get_subset(mode.subsets, partition, texel)];
+        let (mut color_index, mut color_bits) = (indices[texel], mode.index_bits);
+        let (mut alpha_index, mut alpha_bits) = (indices[texel], mode.index_bits);
+        if mode.secondary_index_bits > 0
+        {
+            (alpha_index, alpha_bits) = (secondary_indices[texel], mode.secondary_index_bits);
+            if index_selection == 1
+            {
+                std::mem::swap(&mut color_index, &mut alpha_index);
+                std::mem::swap(&mut color_bits, &mut alpha_bits);
+            }
+        }
+        for channel in 0..3
+        {
+            value[channel] = interpolate(e0[channel], e1[channel], color_index, color_bits);
+        }
+        value[3] = interpolate(e0[3], e1[3], alpha_index, alpha_bits);
+        match rotation
+        {
+            1 => value.swap(0, 3),
+            2 => value.swap(1, 3),
+            3 => value.swap(2, 3),
+            _ => {}
+        }
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::BlockWriter;
+
+    #[test]
+    fn mode_6_interpolates_with_4_bit_indices()
+    {
+        // Endpoints 0 and 127 in every channel, with p-bits 0 and 1, and texel i at index i.
+        let mut block = BlockWriter::default();
+        block.write(1 << 6, 7);
+        for _ in 0..4
+        {
+            block.write(0, 7).write(127, 7);
+        }
+        block.write(0, 1).write(1, 1).write(0, 3);
+        for index in 1..16
+        {
+            block.write(index, 4);
+        }
+        let texels = decode_block(&block.get_bytes());
+        assert_eq!(texels[0], [0; 4]);
+        assert_eq!(texels[1], [16; 4]);
+        assert_eq!(texels[8], [135; 4]);
+        assert_eq!(texels[15], [255; 4]);
+    }
+
+    #[test]
+    fn mode_1_picks_endpoints_by_partition()
+    {
+        // Partition 13 puts the top two rows in subset 0 and the bottom two in subset 1.
+        let mut block = BlockWriter::default();
+        block.write(0b10, 2).write(13, 6);
+        for _ in 0..3
+        {
+            block.write(0, 6).write(0, 6).write(63, 6).write(63, 6);
+        }
+        block.write(0, 1).write(1, 1);
+        block.write(0, 46);
+        let texels = decode_block(&block.get_bytes());
+        assert!(texels[..8].iter().all(|texel| *texel == [0, 0, 0, 255]));
+        assert!(texels[8..].iter().all(|texel| *texel == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn reserved_mode_decodes_to_transparent_black()
+    {
+        assert_eq!(decode_block(&[0; 16]), [[0; 4]; 16]);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::ludo::vulkan::{
+    Allocator, Buffer, BufferError, CommandPool, Device, Fence, FormatBlock, MemoryUsage, Queue, TimelineSignal,
+    Transfer,
+};
+use ash::vk;
+use std::collections::VecDeque;
+
+/// Default size of the staging ring.
+pub const DEFAULT_STAGING_SIZE: vk::DeviceSize = 8 * 1024 * 1024;
+
+/// Identifies an `Uploader::submit`; tickets complete in the order they were handed out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(u64);
+
+struct Submission
+{
+    ticket: u64,
+    command_buffer: vk::CommandBuffer,
+    fence: Fence,
+    /// Staging bytes to give back once the fence is signalled.
+    staging_bytes: vk::DeviceSize,
+}
+
+/// Copies host data into device-local buffers and images on the transfer queue.
+///
+/// Data goes through a host-visible staging ring that is reused as soon as the copies
+/// reading from it have completed; larger uploads are split into chunks. Copies are batched
+/// until `submit`, whose ticket can be waited on from the host, or whose timeline value can
+/// be waited on by another queue.
+pub struct Uploader
+{
+    staging: Buffer<u8>,
+    /// Next free byte of the ring.
+    head: vk::DeviceSize,
+    /// Bytes between the oldest one still in use and `head`, including wrap-around waste.
+    used: vk::DeviceSize,
+    recording: Option<(vk::CommandBuffer, Fence)>,
+    recording_bytes: vk::DeviceSize,
+    in_flight: VecDeque<Submission>,
+    idle: Vec<(vk::CommandBuffer, Fence)>,
+    next_ticket: u64,
+    completed_ticket: u64,
+    alignment: vk::DeviceSize,
+    queue: Queue<Transfer>,
+    command_pool: CommandPool,
+    device: Device,
+}
+// Not used by the demo renderer yet; textures and buffers upload through it.
+#[allow(dead_code)]
+impl Uploader
+{
+    pub fn create(allocator: &Allocator) -> Result<Uploader, vk::Result>
+    {
+        Uploader::with_staging_size(allocator, DEFAULT_STAGING_SIZE)
+    }
+
+    pub fn with_staging_size(allocator: &Allocator, staging_size: vk::DeviceSize) -> Result<Uploader, vk::Result>
+    {
+        let device = allocator.get_device();
+        let staging = Buffer::create(
+            allocator,
+            staging_size as usize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryUsage::Upload)?;
+        let queue = device.get_transfer_queue();
+        let command_pool = CommandPool::create(
+            &device,
+            queue.get_family_index(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER | vk::CommandPoolCreateFlags::TRANSIENT)?;
+        staging.set_name("upload staging");
+        command_pool.set_name("upload commands");
+        let limits = &device.get_physical_device().get_properties().limits;
+        Ok(Uploader {
+            staging,
+            head: 0,
+            used: 0,
+            recording: None,
+            recording_bytes: 0,
+            in_flight: VecDeque::new(),
+            idle: Vec::new(),
+            next_ticket: 1,
+            completed_ticket: 0,
+            alignment: limits.optimal_buffer_copy_offset_alignment.max(16),
+            queue,
+            command_pool,
+            device,
+        })
+    }
+
+    pub fn get_staging_size(&self) -> vk::DeviceSize
+    {
+        self.staging.get_size()
+    }
+
+    /// Records a copy of `data` into `destination`, starting at element `offset`.
+    ///
+    /// Nothing reaches the GPU before `submit`, unless the staging ring runs full.
+    pub fn upload<T: bytemuck::Pod>(&mut self, destination: &Buffer<T>, offset: usize, data: &[T]) -> Result<(), BufferError>
+    {
+        if offset + data.len() > destination.len()
+        {
+            return Err(BufferError::OutOfBounds(offset..offset + data.len(), destination.len()));
+        }
+        debug_assert!(destination.get_usage().contains(vk::BufferUsageFlags::TRANSFER_DST));
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let destination_offset = (offset * std::mem::size_of::<T>()) as vk::DeviceSize;
+        // Half the ring, so that a chunk always fits once the ring has drained.
+        let chunk_size = (self.get_staging_size() / 2).max(1) as usize;
+        let mut done = 0;
+        while done < bytes.len()
+        {
+            let chunk = &bytes[done..bytes.len().min(done + chunk_size)];
+            let staging_offset = self.stage(chunk)?;
+            let command_buffer = self.get_command_buffer()?;
+            let region = vk::BufferCopy {
+                src_offset: staging_offset,
+                dst_offset: destination_offset + done as vk::DeviceSize,
+                size: chunk.len() as vk::DeviceSize,
+            };
+            unsafe {
+                self.device.get_device().cmd_copy_buffer(
+                    command_buffer, self.staging.get_handle(), destination.get_handle(), &[region]);
+            }
+            done += chunk.len();
+        }
+        Ok(())
+    }
+
+    /// Records a layout transition of `range` of `image`, ordered after earlier transfers.
+    pub fn transition_image(
+        &mut self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout) -> Result<(), vk::Result>
+    {
+        let command_buffer = self.get_command_buffer()?;
+        let src_access = if old_layout == vk::ImageLayout::UNDEFINED
+        {
+            vk::AccessFlags::empty()
+        }
+        else
+        {
+            vk::AccessFlags::TRANSFER_WRITE
+        };
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(range);
+        unsafe {
+            self.device.get_device().cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier.build()]);
+        }
+        Ok(())
+    }
+
+    /// Records a copy of tightly packed texel `data` into mip `level` of a 2D color `image`
+    /// in `TRANSFER_DST_OPTIMAL`; large levels are copied in bands of block rows.
+    pub fn upload_image(
+        &mut self,
+        image: vk::Image,
+        block: FormatBlock,
+        level: u32,
+        extent: vk::Extent2D,
+        data: &[u8]) -> Result<(), BufferError>
+    {
+        let row_bytes = (extent.width.div_ceil(block.width) * block.bytes) as usize;
+        let block_rows = extent.height.div_ceil(block.height) as usize;
+        if data.len() < row_bytes * block_rows
+        {
+            return Err(BufferError::OutOfBounds(0..row_bytes * block_rows, data.len()));
+        }
+        let rows_per_chunk = ((self.get_staging_size() / 2) as usize / row_bytes).max(1);
+        let mut row = 0;
+        while row < block_rows
+        {
+            let rows = rows_per_chunk.min(block_rows - row);
+            let staging_offset = self.stage(&data[row * row_bytes..(row + rows) * row_bytes])?;
+            let command_buffer = self.get_command_buffer()?;
+            let y = row as u32 * block.height;
+            let region = vk::BufferImageCopy {
+                buffer_offset: staging_offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: y as i32, z: 0 },
+                // The last band of a compressed image may end in a partial block row.
+                image_extent: vk::Extent3D {
+                    width: extent.width,
+                    height: (rows as u32 * block.height).min(extent.height - y),
+                    depth: 1,
+                },
+            };
+            unsafe {
+                self.device.get_device().cmd_copy_buffer_to_image(
+                    command_buffer,
+                    self.staging.get_handle(),
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region]);
+            }
+            row += rows;
+        }
+        Ok(())
+    }
+
+    /// Copies `bytes` into the staging ring and returns their offset there.
+    fn stage(&mut self, bytes: &[u8]) -> Result<vk::DeviceSize, BufferError>
+    {
+        let staging_offset = self.reserve(bytes.len() as vk::DeviceSize)?;
+        let mapped = self.staging.get_allocation().get_mapped_ptr().ok_or(BufferError::NotHostVisible)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), mapped.add(staging_offset as usize), bytes.len());
+        }
+        Ok(staging_offset)
+    }
+
+    /// Submits the recorded copies; `timeline`, when given, is signalled once they are done.
+    pub fn submit(&mut self, timeline: Option<TimelineSignal>) -> Result<UploadTicket, vk::Result>
+    {
+        if self.recording.is_none() && timeline.is_none()
+        {
+            // Nothing new; done as soon as everything before is.
+            return Ok(UploadTicket(self.next_ticket - 1));
+        }
+        let (command_buffer, fence) = match self.recording.take()
+        {
+            Some(recording) => recording,
+            None => self.begin_recording()?,
+        };
+        let device = self.device.get_device();
+        unsafe { device.end_command_buffer(command_buffer) }?;
+        self.staging.get_allocation().flush()?;
+
+        let command_buffers = [command_buffer];
+        let signal_semaphores : Vec<vk::Semaphore> = timeline.iter().map(|signal| signal.semaphore).collect();
+        let signal_values : Vec<u64> = timeline.iter().map(|signal| signal.value).collect();
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .signal_semaphore_values(&signal_values);
+        let mut submit_info = vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        if timeline.is_some()
+        {
+            submit_info = submit_info.push_next(&mut timeline_info);
+        }
+        unsafe { device.queue_submit(self.queue.get_handle(), &[submit_info.build()], fence.get_handle()) }?;
+
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.in_flight.push_back(Submission {
+            ticket,
+            command_buffer,
+            fence,
+            staging_bytes: self.recording_bytes,
+        });
+        self.recording_bytes = 0;
+        Ok(UploadTicket(ticket))
+    }
+
+    /// Whether the copies of `ticket` have completed.
+    pub fn is_complete(&mut self, ticket: UploadTicket) -> Result<bool, vk::Result>
+    {
+        self.reclaim()?;
+        Ok(ticket.0 <= self.completed_ticket)
+    }
+
+    /// Blocks until the copies of `ticket` have completed.
+    pub fn wait(&mut self, ticket: UploadTicket) -> Result<(), vk::Result>
+    {
+        self.reclaim()?;
+        while ticket.0 > self.completed_ticket
+        {
+            match self.in_flight.front()
+            {
+                Some(submission) => submission.fence.wait(u64::MAX)?,
+                None => break,
+            }
+            self.reclaim()?;
+        }
+        Ok(())
+    }
+
+    /// Submits whatever is recorded and waits for all of it.
+    pub fn flush(&mut self) -> Result<(), vk::Result>
+    {
+        let ticket = self.submit(None)?;
+        self.wait(ticket)
+    }
+
+    fn begin_recording(&mut self) -> Result<(vk::CommandBuffer, Fence), vk::Result>
+    {
+        self.reclaim()?;
+        let (command_buffer, fence) = match self.idle.pop()
+        {
+            Some(idle) => idle,
+            None =>
+            {
+                let command_buffer = self.command_pool.allocate(vk::CommandBufferLevel::PRIMARY, 1)?[0];
+                (command_buffer, Fence::create(&self.device, false)?)
+            }
+        };
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { self.device.get_device().begin_command_buffer(command_buffer, &begin_info) }?;
+        Ok((command_buffer, fence))
+    }
+
+    fn get_command_buffer(&mut self) -> Result<vk::CommandBuffer, vk::Result>
+    {
+        if self.recording.is_none()
+        {
+            self.recording = Some(self.begin_recording()?);
+        }
+        Ok(self.recording.as_ref().unwrap().0)
+    }
+
+    /// Gives back the staging space and command buffers of completed submissions.
+    fn reclaim(&mut self) -> Result<(), vk::Result>
+    {
+        while let Some(submission) = self.in_flight.front()
+        {
+            if !submission.fence.is_signaled()?
+            {
+                break;
+            }
+            let submission = self.in_flight.pop_front().unwrap();
+            submission.fence.reset()?;
+            self.used -= submission.staging_bytes;
+            self.completed_ticket = submission.ticket;
+            unsafe {
+                self.device.get_device().reset_command_buffer(
+                    submission.command_buffer, vk::CommandBufferResetFlags::empty())
+            }?;
+            self.idle.push((submission.command_buffer, submission.fence));
+        }
+        if self.used == 0
+        {
+            self.head = 0;
+        }
+        Ok(())
+    }
+
+    /// Finds `size` free bytes in the ring, submitting and waiting for older copies if needed.
+    fn reserve(&mut self, size: vk::DeviceSize) -> Result<vk::DeviceSize, vk::Result>
+    {
+        let capacity = self.get_staging_size();
+        loop
+        {
+            self.reclaim()?;
+            let aligned = self.head.next_multiple_of(self.alignment);
+            // Either right after `head`, or at the start once the end of the ring is skipped.
+            let (offset, needed) = if aligned + size <= capacity
+            {
+                (aligned, aligned - self.head + size)
+            }
+            else
+            {
+                (0, capacity - self.head + size)
+            };
+            if self.used + needed <= capacity
+            {
+                self.head = offset + size;
+                self.used += needed;
+                self.recording_bytes += needed;
+                return Ok(offset);
+            }
+            if self.recording_bytes > 0
+            {
+                self.submit(None)?;
+            }
+            let oldest = self.in_flight.front().map(|submission| UploadTicket(submission.ticket));
+            match oldest
+            {
+                Some(ticket) => self.wait(ticket)?,
+                None => return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+            }
+        }
+    }
+}
+impl Drop for Uploader
+{
+    fn drop(&mut self)
+    {
+        for submission in &self.in_flight
+        {
+            let _ = submission.fence.wait(u64::MAX);
+        }
+    }
+}
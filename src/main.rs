@@ -4,6 +4,12 @@ mod rc_string;
 
 
 fn main() {
-    let mut ludo = Ludo::default();
+    let mut config = Config::from_env();
+    if let Err(error) = config.apply_args(std::env::args().skip(1)) {
+        eprintln!("{}", error);
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    let mut ludo = Ludo::new(config);
     ludo.run();
 }
\ No newline at end of file